@@ -0,0 +1,53 @@
+use pcan_basic::bus::UsbBus;
+use pcan_basic::socket::usb::UsbCanSocket;
+use pcan_basic::socket::{Baudrate, SendCan};
+use pcan_basic::tracefile::asc::AscReader;
+use pcan_basic::tracefile::Event;
+use std::time::Instant;
+
+fn main() {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "log.asc".to_string());
+    let reader = match AscReader::open(&path) {
+        Ok(reader) => reader,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    let usb_socket = match UsbCanSocket::open(UsbBus::USB1, Baudrate::Baud500K) {
+        Ok(socket) => socket,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    // replay the classic frames of channel 1 with their original timing
+    let started = Instant::now();
+    let mut first = None;
+    for record in reader {
+        let record = match record {
+            Ok(record) => record,
+            Err(err) => {
+                println!("{:?}", err);
+                return;
+            }
+        };
+        let frame = match record.event {
+            Event::Can(frame) if record.bus == 1 => frame,
+            _ => continue,
+        };
+
+        let first = *first.get_or_insert(record.time);
+        let offset = record.time.duration_since(first).unwrap_or_default();
+        if let Some(wait) = offset.checked_sub(started.elapsed()) {
+            std::thread::sleep(wait);
+        }
+        if let Err(err) = usb_socket.send(frame) {
+            println!("{:?}", err);
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use pcan_basic::bus::UsbBus;
+use pcan_basic::error::PcanError;
+use pcan_basic::socket::usb::UsbCanSocket;
+use pcan_basic::socket::{Baudrate, RecvCan};
+use pcan_basic::tracefile::blf::BlfWriter;
+use pcan_basic::tracefile::Direction;
+use std::time::Duration;
+
+fn main() {
+    let usb_socket = match UsbCanSocket::open(UsbBus::USB1, Baudrate::Baud500K) {
+        Ok(socket) => socket,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    let mut writer = match BlfWriter::create("recording.blf") {
+        Ok(writer) => writer,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    // record 10000 frames, the file statistics are written when finished
+    let mut count = 0;
+    while count < 10_000 {
+        match usb_socket.recv() {
+            Ok((frame, timestamp)) => {
+                if let Err(err) = writer.write_can(&frame, &timestamp, Direction::Rx) {
+                    println!("{:?}", err);
+                    return;
+                }
+                count += 1;
+            }
+            Err(PcanError::QrcvEmpty) => std::thread::sleep(Duration::from_millis(1)),
+            Err(err) => {
+                println!("{:?}", err);
+                break;
+            }
+        }
+    }
+
+    if let Err(err) = writer.finish() {
+        println!("{:?}", err);
+    }
+}
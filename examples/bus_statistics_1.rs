@@ -0,0 +1,43 @@
+use pcan_basic::bus::UsbBus;
+use pcan_basic::socket::usb::UsbCanSocket;
+use pcan_basic::socket::Baudrate;
+use pcan_basic::stats::{BusStatistics, StatisticsCollector};
+use std::thread::sleep;
+use std::time::Duration;
+
+fn main() {
+    let usb_socket = match UsbCanSocket::open(UsbBus::USB1, Baudrate::Baud500K) {
+        Ok(socket) => socket,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    let statistics = match BusStatistics::from_socket(&usb_socket) {
+        Ok(statistics) => statistics,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    let collector = StatisticsCollector::spawn(usb_socket, statistics, Duration::from_secs(1));
+
+    loop {
+        sleep(Duration::from_secs(1));
+
+        let snapshot = collector.snapshot();
+        println!(
+            "frames/s={:.1} load={:.1}% errors/s={:.1}",
+            snapshot.frames_per_second, snapshot.bus_load, snapshot.error_frames_per_second
+        );
+
+        for (key, statistics) in snapshot.ids.iter() {
+            println!(
+                "{:?} count={} cycle_time={:?}",
+                key, statistics.count, statistics.cycle_time
+            );
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use pcan_basic::bus::UsbBus;
+use pcan_basic::error::PcanError;
+use pcan_basic::socket::usb::UsbCanSocket;
+use pcan_basic::socket::{Baudrate, CanFrame, RecvCan, SendCan};
+use pcan_basic::tracefile::candump::CandumpWriter;
+use pcan_basic::tracefile::Direction;
+use std::time::Duration;
+
+fn main() {
+    let usb_socket = match UsbCanSocket::open(UsbBus::USB1, Baudrate::Baud500K) {
+        Ok(socket) => socket,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    // send a frame given in cansend syntax, e.g. 123#DEADBEEF
+    if let Some(argument) = std::env::args().nth(1) {
+        let frame = match argument.parse::<CanFrame>() {
+            Ok(frame) => frame,
+            Err(err) => {
+                println!("{:?}", err);
+                return;
+            }
+        };
+        if let Err(err) = usb_socket.send(frame) {
+            println!("{:?}", err);
+            return;
+        }
+    }
+
+    // print received frames like candump -l
+    let mut writer = CandumpWriter::new(std::io::stdout());
+    loop {
+        match usb_socket.recv() {
+            Ok((frame, timestamp)) => {
+                if let Err(err) = writer.write_can(&frame, &timestamp, Direction::Rx) {
+                    println!("{:?}", err);
+                    return;
+                }
+            }
+            Err(PcanError::QrcvEmpty) => std::thread::sleep(Duration::from_millis(1)),
+            Err(err) => {
+                println!("{:?}", err);
+                return;
+            }
+        }
+    }
+}
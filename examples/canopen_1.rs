@@ -0,0 +1,44 @@
+use pcan_basic::bus::UsbBus;
+use pcan_basic::canopen::{CanOpenMaster, Event, NmtCommand, PdoMapping};
+use pcan_basic::socket::usb::UsbCanSocket;
+use pcan_basic::socket::Baudrate;
+use std::time::Duration;
+
+fn main() {
+    let usb_socket = match UsbCanSocket::open(UsbBus::USB1, Baudrate::Baud250K) {
+        Ok(socket) => socket,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    let mut master = CanOpenMaster::new(usb_socket);
+
+    // device name of node 2
+    match master.sdo_upload(2, 0x1008, 0) {
+        Ok(name) => println!("device name: {}", String::from_utf8_lossy(&name)),
+        Err(err) => println!("{:?}", err),
+    }
+
+    // producer heartbeat time of 100 ms
+    if let Err(err) = master.sdo_download(2, 0x1017, 0, &100u16.to_le_bytes()) {
+        println!("{:?}", err);
+    }
+    let _ = master.monitor_heartbeat(2, Duration::from_millis(300));
+
+    // first TPDO of node 2 as described by its mapping parameter
+    let mapping = PdoMapping::from_parameters(&[0x6000_0108, 0x6401_0110]).unwrap();
+
+    let _ = master.nmt(NmtCommand::Start, 2);
+
+    while let Ok(event) = master.recv_event(Duration::from_secs(5)) {
+        match event {
+            Event::Pdo {
+                cob_id: 0x182,
+                data,
+            } => println!("TPDO1: {:?}", mapping.decode(&data)),
+            event => println!("{:?}", event),
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use pcan_basic::bus::UsbBus;
+use pcan_basic::canopen::{
+    AccessType, CanOpenDevice, DataType, Entry, MappedObject, ObjectDictionary, PdoMapping,
+};
+use pcan_basic::socket::usb::UsbCanSocket;
+use pcan_basic::socket::Baudrate;
+use std::thread;
+use std::time::Duration;
+
+fn main() {
+    let usb_socket = match UsbCanSocket::open(UsbBus::USB1, Baudrate::Baud250K) {
+        Ok(socket) => socket,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    // alternatively: pcan_basic::canopen::load_eds("device.eds", 2)
+    let mut od = ObjectDictionary::new();
+    od.insert(
+        0x1000,
+        0,
+        Entry::numeric(
+            "Device type",
+            DataType::Unsigned32,
+            AccessType::ReadOnly,
+            0x0191,
+        ),
+    );
+    od.insert(
+        0x1017,
+        0,
+        Entry::numeric(
+            "Producer heartbeat time",
+            DataType::Unsigned16,
+            AccessType::ReadWrite,
+            100,
+        ),
+    );
+    od.insert(
+        0x6000,
+        1,
+        Entry::numeric("Counter", DataType::Unsigned8, AccessType::ReadOnly, 0)
+            .with_pdo_mapping(true),
+    );
+
+    // TPDO1 sent on every SYNC
+    let mapping = PdoMapping::new(vec![MappedObject::new(0x6000, 1, 8)]).unwrap();
+    od.add_tpdo(0, 0x182, 1, 0, &mapping);
+
+    let device = match CanOpenDevice::new(usb_socket, 2, od).and_then(CanOpenDevice::spawn) {
+        Ok(device) => device,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    for counter in 0..=255u8 {
+        let _ = device.lock().set_value(0x6000, 1, &[counter]);
+        thread::sleep(Duration::from_millis(100));
+    }
+    println!("{:?}", device.lock().state());
+}
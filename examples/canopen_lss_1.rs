@@ -0,0 +1,39 @@
+use pcan_basic::bus::UsbBus;
+use pcan_basic::canopen::{LssMaster, LssMode};
+use pcan_basic::socket::usb::UsbCanSocket;
+use pcan_basic::socket::Baudrate;
+
+fn main() {
+    let usb_socket = match UsbCanSocket::open(UsbBus::USB1, Baudrate::Baud250K) {
+        Ok(socket) => socket,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    let mut master = LssMaster::new(usb_socket);
+
+    // assign node-IDs from 10 on to all devices without one
+    let mut node = 10;
+    loop {
+        match master.fastscan() {
+            Ok(Some(address)) => {
+                println!("found {:x?}, assigning node-ID {}", address, node);
+                if let Err(err) = master
+                    .configure_node_id(node)
+                    .and_then(|_| master.store_configuration())
+                {
+                    println!("{:?}", err);
+                }
+                let _ = master.switch_state_global(LssMode::Waiting);
+                node += 1;
+            }
+            Ok(None) => break,
+            Err(err) => {
+                println!("{:?}", err);
+                break;
+            }
+        }
+    }
+}
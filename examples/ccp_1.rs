@@ -0,0 +1,57 @@
+use pcan_basic::bus::UsbBus;
+use pcan_basic::ccp::{CcpConfig, CcpMaster, DaqList, RESOURCE_DAQ};
+use pcan_basic::socket::usb::UsbCanSocket;
+use pcan_basic::socket::Baudrate;
+use pcan_basic::xcp::OdtEntry;
+use std::time::Duration;
+
+fn main() {
+    let usb_socket = match UsbCanSocket::open(UsbBus::USB1, Baudrate::Baud500K) {
+        Ok(socket) => socket,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    let mut master = CcpMaster::new(usb_socket, CcpConfig::new(0x6F0, 0x6F1));
+    if let Err(err) = master.connect(0x0039) {
+        println!("{:?}", err);
+        return;
+    }
+
+    match master.get_ccp_version() {
+        Ok((main, release)) => println!("CCP {}.{}", main, release),
+        Err(err) => println!("{:?}", err),
+    }
+
+    // the key of this slave is the inverted seed
+    let key = |_: u8, seed: &[u8]| seed.iter().map(|byte| !byte).collect::<Vec<u8>>();
+    if let Err(err) = master.unlock(RESOURCE_DAQ, key) {
+        println!("{:?}", err);
+        return;
+    }
+
+    // measure two 16-bit values in DAQ list 0 on event channel 1
+    let list = DaqList::new(0, 1).with_odt(vec![
+        OdtEntry::new(0x0010_2000, 0, 2),
+        OdtEntry::new(0x0010_2002, 0, 2),
+    ]);
+    if let Err(err) = master
+        .configure_daq(&[list])
+        .and_then(|_| master.start_daq())
+    {
+        println!("{:?}", err);
+        return;
+    }
+
+    for _ in 0..100 {
+        match master.recv_daq(Duration::from_secs(1)) {
+            Ok(packet) => println!("{:02X?}", packet.values),
+            Err(err) => println!("{:?}", err),
+        }
+    }
+
+    let _ = master.stop_daq();
+    let _ = master.disconnect();
+}
@@ -0,0 +1,40 @@
+use pcan_basic::bus::UsbBus;
+use pcan_basic::error::PcanError;
+use pcan_basic::socket::clock::{Anchored, Clock};
+use pcan_basic::socket::usb::UsbCanSocket;
+use pcan_basic::socket::{Baudrate, RecvCan};
+use std::time::{Duration, SystemTime};
+
+fn main() {
+    let usb_socket = match UsbCanSocket::open(UsbBus::USB1, Baudrate::Baud500K) {
+        Ok(socket) => socket,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    let mut clock = Clock::new(usb_socket.anchor()).with_drift_correction(true);
+    loop {
+        match usb_socket.recv() {
+            Ok((frame, timestamp)) => {
+                clock.observe(&timestamp);
+                let time = clock
+                    .to_system_time(&timestamp)
+                    .and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok())
+                    .unwrap_or_default();
+                println!(
+                    "{:.6} ({:+.1} ppm) {:?}",
+                    time.as_secs_f64(),
+                    clock.drift(),
+                    frame
+                );
+            }
+            Err(PcanError::QrcvEmpty) => std::thread::sleep(Duration::from_millis(1)),
+            Err(err) => {
+                println!("{:?}", err);
+                return;
+            }
+        }
+    }
+}
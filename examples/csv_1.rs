@@ -0,0 +1,48 @@
+use pcan_basic::bus::UsbBus;
+use pcan_basic::error::PcanError;
+use pcan_basic::socket::usb::UsbCanSocket;
+use pcan_basic::socket::{Baudrate, RecvCan};
+use pcan_basic::tracefile::columns::{ExportOptions, TimeFormat};
+use pcan_basic::tracefile::csv::CsvWriter;
+use pcan_basic::tracefile::Direction;
+use std::time::Duration;
+
+fn main() {
+    let usb_socket = match UsbCanSocket::open(UsbBus::USB1, Baudrate::Baud500K) {
+        Ok(socket) => socket,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    // e.g. pandas.read_csv("traffic.csv", parse_dates=["timestamp"])
+    let options = ExportOptions::new().with_time_format(TimeFormat::Rfc3339);
+    let mut writer = match CsvWriter::create("traffic.csv", options) {
+        Ok(writer) => writer,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    for _ in 0..1000 {
+        match usb_socket.recv() {
+            Ok((frame, timestamp)) => {
+                if let Err(err) = writer.write_can(&frame, &timestamp, Direction::Rx) {
+                    println!("{:?}", err);
+                    return;
+                }
+            }
+            Err(PcanError::QrcvEmpty) => std::thread::sleep(Duration::from_millis(1)),
+            Err(err) => {
+                println!("{:?}", err);
+                return;
+            }
+        }
+    }
+
+    if let Err(err) = writer.flush() {
+        println!("{:?}", err);
+    }
+}
@@ -0,0 +1,36 @@
+use pcan_basic::bus::UsbBus;
+use pcan_basic::cyclic::{CyclicScheduler, Job};
+use pcan_basic::socket::usb::UsbCanSocket;
+use pcan_basic::socket::{Baudrate, CanFrame, MessageType};
+use std::thread::sleep;
+use std::time::Duration;
+
+fn main() {
+    let usb_socket = match UsbCanSocket::open(UsbBus::USB1, Baudrate::Baud500K) {
+        Ok(socket) => socket,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    let scheduler = CyclicScheduler::new(usb_socket);
+
+    let status = CanFrame::new(0x100, MessageType::Standard, &[0x01, 0x02]).unwrap();
+    let status_job = scheduler
+        .add(Job::fixed(Duration::from_millis(100), status))
+        .unwrap();
+
+    let counter_job = Job::new(Duration::from_millis(10), |cycle| {
+        let counter = (cycle % 16) as u8;
+        CanFrame::new(0x200, MessageType::Standard, &[counter, 0xFF ^ counter]).unwrap()
+    })
+    .with_offset(Duration::from_millis(5));
+    let counter_job = scheduler.add(counter_job).unwrap();
+
+    loop {
+        sleep(Duration::from_secs(1));
+        println!("status={:?}", scheduler.statistics(status_job));
+        println!("counter={:?}", scheduler.statistics(counter_job));
+    }
+}
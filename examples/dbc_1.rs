@@ -0,0 +1,50 @@
+use pcan_basic::bus::UsbBus;
+use pcan_basic::dbc::Database;
+use pcan_basic::error::PcanError;
+use pcan_basic::socket::usb::UsbCanSocket;
+use pcan_basic::socket::{Baudrate, RecvCan};
+use std::time::Duration;
+
+fn main() {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "network.dbc".to_string());
+    let database = match Database::open(&path) {
+        Ok(database) => database,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    let usb_socket = match UsbCanSocket::open(UsbBus::USB1, Baudrate::Baud500K) {
+        Ok(socket) => socket,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    // print the signals of the messages described in the database
+    loop {
+        match usb_socket.recv_frame() {
+            Ok(frame) => {
+                let signals = match database.decode(&frame) {
+                    Ok(signals) => signals,
+                    Err(_) => continue,
+                };
+                for signal in signals {
+                    match signal.label() {
+                        Some(label) => println!("{} = {}", signal.name(), label),
+                        None => println!("{} = {} {}", signal.name(), signal.value, signal.unit()),
+                    }
+                }
+            }
+            Err(PcanError::QrcvEmpty) => std::thread::sleep(Duration::from_millis(1)),
+            Err(err) => {
+                println!("{:?}", err);
+                return;
+            }
+        }
+    }
+}
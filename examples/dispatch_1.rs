@@ -0,0 +1,35 @@
+use pcan_basic::bus::UsbBus;
+use pcan_basic::dispatch::{Dispatcher, Filter, Overflow};
+use pcan_basic::socket::usb::UsbCanSocket;
+use pcan_basic::socket::{Baudrate, MessageType};
+use std::thread;
+
+fn main() {
+    let usb_socket = match UsbCanSocket::open(UsbBus::USB1, Baudrate::Baud500K) {
+        Ok(socket) => socket,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    let dispatcher = Dispatcher::new(usb_socket);
+
+    let diagnostics = dispatcher.subscribe(Filter::Range(0x7E8..=0x7EF, MessageType::Standard));
+    let logger = dispatcher.subscribe_with(Filter::All, 4096, Overflow::DropOldest);
+
+    let diagnostics = thread::spawn(move || {
+        while let Ok((frame, _)) = diagnostics.recv() {
+            println!("diagnostics: {:?}", frame);
+        }
+    });
+
+    let logger = thread::spawn(move || {
+        while let Ok((frame, timestamp)) = logger.recv() {
+            println!("logger: {:?} {:?}", frame, timestamp);
+        }
+    });
+
+    let _ = diagnostics.join();
+    let _ = logger.join();
+}
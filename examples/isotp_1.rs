@@ -0,0 +1,29 @@
+use pcan_basic::bus::UsbBus;
+use pcan_basic::isotp::{IsoTpChannel, IsoTpConfig};
+use pcan_basic::socket::usb::UsbCanSocket;
+use pcan_basic::socket::Baudrate;
+use std::time::Duration;
+
+fn main() {
+    let usb_socket = match UsbCanSocket::open(UsbBus::USB1, Baudrate::Baud500K) {
+        Ok(socket) => socket,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    let config = IsoTpConfig::new(0x7E0, 0x7E8).with_padding(0xCC);
+    let mut channel = IsoTpChannel::new(usb_socket, config);
+
+    // ReadDataByIdentifier: VIN
+    if let Err(err) = channel.send(&[0x22, 0xF1, 0x90]) {
+        println!("{:?}", err);
+        return;
+    }
+
+    match channel.recv_timeout(Duration::from_secs(1)) {
+        Ok(response) => println!("{:02X?}", response),
+        Err(err) => println!("{:?}", err),
+    }
+}
@@ -0,0 +1,50 @@
+use pcan_basic::bus::UsbBus;
+use pcan_basic::j1939::{J1939Node, Name, GLOBAL_ADDRESS};
+use pcan_basic::socket::usb::UsbCanSocket;
+use pcan_basic::socket::Baudrate;
+use std::time::Duration;
+
+fn main() {
+    let usb_socket = match UsbCanSocket::open(UsbBus::USB1, Baudrate::Baud250K) {
+        Ok(socket) => socket,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    let name = Name {
+        identity_number: 0x1234,
+        manufacturer_code: 0x7FF,
+        function: 0x81,
+        industry_group: 0,
+        arbitrary_address_capable: true,
+        ..Name::default()
+    };
+
+    let mut node = J1939Node::new(usb_socket, name, 0xF9);
+    match node.claim_address() {
+        Ok(address) => println!("claimed address {:02X}", address),
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    }
+
+    // software identification
+    match node.request_and_wait(0xFEDA, GLOBAL_ADDRESS, Duration::from_secs(1)) {
+        Ok(message) => println!(
+            "{:02X}: {}",
+            message.source,
+            String::from_utf8_lossy(&message.data)
+        ),
+        Err(err) => println!("{:?}", err),
+    }
+
+    while let Ok(message) = node.recv_timeout(Duration::from_secs(5)) {
+        println!(
+            "{:05X} from {:02X}: {:02X?}",
+            message.pgn, message.source, message.data
+        );
+    }
+}
@@ -0,0 +1,49 @@
+use pcan_basic::bus::UsbBus;
+use pcan_basic::error::PcanError;
+use pcan_basic::socket::usb::UsbCanSocket;
+use pcan_basic::socket::{Baudrate, RecvCan};
+use pcan_basic::tracefile::mf4::{Mf4Options, Mf4Writer};
+use pcan_basic::tracefile::Direction;
+use std::time::Duration;
+
+fn main() {
+    let usb_socket = match UsbCanSocket::open(UsbBus::USB1, Baudrate::Baud500K) {
+        Ok(socket) => socket,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    let mut writer =
+        match Mf4Writer::create("recording.mf4", Mf4Options::new().with_compression(true)) {
+            Ok(writer) => writer,
+            Err(err) => {
+                println!("{:?}", err);
+                return;
+            }
+        };
+
+    // record 10000 frames into compressed data blocks, the file is finalized when finished
+    let mut count = 0;
+    while count < 10_000 {
+        match usb_socket.recv() {
+            Ok((frame, timestamp)) => {
+                if let Err(err) = writer.write_can(&frame, &timestamp, Direction::Rx) {
+                    println!("{:?}", err);
+                    return;
+                }
+                count += 1;
+            }
+            Err(PcanError::QrcvEmpty) => std::thread::sleep(Duration::from_millis(1)),
+            Err(err) => {
+                println!("{:?}", err);
+                break;
+            }
+        }
+    }
+
+    if let Err(err) = writer.finish() {
+        println!("{:?}", err);
+    }
+}
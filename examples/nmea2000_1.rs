@@ -0,0 +1,52 @@
+use pcan_basic::bus::UsbBus;
+use pcan_basic::j1939::{J1939Error, Name};
+use pcan_basic::nmea2000::{Decoder, Nmea2000Node};
+use pcan_basic::socket::usb::UsbCanSocket;
+use pcan_basic::socket::Baudrate;
+use std::time::Duration;
+
+fn main() {
+    let usb_socket = match UsbCanSocket::open(UsbBus::USB1, Baudrate::Baud250K) {
+        Ok(socket) => socket,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    let name = Name {
+        identity_number: 1,
+        manufacturer_code: 0x7FF,
+        function: 130,
+        vehicle_system: 120,
+        industry_group: 4,
+        arbitrary_address_capable: true,
+        ..Name::default()
+    };
+    let mut node = Nmea2000Node::new(usb_socket, name, 0x90);
+    match node.claim_address() {
+        Ok(address) => println!("claimed address {:#04x}", address),
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    }
+
+    let decoder = Decoder::default();
+    loop {
+        match node.recv_timeout(Duration::from_secs(1)) {
+            Ok(message) => match decoder.decode_message(&message) {
+                Some(parameter_group) => println!("{:?}", parameter_group),
+                None => println!(
+                    "PGN {} from {}: {:02X?}",
+                    message.pgn, message.source, message.data
+                ),
+            },
+            Err(J1939Error::Timeout) => {}
+            Err(err) => {
+                println!("{:?}", err);
+                return;
+            }
+        }
+    }
+}
@@ -0,0 +1,48 @@
+use pcan_basic::bus::UsbBus;
+use pcan_basic::obd::{DtcKind, ObdClient};
+use pcan_basic::socket::usb::UsbCanSocket;
+use pcan_basic::socket::Baudrate;
+
+fn main() {
+    let usb_socket = match UsbCanSocket::open(UsbBus::USB1, Baudrate::Baud500K) {
+        Ok(socket) => socket,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    let mut client = ObdClient::new(usb_socket);
+    match client.detect() {
+        Ok(addressing) => println!("{:?}", addressing),
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    }
+
+    if let Ok(vins) = client.read_vin() {
+        for vin in vins {
+            println!("{:X}: VIN {}", vin.ecu, vin.value);
+        }
+    }
+
+    for pid in [0x05, 0x0C, 0x0D] {
+        if let Ok(values) = client.read_pid(pid) {
+            for value in values {
+                println!(
+                    "{:X}: {} {:.1} {}",
+                    value.ecu, value.value.name, value.value.value, value.value.unit
+                );
+            }
+        }
+    }
+
+    if let Ok(responses) = client.read_dtcs(DtcKind::Stored) {
+        for response in responses {
+            for dtc in response.value {
+                println!("{:X}: {}", response.ecu, dtc);
+            }
+        }
+    }
+}
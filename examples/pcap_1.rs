@@ -0,0 +1,46 @@
+use pcan_basic::bus::UsbBus;
+use pcan_basic::error::PcanError;
+use pcan_basic::socket::usb::UsbCanSocket;
+use pcan_basic::socket::{Baudrate, RecvCan};
+use pcan_basic::tracefile::pcap::{CaptureFormat, CaptureWriter};
+use pcan_basic::tracefile::Direction;
+use std::time::Duration;
+
+fn main() {
+    let usb_socket = match UsbCanSocket::open(UsbBus::USB1, Baudrate::Baud500K) {
+        Ok(socket) => socket,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    // live capture into a pipe created with `mkfifo /tmp/pcan` while `wireshark -k -i /tmp/pcan`
+    // is running
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "/tmp/pcan".to_string());
+    let mut writer = match CaptureWriter::open_pipe(&path, CaptureFormat::Pcapng) {
+        Ok(writer) => writer,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    loop {
+        match usb_socket.recv() {
+            Ok((frame, timestamp)) => {
+                if let Err(err) = writer.write_can(&frame, &timestamp, Direction::Rx) {
+                    println!("{:?}", err);
+                    return;
+                }
+            }
+            Err(PcanError::QrcvEmpty) => std::thread::sleep(Duration::from_millis(1)),
+            Err(err) => {
+                println!("{:?}", err);
+                return;
+            }
+        }
+    }
+}
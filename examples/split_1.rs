@@ -0,0 +1,38 @@
+use pcan_basic::bus::UsbBus;
+use pcan_basic::socket::usb::UsbCanSocket;
+use pcan_basic::socket::{Baudrate, CanFrame, MessageType, RecvCan, SendCan, Split};
+use std::thread;
+use std::time::Duration;
+
+fn main() {
+    let usb_socket = match UsbCanSocket::open(UsbBus::USB1, Baudrate::Baud500K) {
+        Ok(socket) => socket,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    let (recv_half, send_half) = usb_socket.split();
+
+    let receiver = thread::spawn(move || loop {
+        match recv_half.recv() {
+            Ok((frame, timestamp)) => println!("{:?} {:?}", frame, timestamp),
+            Err(_) => thread::sleep(Duration::from_millis(1)),
+        }
+    });
+
+    let mut counter = 0u8;
+    loop {
+        let frame = CanFrame::new(0x123, MessageType::Standard, &[counter]).unwrap();
+        if let Err(err) = send_half.send(frame) {
+            println!("{:?}", err);
+        }
+        counter = counter.wrapping_add(1);
+
+        if receiver.is_finished() {
+            break;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,33 @@
+use pcan_basic::bus::UsbBus;
+use pcan_basic::queue::{QueuedFrame, TransmitQueue};
+use pcan_basic::socket::usb::UsbCanSocket;
+use pcan_basic::socket::{Baudrate, CanFrame, MessageType};
+use std::time::Duration;
+
+fn main() {
+    let usb_socket = match UsbCanSocket::open(UsbBus::USB1, Baudrate::Baud500K) {
+        Ok(socket) => socket,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    let mut queue = TransmitQueue::new(usb_socket).with_capacity(4096);
+
+    for i in 0..1000u32 {
+        let frame =
+            CanFrame::new(0x400 + (i % 16), MessageType::Standard, &i.to_le_bytes()).unwrap();
+        let frame = QueuedFrame::new(frame).with_timeout(Duration::from_millis(100));
+        if let Err(err) = queue.send(frame) {
+            println!("{:?}", err);
+        }
+    }
+
+    match queue.flush(Some(Duration::from_secs(1))) {
+        Ok(_) => {}
+        Err(err) => println!("{:?}", err),
+    }
+
+    println!("{:?}", queue.statistics());
+}
@@ -0,0 +1,22 @@
+use pcan_basic::tracefile::trc::TrcReader;
+
+fn main() {
+    let path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "trace.trc".to_string());
+    let reader = match TrcReader::open(&path) {
+        Ok(reader) => reader,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    println!("{:?}", reader.header());
+    for record in reader {
+        match record {
+            Ok(record) => println!("{:?}", record),
+            Err(err) => println!("{:?}", err),
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use pcan_basic::bus::UsbBus;
+use pcan_basic::error::PcanError;
+use pcan_basic::socket::clock::{Anchored, Clock};
+use pcan_basic::socket::usb::UsbCanSocket;
+use pcan_basic::socket::{Baudrate, RecvCan};
+use pcan_basic::trace::TraceFile;
+use pcan_basic::tracefile::trc::{TrcOptions, TrcWriter};
+use pcan_basic::tracefile::Direction;
+use std::time::Duration;
+
+fn main() {
+    let usb_socket = match UsbCanSocket::open(UsbBus::USB1, Baudrate::Baud500K) {
+        Ok(socket) => socket,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    // new file every 1 MB, named after the date and time it was started
+    let options = TrcOptions::new()
+        .with_mode(TraceFile::Segmented)
+        .with_mode(TraceFile::Date)
+        .with_mode(TraceFile::Time)
+        .with_max_size(1024 * 1024);
+    // frames are timed relative to the opening of the socket
+    let clock = Clock::new(usb_socket.anchor()).with_drift_correction(true);
+    let mut writer = match TrcWriter::create("recording.trc", options) {
+        Ok(writer) => writer.with_clock(clock),
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    loop {
+        match usb_socket.recv() {
+            Ok((frame, timestamp)) => {
+                if let Err(err) = writer.write_can(&frame, &timestamp, Direction::Rx) {
+                    println!("{:?}", err);
+                    return;
+                }
+            }
+            Err(PcanError::QrcvEmpty) => std::thread::sleep(Duration::from_millis(1)),
+            Err(err) => {
+                println!("{:?}", err);
+                return;
+            }
+        }
+    }
+}
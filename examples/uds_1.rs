@@ -0,0 +1,48 @@
+use pcan_basic::bus::UsbBus;
+use pcan_basic::isotp::{IsoTpChannel, IsoTpConfig};
+use pcan_basic::socket::usb::UsbCanSocket;
+use pcan_basic::socket::Baudrate;
+use pcan_basic::uds::{SessionType, UdsClient};
+
+fn main() {
+    let usb_socket = match UsbCanSocket::open(UsbBus::USB1, Baudrate::Baud500K) {
+        Ok(socket) => socket,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    let channel = IsoTpChannel::new(
+        usb_socket,
+        IsoTpConfig::new(0x7E0, 0x7E8).with_padding(0xCC),
+    );
+    let client = UdsClient::new(channel);
+
+    match client.diagnostic_session_control(SessionType::ExtendedDiagnostic) {
+        Ok(timing) => println!("{:?}", timing),
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    }
+
+    let result = client.security_access(0x01, |_: u8, seed: &[u8]| {
+        seed.iter().map(|byte| byte ^ 0x5A).collect::<Vec<u8>>()
+    });
+    println!("security access: {:?}", result);
+
+    match client.read_data_by_identifier(0xF190) {
+        Ok(vin) => println!("VIN: {}", String::from_utf8_lossy(&vin)),
+        Err(err) => println!("{:?}", err),
+    }
+
+    match client.read_dtcs_by_status_mask(0xFF) {
+        Ok((_, dtcs)) => {
+            for dtc in dtcs {
+                println!("{:06X} {:02X}", dtc.code, dtc.status);
+            }
+        }
+        Err(err) => println!("{:?}", err),
+    }
+}
@@ -0,0 +1,53 @@
+use pcan_basic::bus::UsbBus;
+use pcan_basic::socket::usb::UsbCanSocket;
+use pcan_basic::socket::Baudrate;
+use pcan_basic::xcp::{DaqList, OdtEntry, XcpConfig, XcpMaster, RESOURCE_DAQ};
+use std::time::Duration;
+
+fn main() {
+    let usb_socket = match UsbCanSocket::open(UsbBus::USB1, Baudrate::Baud500K) {
+        Ok(socket) => socket,
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    };
+
+    let mut master = XcpMaster::new(usb_socket, XcpConfig::new(0x7E0, 0x7E1));
+    match master.connect() {
+        Ok(connection) => println!("{:?}", connection),
+        Err(err) => {
+            println!("{:?}", err);
+            return;
+        }
+    }
+
+    // the key of this slave is the inverted seed
+    let key = |_: u8, seed: &[u8]| seed.iter().map(|byte| !byte).collect::<Vec<u8>>();
+    if let Err(err) = master.unlock(RESOURCE_DAQ, key) {
+        println!("{:?}", err);
+        return;
+    }
+
+    // measure a 32-bit value at 0x20001000 on event channel 0
+    let list = DaqList::new(0)
+        .with_timestamp(true)
+        .with_odt(vec![OdtEntry::new(0x2000_1000, 0, 4)]);
+    if let Err(err) = master
+        .configure_daq(&[list])
+        .and_then(|_| master.start_daq())
+    {
+        println!("{:?}", err);
+        return;
+    }
+
+    for _ in 0..100 {
+        match master.recv_daq(Duration::from_secs(1)) {
+            Ok(packet) => println!("{:?} {:02X?}", packet.timestamp, packet.values),
+            Err(err) => println!("{:?}", err),
+        }
+    }
+
+    let _ = master.stop_daq();
+    let _ = master.disconnect();
+}
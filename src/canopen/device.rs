@@ -0,0 +1,740 @@
+//! CANopen device emulation.
+//!
+//! A [CanOpenDevice] serves an [ObjectDictionary] on a socket: it runs the NMT state machine,
+//! produces heartbeats (object 0x1017), answers node guarding and SDO requests and exchanges
+//! PDOs according to the communication and mapping parameters of the dictionary (objects
+//! 0x1400.., 0x1600.., 0x1800.. and 0x1A00..).
+
+use crate::canopen::emcy::Emergency;
+use crate::canopen::nmt::{heartbeat_frame, NmtCommand, NmtState};
+use crate::canopen::od::ObjectDictionary;
+use crate::canopen::pdo::{MappedObject, PdoMapping};
+use crate::canopen::sdo::SdoServer;
+use crate::canopen::{
+    check_node_id, CanOpenError, NodeId, COB_NMT, COB_NMT_ERROR_CONTROL, COB_SDO_RX, COB_SDO_TX,
+    COB_SYNC,
+};
+use crate::error::PcanError;
+use crate::socket::{CanFrame, MessageType, RecvCan, SendCan};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Bit 31 of a PDO COB-ID disables the PDO.
+const PDO_INVALID: u32 = 0x8000_0000;
+
+struct Rpdo {
+    cob_id: u32,
+    mapping: PdoMapping,
+}
+
+struct Tpdo {
+    cob_id: u32,
+    transmission_type: u8,
+    inhibit_time: Duration,
+    event_timer: Duration,
+    mapping: PdoMapping,
+    syncs: u8,
+    pending: bool,
+    last_sent: Option<Instant>,
+    next_event: Option<Instant>,
+}
+
+impl Tpdo {
+    fn is_synchronous(&self) -> bool {
+        self.transmission_type <= 240
+    }
+}
+
+fn mapping_of(od: &ObjectDictionary, index: u16) -> Option<PdoMapping> {
+    let count = od.value_u64(index, 0)? as u8;
+    let parameters = (1..=count)
+        .map(|subindex| od.value_u64(index, subindex).map(|value| value as u32))
+        .collect::<Option<Vec<_>>>()?;
+    PdoMapping::from_parameters(&parameters).ok()
+}
+
+/// An emulated CANopen device.
+///
+/// The device is driven by its caller through [poll](CanOpenDevice::poll), or runs in a
+/// background thread after [spawn](CanOpenDevice::spawn).
+pub struct CanOpenDevice<S> {
+    socket: S,
+    node: NodeId,
+    od: ObjectDictionary,
+    initial: ObjectDictionary,
+    state: NmtState,
+    sdo: SdoServer,
+    toggle: u8,
+    next_heartbeat: Option<Instant>,
+    rpdos: Vec<Rpdo>,
+    tpdos: Vec<Tpdo>,
+}
+
+impl<S: SendCan + RecvCan> CanOpenDevice<S> {
+    /// Creates a device serving `od`. The dictionary is restored on a reset of the node.
+    pub fn new(
+        socket: S,
+        node: NodeId,
+        od: ObjectDictionary,
+    ) -> Result<CanOpenDevice<S>, CanOpenError> {
+        check_node_id(node)?;
+        Ok(CanOpenDevice {
+            socket,
+            node,
+            initial: od.clone(),
+            od,
+            state: NmtState::BootUp,
+            sdo: SdoServer::new(),
+            toggle: 0,
+            next_heartbeat: None,
+            rpdos: Vec::new(),
+            tpdos: Vec::new(),
+        })
+    }
+
+    pub fn socket(&self) -> &S {
+        &self.socket
+    }
+
+    pub fn into_socket(self) -> S {
+        self.socket
+    }
+
+    pub fn node(&self) -> NodeId {
+        self.node
+    }
+
+    pub fn state(&self) -> NmtState {
+        self.state
+    }
+
+    pub fn od(&self) -> &ObjectDictionary {
+        &self.od
+    }
+
+    /// Sends the boot-up message and enters the pre-operational state.
+    pub fn start(&mut self) -> Result<(), CanOpenError> {
+        self.boot()
+    }
+
+    /// Sets an object as the application, e.g. to update an input. Event driven TPDOs mapping
+    /// the object are sent on the next poll.
+    pub fn set_value(&mut self, index: u16, subindex: u8, data: &[u8]) -> Result<(), CanOpenError> {
+        self.od
+            .set(index, subindex, data)
+            .map_err(CanOpenError::SdoAbort)?;
+        self.object_changed(index, subindex);
+        Ok(())
+    }
+
+    pub fn value(&self, index: u16, subindex: u8) -> Option<&[u8]> {
+        self.od.get(index, subindex).map(|entry| entry.value())
+    }
+
+    pub fn send_emergency(&mut self, emergency: &Emergency) -> Result<(), CanOpenError> {
+        Ok(self.socket.send(emergency.to_frame(self.node))?)
+    }
+
+    /// Processes all received frames and sends due heartbeats and PDOs.
+    pub fn poll(&mut self) -> Result<(), CanOpenError> {
+        loop {
+            match self.socket.recv_frame() {
+                Ok(frame) => self.handle_frame(&frame)?,
+                Err(PcanError::QrcvEmpty) => break,
+                Err(err) => return Err(CanOpenError::Pcan(err)),
+            }
+        }
+        self.check_timers()
+    }
+
+    /* NMT */
+
+    fn boot(&mut self) -> Result<(), CanOpenError> {
+        self.sdo.reset();
+        self.toggle = 0;
+        self.socket
+            .send(heartbeat_frame(self.node, NmtState::BootUp))?;
+        self.state = NmtState::PreOperational;
+        self.configure_heartbeat();
+        self.configure_pdos();
+        Ok(())
+    }
+
+    fn handle_nmt(&mut self, command: NmtCommand) -> Result<(), CanOpenError> {
+        match command {
+            NmtCommand::Start => self.enter(NmtState::Operational),
+            NmtCommand::Stop => self.enter(NmtState::Stopped),
+            NmtCommand::EnterPreOperational => self.enter(NmtState::PreOperational),
+            NmtCommand::ResetNode => {
+                self.od = self.initial.clone();
+                return self.boot();
+            }
+            NmtCommand::ResetCommunication => {
+                let communication = self
+                    .initial
+                    .iter()
+                    .filter(|(index, _, _)| (0x1000..0x2000).contains(index))
+                    .map(|(index, subindex, entry)| (index, subindex, entry.clone()))
+                    .collect::<Vec<_>>();
+                for (index, subindex, entry) in communication {
+                    self.od.insert(index, subindex, entry);
+                }
+                return self.boot();
+            }
+        }
+        Ok(())
+    }
+
+    fn enter(&mut self, state: NmtState) {
+        if state == NmtState::Operational && self.state != NmtState::Operational {
+            let now = Instant::now();
+            for tpdo in self.tpdos.iter_mut() {
+                tpdo.syncs = 0;
+                tpdo.pending = false;
+                tpdo.next_event = match tpdo.event_timer.is_zero() {
+                    true => None,
+                    false => Some(now + tpdo.event_timer),
+                };
+            }
+        }
+        self.state = state;
+    }
+
+    fn configure_heartbeat(&mut self) {
+        self.next_heartbeat = match self.od.value_u64(0x1017, 0) {
+            Some(time) if time > 0 => Some(Instant::now() + Duration::from_millis(time)),
+            _ => None,
+        };
+    }
+
+    fn heartbeat_time(&self) -> Option<Duration> {
+        match self.od.value_u64(0x1017, 0) {
+            Some(time) if time > 0 => Some(Duration::from_millis(time)),
+            _ => None,
+        }
+    }
+
+    /* PDO */
+
+    fn configure_pdos(&mut self) {
+        let od = &self.od;
+        let cob_ids = |base: u16| {
+            (0..512u16).filter_map(move |number| {
+                let cob_id = od.value_u64(base + number, 1)? as u32;
+                match cob_id & PDO_INVALID {
+                    0 => Some((number, cob_id & 0x1FFF_FFFF)),
+                    _ => None,
+                }
+            })
+        };
+
+        self.rpdos = cob_ids(0x1400)
+            .filter_map(|(number, cob_id)| {
+                Some(Rpdo {
+                    cob_id,
+                    mapping: mapping_of(od, 0x1600 + number)?,
+                })
+            })
+            .collect();
+
+        self.tpdos = cob_ids(0x1800)
+            .filter_map(|(number, cob_id)| {
+                let index = 0x1800 + number;
+                Some(Tpdo {
+                    cob_id,
+                    transmission_type: od.value_u64(index, 2).unwrap_or(0xFF) as u8,
+                    inhibit_time: Duration::from_micros(od.value_u64(index, 3).unwrap_or(0) * 100),
+                    event_timer: Duration::from_millis(od.value_u64(index, 5).unwrap_or(0)),
+                    mapping: mapping_of(od, 0x1A00 + number)?,
+                    syncs: 0,
+                    pending: false,
+                    last_sent: None,
+                    next_event: None,
+                })
+            })
+            .collect();
+    }
+
+    fn object_changed(&mut self, index: u16, subindex: u8) {
+        if (0x1400..0x1C00).contains(&index) {
+            self.configure_pdos();
+            return;
+        }
+        if index == 0x1017 {
+            self.configure_heartbeat();
+            return;
+        }
+
+        let object = |mapped: &MappedObject| mapped.index == index && mapped.subindex == subindex;
+        for tpdo in self.tpdos.iter_mut() {
+            if tpdo.mapping.objects().iter().any(object) {
+                tpdo.pending = true;
+            }
+        }
+    }
+
+    fn send_tpdo(&mut self, position: usize, now: Instant) -> Result<(), CanOpenError> {
+        let tpdo = &mut self.tpdos[position];
+        let values = tpdo
+            .mapping
+            .objects()
+            .iter()
+            .map(|object| {
+                let value = self
+                    .od
+                    .value_u64(object.index, object.subindex)
+                    .unwrap_or(0);
+                match object.bits {
+                    64 => value,
+                    bits => value & ((1 << bits) - 1),
+                }
+            })
+            .collect::<Vec<_>>();
+        let data = tpdo.mapping.encode(&values)?;
+
+        tpdo.pending = false;
+        tpdo.last_sent = Some(now);
+        if !tpdo.event_timer.is_zero() {
+            tpdo.next_event = Some(now + tpdo.event_timer);
+        }
+
+        let message_type = match tpdo.cob_id > 0x7FF {
+            true => MessageType::Extended,
+            false => MessageType::Standard,
+        };
+        let frame = CanFrame::new(tpdo.cob_id, message_type, &data)
+            .map_err(|_| CanOpenError::InvalidMapping)?;
+        Ok(self.socket.send(frame)?)
+    }
+
+    fn handle_sync(&mut self) -> Result<(), CanOpenError> {
+        let now = Instant::now();
+        for position in 0..self.tpdos.len() {
+            let tpdo = &mut self.tpdos[position];
+            if !tpdo.is_synchronous() {
+                continue;
+            }
+
+            let due = match tpdo.transmission_type {
+                0 => tpdo.pending,
+                every => {
+                    tpdo.syncs += 1;
+                    tpdo.syncs >= every
+                }
+            };
+            if due {
+                tpdo.syncs = 0;
+                self.send_tpdo(position, now)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_rpdo(&mut self, position: usize, data: &[u8]) {
+        let values = match self.rpdos[position].mapping.decode(data) {
+            Ok(values) => values,
+            Err(_) => return,
+        };
+        let objects = self.rpdos[position].mapping.objects().to_vec();
+
+        for (object, value) in objects.iter().zip(values) {
+            let size = match self.od.get(object.index, object.subindex) {
+                Some(entry) => entry
+                    .data_type
+                    .size()
+                    .unwrap_or((object.bits as usize).div_ceil(8)),
+                None => continue,
+            };
+            if self
+                .od
+                .set(
+                    object.index,
+                    object.subindex,
+                    &value.to_le_bytes()[..size.min(8)],
+                )
+                .is_ok()
+            {
+                self.object_changed(object.index, object.subindex);
+            }
+        }
+    }
+
+    /* Frames */
+
+    fn handle_frame(&mut self, frame: &CanFrame) -> Result<(), CanOpenError> {
+        let cob_id = frame.can_id();
+        let data = frame.data();
+
+        if frame.is_rtr_frame() {
+            if cob_id == COB_NMT_ERROR_CONTROL + self.node as u32 && !frame.is_extended_frame() {
+                let value = (self.toggle << 7) | u8::from(self.state);
+                self.toggle ^= 1;
+                let response = CanFrame::new(cob_id, MessageType::Standard, &[value])
+                    .map_err(|_| CanOpenError::Protocol)?;
+                self.socket.send(response)?;
+            }
+            return Ok(());
+        }
+
+        if !frame.is_extended_frame() {
+            match cob_id {
+                COB_NMT if data.len() == 2 => {
+                    if data[1] == 0 || data[1] == self.node {
+                        if let Ok(command) = NmtCommand::try_from(data[0]) {
+                            self.handle_nmt(command)?;
+                        }
+                    }
+                    return Ok(());
+                }
+                COB_SYNC if self.state == NmtState::Operational => return self.handle_sync(),
+                _ if cob_id == COB_SDO_RX + self.node as u32 => {
+                    if self.state != NmtState::Stopped && data.len() == 8 {
+                        self.handle_sdo(data)?;
+                    }
+                    return Ok(());
+                }
+                _ => {}
+            }
+        }
+
+        if self.state == NmtState::Operational {
+            let extended = frame.is_extended_frame();
+            if let Some(position) = self
+                .rpdos
+                .iter()
+                .position(|rpdo| rpdo.cob_id == cob_id && (rpdo.cob_id > 0x7FF) == extended)
+            {
+                self.handle_rpdo(position, data);
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_sdo(&mut self, data: &[u8]) -> Result<(), CanOpenError> {
+        let mut request = [0u8; 8];
+        request.copy_from_slice(data);
+
+        for response in self.sdo.handle(&request, &mut self.od) {
+            let frame = CanFrame::new(
+                COB_SDO_TX + self.node as u32,
+                MessageType::Standard,
+                &response,
+            )
+            .map_err(|_| CanOpenError::Protocol)?;
+            self.socket.send(frame)?;
+        }
+
+        for (index, subindex) in self.sdo.take_written() {
+            self.object_changed(index, subindex);
+        }
+        Ok(())
+    }
+
+    /* Timers */
+
+    fn check_timers(&mut self) -> Result<(), CanOpenError> {
+        let now = Instant::now();
+
+        if let (Some(next), Some(period)) = (self.next_heartbeat, self.heartbeat_time()) {
+            if now >= next && self.state != NmtState::BootUp {
+                self.socket.send(heartbeat_frame(self.node, self.state))?;
+                // skip missed periods instead of bursting
+                let mut next = next + period;
+                while next <= now {
+                    next += period;
+                }
+                self.next_heartbeat = Some(next);
+            }
+        }
+
+        if self.state != NmtState::Operational {
+            return Ok(());
+        }
+
+        for position in 0..self.tpdos.len() {
+            let tpdo = &self.tpdos[position];
+            if tpdo.is_synchronous() {
+                continue;
+            }
+
+            let inhibited = tpdo
+                .last_sent
+                .is_some_and(|last| now < last + tpdo.inhibit_time);
+            let timer = tpdo.next_event.is_some_and(|next| now >= next);
+            if (tpdo.pending || timer) && !inhibited {
+                self.send_tpdo(position, now)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S: SendCan + RecvCan + Send + 'static> CanOpenDevice<S> {
+    /// Starts the device and polls it in a background thread.
+    pub fn spawn(mut self) -> Result<DeviceRunner<S>, CanOpenError> {
+        self.start()?;
+
+        let device = Arc::new(Mutex::new(self));
+        let running = Arc::new(AtomicBool::new(true));
+
+        let thread_device = device.clone();
+        let thread_running = running.clone();
+        let handle = thread::spawn(move || {
+            while thread_running.load(Ordering::Relaxed) {
+                {
+                    let mut device = match thread_device.lock() {
+                        Ok(device) => device,
+                        Err(poisoned) => poisoned.into_inner(),
+                    };
+                    let _ = device.poll();
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        });
+
+        Ok(DeviceRunner {
+            device,
+            running,
+            handle: Some(handle),
+        })
+    }
+}
+
+const POLL_INTERVAL: Duration = Duration::from_micros(200);
+
+/// A [CanOpenDevice] running in a background thread.
+pub struct DeviceRunner<S> {
+    device: Arc<Mutex<CanOpenDevice<S>>>,
+    running: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl<S> DeviceRunner<S> {
+    /// Gives access to the device, e.g. to update inputs with
+    /// [set_value](CanOpenDevice::set_value). The device is not polled while locked.
+    pub fn lock(&self) -> MutexGuard<'_, CanOpenDevice<S>> {
+        match self.device.lock() {
+            Ok(device) => device,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    /// Stops the background thread and hands back the device.
+    pub fn stop(mut self) -> Option<CanOpenDevice<S>> {
+        self.running.store(false, Ordering::Relaxed);
+        self.handle.take().and_then(|handle| handle.join().ok())?;
+
+        // the runner holds the last reference besides the stopped thread
+        let device = self.device.clone();
+        drop(self);
+        Arc::try_unwrap(device)
+            .ok()
+            .map(|device| match device.into_inner() {
+                Ok(device) => device,
+                Err(poisoned) => poisoned.into_inner(),
+            })
+    }
+}
+
+impl<S> Drop for DeviceRunner<S> {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canopen::master::{CanOpenMaster, Event};
+    use crate::canopen::od::{AccessType, DataType, Entry};
+    use crate::canopen::sdo::SdoAbortCode;
+    use crate::canopen::COB_TPDO1;
+    use crate::socket::pipe::pipe;
+
+    fn dictionary() -> ObjectDictionary {
+        let mut od = ObjectDictionary::new();
+        od.insert(
+            0x1000,
+            0,
+            Entry::numeric(
+                "Device type",
+                DataType::Unsigned32,
+                AccessType::ReadOnly,
+                0x0191,
+            ),
+        );
+        od.insert(
+            0x1008,
+            0,
+            Entry::new(
+                "Manufacturer device name",
+                DataType::VisibleString,
+                AccessType::Constant,
+                b"PCAN-Emulated",
+            ),
+        );
+        od.insert(
+            0x1017,
+            0,
+            Entry::numeric(
+                "Producer heartbeat time",
+                DataType::Unsigned16,
+                AccessType::ReadWrite,
+                0,
+            ),
+        );
+        od.insert(
+            0x2000,
+            0,
+            Entry::new("Domain", DataType::Domain, AccessType::ReadWrite, &[]),
+        );
+        od.insert(
+            0x6000,
+            1,
+            Entry::numeric("Input", DataType::Unsigned8, AccessType::ReadOnly, 0)
+                .with_pdo_mapping(true),
+        );
+        od.insert(
+            0x6200,
+            1,
+            Entry::numeric("Output", DataType::Unsigned16, AccessType::ReadWrite, 0)
+                .with_pdo_mapping(true),
+        );
+
+        let input = PdoMapping::new(vec![MappedObject::new(0x6000, 1, 8)]).unwrap();
+        let output = PdoMapping::new(vec![MappedObject::new(0x6200, 1, 16)]).unwrap();
+        od.add_tpdo(0, COB_TPDO1 + 4, 1, 0, &input);
+        od.add_tpdo(1, 0x284, 0xFF, 0, &output);
+        od.add_rpdo(0, 0x204, &output);
+        od
+    }
+
+    #[test]
+    fn sdo_server_001() {
+        let (master, device) = pipe();
+        let mut master = CanOpenMaster::new(master).with_sdo_timeout(Duration::from_millis(500));
+        let device = CanOpenDevice::new(device, 4, dictionary())
+            .unwrap()
+            .spawn()
+            .unwrap();
+
+        assert_eq!(
+            master.sdo_upload(4, 0x1000, 0),
+            Ok(vec![0x91, 0x01, 0x00, 0x00])
+        );
+        assert_eq!(
+            master.sdo_upload(4, 0x1008, 0),
+            Ok(b"PCAN-Emulated".to_vec())
+        );
+        assert_eq!(
+            master.sdo_download(4, 0x1000, 0, &[0; 4]),
+            Err(CanOpenError::SdoAbort(SdoAbortCode::ReadOnly))
+        );
+        assert_eq!(
+            master.sdo_upload(4, 0x3000, 0),
+            Err(CanOpenError::SdoAbort(SdoAbortCode::ObjectDoesNotExist))
+        );
+
+        let data = (0..100u8).collect::<Vec<_>>();
+        assert_eq!(master.sdo_download(4, 0x2000, 0, &data[..20]), Ok(()));
+        assert_eq!(master.sdo_upload(4, 0x2000, 0), Ok(data[..20].to_vec()));
+        assert_eq!(master.sdo_block_download(4, 0x2000, 0, &data), Ok(()));
+        assert_eq!(master.sdo_block_upload(4, 0x2000, 0), Ok(data.clone()));
+        assert_eq!(device.lock().value(0x2000, 0), Some(&data[..]));
+    }
+
+    #[test]
+    fn nmt_001() {
+        let (master, device) = pipe();
+        let mut master = CanOpenMaster::new(master).with_sdo_timeout(Duration::from_millis(500));
+        let device = CanOpenDevice::new(device, 4, dictionary())
+            .unwrap()
+            .spawn()
+            .unwrap();
+
+        let timeout = Duration::from_millis(500);
+        assert_eq!(master.recv_event(timeout), Ok(Event::BootUp(4)));
+
+        // the heartbeat starts once the producer time is written
+        master
+            .sdo_download(4, 0x1017, 0, &20u16.to_le_bytes())
+            .unwrap();
+        assert_eq!(
+            master.recv_event(timeout),
+            Ok(Event::StateChanged {
+                node: 4,
+                state: NmtState::PreOperational
+            })
+        );
+
+        master.nmt(NmtCommand::Start, 0).unwrap();
+        assert_eq!(
+            master.recv_event(timeout),
+            Ok(Event::StateChanged {
+                node: 4,
+                state: NmtState::Operational
+            })
+        );
+        assert_eq!(device.lock().state(), NmtState::Operational);
+
+        // a reset restores the dictionary, which disables the heartbeat again
+        master.nmt(NmtCommand::ResetNode, 4).unwrap();
+        assert_eq!(master.recv_event(timeout), Ok(Event::BootUp(4)));
+        assert_eq!(master.sdo_upload(4, 0x1017, 0), Ok(vec![0, 0]));
+        assert_eq!(device.stop().unwrap().state(), NmtState::PreOperational);
+    }
+
+    #[test]
+    fn pdo_001() {
+        let (master, device) = pipe();
+        let mut master = CanOpenMaster::new(master);
+        let mut device = CanOpenDevice::new(device, 4, dictionary()).unwrap();
+        device.start().unwrap();
+
+        master.nmt(NmtCommand::Start, 4).unwrap();
+        device.poll().unwrap();
+        assert_eq!(device.state(), NmtState::Operational);
+
+        let timeout = Duration::from_millis(100);
+        assert_eq!(master.recv_event(timeout), Ok(Event::BootUp(4)));
+
+        // synchronous TPDO
+        device.set_value(0x6000, 1, &[0x5A]).unwrap();
+        master.send_sync(None).unwrap();
+        device.poll().unwrap();
+        assert_eq!(
+            master.recv_event(timeout),
+            Ok(Event::Pdo {
+                cob_id: 0x184,
+                data: vec![0x5A]
+            })
+        );
+
+        // the RPDO writes the output, whose event driven TPDO echoes it
+        master.send_pdo(0x204, &[0x34, 0x12]).unwrap();
+        device.poll().unwrap();
+        assert_eq!(device.value(0x6200, 1), Some(&[0x34, 0x12][..]));
+        assert_eq!(
+            master.recv_event(timeout),
+            Ok(Event::Pdo {
+                cob_id: 0x284,
+                data: vec![0x34, 0x12]
+            })
+        );
+
+        // no PDOs outside of the operational state
+        master.nmt(NmtCommand::Stop, 4).unwrap();
+        master.send_pdo(0x204, &[0x00, 0x00]).unwrap();
+        device.poll().unwrap();
+        assert_eq!(device.value(0x6200, 1), Some(&[0x34, 0x12][..]));
+        assert_eq!(
+            master.recv_event(Duration::from_millis(20)),
+            Err(CanOpenError::Timeout)
+        );
+    }
+}
@@ -0,0 +1,301 @@
+//! Electronic data sheets.
+//!
+//! Loads an [ObjectDictionary] from an EDS or DCF file (CiA 306). Values of DCF files
+//! (`ParameterValue`) take precedence over the defaults (`DefaultValue`), and `$NODEID`
+//! expressions such as `$NODEID+0x180` are resolved with the given node-ID.
+
+use crate::canopen::od::{AccessType, DataType, Entry, ObjectDictionary};
+use crate::canopen::NodeId;
+use crate::socket::parse_hex;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+#[derive(Debug, PartialEq)]
+pub enum EdsError {
+    Io(std::io::ErrorKind),
+    /// A line is neither a section, a key-value pair nor a comment.
+    Syntax {
+        line: usize,
+    },
+    /// A key of an object is missing or has an invalid value.
+    InvalidValue {
+        section: String,
+        key: String,
+    },
+}
+
+impl From<std::io::Error> for EdsError {
+    fn from(value: std::io::Error) -> Self {
+        EdsError::Io(value.kind())
+    }
+}
+
+const OBJECT_TYPE_VAR: u64 = 0x7;
+
+/// Reads an EDS or DCF file.
+pub fn load_eds<P: AsRef<Path>>(path: P, node: NodeId) -> Result<ObjectDictionary, EdsError> {
+    parse_eds(&std::fs::read_to_string(path)?, node)
+}
+
+/// Parses the content of an EDS or DCF file.
+pub fn parse_eds(text: &str, node: NodeId) -> Result<ObjectDictionary, EdsError> {
+    let sections = parse_ini(text)?;
+    let mut od = ObjectDictionary::new();
+
+    for (name, keys) in sections.iter() {
+        let (index, subindex) = match parse_section_name(name) {
+            Some(address) => address,
+            None => continue,
+        };
+
+        let invalid = |key: &str| EdsError::InvalidValue {
+            section: name.clone(),
+            key: key.to_string(),
+        };
+
+        // arrays and records only describe their sub-objects
+        let object_type = match keys.get("objecttype") {
+            Some(value) => parse_integer(value, node).ok_or_else(|| invalid("ObjectType"))?,
+            None => OBJECT_TYPE_VAR,
+        };
+        if subindex.is_none() && object_type != OBJECT_TYPE_VAR {
+            continue;
+        }
+
+        let data_type = keys
+            .get("datatype")
+            .and_then(|value| parse_integer(value, node))
+            .ok_or_else(|| invalid("DataType"))?;
+        let data_type = DataType::from(data_type as u16);
+
+        let access = match keys.get("accesstype").map(|value| value.to_lowercase()) {
+            Some(value) if value == "ro" => AccessType::ReadOnly,
+            Some(value) if value == "wo" => AccessType::WriteOnly,
+            Some(value) if value == "rw" || value == "rwr" || value == "rww" => {
+                AccessType::ReadWrite
+            }
+            Some(value) if value == "const" => AccessType::Constant,
+            _ => return Err(invalid("AccessType")),
+        };
+
+        let (key, value) = match keys.get("parametervalue") {
+            Some(value) => ("ParameterValue", value.as_str()),
+            None => (
+                "DefaultValue",
+                keys.get("defaultvalue").map_or("", String::as_str),
+            ),
+        };
+        let value = parse_value(data_type, value, node).ok_or_else(|| invalid(key))?;
+
+        let pdo_mappable = keys
+            .get("pdomapping")
+            .is_some_and(|value| parse_integer(value, node).is_some_and(|value| value != 0));
+        let name = keys.get("parametername").map_or("", String::as_str);
+
+        od.insert(
+            index,
+            subindex.unwrap_or(0),
+            Entry::new(name, data_type, access, &value).with_pdo_mapping(pdo_mappable),
+        );
+    }
+    Ok(od)
+}
+
+/// Splits the file into sections of lower case keys and their values.
+fn parse_ini(text: &str) -> Result<BTreeMap<String, BTreeMap<String, String>>, EdsError> {
+    let mut sections = BTreeMap::new();
+    let mut current: Option<String> = None;
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            let name = name.trim().to_lowercase();
+            sections.entry(name.clone()).or_insert_with(BTreeMap::new);
+            current = Some(name);
+            continue;
+        }
+
+        match (line.split_once('='), current.as_ref()) {
+            (Some((key, value)), Some(section)) => {
+                if let Some(keys) = sections.get_mut(section) {
+                    keys.insert(key.trim().to_lowercase(), value.trim().to_string());
+                }
+            }
+            _ => return Err(EdsError::Syntax { line: number + 1 }),
+        }
+    }
+    Ok(sections)
+}
+
+/// Parses section names like `1018` or `1018sub2`.
+fn parse_section_name(name: &str) -> Option<(u16, Option<u8>)> {
+    let (index, subindex) = match name.split_once("sub") {
+        Some((index, subindex)) => (index, Some(u8::from_str_radix(subindex, 16).ok()?)),
+        None => (name, None),
+    };
+    if index.len() != 4 {
+        return None;
+    }
+    Some((u16::from_str_radix(index, 16).ok()?, subindex))
+}
+
+/// Parses decimal, hexadecimal (`0x`) and octal (leading `0`) integers, including `$NODEID`
+/// sums.
+fn parse_integer(value: &str, node: NodeId) -> Option<u64> {
+    let value = value.trim();
+    if value.contains('+') {
+        return value.split('+').try_fold(0u64, |sum, term| {
+            sum.checked_add(parse_integer(term, node)?)
+        });
+    }
+
+    if value.eq_ignore_ascii_case("$nodeid") {
+        return Some(node as u64);
+    }
+
+    if let Some(hex) = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        u64::from_str_radix(hex, 16).ok()
+    } else if value.len() > 1 && value.starts_with('0') {
+        u64::from_str_radix(&value[1..], 8).ok()
+    } else {
+        value.parse().ok()
+    }
+}
+
+fn parse_value(data_type: DataType, value: &str, node: NodeId) -> Option<Vec<u8>> {
+    let size = match data_type {
+        DataType::VisibleString => return Some(value.as_bytes().to_vec()),
+        DataType::OctetString | DataType::Domain => {
+            // hex digits, optionally separated by spaces
+            return parse_hex(&value.split_whitespace().collect::<String>());
+        }
+        DataType::Real32 if !value.is_empty() => {
+            return Some(value.parse::<f32>().ok()?.to_le_bytes().to_vec())
+        }
+        DataType::Real64 if !value.is_empty() => {
+            return Some(value.parse::<f64>().ok()?.to_le_bytes().to_vec())
+        }
+        data_type => data_type.size().unwrap_or(0),
+    };
+
+    if value.is_empty() {
+        return Some(vec![0; size]);
+    }
+
+    let number = match value.trim().strip_prefix('-') {
+        Some(magnitude) => parse_integer(magnitude, node)?.wrapping_neg(),
+        None => parse_integer(value, node)?,
+    };
+    Some(number.to_le_bytes()[..size].to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EDS: &str = "
+[DeviceInfo]
+VendorName=PEAK-System
+
+[1000]
+ParameterName=Device type
+ObjectType=0x7
+DataType=0x0007
+AccessType=ro
+DefaultValue=0x00000191
+PDOMapping=0
+
+[1008]
+ParameterName=Manufacturer device name
+DataType=0x0009
+AccessType=const
+DefaultValue=PCAN-MicroMod
+
+[1800]
+ParameterName=TPDO communication parameter
+ObjectType=0x9
+SubNumber=2
+
+[1800sub0]
+ParameterName=Highest sub-index supported
+DataType=0x0005
+AccessType=const
+DefaultValue=5
+
+[1800sub1]
+ParameterName=COB-ID
+DataType=0x0007
+AccessType=rw
+DefaultValue=$NODEID+0x180
+
+[6401sub1]
+ParameterName=Analog input 1
+DataType=0x0003
+AccessType=ro
+DefaultValue=-2
+ParameterValue=010
+PDOMapping=1
+";
+
+    #[test]
+    fn parse_eds_001() {
+        let od = parse_eds(EDS, 5).unwrap();
+        assert_eq!(od.len(), 5);
+        assert_eq!(od.value_u64(0x1000, 0), Some(0x0191));
+        assert_eq!(od.read(0x1008, 0), Ok(&b"PCAN-MicroMod"[..]));
+        assert_eq!(od.value_u64(0x1800, 1), Some(0x185));
+        assert!(od.get(0x1800, 2).is_none());
+
+        let entry = od.get(0x6401, 1).unwrap();
+        assert_eq!(entry.data_type, DataType::Integer16);
+        assert_eq!(entry.access, AccessType::ReadOnly);
+        assert!(entry.pdo_mappable);
+        assert_eq!(entry.value(), &[8, 0]);
+
+        assert_eq!(
+            parse_eds("[1000]\nDataType=0x0007\nAccessType=rx\n", 1),
+            Err(EdsError::InvalidValue {
+                section: "1000".to_string(),
+                key: "AccessType".to_string()
+            })
+        );
+        assert_eq!(
+            parse_eds("[1000]\nDataType\n", 1),
+            Err(EdsError::Syntax { line: 2 })
+        );
+    }
+
+    #[test]
+    fn parse_eds_002() {
+        let od = parse_eds(
+            "[2000]\nDataType=0x000A\nAccessType=rw\nDefaultValue=0A 1b 2C\n",
+            1,
+        )
+        .unwrap();
+        assert_eq!(od.read(0x2000, 0), Ok(&[0x0A, 0x1B, 0x2C][..]));
+
+        for value in ["0A1", "0G", "a\u{e9}1"] {
+            let eds = format!(
+                "[2000]\nDataType=0x000F\nAccessType=rw\nDefaultValue={}\n",
+                value
+            );
+            assert_eq!(
+                parse_eds(&eds, 1),
+                Err(EdsError::InvalidValue {
+                    section: "2000".to_string(),
+                    key: "DefaultValue".to_string()
+                })
+            );
+        }
+    }
+}
@@ -0,0 +1,56 @@
+//! Emergency objects.
+
+use crate::canopen::{CanOpenError, NodeId, COB_EMCY};
+use crate::socket::{CanFrame, MessageType};
+
+/// Content of an emergency message.
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub struct Emergency {
+    /// Emergency error code, e.g. `0x8130` for a life guard error. Zero resets an error.
+    pub error_code: u16,
+    /// Value of the error register, object 0x1001.
+    pub error_register: u8,
+    pub manufacturer_data: [u8; 5],
+}
+
+impl Emergency {
+    pub fn new(error_code: u16, error_register: u8) -> Emergency {
+        Emergency {
+            error_code,
+            error_register,
+            manufacturer_data: [0; 5],
+        }
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Emergency, CanOpenError> {
+        if data.len() != 8 {
+            return Err(CanOpenError::Protocol);
+        }
+
+        let mut manufacturer_data = [0; 5];
+        manufacturer_data.copy_from_slice(&data[3..8]);
+        Ok(Emergency {
+            error_code: u16::from_le_bytes([data[0], data[1]]),
+            error_register: data[2],
+            manufacturer_data,
+        })
+    }
+
+    pub fn encode(&self) -> [u8; 8] {
+        let mut data = [0; 8];
+        data[..2].copy_from_slice(&self.error_code.to_le_bytes());
+        data[2] = self.error_register;
+        data[3..].copy_from_slice(&self.manufacturer_data);
+        data
+    }
+
+    /// Builds the emergency message of `node`.
+    pub fn to_frame(&self, node: NodeId) -> CanFrame {
+        CanFrame::new(
+            COB_EMCY + node as u32,
+            MessageType::Standard,
+            &self.encode(),
+        )
+        .expect("an emergency message fits into a frame")
+    }
+}
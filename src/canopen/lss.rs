@@ -0,0 +1,551 @@
+//! Layer setting services (CiA 305).
+//!
+//! The [LssMaster] assigns node-IDs and bit rates to devices. Devices are addressed by their
+//! [LssAddress], the identity object 0x1018, either all at once (global) or one at a time
+//! (selective). Devices without a node-ID can be discovered with [fastscan](LssMaster::fastscan).
+
+use crate::canopen::{CanOpenError, NodeId};
+use crate::error::PcanError;
+use crate::socket::{Baudrate, CanFrame, MessageType, RecvCan, SendCan};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// LSS requests, master to slave.
+pub const COB_LSS_MASTER: u32 = 0x7E5;
+/// LSS responses, slave to master.
+pub const COB_LSS_SLAVE: u32 = 0x7E4;
+
+/* Command specifiers */
+
+const SWITCH_STATE_GLOBAL: u8 = 0x04;
+const SWITCH_STATE_SELECTIVE_VENDOR_ID: u8 = 0x40;
+const SWITCH_STATE_SELECTIVE_RESPONSE: u8 = 0x44;
+const CONFIGURE_NODE_ID: u8 = 0x11;
+const CONFIGURE_BIT_TIMING: u8 = 0x13;
+const ACTIVATE_BIT_TIMING: u8 = 0x15;
+const STORE_CONFIGURATION: u8 = 0x17;
+const IDENTIFY_SLAVE: u8 = 0x4F;
+const FASTSCAN: u8 = 0x51;
+const INQUIRE_VENDOR_ID: u8 = 0x5A;
+const INQUIRE_NODE_ID: u8 = 0x5E;
+
+/// Node-ID of a device that has not been configured yet.
+pub const UNCONFIGURED_NODE_ID: NodeId = 0xFF;
+
+/// `BitChecked` value of a fastscan request asking whether any unconfigured device is present.
+const FASTSCAN_CONFIRM: u8 = 0x80;
+
+/// LSS address of a device, i.e. the content of its identity object 0x1018.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Default)]
+pub struct LssAddress {
+    pub vendor_id: u32,
+    pub product_code: u32,
+    pub revision_number: u32,
+    pub serial_number: u32,
+}
+
+impl LssAddress {
+    pub fn new(
+        vendor_id: u32,
+        product_code: u32,
+        revision_number: u32,
+        serial_number: u32,
+    ) -> LssAddress {
+        LssAddress {
+            vendor_id,
+            product_code,
+            revision_number,
+            serial_number,
+        }
+    }
+
+    fn to_array(self) -> [u32; 4] {
+        [
+            self.vendor_id,
+            self.product_code,
+            self.revision_number,
+            self.serial_number,
+        ]
+    }
+
+    fn from_array(parts: [u32; 4]) -> LssAddress {
+        LssAddress::new(parts[0], parts[1], parts[2], parts[3])
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum LssMode {
+    Waiting,
+    Configuration,
+}
+
+/// Index of `baudrate` in the standard bit timing table of CiA 305. Bit rates without an entry
+/// return `None`.
+pub fn bit_timing_index(baudrate: Baudrate) -> Option<u8> {
+    match baudrate {
+        Baudrate::Baud1M => Some(0),
+        Baudrate::Baud800K => Some(1),
+        Baudrate::Baud500K => Some(2),
+        Baudrate::Baud250K => Some(3),
+        Baudrate::Baud125K => Some(4),
+        Baudrate::Baud100K => Some(5),
+        Baudrate::Baud50K => Some(6),
+        Baudrate::Baud20K => Some(7),
+        Baudrate::Baud10K => Some(8),
+        _ => None,
+    }
+}
+
+const POLL_INTERVAL: Duration = Duration::from_micros(100);
+
+/// An LSS master on a single socket.
+///
+/// Frames other than LSS responses are dropped, so the master should not share the bus with
+/// regular network management while commissioning.
+pub struct LssMaster<S> {
+    socket: S,
+    timeout: Duration,
+}
+
+impl<S: SendCan + RecvCan> LssMaster<S> {
+    pub fn new(socket: S) -> LssMaster<S> {
+        LssMaster {
+            socket,
+            timeout: Duration::from_millis(100),
+        }
+    }
+
+    /// Sets how long to wait for responses. Fastscan waits this long after each of its
+    /// 133 requests.
+    pub fn with_timeout(mut self, timeout: Duration) -> LssMaster<S> {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn socket(&self) -> &S {
+        &self.socket
+    }
+
+    pub fn into_socket(self) -> S {
+        self.socket
+    }
+
+    fn send(&self, data: [u8; 8]) -> Result<(), CanOpenError> {
+        let frame = CanFrame::new(COB_LSS_MASTER, MessageType::Standard, &data)
+            .map_err(|_| CanOpenError::Protocol)?;
+        Ok(self.socket.send(frame)?)
+    }
+
+    /// Drops all pending responses, e.g. late answers to a previous request.
+    fn clear(&self) -> Result<(), CanOpenError> {
+        loop {
+            match self.socket.recv_frame() {
+                Ok(_) => {}
+                Err(PcanError::QrcvEmpty) => return Ok(()),
+                Err(err) => return Err(CanOpenError::Pcan(err)),
+            }
+        }
+    }
+
+    /// Waits for a response with the command specifier `command`.
+    fn response(&self, command: u8) -> Result<[u8; 8], CanOpenError> {
+        let deadline = Instant::now() + self.timeout;
+
+        loop {
+            match self.socket.recv_frame() {
+                Ok(frame)
+                    if frame.can_id() == COB_LSS_SLAVE
+                        && !frame.is_extended_frame()
+                        && !frame.is_rtr_frame()
+                        && frame.dlc() == 8
+                        && frame.data()[0] == command =>
+                {
+                    let mut response = [0; 8];
+                    response.copy_from_slice(frame.data());
+                    return Ok(response);
+                }
+                Ok(_) => {}
+                Err(PcanError::QrcvEmpty) => {
+                    if Instant::now() >= deadline {
+                        return Err(CanOpenError::Timeout);
+                    }
+                    thread::sleep(POLL_INTERVAL);
+                }
+                Err(err) => return Err(CanOpenError::Pcan(err)),
+            }
+        }
+    }
+
+    fn request(&self, data: [u8; 8]) -> Result<[u8; 8], CanOpenError> {
+        self.clear()?;
+        self.send(data)?;
+        self.response(data[0])
+    }
+
+    /// Checks the error code of a configuration response.
+    fn confirm(&self, data: [u8; 8]) -> Result<(), CanOpenError> {
+        let response = self.request(data)?;
+        match response[1] {
+            0 => Ok(()),
+            error_code => Err(CanOpenError::LssFailed {
+                error_code,
+                specific_error: response[2],
+            }),
+        }
+    }
+
+    /* Switch state */
+
+    /// Switches all devices into the waiting or the configuration state.
+    pub fn switch_state_global(&mut self, mode: LssMode) -> Result<(), CanOpenError> {
+        let mode = match mode {
+            LssMode::Waiting => 0,
+            LssMode::Configuration => 1,
+        };
+        self.send([SWITCH_STATE_GLOBAL, mode, 0, 0, 0, 0, 0, 0])
+    }
+
+    /// Switches the device with the given address into the configuration state.
+    pub fn switch_state_selective(&mut self, address: &LssAddress) -> Result<(), CanOpenError> {
+        self.clear()?;
+        for (offset, part) in address.to_array().iter().enumerate() {
+            let mut data = [0; 8];
+            data[0] = SWITCH_STATE_SELECTIVE_VENDOR_ID + offset as u8;
+            data[1..5].copy_from_slice(&part.to_le_bytes());
+            self.send(data)?;
+        }
+        self.response(SWITCH_STATE_SELECTIVE_RESPONSE).map(|_| ())
+    }
+
+    /* Configuration */
+
+    /// Assigns a node-ID to the device in configuration state. [UNCONFIGURED_NODE_ID] removes it.
+    pub fn configure_node_id(&mut self, node: NodeId) -> Result<(), CanOpenError> {
+        if !(1..=127).contains(&node) && node != UNCONFIGURED_NODE_ID {
+            return Err(CanOpenError::InvalidNodeId);
+        }
+        self.confirm([CONFIGURE_NODE_ID, node, 0, 0, 0, 0, 0, 0])
+    }
+
+    /// Selects a bit rate from the standard bit timing table. It becomes active with
+    /// [activate_bit_timing](LssMaster::activate_bit_timing) or after a reset.
+    pub fn configure_bit_timing(&mut self, baudrate: Baudrate) -> Result<(), CanOpenError> {
+        let index = bit_timing_index(baudrate).ok_or(CanOpenError::ValueOutOfRange)?;
+        self.configure_bit_timing_table(0, index)
+    }
+
+    /// Selects entry `index` of bit timing table `table`. Table 0 is the standard table, tables
+    /// 128 to 255 are manufacturer specific.
+    pub fn configure_bit_timing_table(&mut self, table: u8, index: u8) -> Result<(), CanOpenError> {
+        self.confirm([CONFIGURE_BIT_TIMING, table, index, 0, 0, 0, 0, 0])
+    }
+
+    /// Makes all devices switch to their configured bit rate. The devices stop transmitting for
+    /// `switch_delay` before and after switching, so the master must change its own bit rate in
+    /// between.
+    pub fn activate_bit_timing(&mut self, switch_delay: Duration) -> Result<(), CanOpenError> {
+        let delay = u16::try_from(switch_delay.as_millis())
+            .map_err(|_| CanOpenError::ValueOutOfRange)?
+            .to_le_bytes();
+        self.send([ACTIVATE_BIT_TIMING, delay[0], delay[1], 0, 0, 0, 0, 0])
+    }
+
+    /// Stores the configured node-ID and bit timing in the non-volatile memory of the device.
+    pub fn store_configuration(&mut self) -> Result<(), CanOpenError> {
+        self.confirm([STORE_CONFIGURATION, 0, 0, 0, 0, 0, 0, 0])
+    }
+
+    /* Inquiry */
+
+    /// Reads the LSS address of the device in configuration state.
+    pub fn inquire_identity(&mut self) -> Result<LssAddress, CanOpenError> {
+        let mut parts = [0u32; 4];
+        for (offset, part) in parts.iter_mut().enumerate() {
+            let response = self.request([INQUIRE_VENDOR_ID + offset as u8, 0, 0, 0, 0, 0, 0, 0])?;
+            *part = u32::from_le_bytes([response[1], response[2], response[3], response[4]]);
+        }
+        Ok(LssAddress::from_array(parts))
+    }
+
+    /// Reads the active node-ID of the device in configuration state.
+    pub fn inquire_node_id(&mut self) -> Result<NodeId, CanOpenError> {
+        Ok(self.request([INQUIRE_NODE_ID, 0, 0, 0, 0, 0, 0, 0])?[1])
+    }
+
+    /* Fastscan */
+
+    /// Sends a fastscan request and returns whether any device answered.
+    fn fastscan_request(
+        &self,
+        id_number: u32,
+        bit_checked: u8,
+        sub: u8,
+        next: u8,
+    ) -> Result<bool, CanOpenError> {
+        self.clear()?;
+
+        let mut data = [0; 8];
+        data[0] = FASTSCAN;
+        data[1..5].copy_from_slice(&id_number.to_le_bytes());
+        data[5] = bit_checked;
+        data[6] = sub;
+        data[7] = next;
+        self.send(data)?;
+
+        // several devices may answer, each of them with its own frame on virtual buses
+        let deadline = Instant::now() + self.timeout;
+        let mut answered = false;
+        while Instant::now() < deadline {
+            match self.socket.recv_frame() {
+                Ok(frame) => {
+                    answered |= frame.can_id() == COB_LSS_SLAVE
+                        && !frame.is_extended_frame()
+                        && frame.data().first() == Some(&IDENTIFY_SLAVE)
+                }
+                Err(PcanError::QrcvEmpty) => thread::sleep(POLL_INTERVAL),
+                Err(err) => return Err(CanOpenError::Pcan(err)),
+            }
+        }
+        Ok(answered)
+    }
+
+    /// Discovers a device without node-ID, determining its LSS address bit by bit.
+    ///
+    /// Returns `None` if no such device is present. The discovered device is left in the
+    /// configuration state, ready for [configure_node_id](LssMaster::configure_node_id). Repeat
+    /// until `None` is returned to find all of them.
+    pub fn fastscan(&mut self) -> Result<Option<LssAddress>, CanOpenError> {
+        if !self.fastscan_request(0, FASTSCAN_CONFIRM, 0, 0)? {
+            return Ok(None);
+        }
+
+        let mut parts = [0u32; 4];
+        for sub in 0..4u8 {
+            let mut id_number = 0u32;
+
+            // devices answer if their bits above and including bit_checked match
+            for bit_checked in (0..32u8).rev() {
+                if !self.fastscan_request(id_number, bit_checked, sub, sub)? {
+                    id_number |= 1 << bit_checked;
+                }
+            }
+
+            // confirm the complete part, moving on to the next one
+            let next = (sub + 1) % 4;
+            if !self.fastscan_request(id_number, 0, sub, next)? {
+                return Err(CanOpenError::Protocol);
+            }
+            parts[sub as usize] = id_number;
+        }
+
+        Ok(Some(LssAddress::from_array(parts)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::socket::pipe::{PipeBus, PipeSocket};
+    use crate::socket::Timestamp;
+    use std::cell::RefCell;
+
+    struct Slave {
+        socket: PipeSocket,
+        address: [u32; 4],
+        node: NodeId,
+        configuration: bool,
+        selective: usize,
+        fastscan_position: u8,
+        bit_timing: Option<(u8, u8)>,
+    }
+
+    impl Slave {
+        fn respond(&self, data: [u8; 8]) {
+            self.socket
+                .send(CanFrame::new(COB_LSS_SLAVE, MessageType::Standard, &data).unwrap())
+                .unwrap();
+        }
+
+        fn handle(&mut self, data: &[u8]) {
+            let value = u32::from_le_bytes([data[1], data[2], data[3], data[4]]);
+            match data[0] {
+                SWITCH_STATE_GLOBAL => self.configuration = data[1] == 1,
+                0x40..=0x43 => {
+                    let part = (data[0] - 0x40) as usize;
+                    self.selective = match part == self.selective && value == self.address[part] {
+                        true => self.selective + 1,
+                        false => 0,
+                    };
+                    if self.selective == 4 {
+                        self.selective = 0;
+                        self.configuration = true;
+                        self.respond([SWITCH_STATE_SELECTIVE_RESPONSE, 0, 0, 0, 0, 0, 0, 0]);
+                    }
+                }
+                _ if !self.configuration && data[0] != FASTSCAN => {}
+                CONFIGURE_NODE_ID => {
+                    let error = match (1..=127).contains(&data[1]) || data[1] == 0xFF {
+                        true => {
+                            self.node = data[1];
+                            0
+                        }
+                        false => 1,
+                    };
+                    self.respond([CONFIGURE_NODE_ID, error, 0, 0, 0, 0, 0, 0]);
+                }
+                CONFIGURE_BIT_TIMING => {
+                    self.bit_timing = Some((data[1], data[2]));
+                    self.respond([CONFIGURE_BIT_TIMING, 0, 0, 0, 0, 0, 0, 0]);
+                }
+                STORE_CONFIGURATION => self.respond([STORE_CONFIGURATION, 1, 0, 0, 0, 0, 0, 0]),
+                0x5A..=0x5D => {
+                    let mut response = [0; 8];
+                    response[0] = data[0];
+                    response[1..5]
+                        .copy_from_slice(&self.address[(data[0] - 0x5A) as usize].to_le_bytes());
+                    self.respond(response);
+                }
+                INQUIRE_NODE_ID => self.respond([INQUIRE_NODE_ID, self.node, 0, 0, 0, 0, 0, 0]),
+                FASTSCAN if self.node == UNCONFIGURED_NODE_ID && !self.configuration => {
+                    let (bit_checked, sub, next) = (data[5], data[6], data[7]);
+                    if bit_checked == FASTSCAN_CONFIRM {
+                        self.fastscan_position = 0;
+                        self.respond([IDENTIFY_SLAVE, 0, 0, 0, 0, 0, 0, 0]);
+                    } else if sub == self.fastscan_position
+                        && (value ^ self.address[sub as usize]) >> bit_checked == 0
+                    {
+                        self.respond([IDENTIFY_SLAVE, 0, 0, 0, 0, 0, 0, 0]);
+                        if bit_checked == 0 && next != sub {
+                            self.fastscan_position = next;
+                            self.configuration = next < sub;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Socket of the master handing each request to the slaves before returning, so their
+    /// answers are queued when the master waits for them.
+    struct Network {
+        socket: PipeSocket,
+        slaves: RefCell<Vec<Slave>>,
+    }
+
+    impl Network {
+        fn new(addresses: &[[u32; 4]]) -> Network {
+            let bus = PipeBus::new();
+            let slaves = addresses
+                .iter()
+                .map(|address| Slave {
+                    socket: bus.connect(),
+                    address: *address,
+                    node: UNCONFIGURED_NODE_ID,
+                    configuration: false,
+                    selective: 0,
+                    fastscan_position: 0,
+                    bit_timing: None,
+                })
+                .collect();
+            Network {
+                socket: bus.connect(),
+                slaves: RefCell::new(slaves),
+            }
+        }
+    }
+
+    impl SendCan for Network {
+        fn send(&self, frame: CanFrame) -> Result<(), PcanError> {
+            self.socket.send(frame)?;
+            for slave in self.slaves.borrow_mut().iter_mut() {
+                while let Ok(frame) = slave.socket.recv_frame() {
+                    if frame.can_id() == COB_LSS_MASTER {
+                        slave.handle(frame.data());
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl RecvCan for Network {
+        fn recv(&self) -> Result<(CanFrame, Timestamp), PcanError> {
+            self.socket.recv()
+        }
+
+        fn recv_frame(&self) -> Result<CanFrame, PcanError> {
+            self.socket.recv_frame()
+        }
+    }
+
+    #[test]
+    fn lss_001() {
+        let address = [0x0000_0175, 0x0000_1234, 0x0001_0002, 0x00C0_FFEE];
+        let network = Network::new(&[address]);
+
+        let mut master = LssMaster::new(network).with_timeout(Duration::from_millis(1));
+        master
+            .switch_state_selective(&LssAddress::from_array(address))
+            .unwrap();
+        assert_eq!(
+            master.inquire_identity(),
+            Ok(LssAddress::from_array(address))
+        );
+        assert_eq!(
+            master.configure_node_id(0),
+            Err(CanOpenError::InvalidNodeId)
+        );
+        assert_eq!(master.configure_node_id(12), Ok(()));
+        assert_eq!(master.inquire_node_id(), Ok(12));
+        assert_eq!(master.configure_bit_timing(Baudrate::Baud125K), Ok(()));
+        assert_eq!(
+            master.configure_bit_timing(Baudrate::Baud33K),
+            Err(CanOpenError::ValueOutOfRange)
+        );
+        assert_eq!(
+            master.store_configuration(),
+            Err(CanOpenError::LssFailed {
+                error_code: 1,
+                specific_error: 0
+            })
+        );
+        master.switch_state_global(LssMode::Waiting).unwrap();
+        assert_eq!(master.inquire_node_id(), Err(CanOpenError::Timeout));
+
+        let slaves = master.into_socket().slaves.into_inner();
+        assert_eq!(slaves[0].bit_timing, Some((0, 4)));
+    }
+
+    #[test]
+    fn fastscan_001() {
+        let first = [0x0000_0175, 0x0000_1234, 0x0001_0002, 0x0000_0001];
+        let second = [0x0000_0175, 0x0000_1234, 0x0001_0002, 0x0000_0002];
+        let network = Network::new(&[first, second]);
+
+        let mut master = LssMaster::new(network).with_timeout(Duration::from_millis(1));
+        let mut found = Vec::new();
+        while let Some(address) = master.fastscan().unwrap() {
+            master.configure_node_id(found.len() as u8 + 1).unwrap();
+            master.switch_state_global(LssMode::Waiting).unwrap();
+            found.push(address);
+        }
+
+        assert_eq!(
+            found,
+            vec![
+                LssAddress::from_array(first),
+                LssAddress::from_array(second)
+            ]
+        );
+
+        let nodes = master
+            .into_socket()
+            .slaves
+            .into_inner()
+            .iter()
+            .map(|slave| slave.node)
+            .collect::<Vec<_>>();
+        assert_eq!(nodes, vec![1, 2]);
+    }
+}
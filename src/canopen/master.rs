@@ -0,0 +1,522 @@
+//! CANopen master.
+
+use crate::canopen::emcy::Emergency;
+use crate::canopen::nmt::{nmt_frame, NmtCommand, NmtState};
+use crate::canopen::sdo::{SdoChannel, SdoClient};
+use crate::canopen::sync::sync_frame;
+use crate::canopen::{
+    check_node_id, CanOpenError, NodeId, COB_EMCY, COB_NMT_ERROR_CONTROL, COB_SDO_RX, COB_SDO_TX,
+    COB_SYNC, COB_TPDO1,
+};
+use crate::error::PcanError;
+use crate::socket::{CanFrame, MessageType, RecvCan, SendCan};
+use std::collections::{BTreeMap, VecDeque};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Something that happened on the network, reported by [recv_event](CanOpenMaster::recv_event).
+#[derive(Debug, PartialEq, Clone)]
+pub enum Event {
+    /// The node sent its boot-up message.
+    BootUp(NodeId),
+    /// A heartbeat or node guarding response reported a new state.
+    StateChanged {
+        node: NodeId,
+        state: NmtState,
+    },
+    /// No heartbeat arrived within the consumer time.
+    HeartbeatTimeout(NodeId),
+    /// No node guarding response arrived within the life time, or its toggle bit was wrong.
+    NodeGuardingError(NodeId),
+    Emergency {
+        node: NodeId,
+        emergency: Emergency,
+    },
+    Sync {
+        counter: Option<u8>,
+    },
+    /// A PDO with an identifier of the predefined connection set.
+    Pdo {
+        cob_id: u32,
+        data: Vec<u8>,
+    },
+}
+
+struct Heartbeat {
+    consumer_time: Duration,
+    last: Instant,
+    timed_out: bool,
+}
+
+struct Guarding {
+    guard_time: Duration,
+    life_time: Duration,
+    next_request: Instant,
+    last_response: Instant,
+    toggle: Option<u8>,
+    failed: bool,
+}
+
+#[derive(Default)]
+struct Node {
+    state: Option<NmtState>,
+    heartbeat: Option<Heartbeat>,
+    guarding: Option<Guarding>,
+}
+
+const POLL_INTERVAL: Duration = Duration::from_micros(100);
+
+/// A CANopen master driving NMT, SDO and the monitoring of the nodes over a single socket.
+///
+/// The master is driven by its caller: frames are processed while waiting in
+/// [recv_event](CanOpenMaster::recv_event) or in an SDO transfer, or when calling
+/// [poll](CanOpenMaster::poll).
+pub struct CanOpenMaster<S> {
+    socket: S,
+    sdo_timeout: Duration,
+    block_size: u8,
+    nodes: BTreeMap<NodeId, Node>,
+    sdo_responses: VecDeque<(NodeId, [u8; 8])>,
+    events: VecDeque<Event>,
+}
+
+impl<S: SendCan + RecvCan> CanOpenMaster<S> {
+    pub fn new(socket: S) -> CanOpenMaster<S> {
+        CanOpenMaster {
+            socket,
+            sdo_timeout: Duration::from_millis(1000),
+            block_size: 127,
+            nodes: BTreeMap::new(),
+            sdo_responses: VecDeque::new(),
+            events: VecDeque::new(),
+        }
+    }
+
+    pub fn with_sdo_timeout(mut self, sdo_timeout: Duration) -> CanOpenMaster<S> {
+        self.sdo_timeout = sdo_timeout;
+        self
+    }
+
+    /// Sets the number of segments per block requested in block uploads.
+    pub fn with_block_size(mut self, block_size: u8) -> CanOpenMaster<S> {
+        self.block_size = block_size.clamp(1, 127);
+        self
+    }
+
+    pub fn socket(&self) -> &S {
+        &self.socket
+    }
+
+    pub fn into_socket(self) -> S {
+        self.socket
+    }
+
+    /* NMT */
+
+    /// Sends an NMT command to `node`, or to all nodes if `node` is zero.
+    pub fn nmt(&mut self, command: NmtCommand, node: NodeId) -> Result<(), CanOpenError> {
+        if node > 127 {
+            return Err(CanOpenError::InvalidNodeId);
+        }
+        Ok(self.socket.send(nmt_frame(command, node))?)
+    }
+
+    /// Returns the last state reported by `node`.
+    pub fn node_state(&self, node: NodeId) -> Option<NmtState> {
+        self.nodes.get(&node).and_then(|node| node.state)
+    }
+
+    /// Expects a heartbeat of `node` at least every `consumer_time`.
+    pub fn monitor_heartbeat(
+        &mut self,
+        node: NodeId,
+        consumer_time: Duration,
+    ) -> Result<(), CanOpenError> {
+        check_node_id(node)?;
+        self.nodes.entry(node).or_default().heartbeat = Some(Heartbeat {
+            consumer_time,
+            last: Instant::now(),
+            timed_out: false,
+        });
+        Ok(())
+    }
+
+    /// Guards `node` every `guard_time`. The node is lost if no response arrives within
+    /// `guard_time * life_time_factor`.
+    pub fn monitor_node_guarding(
+        &mut self,
+        node: NodeId,
+        guard_time: Duration,
+        life_time_factor: u8,
+    ) -> Result<(), CanOpenError> {
+        check_node_id(node)?;
+        let now = Instant::now();
+        self.nodes.entry(node).or_default().guarding = Some(Guarding {
+            guard_time,
+            life_time: guard_time * life_time_factor.max(1) as u32,
+            next_request: now,
+            last_response: now,
+            toggle: None,
+            failed: false,
+        });
+        Ok(())
+    }
+
+    /// Stops monitoring `node`.
+    pub fn unmonitor(&mut self, node: NodeId) {
+        if let Some(node) = self.nodes.get_mut(&node) {
+            node.heartbeat = None;
+            node.guarding = None;
+        }
+    }
+
+    /* SYNC, EMCY and PDO */
+
+    pub fn send_sync(&mut self, counter: Option<u8>) -> Result<(), CanOpenError> {
+        Ok(self.socket.send(sync_frame(counter))?)
+    }
+
+    /// Sends an emergency message on behalf of `node`.
+    pub fn send_emcy(&mut self, node: NodeId, emergency: &Emergency) -> Result<(), CanOpenError> {
+        check_node_id(node)?;
+        Ok(self.socket.send(emergency.to_frame(node))?)
+    }
+
+    /// Sends PDO data, e.g. encoded with a [PdoMapping](crate::canopen::PdoMapping).
+    pub fn send_pdo(&mut self, cob_id: u32, data: &[u8]) -> Result<(), CanOpenError> {
+        let frame = CanFrame::new(cob_id, MessageType::Standard, data)
+            .map_err(|_| CanOpenError::InvalidMapping)?;
+        Ok(self.socket.send(frame)?)
+    }
+
+    /* Events */
+
+    /// Processes all frames received and checks the monitoring timers.
+    pub fn poll(&mut self) -> Result<(), CanOpenError> {
+        while self.poll_frame()? {}
+        Ok(())
+    }
+
+    /// Waits for the next event.
+    pub fn recv_event(&mut self, timeout: Duration) -> Result<Event, CanOpenError> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(event);
+            }
+
+            if !self.poll_frame()? {
+                if Instant::now() >= deadline {
+                    return Err(CanOpenError::Timeout);
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+
+    fn check_timers(&mut self) -> Result<(), CanOpenError> {
+        let now = Instant::now();
+
+        for (id, node) in self.nodes.iter_mut() {
+            if let Some(heartbeat) = node.heartbeat.as_mut() {
+                if !heartbeat.timed_out && now >= heartbeat.last + heartbeat.consumer_time {
+                    heartbeat.timed_out = true;
+                    self.events.push_back(Event::HeartbeatTimeout(*id));
+                }
+            }
+
+            if let Some(guarding) = node.guarding.as_mut() {
+                if !guarding.failed && now >= guarding.last_response + guarding.life_time {
+                    guarding.failed = true;
+                    self.events.push_back(Event::NodeGuardingError(*id));
+                }
+
+                if now >= guarding.next_request {
+                    guarding.next_request = now + guarding.guard_time;
+                    let frame = CanFrame::new_rtr(
+                        COB_NMT_ERROR_CONTROL + *id as u32,
+                        MessageType::Standard,
+                        1,
+                    )
+                    .map_err(|_| CanOpenError::Protocol)?;
+                    self.socket.send(frame)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a single frame, if any, and processes it.
+    fn poll_frame(&mut self) -> Result<bool, CanOpenError> {
+        self.check_timers()?;
+
+        let frame = match self.socket.recv_frame() {
+            Ok(frame) => frame,
+            Err(PcanError::QrcvEmpty) => return Ok(false),
+            Err(err) => return Err(CanOpenError::Pcan(err)),
+        };
+
+        if !frame.is_extended_frame() && !frame.is_rtr_frame() {
+            self.handle_frame(frame.can_id(), frame.data());
+        }
+        Ok(true)
+    }
+
+    fn handle_frame(&mut self, cob_id: u32, data: &[u8]) {
+        let function = cob_id & 0x780;
+        let node = (cob_id & 0x7F) as NodeId;
+
+        match (function, node) {
+            (COB_SYNC, 0) => self.events.push_back(Event::Sync {
+                counter: data.first().copied(),
+            }),
+            (COB_EMCY, _) => {
+                if let Ok(emergency) = Emergency::decode(data) {
+                    self.events.push_back(Event::Emergency { node, emergency });
+                }
+            }
+            (COB_SDO_TX, 1..) if data.len() == 8 => {
+                let mut response = [0; 8];
+                response.copy_from_slice(data);
+                self.sdo_responses.push_back((node, response));
+            }
+            (COB_NMT_ERROR_CONTROL, 1..) if data.len() == 1 => {
+                self.handle_error_control(node, data[0]);
+            }
+            (COB_TPDO1..=0x500, 1..) => self.events.push_back(Event::Pdo {
+                cob_id,
+                data: data.to_vec(),
+            }),
+            _ => {}
+        }
+    }
+
+    fn handle_error_control(&mut self, id: NodeId, value: u8) {
+        let entry = self.nodes.entry(id).or_default();
+        let now = Instant::now();
+        let state = NmtState::from(value & 0x7F);
+
+        if state == NmtState::BootUp {
+            entry.state = Some(NmtState::BootUp);
+            if let Some(guarding) = entry.guarding.as_mut() {
+                guarding.toggle = None;
+            }
+            self.events.push_back(Event::BootUp(id));
+            return;
+        }
+
+        // node guarding responses carry a toggle bit, heartbeats never do
+        match entry.guarding.as_mut() {
+            Some(guarding) => {
+                let toggle = value >> 7;
+                if guarding.toggle.is_some_and(|previous| previous == toggle) {
+                    guarding.failed = true;
+                    self.events.push_back(Event::NodeGuardingError(id));
+                } else {
+                    guarding.failed = false;
+                }
+                guarding.toggle = Some(toggle);
+                guarding.last_response = now;
+            }
+            None => {
+                if let Some(heartbeat) = entry.heartbeat.as_mut() {
+                    heartbeat.last = now;
+                    heartbeat.timed_out = false;
+                }
+            }
+        }
+
+        if entry.state != Some(state) {
+            entry.state = Some(state);
+            self.events
+                .push_back(Event::StateChanged { node: id, state });
+        }
+    }
+
+    /* SDO */
+
+    fn sdo_client<T, F>(&mut self, node: NodeId, transfer: F) -> Result<T, CanOpenError>
+    where
+        F: FnOnce(&mut SdoClient<'_, SdoLink<'_, S>>) -> Result<T, CanOpenError>,
+    {
+        check_node_id(node)?;
+        self.sdo_responses.retain(|(from, _)| *from != node);
+
+        let timeout = self.sdo_timeout;
+        let mut link = SdoLink { master: self, node };
+        let mut client = SdoClient::new(&mut link, timeout);
+        transfer(&mut client)
+    }
+
+    /// Reads an object of `node` with an expedited or segmented transfer.
+    pub fn sdo_upload(
+        &mut self,
+        node: NodeId,
+        index: u16,
+        subindex: u8,
+    ) -> Result<Vec<u8>, CanOpenError> {
+        self.sdo_client(node, |client| client.upload(index, subindex))
+    }
+
+    /// Writes an object of `node` with an expedited or segmented transfer.
+    pub fn sdo_download(
+        &mut self,
+        node: NodeId,
+        index: u16,
+        subindex: u8,
+        data: &[u8],
+    ) -> Result<(), CanOpenError> {
+        self.sdo_client(node, |client| client.download(index, subindex, data))
+    }
+
+    /// Reads an object of `node` with a block transfer.
+    pub fn sdo_block_upload(
+        &mut self,
+        node: NodeId,
+        index: u16,
+        subindex: u8,
+    ) -> Result<Vec<u8>, CanOpenError> {
+        let block_size = self.block_size;
+        self.sdo_client(node, |client| {
+            client.block_upload(index, subindex, block_size)
+        })
+    }
+
+    /// Writes an object of `node` with a block transfer.
+    pub fn sdo_block_download(
+        &mut self,
+        node: NodeId,
+        index: u16,
+        subindex: u8,
+        data: &[u8],
+    ) -> Result<(), CanOpenError> {
+        self.sdo_client(node, |client| client.block_download(index, subindex, data))
+    }
+}
+
+/// Default SDO channel of a node, processing all other frames while waiting.
+struct SdoLink<'a, S> {
+    master: &'a mut CanOpenMaster<S>,
+    node: NodeId,
+}
+
+impl<S: SendCan + RecvCan> SdoChannel for SdoLink<'_, S> {
+    fn send_sdo(&mut self, message: [u8; 8]) -> Result<(), CanOpenError> {
+        let frame = CanFrame::new(
+            COB_SDO_RX + self.node as u32,
+            MessageType::Standard,
+            &message,
+        )
+        .map_err(|_| CanOpenError::Protocol)?;
+        Ok(self.master.socket.send(frame)?)
+    }
+
+    fn recv_sdo(&mut self, timeout: Duration) -> Result<[u8; 8], CanOpenError> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let responses = &mut self.master.sdo_responses;
+            if let Some(position) = responses.iter().position(|(from, _)| *from == self.node) {
+                if let Some((_, response)) = responses.remove(position) {
+                    return Ok(response);
+                }
+            }
+
+            if !self.master.poll_frame()? {
+                if Instant::now() >= deadline {
+                    return Err(CanOpenError::Timeout);
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canopen::nmt::heartbeat_frame;
+    use crate::socket::pipe::pipe;
+
+    #[test]
+    fn events_001() {
+        let (master, node) = pipe();
+        let mut master = CanOpenMaster::new(master);
+        master
+            .monitor_heartbeat(5, Duration::from_millis(50))
+            .unwrap();
+
+        node.send(heartbeat_frame(5, NmtState::BootUp)).unwrap();
+        node.send(Emergency::new(0x8130, 0x11).to_frame(5)).unwrap();
+        node.send(heartbeat_frame(5, NmtState::PreOperational))
+            .unwrap();
+
+        let timeout = Duration::from_millis(500);
+        assert_eq!(master.recv_event(timeout), Ok(Event::BootUp(5)));
+        assert_eq!(
+            master.recv_event(timeout),
+            Ok(Event::Emergency {
+                node: 5,
+                emergency: Emergency::new(0x8130, 0x11)
+            })
+        );
+        assert_eq!(
+            master.recv_event(timeout),
+            Ok(Event::StateChanged {
+                node: 5,
+                state: NmtState::PreOperational
+            })
+        );
+        assert_eq!(master.recv_event(timeout), Ok(Event::HeartbeatTimeout(5)));
+
+        master.nmt(NmtCommand::Start, 5).unwrap();
+        assert_eq!(node.recv_frame().unwrap(), nmt_frame(NmtCommand::Start, 5));
+    }
+
+    #[test]
+    fn sdo_001() {
+        let (master, node) = pipe();
+        let mut master = CanOpenMaster::new(master);
+
+        let server = thread::spawn(move || {
+            let deadline = Instant::now() + Duration::from_secs(2);
+            while Instant::now() < deadline {
+                match node.recv_frame() {
+                    Ok(frame) if frame.can_id() == COB_SDO_RX + 3 => {
+                        // answers every upload with the device type
+                        let response = [0x43, 0x00, 0x10, 0x00, 0x91, 0x01, 0x0F, 0x00];
+                        node.send(heartbeat_frame(3, NmtState::Operational))
+                            .unwrap();
+                        node.send(
+                            CanFrame::new(COB_SDO_TX + 3, MessageType::Standard, &response)
+                                .unwrap(),
+                        )
+                        .unwrap();
+                        return;
+                    }
+                    _ => thread::sleep(Duration::from_millis(1)),
+                }
+            }
+        });
+
+        assert_eq!(
+            master.sdo_upload(3, 0x1000, 0),
+            Ok(vec![0x91, 0x01, 0x0F, 0x00])
+        );
+        server.join().unwrap();
+
+        // the heartbeat received during the transfer is kept
+        assert_eq!(
+            master.recv_event(Duration::from_millis(100)),
+            Ok(Event::StateChanged {
+                node: 3,
+                state: NmtState::Operational
+            })
+        );
+        assert_eq!(
+            master.sdo_upload(0, 0x1000, 0),
+            Err(CanOpenError::InvalidNodeId)
+        );
+    }
+}
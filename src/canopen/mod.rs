@@ -0,0 +1,94 @@
+//! CANopen (CiA 301) on top of the send/recv traits.
+//!
+//! The [CanOpenMaster](master::CanOpenMaster) controls the network state of the nodes, monitors
+//! them with heartbeats or node guarding and accesses their object dictionaries with SDO. PDO
+//! contents are described by a [PdoMapping](pdo::PdoMapping).
+//!
+//! A [CanOpenDevice](device::CanOpenDevice) emulates a node serving an
+//! [ObjectDictionary](od::ObjectDictionary), populated in code or loaded from an EDS or DCF file
+//! with [load_eds](eds::load_eds). Node-IDs and bit rates of new devices are assigned with the
+//! [LssMaster](lss::LssMaster).
+
+use crate::error::PcanError;
+
+pub mod device;
+pub mod eds;
+pub mod emcy;
+pub mod lss;
+pub mod master;
+pub mod nmt;
+pub mod od;
+pub mod pdo;
+pub mod sdo;
+pub mod sync;
+
+pub use device::{CanOpenDevice, DeviceRunner};
+pub use eds::{load_eds, parse_eds, EdsError};
+pub use emcy::Emergency;
+pub use lss::{LssAddress, LssMaster, LssMode};
+pub use master::{CanOpenMaster, Event};
+pub use nmt::{NmtCommand, NmtState};
+pub use od::{AccessType, DataType, Entry, ObjectDictionary};
+pub use pdo::{MappedObject, PdoMapping};
+pub use sdo::{SdoAbortCode, SdoServer};
+
+#[derive(Debug, PartialEq)]
+pub enum CanOpenError {
+    Pcan(PcanError),
+    /// The node did not answer in time.
+    Timeout,
+    /// The SDO transfer was aborted by the server.
+    SdoAbort(SdoAbortCode),
+    /// The peer violated the protocol. The transfer was aborted.
+    Protocol,
+    /// The CRC of a block transfer did not match.
+    Crc,
+    /// Node-IDs range from 1 to 127.
+    InvalidNodeId,
+    InvalidMapping,
+    /// A value does not fit into the bits mapped for it.
+    ValueOutOfRange,
+    /// An LSS slave rejected a configuration request.
+    LssFailed {
+        error_code: u8,
+        specific_error: u8,
+    },
+}
+
+impl From<PcanError> for CanOpenError {
+    fn from(value: PcanError) -> Self {
+        CanOpenError::Pcan(value)
+    }
+}
+
+/// Node-ID of a CANopen device, from 1 to 127.
+pub type NodeId = u8;
+
+/* Predefined connection set */
+
+pub const COB_NMT: u32 = 0x000;
+pub const COB_SYNC: u32 = 0x080;
+pub const COB_EMCY: u32 = 0x080;
+pub const COB_TIME: u32 = 0x100;
+pub const COB_TPDO1: u32 = 0x180;
+pub const COB_RPDO1: u32 = 0x200;
+pub const COB_TPDO2: u32 = 0x280;
+pub const COB_RPDO2: u32 = 0x300;
+pub const COB_TPDO3: u32 = 0x380;
+pub const COB_RPDO3: u32 = 0x400;
+pub const COB_TPDO4: u32 = 0x480;
+pub const COB_RPDO4: u32 = 0x500;
+/// SDO responses, server to client.
+pub const COB_SDO_TX: u32 = 0x580;
+/// SDO requests, client to server.
+pub const COB_SDO_RX: u32 = 0x600;
+/// Heartbeat, boot-up and node guarding.
+pub const COB_NMT_ERROR_CONTROL: u32 = 0x700;
+
+pub(crate) fn check_node_id(node: NodeId) -> Result<(), CanOpenError> {
+    if (1..=127).contains(&node) {
+        Ok(())
+    } else {
+        Err(CanOpenError::InvalidNodeId)
+    }
+}
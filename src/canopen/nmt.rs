@@ -0,0 +1,90 @@
+//! Network management.
+
+use crate::canopen::{CanOpenError, NodeId, COB_NMT, COB_NMT_ERROR_CONTROL};
+use crate::socket::{CanFrame, MessageType};
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum NmtCommand {
+    Start,
+    Stop,
+    EnterPreOperational,
+    ResetNode,
+    ResetCommunication,
+}
+
+impl From<NmtCommand> for u8 {
+    fn from(value: NmtCommand) -> Self {
+        match value {
+            NmtCommand::Start => 0x01,
+            NmtCommand::Stop => 0x02,
+            NmtCommand::EnterPreOperational => 0x80,
+            NmtCommand::ResetNode => 0x81,
+            NmtCommand::ResetCommunication => 0x82,
+        }
+    }
+}
+
+impl TryFrom<u8> for NmtCommand {
+    type Error = CanOpenError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0x01 => Ok(NmtCommand::Start),
+            0x02 => Ok(NmtCommand::Stop),
+            0x80 => Ok(NmtCommand::EnterPreOperational),
+            0x81 => Ok(NmtCommand::ResetNode),
+            0x82 => Ok(NmtCommand::ResetCommunication),
+            _ => Err(CanOpenError::Protocol),
+        }
+    }
+}
+
+/// State reported in heartbeat and node guarding messages.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum NmtState {
+    BootUp,
+    Stopped,
+    Operational,
+    PreOperational,
+    Unknown(u8),
+}
+
+impl From<u8> for NmtState {
+    fn from(value: u8) -> Self {
+        match value {
+            0x00 => NmtState::BootUp,
+            0x04 => NmtState::Stopped,
+            0x05 => NmtState::Operational,
+            0x7F => NmtState::PreOperational,
+            _ => NmtState::Unknown(value),
+        }
+    }
+}
+
+impl From<NmtState> for u8 {
+    fn from(value: NmtState) -> Self {
+        match value {
+            NmtState::BootUp => 0x00,
+            NmtState::Stopped => 0x04,
+            NmtState::Operational => 0x05,
+            NmtState::PreOperational => 0x7F,
+            NmtState::Unknown(value) => value,
+        }
+    }
+}
+
+/// Builds an NMT command for `node`, or for all nodes if `node` is zero.
+pub fn nmt_frame(command: NmtCommand, node: NodeId) -> CanFrame {
+    CanFrame::new(COB_NMT, MessageType::Standard, &[command.into(), node])
+        .expect("an NMT command fits into a frame")
+}
+
+/// Builds a heartbeat or, with [NmtState::BootUp], a boot-up message.
+pub fn heartbeat_frame(node: NodeId, state: NmtState) -> CanFrame {
+    CanFrame::new(
+        COB_NMT_ERROR_CONTROL + node as u32,
+        MessageType::Standard,
+        &[state.into()],
+    )
+    .expect("a heartbeat fits into a frame")
+}
@@ -0,0 +1,392 @@
+//! Object dictionary.
+
+use crate::canopen::pdo::PdoMapping;
+use crate::canopen::sdo::SdoAbortCode;
+use std::collections::BTreeMap;
+
+/// Data types of CiA 301, identified by their index in the object dictionary.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum DataType {
+    Boolean,
+    Integer8,
+    Integer16,
+    Integer32,
+    Integer64,
+    Unsigned8,
+    Unsigned16,
+    Unsigned32,
+    Unsigned64,
+    Real32,
+    Real64,
+    VisibleString,
+    OctetString,
+    UnicodeString,
+    Domain,
+    Other(u16),
+}
+
+impl DataType {
+    /// Size of values of this type in bytes, `None` for types of variable length.
+    pub fn size(&self) -> Option<usize> {
+        match self {
+            DataType::Boolean | DataType::Integer8 | DataType::Unsigned8 => Some(1),
+            DataType::Integer16 | DataType::Unsigned16 => Some(2),
+            DataType::Integer32 | DataType::Unsigned32 | DataType::Real32 => Some(4),
+            DataType::Integer64 | DataType::Unsigned64 | DataType::Real64 => Some(8),
+            _ => None,
+        }
+    }
+}
+
+impl From<u16> for DataType {
+    fn from(value: u16) -> Self {
+        match value {
+            0x0001 => DataType::Boolean,
+            0x0002 => DataType::Integer8,
+            0x0003 => DataType::Integer16,
+            0x0004 => DataType::Integer32,
+            0x0005 => DataType::Unsigned8,
+            0x0006 => DataType::Unsigned16,
+            0x0007 => DataType::Unsigned32,
+            0x0008 => DataType::Real32,
+            0x0009 => DataType::VisibleString,
+            0x000A => DataType::OctetString,
+            0x000B => DataType::UnicodeString,
+            0x000F => DataType::Domain,
+            0x0011 => DataType::Real64,
+            0x0015 => DataType::Integer64,
+            0x001B => DataType::Unsigned64,
+            _ => DataType::Other(value),
+        }
+    }
+}
+
+impl From<DataType> for u16 {
+    fn from(value: DataType) -> Self {
+        match value {
+            DataType::Boolean => 0x0001,
+            DataType::Integer8 => 0x0002,
+            DataType::Integer16 => 0x0003,
+            DataType::Integer32 => 0x0004,
+            DataType::Unsigned8 => 0x0005,
+            DataType::Unsigned16 => 0x0006,
+            DataType::Unsigned32 => 0x0007,
+            DataType::Real32 => 0x0008,
+            DataType::VisibleString => 0x0009,
+            DataType::OctetString => 0x000A,
+            DataType::UnicodeString => 0x000B,
+            DataType::Domain => 0x000F,
+            DataType::Real64 => 0x0011,
+            DataType::Integer64 => 0x0015,
+            DataType::Unsigned64 => 0x001B,
+            DataType::Other(value) => value,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum AccessType {
+    ReadOnly,
+    WriteOnly,
+    ReadWrite,
+    Constant,
+}
+
+impl AccessType {
+    pub fn is_readable(&self) -> bool {
+        *self != AccessType::WriteOnly
+    }
+
+    pub fn is_writable(&self) -> bool {
+        matches!(self, AccessType::WriteOnly | AccessType::ReadWrite)
+    }
+}
+
+/// A sub-object of the dictionary.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Entry {
+    pub name: String,
+    pub data_type: DataType,
+    pub access: AccessType,
+    pub pdo_mappable: bool,
+    value: Vec<u8>,
+}
+
+impl Entry {
+    pub fn new(name: &str, data_type: DataType, access: AccessType, value: &[u8]) -> Entry {
+        Entry {
+            name: name.to_string(),
+            data_type,
+            access,
+            pdo_mappable: false,
+            value: value.to_vec(),
+        }
+    }
+
+    /// Creates an entry of a numeric type from its value.
+    pub fn numeric(name: &str, data_type: DataType, access: AccessType, value: u64) -> Entry {
+        let size = data_type.size().unwrap_or(8);
+        Entry::new(name, data_type, access, &value.to_le_bytes()[..size])
+    }
+
+    pub fn with_pdo_mapping(mut self, pdo_mappable: bool) -> Entry {
+        self.pdo_mappable = pdo_mappable;
+        self
+    }
+
+    pub fn value(&self) -> &[u8] {
+        &self.value
+    }
+
+    /// Interprets up to 8 bytes of the value as a little endian number.
+    pub fn as_u64(&self) -> u64 {
+        let mut bytes = [0u8; 8];
+        let length = self.value.len().min(8);
+        bytes[..length].copy_from_slice(&self.value[..length]);
+        u64::from_le_bytes(bytes)
+    }
+}
+
+/// Objects of a device, addressed by index and sub-index.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct ObjectDictionary {
+    entries: BTreeMap<(u16, u8), Entry>,
+}
+
+impl ObjectDictionary {
+    pub fn new() -> ObjectDictionary {
+        ObjectDictionary::default()
+    }
+
+    pub fn insert(&mut self, index: u16, subindex: u8, entry: Entry) {
+        self.entries.insert((index, subindex), entry);
+    }
+
+    pub fn remove(&mut self, index: u16, subindex: u8) -> Option<Entry> {
+        self.entries.remove(&(index, subindex))
+    }
+
+    pub fn get(&self, index: u16, subindex: u8) -> Option<&Entry> {
+        self.entries.get(&(index, subindex))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Iterates over all entries ordered by index and sub-index.
+    pub fn iter(&self) -> impl Iterator<Item = (u16, u8, &Entry)> {
+        self.entries
+            .iter()
+            .map(|((index, subindex), entry)| (*index, *subindex, entry))
+    }
+
+    fn lookup(&self, index: u16, subindex: u8) -> Result<&Entry, SdoAbortCode> {
+        match self.entries.get(&(index, subindex)) {
+            Some(entry) => Ok(entry),
+            None if self
+                .entries
+                .range((index, 0)..=(index, 255))
+                .next()
+                .is_some() =>
+            {
+                Err(SdoAbortCode::SubIndexDoesNotExist)
+            }
+            None => Err(SdoAbortCode::ObjectDoesNotExist),
+        }
+    }
+
+    /// Reads an entry on behalf of a client, checking the access type.
+    pub fn read(&self, index: u16, subindex: u8) -> Result<&[u8], SdoAbortCode> {
+        let entry = self.lookup(index, subindex)?;
+        if !entry.access.is_readable() {
+            return Err(SdoAbortCode::WriteOnly);
+        }
+        Ok(&entry.value)
+    }
+
+    /// Writes an entry on behalf of a client, checking the access type and the length.
+    pub fn write(&mut self, index: u16, subindex: u8, data: &[u8]) -> Result<(), SdoAbortCode> {
+        if !self.lookup(index, subindex)?.access.is_writable() {
+            return Err(SdoAbortCode::ReadOnly);
+        }
+        self.set(index, subindex, data)
+    }
+
+    /// Sets the value of an entry regardless of its access type, e.g. to update inputs.
+    pub fn set(&mut self, index: u16, subindex: u8, data: &[u8]) -> Result<(), SdoAbortCode> {
+        self.lookup(index, subindex)?;
+        let entry = self
+            .entries
+            .get_mut(&(index, subindex))
+            .ok_or(SdoAbortCode::ObjectDoesNotExist)?;
+
+        match entry.data_type.size() {
+            Some(size) if data.len() > size => return Err(SdoAbortCode::LengthTooHigh),
+            Some(size) if data.len() < size => return Err(SdoAbortCode::LengthTooLow),
+            _ => {}
+        }
+
+        entry.value = data.to_vec();
+        Ok(())
+    }
+
+    /// Reads a numeric value.
+    pub fn value_u64(&self, index: u16, subindex: u8) -> Option<u64> {
+        self.get(index, subindex).map(Entry::as_u64)
+    }
+
+    /// Sets a numeric value, truncated to the size of the data type of the entry.
+    pub fn set_u64(&mut self, index: u16, subindex: u8, value: u64) -> Result<(), SdoAbortCode> {
+        let size = self.lookup(index, subindex)?.data_type.size().unwrap_or(8);
+        self.set(index, subindex, &value.to_le_bytes()[..size])
+    }
+
+    fn insert_pdo(
+        &mut self,
+        communication: u16,
+        cob_id: u32,
+        transmission_type: u8,
+        mapping: &PdoMapping,
+    ) {
+        use AccessType::ReadWrite;
+
+        self.insert(
+            communication,
+            0,
+            Entry::numeric(
+                "Highest sub-index supported",
+                DataType::Unsigned8,
+                AccessType::Constant,
+                5,
+            ),
+        );
+        self.insert(
+            communication,
+            1,
+            Entry::numeric("COB-ID", DataType::Unsigned32, ReadWrite, cob_id as u64),
+        );
+        self.insert(
+            communication,
+            2,
+            Entry::numeric(
+                "Transmission type",
+                DataType::Unsigned8,
+                ReadWrite,
+                transmission_type as u64,
+            ),
+        );
+
+        let index = communication + 0x200;
+        self.insert(
+            index,
+            0,
+            Entry::numeric(
+                "Number of mapped objects",
+                DataType::Unsigned8,
+                ReadWrite,
+                mapping.objects().len() as u64,
+            ),
+        );
+        for (subindex, object) in mapping.objects().iter().enumerate() {
+            let name = format!("Mapped object {}", subindex + 1);
+            self.insert(
+                index,
+                subindex as u8 + 1,
+                Entry::numeric(
+                    &name,
+                    DataType::Unsigned32,
+                    ReadWrite,
+                    object.to_parameter() as u64,
+                ),
+            );
+        }
+    }
+
+    /// Adds the communication and mapping parameters of RPDO `number`, counting from zero.
+    pub fn add_rpdo(&mut self, number: u16, cob_id: u32, mapping: &PdoMapping) {
+        self.insert_pdo(0x1400 + number, cob_id, 0xFF, mapping);
+    }
+
+    /// Adds the communication and mapping parameters of TPDO `number`, counting from zero.
+    ///
+    /// Transmission types 1 to 240 send the PDO every n-th SYNC, 0 on the SYNC after a mapped
+    /// object changed and 254 or 255 whenever a mapped object changes. A non-zero `event_timer`
+    /// (in ms) additionally sends event driven PDOs periodically.
+    pub fn add_tpdo(
+        &mut self,
+        number: u16,
+        cob_id: u32,
+        transmission_type: u8,
+        event_timer: u16,
+        mapping: &PdoMapping,
+    ) {
+        let communication = 0x1800 + number;
+        self.insert_pdo(communication, cob_id, transmission_type, mapping);
+        self.insert(
+            communication,
+            3,
+            Entry::numeric(
+                "Inhibit time",
+                DataType::Unsigned16,
+                AccessType::ReadWrite,
+                0,
+            ),
+        );
+        self.insert(
+            communication,
+            5,
+            Entry::numeric(
+                "Event timer",
+                DataType::Unsigned16,
+                AccessType::ReadWrite,
+                event_timer as u64,
+            ),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn object_dictionary_001() {
+        let mut od = ObjectDictionary::new();
+        od.insert(
+            0x1000,
+            0,
+            Entry::numeric(
+                "Device type",
+                DataType::Unsigned32,
+                AccessType::ReadOnly,
+                0x0191,
+            ),
+        );
+        od.insert(
+            0x1017,
+            0,
+            Entry::numeric(
+                "Producer heartbeat time",
+                DataType::Unsigned16,
+                AccessType::ReadWrite,
+                0,
+            ),
+        );
+
+        assert_eq!(od.read(0x1000, 0), Ok(&[0x91, 0x01, 0x00, 0x00][..]));
+        assert_eq!(od.read(0x1000, 1), Err(SdoAbortCode::SubIndexDoesNotExist));
+        assert_eq!(od.read(0x2000, 0), Err(SdoAbortCode::ObjectDoesNotExist));
+        assert_eq!(od.write(0x1000, 0, &[0; 4]), Err(SdoAbortCode::ReadOnly));
+        assert_eq!(
+            od.write(0x1017, 0, &[0; 4]),
+            Err(SdoAbortCode::LengthTooHigh)
+        );
+        assert_eq!(od.write(0x1017, 0, &[0x64, 0x00]), Ok(()));
+        assert_eq!(od.value_u64(0x1017, 0), Some(100));
+    }
+}
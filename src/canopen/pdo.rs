@@ -0,0 +1,172 @@
+//! Process data objects.
+//!
+//! A [PdoMapping] lists the objects mapped into a PDO in the order of the mapping parameter
+//! (objects 0x1600.. for RPDOs and 0x1A00.. for TPDOs). Values are packed little endian, starting
+//! at the least significant bit of the first byte.
+
+use crate::canopen::CanOpenError;
+
+/// An object mapped into a PDO.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct MappedObject {
+    pub index: u16,
+    pub subindex: u8,
+    /// Length of the object in bits.
+    pub bits: u8,
+}
+
+impl MappedObject {
+    pub fn new(index: u16, subindex: u8, bits: u8) -> MappedObject {
+        MappedObject {
+            index,
+            subindex,
+            bits,
+        }
+    }
+
+    /// Decodes an entry of a mapping parameter, e.g. `0x6000_0108`.
+    pub fn from_parameter(parameter: u32) -> MappedObject {
+        MappedObject {
+            index: (parameter >> 16) as u16,
+            subindex: (parameter >> 8) as u8,
+            bits: parameter as u8,
+        }
+    }
+
+    pub fn to_parameter(&self) -> u32 {
+        ((self.index as u32) << 16) | ((self.subindex as u32) << 8) | self.bits as u32
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct PdoMapping {
+    objects: Vec<MappedObject>,
+}
+
+impl PdoMapping {
+    /// Creates a mapping. The objects must be 1 to 64 bits long and fit into 8 bytes together.
+    pub fn new(objects: Vec<MappedObject>) -> Result<PdoMapping, CanOpenError> {
+        let bits = objects
+            .iter()
+            .map(|object| object.bits as usize)
+            .sum::<usize>();
+
+        if bits > 64
+            || objects
+                .iter()
+                .any(|object| object.bits == 0 || object.bits > 64)
+        {
+            return Err(CanOpenError::InvalidMapping);
+        }
+        Ok(PdoMapping { objects })
+    }
+
+    /// Creates a mapping from the entries of a mapping parameter.
+    pub fn from_parameters(parameters: &[u32]) -> Result<PdoMapping, CanOpenError> {
+        PdoMapping::new(
+            parameters
+                .iter()
+                .map(|parameter| MappedObject::from_parameter(*parameter))
+                .collect(),
+        )
+    }
+
+    pub fn objects(&self) -> &[MappedObject] {
+        &self.objects
+    }
+
+    /// Length of the PDO in bytes.
+    pub fn len(&self) -> usize {
+        let bits = self
+            .objects
+            .iter()
+            .map(|object| object.bits as usize)
+            .sum::<usize>();
+        bits.div_ceil(8)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    /// Packs one value per mapped object into the PDO data.
+    pub fn encode(&self, values: &[u64]) -> Result<Vec<u8>, CanOpenError> {
+        if values.len() != self.objects.len() {
+            return Err(CanOpenError::InvalidMapping);
+        }
+
+        let mut packed = 0u64;
+        let mut offset = 0u32;
+        for (object, value) in self.objects.iter().zip(values) {
+            if object.bits < 64 && *value >> object.bits != 0 {
+                return Err(CanOpenError::ValueOutOfRange);
+            }
+            packed |= value.checked_shl(offset).unwrap_or(0);
+            offset += object.bits as u32;
+        }
+
+        Ok(packed.to_le_bytes()[..self.len()].to_vec())
+    }
+
+    /// Unpacks the values of the mapped objects from PDO data.
+    pub fn decode(&self, data: &[u8]) -> Result<Vec<u64>, CanOpenError> {
+        if data.len() < self.len() {
+            return Err(CanOpenError::Protocol);
+        }
+
+        let mut bytes = [0u8; 8];
+        bytes[..self.len()].copy_from_slice(&data[..self.len()]);
+        let packed = u64::from_le_bytes(bytes);
+
+        let mut offset = 0u32;
+        Ok(self
+            .objects
+            .iter()
+            .map(|object| {
+                let value = packed.checked_shr(offset).unwrap_or(0);
+                offset += object.bits as u32;
+                match object.bits {
+                    64 => value,
+                    bits => value & ((1 << bits) - 1),
+                }
+            })
+            .collect())
+    }
+}
+
+/// Interprets the lowest `bits` bits of `value` as a two's complement number.
+pub fn sign_extend(value: u64, bits: u8) -> i64 {
+    let shift = 64 - bits.clamp(1, 64) as u32;
+    ((value << shift) as i64) >> shift
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pdo_mapping_001() {
+        let mapping = PdoMapping::from_parameters(&[
+            0x6000_0101,
+            0x6000_0203,
+            0x6000_0304,
+            0x6401_0110,
+            0x2000_0020,
+        ])
+        .unwrap();
+        assert_eq!(mapping.len(), 7);
+
+        let data = mapping.encode(&[1, 0b101, 0, 0x1234, 0xDEAD_BEEF]).unwrap();
+        assert_eq!(data, vec![0x0B, 0x34, 0x12, 0xEF, 0xBE, 0xAD, 0xDE]);
+        assert_eq!(
+            mapping.decode(&data).unwrap(),
+            vec![1, 0b101, 0, 0x1234, 0xDEAD_BEEF]
+        );
+
+        assert_eq!(
+            mapping.encode(&[2, 0, 0, 0, 0]),
+            Err(CanOpenError::ValueOutOfRange)
+        );
+        assert_eq!(sign_extend(0xFFFE, 16), -2);
+    }
+}
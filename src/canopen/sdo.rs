@@ -0,0 +1,1288 @@
+//! Service data objects.
+//!
+//! Expedited, segmented and block transfers of CiA 301. The client side runs over an
+//! [SdoChannel], which carries the 8-byte SDO messages between client and server. The
+//! [SdoServer] answers requests from an [ObjectDictionary].
+
+use crate::canopen::od::ObjectDictionary;
+use crate::canopen::CanOpenError;
+use std::time::Duration;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum SdoAbortCode {
+    ToggleBitNotAlternated,
+    ProtocolTimeout,
+    InvalidCommandSpecifier,
+    InvalidBlockSize,
+    InvalidSequenceNumber,
+    CrcError,
+    OutOfMemory,
+    UnsupportedAccess,
+    WriteOnly,
+    ReadOnly,
+    ObjectDoesNotExist,
+    ObjectCannotBeMapped,
+    PdoLengthExceeded,
+    ParameterIncompatibility,
+    InternalIncompatibility,
+    HardwareError,
+    LengthMismatch,
+    LengthTooHigh,
+    LengthTooLow,
+    SubIndexDoesNotExist,
+    InvalidValue,
+    ValueTooHigh,
+    ValueTooLow,
+    MaximumBelowMinimum,
+    ResourceNotAvailable,
+    GeneralError,
+    CannotTransfer,
+    CannotTransferLocalControl,
+    CannotTransferDeviceState,
+    NoObjectDictionary,
+    NoDataAvailable,
+    Other(u32),
+}
+
+impl From<u32> for SdoAbortCode {
+    fn from(value: u32) -> Self {
+        match value {
+            0x0503_0000 => SdoAbortCode::ToggleBitNotAlternated,
+            0x0504_0000 => SdoAbortCode::ProtocolTimeout,
+            0x0504_0001 => SdoAbortCode::InvalidCommandSpecifier,
+            0x0504_0002 => SdoAbortCode::InvalidBlockSize,
+            0x0504_0003 => SdoAbortCode::InvalidSequenceNumber,
+            0x0504_0004 => SdoAbortCode::CrcError,
+            0x0504_0005 => SdoAbortCode::OutOfMemory,
+            0x0601_0000 => SdoAbortCode::UnsupportedAccess,
+            0x0601_0001 => SdoAbortCode::WriteOnly,
+            0x0601_0002 => SdoAbortCode::ReadOnly,
+            0x0602_0000 => SdoAbortCode::ObjectDoesNotExist,
+            0x0604_0041 => SdoAbortCode::ObjectCannotBeMapped,
+            0x0604_0042 => SdoAbortCode::PdoLengthExceeded,
+            0x0604_0043 => SdoAbortCode::ParameterIncompatibility,
+            0x0604_0047 => SdoAbortCode::InternalIncompatibility,
+            0x0606_0000 => SdoAbortCode::HardwareError,
+            0x0607_0010 => SdoAbortCode::LengthMismatch,
+            0x0607_0012 => SdoAbortCode::LengthTooHigh,
+            0x0607_0013 => SdoAbortCode::LengthTooLow,
+            0x0609_0011 => SdoAbortCode::SubIndexDoesNotExist,
+            0x0609_0030 => SdoAbortCode::InvalidValue,
+            0x0609_0031 => SdoAbortCode::ValueTooHigh,
+            0x0609_0032 => SdoAbortCode::ValueTooLow,
+            0x0609_0036 => SdoAbortCode::MaximumBelowMinimum,
+            0x060A_0023 => SdoAbortCode::ResourceNotAvailable,
+            0x0800_0000 => SdoAbortCode::GeneralError,
+            0x0800_0020 => SdoAbortCode::CannotTransfer,
+            0x0800_0021 => SdoAbortCode::CannotTransferLocalControl,
+            0x0800_0022 => SdoAbortCode::CannotTransferDeviceState,
+            0x0800_0023 => SdoAbortCode::NoObjectDictionary,
+            0x0800_0024 => SdoAbortCode::NoDataAvailable,
+            _ => SdoAbortCode::Other(value),
+        }
+    }
+}
+
+impl From<SdoAbortCode> for u32 {
+    fn from(value: SdoAbortCode) -> Self {
+        match value {
+            SdoAbortCode::ToggleBitNotAlternated => 0x0503_0000,
+            SdoAbortCode::ProtocolTimeout => 0x0504_0000,
+            SdoAbortCode::InvalidCommandSpecifier => 0x0504_0001,
+            SdoAbortCode::InvalidBlockSize => 0x0504_0002,
+            SdoAbortCode::InvalidSequenceNumber => 0x0504_0003,
+            SdoAbortCode::CrcError => 0x0504_0004,
+            SdoAbortCode::OutOfMemory => 0x0504_0005,
+            SdoAbortCode::UnsupportedAccess => 0x0601_0000,
+            SdoAbortCode::WriteOnly => 0x0601_0001,
+            SdoAbortCode::ReadOnly => 0x0601_0002,
+            SdoAbortCode::ObjectDoesNotExist => 0x0602_0000,
+            SdoAbortCode::ObjectCannotBeMapped => 0x0604_0041,
+            SdoAbortCode::PdoLengthExceeded => 0x0604_0042,
+            SdoAbortCode::ParameterIncompatibility => 0x0604_0043,
+            SdoAbortCode::InternalIncompatibility => 0x0604_0047,
+            SdoAbortCode::HardwareError => 0x0606_0000,
+            SdoAbortCode::LengthMismatch => 0x0607_0010,
+            SdoAbortCode::LengthTooHigh => 0x0607_0012,
+            SdoAbortCode::LengthTooLow => 0x0607_0013,
+            SdoAbortCode::SubIndexDoesNotExist => 0x0609_0011,
+            SdoAbortCode::InvalidValue => 0x0609_0030,
+            SdoAbortCode::ValueTooHigh => 0x0609_0031,
+            SdoAbortCode::ValueTooLow => 0x0609_0032,
+            SdoAbortCode::MaximumBelowMinimum => 0x0609_0036,
+            SdoAbortCode::ResourceNotAvailable => 0x060A_0023,
+            SdoAbortCode::GeneralError => 0x0800_0000,
+            SdoAbortCode::CannotTransfer => 0x0800_0020,
+            SdoAbortCode::CannotTransferLocalControl => 0x0800_0021,
+            SdoAbortCode::CannotTransferDeviceState => 0x0800_0022,
+            SdoAbortCode::NoObjectDictionary => 0x0800_0023,
+            SdoAbortCode::NoDataAvailable => 0x0800_0024,
+            SdoAbortCode::Other(value) => value,
+        }
+    }
+}
+
+/* Protocol */
+
+pub(crate) const CCS_DOWNLOAD_SEGMENT: u8 = 0;
+pub(crate) const CCS_INITIATE_DOWNLOAD: u8 = 1;
+pub(crate) const CCS_INITIATE_UPLOAD: u8 = 2;
+pub(crate) const CCS_UPLOAD_SEGMENT: u8 = 3;
+pub(crate) const CS_ABORT: u8 = 4;
+pub(crate) const CCS_BLOCK_UPLOAD: u8 = 5;
+pub(crate) const CCS_BLOCK_DOWNLOAD: u8 = 6;
+
+pub(crate) const SCS_UPLOAD_SEGMENT: u8 = 0;
+pub(crate) const SCS_DOWNLOAD_SEGMENT: u8 = 1;
+pub(crate) const SCS_INITIATE_UPLOAD: u8 = 2;
+pub(crate) const SCS_INITIATE_DOWNLOAD: u8 = 3;
+pub(crate) const SCS_BLOCK_DOWNLOAD: u8 = 5;
+pub(crate) const SCS_BLOCK_UPLOAD: u8 = 6;
+
+/// CRC of block transfers (CRC-16-CCITT, polynomial 0x1021, initial value 0).
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |crc, byte| {
+        (0..8).fold(crc ^ ((*byte as u16) << 8), |crc, _| {
+            if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            }
+        })
+    })
+}
+
+/// Builds an abort transfer message.
+pub fn abort_message(index: u16, subindex: u8, code: SdoAbortCode) -> [u8; 8] {
+    let mut message = [0; 8];
+    message[0] = CS_ABORT << 5;
+    message[1..3].copy_from_slice(&index.to_le_bytes());
+    message[3] = subindex;
+    message[4..].copy_from_slice(&u32::from(code).to_le_bytes());
+    message
+}
+
+/// Carries the SDO messages between a client and a single server.
+pub trait SdoChannel {
+    fn send_sdo(&mut self, message: [u8; 8]) -> Result<(), CanOpenError>;
+
+    /// Waits for the next message of the server. Returns [Timeout](CanOpenError::Timeout) if none
+    /// arrived in time.
+    fn recv_sdo(&mut self, timeout: Duration) -> Result<[u8; 8], CanOpenError>;
+}
+
+fn initiate(command: u8, index: u16, subindex: u8, data: &[u8]) -> [u8; 8] {
+    let mut message = [0; 8];
+    message[0] = command;
+    message[1..3].copy_from_slice(&index.to_le_bytes());
+    message[3] = subindex;
+    message[4..4 + data.len()].copy_from_slice(data);
+    message
+}
+
+/// SDO client of a single transfer.
+pub struct SdoClient<'a, C: SdoChannel> {
+    channel: &'a mut C,
+    timeout: Duration,
+    index: u16,
+    subindex: u8,
+}
+
+impl<'a, C: SdoChannel> SdoClient<'a, C> {
+    pub fn new(channel: &'a mut C, timeout: Duration) -> SdoClient<'a, C> {
+        SdoClient {
+            channel,
+            timeout,
+            index: 0,
+            subindex: 0,
+        }
+    }
+
+    fn abort(&mut self, code: SdoAbortCode) -> CanOpenError {
+        let _ = self
+            .channel
+            .send_sdo(abort_message(self.index, self.subindex, code));
+        match code {
+            SdoAbortCode::ProtocolTimeout => CanOpenError::Timeout,
+            SdoAbortCode::CrcError => CanOpenError::Crc,
+            _ => CanOpenError::Protocol,
+        }
+    }
+
+    /// Waits for a response, turning abort messages and timeouts into errors.
+    fn response(&mut self) -> Result<[u8; 8], CanOpenError> {
+        let response = match self.channel.recv_sdo(self.timeout) {
+            Ok(response) => response,
+            Err(CanOpenError::Timeout) => return Err(self.abort(SdoAbortCode::ProtocolTimeout)),
+            Err(err) => return Err(err),
+        };
+
+        if response[0] >> 5 == CS_ABORT {
+            let code = u32::from_le_bytes([response[4], response[5], response[6], response[7]]);
+            return Err(CanOpenError::SdoAbort(SdoAbortCode::from(code)));
+        }
+        Ok(response)
+    }
+
+    /// Waits for a response with the given server command specifier, aborting the transfer
+    /// otherwise.
+    fn expect(&mut self, scs: u8) -> Result<[u8; 8], CanOpenError> {
+        let response = self.response()?;
+        if response[0] >> 5 != scs {
+            return Err(self.abort(SdoAbortCode::InvalidCommandSpecifier));
+        }
+        Ok(response)
+    }
+
+    fn check_multiplexer(&mut self, response: &[u8; 8]) -> Result<(), CanOpenError> {
+        if response[1..3] != self.index.to_le_bytes() || response[3] != self.subindex {
+            return Err(self.abort(SdoAbortCode::GeneralError));
+        }
+        Ok(())
+    }
+
+    /// Reads an object with an expedited or segmented transfer.
+    pub fn upload(&mut self, index: u16, subindex: u8) -> Result<Vec<u8>, CanOpenError> {
+        self.index = index;
+        self.subindex = subindex;
+        self.channel
+            .send_sdo(initiate(CCS_INITIATE_UPLOAD << 5, index, subindex, &[]))?;
+
+        let response = self.expect(SCS_INITIATE_UPLOAD)?;
+        self.check_multiplexer(&response)?;
+
+        let expedited = response[0] & 0x02 != 0;
+        let size_indicated = response[0] & 0x01 != 0;
+        if expedited {
+            let length = match size_indicated {
+                true => 4 - ((response[0] >> 2) & 0x03) as usize,
+                false => 4,
+            };
+            return Ok(response[4..4 + length].to_vec());
+        }
+
+        let size = match size_indicated {
+            true => Some(u32::from_le_bytes([
+                response[4],
+                response[5],
+                response[6],
+                response[7],
+            ])),
+            false => None,
+        };
+
+        let mut data = Vec::new();
+        let mut toggle = 0u8;
+        loop {
+            self.channel.send_sdo([
+                (CCS_UPLOAD_SEGMENT << 5) | (toggle << 4),
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ])?;
+
+            let segment = self.expect(SCS_UPLOAD_SEGMENT)?;
+            if (segment[0] >> 4) & 0x01 != toggle {
+                return Err(self.abort(SdoAbortCode::ToggleBitNotAlternated));
+            }
+
+            let unused = ((segment[0] >> 1) & 0x07) as usize;
+            data.extend_from_slice(&segment[1..8 - unused]);
+            toggle ^= 1;
+
+            if segment[0] & 0x01 != 0 {
+                break;
+            }
+        }
+
+        match size {
+            Some(size) if size as usize != data.len() => {
+                Err(self.abort(SdoAbortCode::LengthMismatch))
+            }
+            _ => Ok(data),
+        }
+    }
+
+    /// Writes an object, expedited for up to 4 bytes and segmented otherwise.
+    pub fn download(&mut self, index: u16, subindex: u8, data: &[u8]) -> Result<(), CanOpenError> {
+        self.index = index;
+        self.subindex = subindex;
+
+        if data.len() <= 4 {
+            let command =
+                (CCS_INITIATE_DOWNLOAD << 5) | (((4 - data.len()) as u8) << 2) | 0x02 | 0x01;
+            self.channel
+                .send_sdo(initiate(command, index, subindex, data))?;
+            let response = self.expect(SCS_INITIATE_DOWNLOAD)?;
+            return self.check_multiplexer(&response);
+        }
+
+        let size = (data.len() as u32).to_le_bytes();
+        self.channel.send_sdo(initiate(
+            (CCS_INITIATE_DOWNLOAD << 5) | 0x01,
+            index,
+            subindex,
+            &size,
+        ))?;
+        let response = self.expect(SCS_INITIATE_DOWNLOAD)?;
+        self.check_multiplexer(&response)?;
+
+        let mut toggle = 0u8;
+        let mut chunks = data.chunks(7).peekable();
+        while let Some(chunk) = chunks.next() {
+            let last = chunks.peek().is_none();
+            let mut segment = [0u8; 8];
+            segment[0] = (CCS_DOWNLOAD_SEGMENT << 5)
+                | (toggle << 4)
+                | (((7 - chunk.len()) as u8) << 1)
+                | last as u8;
+            segment[1..1 + chunk.len()].copy_from_slice(chunk);
+            self.channel.send_sdo(segment)?;
+
+            let response = self.expect(SCS_DOWNLOAD_SEGMENT)?;
+            if (response[0] >> 4) & 0x01 != toggle {
+                return Err(self.abort(SdoAbortCode::ToggleBitNotAlternated));
+            }
+            toggle ^= 1;
+        }
+        Ok(())
+    }
+
+    /// Reads an object with a block transfer of up to `block_size` segments per block.
+    pub fn block_upload(
+        &mut self,
+        index: u16,
+        subindex: u8,
+        block_size: u8,
+    ) -> Result<Vec<u8>, CanOpenError> {
+        self.index = index;
+        self.subindex = subindex;
+        let block_size = block_size.clamp(1, 127);
+
+        // initiate, with CRC support
+        self.channel.send_sdo(initiate(
+            (CCS_BLOCK_UPLOAD << 5) | 0x04,
+            index,
+            subindex,
+            &[block_size, 0],
+        ))?;
+
+        let response = self.expect(SCS_BLOCK_UPLOAD)?;
+        self.check_multiplexer(&response)?;
+        let crc_supported = response[0] & 0x04 != 0;
+        let size = match response[0] & 0x02 != 0 {
+            true => Some(u32::from_le_bytes([
+                response[4],
+                response[5],
+                response[6],
+                response[7],
+            ])),
+            false => None,
+        };
+
+        self.channel
+            .send_sdo([(CCS_BLOCK_UPLOAD << 5) | 0x03, 0, 0, 0, 0, 0, 0, 0])?;
+
+        let mut data = Vec::new();
+        let mut last_segment = [0u8; 7];
+        loop {
+            let mut sequence = 0u8;
+            let mut block = Vec::with_capacity(block_size as usize * 7);
+            let mut complete = false;
+
+            loop {
+                let segment = match self.channel.recv_sdo(self.timeout) {
+                    Ok(segment) => segment,
+                    Err(CanOpenError::Timeout) => {
+                        return Err(self.abort(SdoAbortCode::ProtocolTimeout))
+                    }
+                    Err(err) => return Err(err),
+                };
+
+                if segment[0] == (CS_ABORT << 5) {
+                    let code = u32::from_le_bytes([segment[4], segment[5], segment[6], segment[7]]);
+                    return Err(CanOpenError::SdoAbort(SdoAbortCode::from(code)));
+                }
+
+                // segments after a lost one are ignored and repeated by the server
+                let number = segment[0] & 0x7F;
+                let last = segment[0] & 0x80 != 0;
+                if number == sequence + 1 {
+                    sequence += 1;
+                    block.extend_from_slice(&segment[1..]);
+                    last_segment.copy_from_slice(&segment[1..]);
+                    complete = last;
+                }
+
+                // the block ends with its last segment even if one before it was lost, the
+                // acknowledgement then names the last segment received in order
+                if last || number >= block_size {
+                    break;
+                }
+            }
+
+            data.extend_from_slice(&block);
+            self.channel.send_sdo([
+                (CCS_BLOCK_UPLOAD << 5) | 0x02,
+                sequence,
+                block_size,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ])?;
+
+            if complete {
+                break;
+            }
+        }
+
+        let end = self.expect(SCS_BLOCK_UPLOAD)?;
+        if end[0] & 0x03 != 0x01 {
+            return Err(self.abort(SdoAbortCode::InvalidCommandSpecifier));
+        }
+
+        // the last segment carries 7 - n bytes of data
+        let unused = ((end[0] >> 2) & 0x07) as usize;
+        data.truncate(data.len() - unused.min(last_segment.len()));
+
+        if crc_supported && crc16(&data) != u16::from_le_bytes([end[1], end[2]]) {
+            return Err(self.abort(SdoAbortCode::CrcError));
+        }
+        if let Some(size) = size {
+            if size as usize != data.len() {
+                return Err(self.abort(SdoAbortCode::LengthMismatch));
+            }
+        }
+
+        self.channel
+            .send_sdo([(CCS_BLOCK_UPLOAD << 5) | 0x01, 0, 0, 0, 0, 0, 0, 0])?;
+        Ok(data)
+    }
+
+    /// Writes an object with a block transfer.
+    pub fn block_download(
+        &mut self,
+        index: u16,
+        subindex: u8,
+        data: &[u8],
+    ) -> Result<(), CanOpenError> {
+        self.index = index;
+        self.subindex = subindex;
+
+        let size = (data.len() as u32).to_le_bytes();
+        self.channel.send_sdo(initiate(
+            (CCS_BLOCK_DOWNLOAD << 5) | 0x04 | 0x02,
+            index,
+            subindex,
+            &size,
+        ))?;
+
+        let response = self.expect(SCS_BLOCK_DOWNLOAD)?;
+        self.check_multiplexer(&response)?;
+        let crc_supported = response[0] & 0x04 != 0;
+        let mut block_size = response[4];
+        if !(1..=127).contains(&block_size) {
+            return Err(self.abort(SdoAbortCode::InvalidBlockSize));
+        }
+
+        let segments = data.chunks(7).collect::<Vec<_>>();
+        let mut next = 0usize;
+        while next < segments.len() {
+            let count = (segments.len() - next).min(block_size as usize);
+            for sequence in 1..=count {
+                let chunk = segments[next + sequence - 1];
+                let last = next + sequence == segments.len();
+                let mut segment = [0u8; 8];
+                segment[0] = ((last as u8) << 7) | sequence as u8;
+                segment[1..1 + chunk.len()].copy_from_slice(chunk);
+                self.channel.send_sdo(segment)?;
+            }
+
+            let response = self.expect(SCS_BLOCK_DOWNLOAD)?;
+            if response[0] & 0x03 != 0x02 {
+                return Err(self.abort(SdoAbortCode::InvalidCommandSpecifier));
+            }
+
+            let acknowledged = response[1] as usize;
+            if acknowledged > count {
+                return Err(self.abort(SdoAbortCode::InvalidSequenceNumber));
+            }
+            next += acknowledged;
+            block_size = response[2];
+            if !(1..=127).contains(&block_size) {
+                return Err(self.abort(SdoAbortCode::InvalidBlockSize));
+            }
+        }
+
+        let unused = match data.len() % 7 {
+            0 if !data.is_empty() => 0,
+            0 => 7,
+            length => 7 - length,
+        } as u8;
+        let crc = match crc_supported {
+            true => crc16(data),
+            false => 0,
+        };
+        let [low, high] = crc.to_le_bytes();
+        self.channel.send_sdo([
+            (CCS_BLOCK_DOWNLOAD << 5) | (unused << 2) | 0x01,
+            low,
+            high,
+            0,
+            0,
+            0,
+            0,
+            0,
+        ])?;
+
+        let end = self.expect(SCS_BLOCK_DOWNLOAD)?;
+        if end[0] & 0x03 != 0x01 {
+            return Err(self.abort(SdoAbortCode::InvalidCommandSpecifier));
+        }
+        Ok(())
+    }
+}
+
+/* Server */
+
+enum ServerState {
+    Idle,
+    Download {
+        data: Vec<u8>,
+        size: Option<usize>,
+        toggle: u8,
+    },
+    Upload {
+        data: Vec<u8>,
+        offset: usize,
+        toggle: u8,
+    },
+    BlockDownload {
+        data: Vec<u8>,
+        size: Option<usize>,
+        crc: bool,
+        sequence: u8,
+        complete: bool,
+    },
+    BlockUpload {
+        data: Vec<u8>,
+        crc: bool,
+        block_size: u8,
+        offset: usize,
+        sent: usize,
+        started: bool,
+    },
+}
+
+/// Number of segments per block the server accepts in block downloads.
+const SERVER_BLOCK_SIZE: u8 = 127;
+
+/// SDO server answering the requests of a single client from an [ObjectDictionary].
+///
+/// The server does not do any I/O: each request is passed to [handle](SdoServer::handle), which
+/// returns the responses to send.
+pub struct SdoServer {
+    state: ServerState,
+    index: u16,
+    subindex: u8,
+    written: Vec<(u16, u8)>,
+}
+
+impl Default for SdoServer {
+    fn default() -> Self {
+        SdoServer::new()
+    }
+}
+
+impl SdoServer {
+    pub fn new() -> SdoServer {
+        SdoServer {
+            state: ServerState::Idle,
+            index: 0,
+            subindex: 0,
+            written: Vec::new(),
+        }
+    }
+
+    /// Cancels a running transfer.
+    pub fn reset(&mut self) {
+        self.state = ServerState::Idle;
+    }
+
+    /// Returns the objects written since the last call.
+    pub fn take_written(&mut self) -> Vec<(u16, u8)> {
+        std::mem::take(&mut self.written)
+    }
+
+    fn abort(&mut self, code: SdoAbortCode) -> Vec<[u8; 8]> {
+        self.state = ServerState::Idle;
+        vec![abort_message(self.index, self.subindex, code)]
+    }
+
+    fn write(&mut self, od: &mut ObjectDictionary, data: &[u8]) -> Result<(), SdoAbortCode> {
+        od.write(self.index, self.subindex, data)?;
+        self.written.push((self.index, self.subindex));
+        Ok(())
+    }
+
+    /// Processes a request of the client and returns the responses.
+    pub fn handle(&mut self, request: &[u8; 8], od: &mut ObjectDictionary) -> Vec<[u8; 8]> {
+        let command = request[0] >> 5;
+
+        // segments of a block download carry a sequence number instead of a command specifier
+        if let ServerState::BlockDownload {
+            complete: false, ..
+        } = self.state
+        {
+            if request[0] != CS_ABORT << 5 {
+                return self.block_download_segment(request);
+            }
+        }
+
+        match command {
+            CS_ABORT => {
+                self.state = ServerState::Idle;
+                Vec::new()
+            }
+            CCS_INITIATE_DOWNLOAD => self.initiate_download(request, od),
+            CCS_DOWNLOAD_SEGMENT => self.download_segment(request, od),
+            CCS_INITIATE_UPLOAD => self.initiate_upload(request, od),
+            CCS_UPLOAD_SEGMENT => self.upload_segment(request),
+            CCS_BLOCK_DOWNLOAD => self.block_download(request, od),
+            CCS_BLOCK_UPLOAD => self.block_upload(request, od),
+            _ => self.abort(SdoAbortCode::InvalidCommandSpecifier),
+        }
+    }
+
+    fn set_multiplexer(&mut self, request: &[u8; 8]) {
+        self.index = u16::from_le_bytes([request[1], request[2]]);
+        self.subindex = request[3];
+    }
+
+    fn check_writable(&self, od: &ObjectDictionary) -> Result<(), SdoAbortCode> {
+        match od.get(self.index, self.subindex) {
+            Some(entry) if entry.access.is_writable() => Ok(()),
+            Some(_) => Err(SdoAbortCode::ReadOnly),
+            None => od.read(self.index, self.subindex).map(|_| ()),
+        }
+    }
+
+    /// Rejects downloads announcing more data than an entry of fixed size holds.
+    fn check_size(&self, od: &ObjectDictionary, size: Option<usize>) -> Result<(), SdoAbortCode> {
+        let capacity = od
+            .get(self.index, self.subindex)
+            .and_then(|entry| entry.data_type.size());
+        match (size, capacity) {
+            (Some(size), Some(capacity)) if size > capacity => Err(SdoAbortCode::LengthTooHigh),
+            _ => Ok(()),
+        }
+    }
+
+    fn response(&self, command: u8, data: &[u8]) -> [u8; 8] {
+        initiate(command, self.index, self.subindex, data)
+    }
+
+    fn initiate_download(&mut self, request: &[u8; 8], od: &mut ObjectDictionary) -> Vec<[u8; 8]> {
+        self.set_multiplexer(request);
+        let expedited = request[0] & 0x02 != 0;
+        let size_indicated = request[0] & 0x01 != 0;
+
+        if expedited {
+            // without size, the size of the object is assumed
+            let length = match size_indicated {
+                true => 4 - ((request[0] >> 2) & 0x03) as usize,
+                false => od
+                    .get(self.index, self.subindex)
+                    .and_then(|entry| entry.data_type.size())
+                    .unwrap_or(4)
+                    .min(4),
+            };
+            return match self.write(od, &request[4..4 + length]) {
+                Ok(()) => {
+                    self.state = ServerState::Idle;
+                    vec![self.response(SCS_INITIATE_DOWNLOAD << 5, &[])]
+                }
+                Err(code) => self.abort(code),
+            };
+        }
+
+        if let Err(code) = self.check_writable(od) {
+            return self.abort(code);
+        }
+
+        let size = match size_indicated {
+            true => {
+                Some(u32::from_le_bytes([request[4], request[5], request[6], request[7]]) as usize)
+            }
+            false => None,
+        };
+        if let Err(code) = self.check_size(od, size) {
+            return self.abort(code);
+        }
+        self.state = ServerState::Download {
+            data: Vec::new(),
+            size,
+            toggle: 0,
+        };
+        vec![self.response(SCS_INITIATE_DOWNLOAD << 5, &[])]
+    }
+
+    fn download_segment(&mut self, request: &[u8; 8], od: &mut ObjectDictionary) -> Vec<[u8; 8]> {
+        let ServerState::Download { data, size, toggle } = &mut self.state else {
+            return self.abort(SdoAbortCode::InvalidCommandSpecifier);
+        };
+
+        let received = (request[0] >> 4) & 0x01;
+        if received != *toggle {
+            return self.abort(SdoAbortCode::ToggleBitNotAlternated);
+        }
+        *toggle ^= 1;
+
+        let unused = ((request[0] >> 1) & 0x07) as usize;
+        data.extend_from_slice(&request[1..8 - unused]);
+        let response = [
+            (SCS_DOWNLOAD_SEGMENT << 5) | (received << 4),
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+        ];
+
+        if request[0] & 0x01 == 0 {
+            return vec![response];
+        }
+
+        let data = std::mem::take(data);
+        if size.is_some_and(|size| size != data.len()) {
+            return self.abort(SdoAbortCode::LengthMismatch);
+        }
+        self.state = ServerState::Idle;
+        match self.write(od, &data) {
+            Ok(()) => vec![response],
+            Err(code) => self.abort(code),
+        }
+    }
+
+    fn initiate_upload(&mut self, request: &[u8; 8], od: &ObjectDictionary) -> Vec<[u8; 8]> {
+        self.set_multiplexer(request);
+        let data = match od.read(self.index, self.subindex) {
+            Ok(data) => data.to_vec(),
+            Err(code) => return self.abort(code),
+        };
+
+        if data.len() <= 4 {
+            self.state = ServerState::Idle;
+            let unused = (4 - data.len()) as u8;
+            return vec![self.response((SCS_INITIATE_UPLOAD << 5) | (unused << 2) | 0x03, &data)];
+        }
+
+        let size = (data.len() as u32).to_le_bytes();
+        self.state = ServerState::Upload {
+            data,
+            offset: 0,
+            toggle: 0,
+        };
+        vec![self.response((SCS_INITIATE_UPLOAD << 5) | 0x01, &size)]
+    }
+
+    fn upload_segment(&mut self, request: &[u8; 8]) -> Vec<[u8; 8]> {
+        let ServerState::Upload {
+            data,
+            offset,
+            toggle,
+        } = &mut self.state
+        else {
+            return self.abort(SdoAbortCode::InvalidCommandSpecifier);
+        };
+
+        let received = (request[0] >> 4) & 0x01;
+        if received != *toggle {
+            return self.abort(SdoAbortCode::ToggleBitNotAlternated);
+        }
+        *toggle ^= 1;
+
+        let chunk = &data[*offset..(*offset + 7).min(data.len())];
+        *offset += chunk.len();
+        let last = *offset == data.len();
+
+        let mut response = [0u8; 8];
+        response[0] = (SCS_UPLOAD_SEGMENT << 5)
+            | (received << 4)
+            | (((7 - chunk.len()) as u8) << 1)
+            | last as u8;
+        response[1..1 + chunk.len()].copy_from_slice(chunk);
+
+        if last {
+            self.state = ServerState::Idle;
+        }
+        vec![response]
+    }
+
+    fn block_download(&mut self, request: &[u8; 8], od: &mut ObjectDictionary) -> Vec<[u8; 8]> {
+        match request[0] & 0x01 {
+            // initiate
+            0 => {
+                self.set_multiplexer(request);
+                if let Err(code) = self.check_writable(od) {
+                    return self.abort(code);
+                }
+
+                let size =
+                    match request[0] & 0x02 != 0 {
+                        true => Some(u32::from_le_bytes([
+                            request[4], request[5], request[6], request[7],
+                        ]) as usize),
+                        false => None,
+                    };
+                if let Err(code) = self.check_size(od, size) {
+                    return self.abort(code);
+                }
+                self.state = ServerState::BlockDownload {
+                    data: Vec::new(),
+                    size,
+                    crc: request[0] & 0x04 != 0,
+                    sequence: 0,
+                    complete: false,
+                };
+                vec![self.response((SCS_BLOCK_DOWNLOAD << 5) | 0x04, &[SERVER_BLOCK_SIZE])]
+            }
+            // end
+            _ => {
+                let ServerState::BlockDownload {
+                    data,
+                    size,
+                    crc,
+                    complete: true,
+                    ..
+                } = &mut self.state
+                else {
+                    return self.abort(SdoAbortCode::InvalidCommandSpecifier);
+                };
+
+                let unused = ((request[0] >> 2) & 0x07) as usize;
+                let length = data.len().saturating_sub(unused);
+                data.truncate(length);
+
+                if *crc && crc16(data) != u16::from_le_bytes([request[1], request[2]]) {
+                    return self.abort(SdoAbortCode::CrcError);
+                }
+                if size.is_some_and(|size| size != data.len()) {
+                    return self.abort(SdoAbortCode::LengthMismatch);
+                }
+
+                let data = std::mem::take(data);
+                self.state = ServerState::Idle;
+                match self.write(od, &data) {
+                    Ok(()) => vec![[(SCS_BLOCK_DOWNLOAD << 5) | 0x01, 0, 0, 0, 0, 0, 0, 0]],
+                    Err(code) => self.abort(code),
+                }
+            }
+        }
+    }
+
+    fn block_download_segment(&mut self, request: &[u8; 8]) -> Vec<[u8; 8]> {
+        let ServerState::BlockDownload {
+            data,
+            sequence,
+            complete,
+            ..
+        } = &mut self.state
+        else {
+            return Vec::new();
+        };
+
+        let last = request[0] & 0x80 != 0;
+        let received = request[0] & 0x7F;
+
+        // segments after a lost one are dropped, the client repeats them
+        if received == *sequence + 1 {
+            *sequence = received;
+            data.extend_from_slice(&request[1..]);
+            *complete = last;
+        }
+
+        if *complete || received == SERVER_BLOCK_SIZE || (last && received != *sequence) {
+            let acknowledged = *sequence;
+            *sequence = 0;
+            return vec![[
+                (SCS_BLOCK_DOWNLOAD << 5) | 0x02,
+                acknowledged,
+                SERVER_BLOCK_SIZE,
+                0,
+                0,
+                0,
+                0,
+                0,
+            ]];
+        }
+        Vec::new()
+    }
+
+    fn block_upload(&mut self, request: &[u8; 8], od: &ObjectDictionary) -> Vec<[u8; 8]> {
+        match request[0] & 0x03 {
+            // initiate
+            0 => {
+                self.set_multiplexer(request);
+                let data = match od.read(self.index, self.subindex) {
+                    Ok(data) => data.to_vec(),
+                    Err(code) => return self.abort(code),
+                };
+                let block_size = request[4];
+                if !(1..=127).contains(&block_size) {
+                    return self.abort(SdoAbortCode::InvalidBlockSize);
+                }
+
+                let size = (data.len() as u32).to_le_bytes();
+                self.state = ServerState::BlockUpload {
+                    data,
+                    crc: request[0] & 0x04 != 0,
+                    block_size,
+                    offset: 0,
+                    sent: 0,
+                    started: false,
+                };
+                vec![self.response((SCS_BLOCK_UPLOAD << 5) | 0x04 | 0x02, &size)]
+            }
+            // start
+            3 => match &mut self.state {
+                ServerState::BlockUpload { started, .. } if !*started => {
+                    *started = true;
+                    self.upload_block()
+                }
+                _ => self.abort(SdoAbortCode::InvalidCommandSpecifier),
+            },
+            // block acknowledged
+            2 => {
+                let ServerState::BlockUpload {
+                    data,
+                    crc,
+                    block_size,
+                    offset,
+                    sent,
+                    started: true,
+                } = &mut self.state
+                else {
+                    return self.abort(SdoAbortCode::InvalidCommandSpecifier);
+                };
+
+                let acknowledged = request[1] as usize;
+                if acknowledged > *sent {
+                    return self.abort(SdoAbortCode::InvalidSequenceNumber);
+                }
+                *offset = (*offset + acknowledged * 7).min(data.len());
+                *block_size = request[2];
+                if !(1..=127).contains(block_size) {
+                    return self.abort(SdoAbortCode::InvalidBlockSize);
+                }
+
+                if *offset == data.len() && acknowledged == *sent {
+                    let unused = match data.len() % 7 {
+                        0 if !data.is_empty() => 0,
+                        0 => 7,
+                        length => 7 - length,
+                    } as u8;
+                    let crc = match *crc {
+                        true => crc16(data),
+                        false => 0,
+                    };
+                    let [low, high] = crc.to_le_bytes();
+                    *sent = 0;
+                    return vec![[
+                        (SCS_BLOCK_UPLOAD << 5) | (unused << 2) | 0x01,
+                        low,
+                        high,
+                        0,
+                        0,
+                        0,
+                        0,
+                        0,
+                    ]];
+                }
+                self.upload_block()
+            }
+            // end
+            _ => {
+                self.state = ServerState::Idle;
+                Vec::new()
+            }
+        }
+    }
+
+    fn upload_block(&mut self) -> Vec<[u8; 8]> {
+        let ServerState::BlockUpload {
+            data,
+            block_size,
+            offset,
+            sent,
+            ..
+        } = &mut self.state
+        else {
+            return Vec::new();
+        };
+
+        let remaining = &data[*offset..];
+        let chunks = match remaining.is_empty() {
+            true => vec![&remaining[..0]],
+            false => remaining.chunks(7).take(*block_size as usize).collect(),
+        };
+        let count = chunks.len();
+        *sent = count;
+
+        chunks
+            .into_iter()
+            .enumerate()
+            .map(|(position, chunk)| {
+                let last = *offset + (position * 7) + chunk.len() == data.len();
+                let mut segment = [0u8; 8];
+                segment[0] = ((last as u8) << 7) | (position + 1) as u8;
+                segment[1..1 + chunk.len()].copy_from_slice(chunk);
+                segment
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canopen::od::{AccessType, DataType, Entry};
+    use std::collections::VecDeque;
+
+    /// Server replaying a script: each request is compared with the expected prefix and answered
+    /// with the given responses.
+    struct Script {
+        steps: VecDeque<(Vec<u8>, Vec<[u8; 8]>)>,
+        responses: VecDeque<[u8; 8]>,
+    }
+
+    impl Script {
+        fn new(steps: Vec<(Vec<u8>, Vec<[u8; 8]>)>) -> Script {
+            Script {
+                steps: steps.into(),
+                responses: VecDeque::new(),
+            }
+        }
+    }
+
+    impl SdoChannel for Script {
+        fn send_sdo(&mut self, message: [u8; 8]) -> Result<(), CanOpenError> {
+            let (expected, responses) = self.steps.pop_front().expect("unexpected request");
+            assert_eq!(&message[..expected.len()], expected.as_slice());
+            self.responses.extend(responses);
+            Ok(())
+        }
+
+        fn recv_sdo(&mut self, _: Duration) -> Result<[u8; 8], CanOpenError> {
+            self.responses.pop_front().ok_or(CanOpenError::Timeout)
+        }
+    }
+
+    fn client(script: &mut Script) -> SdoClient<'_, Script> {
+        SdoClient::new(script, Duration::from_millis(100))
+    }
+
+    #[test]
+    fn crc16_001() {
+        assert_eq!(crc16(b"123456789"), 0x31C3);
+    }
+
+    #[test]
+    fn upload_001() {
+        let mut script = Script::new(vec![
+            (
+                vec![0x40, 0x18, 0x10, 0x01],
+                vec![[0x43, 0x18, 0x10, 0x01, 0x78, 0x56, 0x34, 0x12]],
+            ),
+            (
+                vec![0x40, 0x08, 0x10, 0x00],
+                vec![[0x41, 0x08, 0x10, 0x00, 10, 0, 0, 0]],
+            ),
+            (
+                vec![0x60],
+                vec![[0x00, b'P', b'C', b'A', b'N', b'-', b'U', b'S']],
+            ),
+            (vec![0x70], vec![[0x19, b'B', b'F', b'D', 0, 0, 0, 0]]),
+            (
+                vec![0x40, 0x00, 0x20, 0x00],
+                vec![[0x80, 0x00, 0x20, 0x00, 0x00, 0x00, 0x02, 0x06]],
+            ),
+        ]);
+
+        let mut client = client(&mut script);
+        assert_eq!(client.upload(0x1018, 1), Ok(vec![0x78, 0x56, 0x34, 0x12]));
+        assert_eq!(client.upload(0x1008, 0), Ok(b"PCAN-USBFD".to_vec()));
+        assert_eq!(
+            client.upload(0x2000, 0),
+            Err(CanOpenError::SdoAbort(SdoAbortCode::ObjectDoesNotExist))
+        );
+    }
+
+    #[test]
+    fn upload_002() {
+        // the announced size is not preallocated, the transfer ends with the last segment
+        let mut script = Script::new(vec![
+            (
+                vec![0x40, 0x08, 0x10, 0x00],
+                vec![[0x41, 0x08, 0x10, 0x00, 0xFF, 0xFF, 0xFF, 0xFF]],
+            ),
+            (vec![0x60], vec![[0x0B, b'P', b'C', b'A', b'N', 0, 0, 0]]),
+            (vec![0x80, 0x08, 0x10, 0x00, 0x10, 0x00, 0x07, 0x06], vec![]),
+        ]);
+
+        let mut client = client(&mut script);
+        assert_eq!(client.upload(0x1008, 0), Err(CanOpenError::Protocol));
+    }
+
+    #[test]
+    fn download_001() {
+        let mut script = Script::new(vec![
+            (
+                vec![0x2B, 0x17, 0x10, 0x00, 0xE8, 0x03],
+                vec![[0x60, 0x17, 0x10, 0x00, 0, 0, 0, 0]],
+            ),
+            (
+                vec![0x21, 0x00, 0x20, 0x00, 9, 0, 0, 0],
+                vec![[0x60, 0x00, 0x20, 0x00, 0, 0, 0, 0]],
+            ),
+            (
+                vec![0x00, 1, 2, 3, 4, 5, 6, 7],
+                vec![[0x20, 0, 0, 0, 0, 0, 0, 0]],
+            ),
+            (vec![0x1B, 8, 9], vec![[0x30, 0, 0, 0, 0, 0, 0, 0]]),
+        ]);
+
+        let mut client = client(&mut script);
+        assert_eq!(client.download(0x1017, 0, &1000u16.to_le_bytes()), Ok(()));
+        assert_eq!(
+            client.download(0x2000, 0, &[1, 2, 3, 4, 5, 6, 7, 8, 9]),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn block_transfer_001() {
+        let data = (1..=20u8).collect::<Vec<_>>();
+        let [low, high] = crc16(&data).to_le_bytes();
+
+        let mut script = Script::new(vec![
+            (
+                vec![0xC6, 0x00, 0x20, 0x01, 20, 0, 0, 0],
+                vec![[0xA4, 0x00, 0x20, 0x01, 2, 0, 0, 0]],
+            ),
+            (vec![0x01, 1, 2, 3, 4, 5, 6, 7], vec![]),
+            (
+                vec![0x02, 8, 9, 10, 11, 12, 13, 14],
+                vec![[0xA2, 2, 2, 0, 0, 0, 0, 0]],
+            ),
+            (
+                vec![0x81, 15, 16, 17, 18, 19, 20],
+                vec![[0xA2, 1, 2, 0, 0, 0, 0, 0]],
+            ),
+            (vec![0xC5, low, high], vec![[0xA1, 0, 0, 0, 0, 0, 0, 0]]),
+            (
+                vec![0xA4, 0x00, 0x20, 0x01, 127],
+                vec![[0xC6, 0x00, 0x20, 0x01, 20, 0, 0, 0]],
+            ),
+            (
+                vec![0xA3],
+                vec![
+                    [0x01, 1, 2, 3, 4, 5, 6, 7],
+                    [0x02, 8, 9, 10, 11, 12, 13, 14],
+                    [0x83, 15, 16, 17, 18, 19, 20, 0],
+                ],
+            ),
+            (vec![0xA2, 3, 127], vec![[0xC5, low, high, 0, 0, 0, 0, 0]]),
+            (vec![0xA1], vec![]),
+        ]);
+
+        let mut client = client(&mut script);
+        assert_eq!(client.block_download(0x2000, 1, &data), Ok(()));
+        assert_eq!(client.block_upload(0x2000, 1, 127), Ok(data));
+    }
+
+    #[test]
+    fn block_transfer_002() {
+        let data = (1..=20u8).collect::<Vec<_>>();
+        let [low, high] = crc16(&data).to_le_bytes();
+        let segments = [
+            [0x01, 1, 2, 3, 4, 5, 6, 7],
+            [0x02, 8, 9, 10, 11, 12, 13, 14],
+        ];
+
+        // the first segment of a full block is lost
+        let mut script = Script::new(vec![
+            (
+                vec![0xA4, 0x00, 0x20, 0x01, 2],
+                vec![[0xC6, 0x00, 0x20, 0x01, 20, 0, 0, 0]],
+            ),
+            (vec![0xA3], vec![segments[1]]),
+            (vec![0xA2, 0, 2], segments.to_vec()),
+            (vec![0xA2, 2, 2], vec![[0x81, 15, 16, 17, 18, 19, 20, 0]]),
+            (vec![0xA2, 1, 2], vec![[0xC5, low, high, 0, 0, 0, 0, 0]]),
+            (vec![0xA1], vec![]),
+        ]);
+        assert_eq!(
+            client(&mut script).block_upload(0x2000, 1, 2),
+            Ok(data.clone())
+        );
+
+        // a segment before the last one is lost
+        let mut script = Script::new(vec![
+            (
+                vec![0xA4, 0x00, 0x20, 0x01, 127],
+                vec![[0xC6, 0x00, 0x20, 0x01, 20, 0, 0, 0]],
+            ),
+            (
+                vec![0xA3],
+                vec![segments[0], [0x83, 15, 16, 17, 18, 19, 20, 0]],
+            ),
+            (
+                vec![0xA2, 1, 127],
+                vec![
+                    [0x01, 8, 9, 10, 11, 12, 13, 14],
+                    [0x82, 15, 16, 17, 18, 19, 20, 0],
+                ],
+            ),
+            (vec![0xA2, 2, 127], vec![[0xC5, low, high, 0, 0, 0, 0, 0]]),
+            (vec![0xA1], vec![]),
+        ]);
+        assert_eq!(client(&mut script).block_upload(0x2000, 1, 127), Ok(data));
+    }
+
+    #[test]
+    fn server_001() {
+        let mut od = ObjectDictionary::new();
+        od.insert(
+            0x1017,
+            0,
+            Entry::numeric(
+                "Producer heartbeat time",
+                DataType::Unsigned16,
+                AccessType::ReadWrite,
+                0,
+            ),
+        );
+        let too_high = abort_message(0x1017, 0, SdoAbortCode::LengthTooHigh);
+
+        let mut server = SdoServer::new();
+        for request in [
+            [0x21, 0x17, 0x10, 0x00, 0xFF, 0xFF, 0xFF, 0xFF],
+            [0xC2, 0x17, 0x10, 0x00, 0xFF, 0xFF, 0xFF, 0xFF],
+        ] {
+            assert_eq!(server.handle(&request, &mut od), vec![too_high]);
+        }
+        assert_eq!(
+            server.handle(&[0x21, 0x17, 0x10, 0x00, 2, 0, 0, 0], &mut od),
+            vec![[0x60, 0x17, 0x10, 0x00, 0, 0, 0, 0]]
+        );
+    }
+}
@@ -0,0 +1,28 @@
+//! SYNC producer.
+
+use crate::canopen::COB_SYNC;
+use crate::cyclic::Job;
+use crate::socket::{CanFrame, MessageType};
+use std::time::Duration;
+
+/// Builds a SYNC message, with the optional counter of CiA 301 version 4.1.
+pub fn sync_frame(counter: Option<u8>) -> CanFrame {
+    let data = match counter {
+        Some(counter) => vec![counter],
+        None => vec![],
+    };
+    CanFrame::new(COB_SYNC, MessageType::Standard, &data).expect("a SYNC fits into a frame")
+}
+
+/// Creates a [CyclicScheduler](crate::cyclic::CyclicScheduler) job producing SYNC messages.
+///
+/// With a `counter_overflow` between 2 and 240 the messages carry a counter running from 1 to the
+/// overflow value, else they are empty.
+pub fn sync_job(period: Duration, counter_overflow: Option<u8>) -> Job {
+    match counter_overflow {
+        Some(overflow @ 2..=240) => Job::new(period, move |cycle| {
+            sync_frame(Some((cycle % overflow as u64) as u8 + 1))
+        }),
+        _ => Job::fixed(period, sync_frame(None)),
+    }
+}
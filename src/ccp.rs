@@ -0,0 +1,939 @@
+//! CAN calibration protocol (ASAP1a CCP 2.1) master.
+//!
+//! A [CcpMaster] sends command receive objects (CRO) to a slave and waits for the command return
+//! message (CRM) echoing the command counter, so late answers to earlier commands are ignored. On
+//! a timeout or a busy slave the command is repeated with a new counter. DAQ lists are predefined
+//! by the slave; [configure_daq](CcpMaster::configure_daq) fills their ODTs and
+//! [recv_daq](CcpMaster::recv_daq) decodes the data acquisition messages.
+
+use crate::error::PcanError;
+use crate::socket::{CanFrame, MessageType, RecvCan, SendCan};
+use crate::uds::SeedKey;
+use crate::xcp::{ByteOrder, OdtEntry};
+use std::collections::VecDeque;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, PartialEq)]
+pub enum CcpError {
+    Pcan(PcanError),
+    /// The slave did not answer within the timeout, including all repetitions.
+    Timeout,
+    /// The slave answered with an error code.
+    CommandError {
+        command: u8,
+        code: ErrorCode,
+    },
+    /// The response is too short or malformed.
+    InvalidResponse,
+    /// The command requires a connection established with [connect](CcpMaster::connect).
+    NotConnected,
+    /// The data does not fit into a single command.
+    PayloadTooLarge,
+    /// The DAQ lists do not fit into the DAQ lists of the slave.
+    InvalidDaqConfig,
+}
+
+impl From<PcanError> for CcpError {
+    fn from(value: PcanError) -> Self {
+        CcpError::Pcan(value)
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ErrorCode {
+    DaqProcessorOverload,
+    ProcessorBusy,
+    DaqProcessorBusy,
+    InternalTimeout,
+    KeyRequest,
+    SessionStatusRequest,
+    ColdStartRequest,
+    CalDataInitRequest,
+    DaqListInitRequest,
+    CodeUpdateRequest,
+    UnknownCommand,
+    CommandSyntax,
+    ParameterOutOfRange,
+    AccessDenied,
+    Overload,
+    AccessLocked,
+    ResourceNotAvailable,
+    Other(u8),
+}
+
+impl ErrorCode {
+    /// Whether the command may succeed when repeated (error category C1).
+    pub fn is_busy(&self) -> bool {
+        matches!(
+            self,
+            ErrorCode::ProcessorBusy | ErrorCode::DaqProcessorBusy | ErrorCode::InternalTimeout
+        )
+    }
+}
+
+impl From<u8> for ErrorCode {
+    fn from(value: u8) -> Self {
+        match value {
+            0x01 => ErrorCode::DaqProcessorOverload,
+            0x10 => ErrorCode::ProcessorBusy,
+            0x11 => ErrorCode::DaqProcessorBusy,
+            0x12 => ErrorCode::InternalTimeout,
+            0x18 => ErrorCode::KeyRequest,
+            0x19 => ErrorCode::SessionStatusRequest,
+            0x20 => ErrorCode::ColdStartRequest,
+            0x21 => ErrorCode::CalDataInitRequest,
+            0x22 => ErrorCode::DaqListInitRequest,
+            0x23 => ErrorCode::CodeUpdateRequest,
+            0x30 => ErrorCode::UnknownCommand,
+            0x31 => ErrorCode::CommandSyntax,
+            0x32 => ErrorCode::ParameterOutOfRange,
+            0x33 => ErrorCode::AccessDenied,
+            0x34 => ErrorCode::Overload,
+            0x35 => ErrorCode::AccessLocked,
+            0x36 => ErrorCode::ResourceNotAvailable,
+            _ => ErrorCode::Other(value),
+        }
+    }
+}
+
+impl From<ErrorCode> for u8 {
+    fn from(value: ErrorCode) -> Self {
+        match value {
+            ErrorCode::DaqProcessorOverload => 0x01,
+            ErrorCode::ProcessorBusy => 0x10,
+            ErrorCode::DaqProcessorBusy => 0x11,
+            ErrorCode::InternalTimeout => 0x12,
+            ErrorCode::KeyRequest => 0x18,
+            ErrorCode::SessionStatusRequest => 0x19,
+            ErrorCode::ColdStartRequest => 0x20,
+            ErrorCode::CalDataInitRequest => 0x21,
+            ErrorCode::DaqListInitRequest => 0x22,
+            ErrorCode::CodeUpdateRequest => 0x23,
+            ErrorCode::UnknownCommand => 0x30,
+            ErrorCode::CommandSyntax => 0x31,
+            ErrorCode::ParameterOutOfRange => 0x32,
+            ErrorCode::AccessDenied => 0x33,
+            ErrorCode::Overload => 0x34,
+            ErrorCode::AccessLocked => 0x35,
+            ErrorCode::ResourceNotAvailable => 0x36,
+            ErrorCode::Other(value) => value,
+        }
+    }
+}
+
+/* Commands */
+
+pub const CONNECT: u8 = 0x01;
+pub const SET_MTA: u8 = 0x02;
+pub const DNLOAD: u8 = 0x03;
+pub const UPLOAD: u8 = 0x04;
+pub const START_STOP: u8 = 0x06;
+pub const DISCONNECT: u8 = 0x07;
+pub const START_STOP_ALL: u8 = 0x08;
+pub const SHORT_UP: u8 = 0x0F;
+pub const GET_SEED: u8 = 0x12;
+pub const UNLOCK: u8 = 0x13;
+pub const GET_DAQ_SIZE: u8 = 0x14;
+pub const SET_DAQ_PTR: u8 = 0x15;
+pub const WRITE_DAQ: u8 = 0x16;
+pub const EXCHANGE_ID: u8 = 0x17;
+pub const GET_CCP_VERSION: u8 = 0x1B;
+
+/* Packet identifiers of the slave */
+
+const PID_CRM: u8 = 0xFF;
+const PID_EVENT: u8 = 0xFE;
+
+/* Resources */
+
+pub const RESOURCE_CAL: u8 = 0x01;
+pub const RESOURCE_DAQ: u8 = 0x02;
+pub const RESOURCE_PGM: u8 = 0x40;
+
+/// Result of [exchange_id](CcpMaster::exchange_id). The slave sets MTA0 to its ID, so the ID can
+/// be read with [upload](CcpMaster::upload).
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct SlaveId {
+    pub length: u8,
+    pub data_type: u8,
+    /// Resources available in the slave, see `RESOURCE_*`.
+    pub resource_availability: u8,
+    /// Resources protected by seed and key, see `RESOURCE_*`.
+    pub resource_protection: u8,
+}
+
+/* DAQ lists */
+
+/// Configuration of a DAQ list of the slave.
+///
+/// Each ODT becomes one data acquisition message with up to 7 bytes of data.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DaqList {
+    number: u8,
+    event_channel: u8,
+    prescaler: u16,
+    odts: Vec<Vec<OdtEntry>>,
+}
+
+impl DaqList {
+    /// Creates a configuration of the DAQ list `number`, sampled on `event_channel`.
+    pub fn new(number: u8, event_channel: u8) -> DaqList {
+        DaqList {
+            number,
+            event_channel,
+            prescaler: 1,
+            odts: Vec::new(),
+        }
+    }
+
+    /// Transmits only every n-th sample of the event channel.
+    pub fn with_prescaler(mut self, prescaler: u16) -> DaqList {
+        self.prescaler = prescaler.max(1);
+        self
+    }
+
+    pub fn with_odt(mut self, entries: Vec<OdtEntry>) -> DaqList {
+        self.odts.push(entries);
+        self
+    }
+
+    pub fn number(&self) -> u8 {
+        self.number
+    }
+
+    pub fn odts(&self) -> &[Vec<OdtEntry>] {
+        &self.odts
+    }
+}
+
+/// A decoded data acquisition message.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DaqPacket {
+    /// Number of the DAQ list in the slave.
+    pub daq_list: u8,
+    pub odt: u8,
+    /// Raw values of the ODT entries, in the byte order of the slave.
+    pub values: Vec<Vec<u8>>,
+}
+
+/// Settings of a [CcpMaster].
+#[derive(Debug, PartialEq, Clone)]
+pub struct CcpConfig {
+    cro_id: u32,
+    dto_id: u32,
+    msg_type: MessageType,
+    timeout: Duration,
+    retries: u8,
+    byte_order: ByteOrder,
+}
+
+impl CcpConfig {
+    /// Creates a configuration sending commands with `cro_id` and receiving responses and DAQ
+    /// messages with `dto_id` using 11-bit identifiers.
+    pub fn new(cro_id: u32, dto_id: u32) -> CcpConfig {
+        CcpConfig {
+            cro_id,
+            dto_id,
+            msg_type: MessageType::Standard,
+            timeout: Duration::from_millis(25),
+            retries: 2,
+            byte_order: ByteOrder::Motorola,
+        }
+    }
+
+    pub fn with_message_type(mut self, msg_type: MessageType) -> CcpConfig {
+        self.msg_type = msg_type;
+        self
+    }
+
+    /// Sets how long to wait for a response, 25 ms by default.
+    pub fn with_timeout(mut self, timeout: Duration) -> CcpConfig {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets how often a command is repeated after a timeout or a busy response, 2 by default.
+    pub fn with_retries(mut self, retries: u8) -> CcpConfig {
+        self.retries = retries;
+        self
+    }
+
+    /// Sets the byte order of addresses and values of the slave, Motorola by default.
+    pub fn with_byte_order(mut self, byte_order: ByteOrder) -> CcpConfig {
+        self.byte_order = byte_order;
+        self
+    }
+
+    pub fn cro_id(&self) -> u32 {
+        self.cro_id
+    }
+
+    pub fn dto_id(&self) -> u32 {
+        self.dto_id
+    }
+}
+
+/* CcpMaster */
+
+const POLL_INTERVAL: Duration = Duration::from_micros(100);
+
+/// Layout of a prepared DAQ list.
+struct DaqLayout {
+    number: u8,
+    first_pid: u8,
+    odts: Vec<Vec<u8>>,
+}
+
+/// A CCP master talking to a single slave.
+///
+/// DAQ messages received while waiting for a response are queued for
+/// [recv_daq](CcpMaster::recv_daq); event messages are discarded.
+pub struct CcpMaster<S> {
+    socket: S,
+    config: CcpConfig,
+    counter: u8,
+    station: Option<u16>,
+    daq: Vec<DaqLayout>,
+    packets: VecDeque<DaqPacket>,
+}
+
+impl<S: SendCan + RecvCan> CcpMaster<S> {
+    pub fn new(socket: S, config: CcpConfig) -> CcpMaster<S> {
+        CcpMaster {
+            socket,
+            config,
+            counter: 0,
+            station: None,
+            daq: Vec::new(),
+            packets: VecDeque::new(),
+        }
+    }
+
+    pub fn socket(&self) -> &S {
+        &self.socket
+    }
+
+    pub fn into_socket(self) -> S {
+        self.socket
+    }
+
+    pub fn config(&self) -> &CcpConfig {
+        &self.config
+    }
+
+    /// Station address of the connected slave.
+    pub fn station(&self) -> Option<u16> {
+        self.station
+    }
+
+    fn connected(&self) -> Result<u16, CcpError> {
+        self.station.ok_or(CcpError::NotConnected)
+    }
+
+    /* Transport */
+
+    /// Sends a command, repeating it on timeouts and busy responses, and returns the CRM.
+    fn command(&mut self, command: u8, parameters: &[u8]) -> Result<[u8; 8], CcpError> {
+        let mut attempt = 0;
+        loop {
+            match self.command_once(command, parameters) {
+                Err(CcpError::Timeout) if attempt < self.config.retries => {}
+                Err(CcpError::CommandError { code, .. })
+                    if code.is_busy() && attempt < self.config.retries => {}
+                result => return result,
+            }
+            attempt += 1;
+        }
+    }
+
+    fn command_once(&mut self, command: u8, parameters: &[u8]) -> Result<[u8; 8], CcpError> {
+        self.counter = self.counter.wrapping_add(1);
+        let counter = self.counter;
+
+        let mut data = [0u8; 8];
+        data[0] = command;
+        data[1] = counter;
+        data[2..2 + parameters.len()].copy_from_slice(parameters);
+        let frame = CanFrame::new(self.config.cro_id, self.config.msg_type, &data)
+            .expect("a command fits into a frame");
+        self.socket.send(frame)?;
+
+        let deadline = Instant::now() + self.config.timeout;
+        loop {
+            let frame = match self.socket.recv_frame() {
+                Ok(frame) => frame,
+                Err(PcanError::QrcvEmpty) => {
+                    if Instant::now() >= deadline {
+                        return Err(CcpError::Timeout);
+                    }
+                    thread::sleep(POLL_INTERVAL);
+                    continue;
+                }
+                Err(err) => return Err(CcpError::Pcan(err)),
+            };
+
+            if !self.is_from_slave(&frame) || frame.data().is_empty() {
+                continue;
+            }
+
+            let data = frame.data();
+            match data[0] {
+                // responses to earlier commands are stale
+                PID_CRM if data.len() >= 3 && data[2] == counter => {
+                    let mut response = [0u8; 8];
+                    response[..data.len()].copy_from_slice(data);
+                    return match response[1] {
+                        // a DAQ processor overload is only a warning
+                        0x00 | 0x01 => Ok(response),
+                        code => Err(CcpError::CommandError {
+                            command,
+                            code: ErrorCode::from(code),
+                        }),
+                    };
+                }
+                PID_CRM | PID_EVENT => {}
+                _ => self.queue_daq(data),
+            }
+        }
+    }
+
+    fn is_from_slave(&self, frame: &CanFrame) -> bool {
+        frame.can_id() == self.config.dto_id
+            && frame.is_extended_frame() == (self.config.msg_type == MessageType::Extended)
+    }
+
+    /* Session */
+
+    /// Connects to the slave with the station address `station`.
+    pub fn connect(&mut self, station: u16) -> Result<(), CcpError> {
+        self.command(CONNECT, &station.to_le_bytes())?;
+        self.station = Some(station);
+        Ok(())
+    }
+
+    /// Ends the session with the slave.
+    pub fn disconnect(&mut self) -> Result<(), CcpError> {
+        let [low, high] = self.connected()?.to_le_bytes();
+        self.command(DISCONNECT, &[0x01, 0x00, low, high])?;
+        self.station = None;
+        Ok(())
+    }
+
+    /// Exchanges the protocol version, returning the main version and the release of the slave.
+    pub fn get_ccp_version(&mut self) -> Result<(u8, u8), CcpError> {
+        self.connected()?;
+        let response = self.command(GET_CCP_VERSION, &[2, 1])?;
+        Ok((response[3], response[4]))
+    }
+
+    /// Sends up to 6 bytes identifying the master and returns the description of the slave ID.
+    pub fn exchange_id(&mut self, master_id: &[u8]) -> Result<SlaveId, CcpError> {
+        self.connected()?;
+        if master_id.len() > 6 {
+            return Err(CcpError::PayloadTooLarge);
+        }
+        let response = self.command(EXCHANGE_ID, master_id)?;
+        Ok(SlaveId {
+            length: response[3],
+            data_type: response[4],
+            resource_availability: response[5],
+            resource_protection: response[6],
+        })
+    }
+
+    /* Seed and key */
+
+    /// Unlocks `resource` (one of `RESOURCE_*`) with the key computed by `seed_key` from the
+    /// seed of the slave. The level passed to `seed_key` is the resource. Returns the resources
+    /// unlocked afterwards, or `resource` if it was not protected.
+    pub fn unlock<K: SeedKey>(&mut self, resource: u8, mut seed_key: K) -> Result<u8, CcpError> {
+        self.connected()?;
+        let response = self.command(GET_SEED, &[resource])?;
+        if response[3] == 0 {
+            return Ok(resource);
+        }
+
+        let key = seed_key.key(resource, &response[4..8]);
+        if key.len() > 6 {
+            return Err(CcpError::PayloadTooLarge);
+        }
+        let response = self.command(UNLOCK, &key)?;
+        Ok(response[3])
+    }
+
+    /* Memory */
+
+    /// Sets memory transfer address `mta`, 0 for uploads and downloads or 1 for other commands.
+    pub fn set_mta(&mut self, mta: u8, address: u32, extension: u8) -> Result<(), CcpError> {
+        self.connected()?;
+        let mut parameters = [mta, extension, 0, 0, 0, 0];
+        parameters[2..].copy_from_slice(&self.config.byte_order.encode_u32(address));
+        self.command(SET_MTA, &parameters).map(|_| ())
+    }
+
+    /// Reads `size` bytes from MTA0, which is advanced accordingly.
+    pub fn upload(&mut self, size: usize) -> Result<Vec<u8>, CcpError> {
+        self.connected()?;
+        let mut data = Vec::with_capacity(size);
+        while data.len() < size {
+            let count = (size - data.len()).min(5);
+            let response = self.command(UPLOAD, &[count as u8])?;
+            data.extend_from_slice(&response[3..3 + count]);
+        }
+        Ok(data)
+    }
+
+    /// Reads up to 5 bytes from `address` without changing MTA0.
+    pub fn short_upload(
+        &mut self,
+        address: u32,
+        extension: u8,
+        size: u8,
+    ) -> Result<Vec<u8>, CcpError> {
+        self.connected()?;
+        if size > 5 {
+            return Err(CcpError::PayloadTooLarge);
+        }
+        let mut parameters = [size, extension, 0, 0, 0, 0];
+        parameters[2..].copy_from_slice(&self.config.byte_order.encode_u32(address));
+        let response = self.command(SHORT_UP, &parameters)?;
+        Ok(response[3..3 + size as usize].to_vec())
+    }
+
+    /// Writes `data` to MTA0, which is advanced accordingly.
+    pub fn download(&mut self, data: &[u8]) -> Result<(), CcpError> {
+        self.connected()?;
+        for chunk in data.chunks(5) {
+            let mut parameters = vec![chunk.len() as u8];
+            parameters.extend_from_slice(chunk);
+            self.command(DNLOAD, &parameters)?;
+        }
+        Ok(())
+    }
+
+    /* DAQ */
+
+    /// Fills the DAQ lists of the slave with `lists` and prepares them for
+    /// [start_daq](CcpMaster::start_daq).
+    pub fn configure_daq(&mut self, lists: &[DaqList]) -> Result<(), CcpError> {
+        self.connected()?;
+        for list in lists {
+            for entries in list.odts.iter() {
+                let length = entries
+                    .iter()
+                    .map(|entry| entry.size as usize)
+                    .sum::<usize>();
+                let sizes_valid = entries.iter().all(|entry| matches!(entry.size, 1 | 2 | 4));
+                if entries.is_empty() || length > 7 || !sizes_valid {
+                    return Err(CcpError::InvalidDaqConfig);
+                }
+            }
+            if list.odts.is_empty() {
+                return Err(CcpError::InvalidDaqConfig);
+            }
+        }
+
+        self.daq.clear();
+        let dto_id = self.config.dto_id.to_le_bytes();
+        let mut layouts = Vec::with_capacity(lists.len());
+        for list in lists {
+            // clears the list and reports its size
+            let mut parameters = [list.number, 0, 0, 0, 0, 0];
+            parameters[2..].copy_from_slice(&dto_id);
+            let response = self.command(GET_DAQ_SIZE, &parameters)?;
+            if list.odts.len() > response[3] as usize {
+                return Err(CcpError::InvalidDaqConfig);
+            }
+
+            for (odt, entries) in list.odts.iter().enumerate() {
+                for (element, entry) in entries.iter().enumerate() {
+                    self.command(SET_DAQ_PTR, &[list.number, odt as u8, element as u8])?;
+                    let mut parameters = [entry.size, entry.extension, 0, 0, 0, 0];
+                    parameters[2..]
+                        .copy_from_slice(&self.config.byte_order.encode_u32(entry.address));
+                    self.command(WRITE_DAQ, &parameters)?;
+                }
+            }
+
+            let [prescaler_high, prescaler_low] = list.prescaler.to_be_bytes();
+            self.command(
+                START_STOP,
+                &[
+                    0x02,
+                    list.number,
+                    list.odts.len() as u8 - 1,
+                    list.event_channel,
+                    prescaler_high,
+                    prescaler_low,
+                ],
+            )?;
+
+            layouts.push(DaqLayout {
+                number: list.number,
+                first_pid: response[4],
+                odts: list
+                    .odts
+                    .iter()
+                    .map(|entries| entries.iter().map(|entry| entry.size).collect())
+                    .collect(),
+            });
+        }
+
+        self.daq = layouts;
+        Ok(())
+    }
+
+    /// Starts all prepared DAQ lists synchronously.
+    pub fn start_daq(&mut self) -> Result<(), CcpError> {
+        self.connected()?;
+        self.packets.clear();
+        self.command(START_STOP_ALL, &[0x01]).map(|_| ())
+    }
+
+    /// Stops all DAQ lists.
+    pub fn stop_daq(&mut self) -> Result<(), CcpError> {
+        self.connected()?;
+        self.command(START_STOP_ALL, &[0x00]).map(|_| ())
+    }
+
+    /// Decodes a DAQ message, dropping messages of unknown ODTs.
+    fn queue_daq(&mut self, data: &[u8]) {
+        let pid = data[0];
+        let found = self.daq.iter().find_map(|layout| {
+            let odt = pid.checked_sub(layout.first_pid)? as usize;
+            (odt < layout.odts.len()).then_some((layout, odt))
+        });
+        let (layout, odt) = match found {
+            Some(found) => found,
+            None => return,
+        };
+
+        let mut offset = 1;
+        let mut values = Vec::with_capacity(layout.odts[odt].len());
+        for size in layout.odts[odt].iter() {
+            let size = *size as usize;
+            match data.get(offset..offset + size) {
+                Some(value) => values.push(value.to_vec()),
+                None => return,
+            }
+            offset += size;
+        }
+
+        let packet = DaqPacket {
+            daq_list: layout.number,
+            odt: odt as u8,
+            values,
+        };
+        self.packets.push_back(packet);
+    }
+
+    /// Waits for the next DAQ message.
+    pub fn recv_daq(&mut self, timeout: Duration) -> Result<DaqPacket, CcpError> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            if let Some(packet) = self.packets.pop_front() {
+                return Ok(packet);
+            }
+
+            match self.socket.recv_frame() {
+                Ok(frame) => {
+                    if self.is_from_slave(&frame)
+                        && !frame.data().is_empty()
+                        && frame.data()[0] < PID_EVENT
+                    {
+                        self.queue_daq(frame.data());
+                    }
+                }
+                Err(PcanError::QrcvEmpty) => {
+                    if Instant::now() >= deadline {
+                        return Err(CcpError::Timeout);
+                    }
+                    thread::sleep(POLL_INTERVAL);
+                }
+                Err(err) => return Err(CcpError::Pcan(err)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::socket::pipe::{pipe, PipeSocket};
+    use std::thread::JoinHandle;
+
+    const CRO_ID: u32 = 0x6F0;
+    const DTO_ID: u32 = 0x6F1;
+    const STATION: u16 = 0x0039;
+
+    /// Big endian slave with 256 bytes of memory and two DAQ lists of 4 ODTs, protecting
+    /// calibration with a seed whose key is the inverted seed. The first GET_CCP_VERSION is
+    /// ignored to test repetitions.
+    struct Slave {
+        socket: PipeSocket,
+        memory: Vec<u8>,
+        mta: usize,
+        locked: bool,
+        connected: bool,
+        version_requests: usize,
+        daq: Vec<Vec<Vec<(usize, usize)>>>,
+        pointer: (usize, usize),
+        prepared: Vec<usize>,
+    }
+
+    impl Slave {
+        fn respond(&self, counter: u8, error: u8, data: &[u8]) {
+            let mut response = vec![PID_CRM, error, counter];
+            response.extend_from_slice(data);
+            response.resize(8, 0);
+            self.socket
+                .send(CanFrame::new(DTO_ID, MessageType::Standard, &response).unwrap())
+                .unwrap();
+        }
+
+        fn handle(&mut self, request: &[u8]) -> bool {
+            let (command, counter) = (request[0], request[1]);
+            let address =
+                u32::from_be_bytes([request[4], request[5], request[6], request[7]]) as usize;
+            if command != CONNECT && !self.connected {
+                return true;
+            }
+
+            match command {
+                CONNECT => {
+                    if u16::from_le_bytes([request[2], request[3]]) == STATION {
+                        self.connected = true;
+                        // a stale response with the previous counter
+                        self.respond(counter.wrapping_sub(1), 0x30, &[]);
+                        self.respond(counter, 0, &[]);
+                    }
+                }
+                GET_CCP_VERSION => {
+                    self.version_requests += 1;
+                    if self.version_requests > 1 {
+                        self.respond(counter, 0, &[2, 1]);
+                    }
+                }
+                EXCHANGE_ID => {
+                    self.mta = 0xF0;
+                    self.respond(counter, 0, &[4, 0, 0x03, self.locked as u8]);
+                }
+                GET_SEED => self.respond(counter, 0, &[self.locked as u8, 0x12, 0x34, 0x56, 0x78]),
+                UNLOCK => {
+                    if request[2..6] == [0xED, 0xCB, 0xA9, 0x87] {
+                        self.locked = false;
+                        self.respond(counter, 0, &[RESOURCE_CAL | RESOURCE_DAQ]);
+                    } else {
+                        self.respond(counter, 0x35, &[]);
+                    }
+                }
+                UPLOAD => {
+                    let size = request[2] as usize;
+                    let data = self.memory[self.mta..self.mta + size].to_vec();
+                    self.mta += size;
+                    self.respond(counter, 0, &data);
+                }
+                _ if self.locked => self.respond(counter, 0x35, &[]),
+                SET_MTA => {
+                    self.mta = address;
+                    self.respond(counter, 0, &[]);
+                }
+                SHORT_UP => {
+                    let size = request[2] as usize;
+                    let data = self.memory[address..address + size].to_vec();
+                    self.respond(counter, 0, &data);
+                }
+                DNLOAD => {
+                    let size = request[2] as usize;
+                    self.memory[self.mta..self.mta + size].copy_from_slice(&request[3..3 + size]);
+                    self.mta += size;
+                    self.respond(counter, 0, &[0, 0, 0, 0, self.mta as u8]);
+                }
+                GET_DAQ_SIZE => {
+                    let list = request[2] as usize;
+                    self.daq[list] = vec![Vec::new(); 4];
+                    self.respond(counter, 0, &[4, 4 * list as u8]);
+                }
+                SET_DAQ_PTR => {
+                    self.pointer = (request[2] as usize, request[3] as usize);
+                    self.respond(counter, 0, &[]);
+                }
+                WRITE_DAQ => {
+                    let (list, odt) = self.pointer;
+                    self.daq[list][odt].push((address, request[2] as usize));
+                    self.respond(counter, 0, &[]);
+                }
+                START_STOP => {
+                    self.prepared.push(request[3] as usize);
+                    self.respond(counter, 0, &[]);
+                }
+                START_STOP_ALL => {
+                    self.respond(counter, 0, &[]);
+                    if request[2] == 1 {
+                        for list in self.prepared.iter() {
+                            for (odt, entries) in self.daq[*list].iter().enumerate() {
+                                if entries.is_empty() {
+                                    continue;
+                                }
+                                let mut packet = vec![(4 * list + odt) as u8];
+                                for (address, size) in entries {
+                                    packet
+                                        .extend_from_slice(&self.memory[*address..address + size]);
+                                }
+                                self.socket
+                                    .send(
+                                        CanFrame::new(DTO_ID, MessageType::Standard, &packet)
+                                            .unwrap(),
+                                    )
+                                    .unwrap();
+                            }
+                        }
+                    }
+                }
+                DISCONNECT => {
+                    self.respond(counter, 0, &[]);
+                    return false;
+                }
+                _ => self.respond(counter, 0x30, &[]),
+            }
+            true
+        }
+    }
+
+    fn spawn_slave() -> (CcpMaster<PipeSocket>, JoinHandle<Vec<u8>>) {
+        let (master, slave) = pipe();
+        let config = CcpConfig::new(CRO_ID, DTO_ID).with_timeout(Duration::from_millis(100));
+
+        let handle = thread::spawn(move || {
+            let mut slave = Slave {
+                socket: slave,
+                memory: (0..=255u8).collect(),
+                mta: 0,
+                locked: true,
+                connected: false,
+                version_requests: 0,
+                daq: vec![Vec::new(); 2],
+                pointer: (0, 0),
+                prepared: Vec::new(),
+            };
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while Instant::now() < deadline {
+                match slave.socket.recv_frame() {
+                    Ok(frame) if frame.can_id() == CRO_ID && frame.data().len() == 8 => {
+                        if !slave.handle(frame.data()) {
+                            break;
+                        }
+                    }
+                    _ => thread::sleep(Duration::from_micros(50)),
+                }
+            }
+            slave.memory
+        });
+
+        (CcpMaster::new(master, config), handle)
+    }
+
+    fn key(resource: u8, seed: &[u8]) -> Vec<u8> {
+        assert_eq!(resource, RESOURCE_CAL);
+        seed.iter().map(|byte| !byte).collect()
+    }
+
+    #[test]
+    fn ccp_001() {
+        let (mut master, slave) = spawn_slave();
+        assert_eq!(master.get_ccp_version(), Err(CcpError::NotConnected));
+
+        master.connect(STATION).unwrap();
+        assert_eq!(master.get_ccp_version(), Ok((2, 1)));
+        assert_eq!(
+            master.exchange_id(b"PCAN"),
+            Ok(SlaveId {
+                length: 4,
+                data_type: 0,
+                resource_availability: 0x03,
+                resource_protection: 1
+            })
+        );
+        assert_eq!(master.upload(4), Ok(vec![0xF0, 0xF1, 0xF2, 0xF3]));
+
+        assert_eq!(
+            master.short_upload(0x10, 0, 4),
+            Err(CcpError::CommandError {
+                command: SHORT_UP,
+                code: ErrorCode::AccessLocked
+            })
+        );
+        assert_eq!(
+            master.unlock(RESOURCE_CAL, key),
+            Ok(RESOURCE_CAL | RESOURCE_DAQ)
+        );
+
+        assert_eq!(
+            master.short_upload(0x10, 0, 4),
+            Ok(vec![0x10, 0x11, 0x12, 0x13])
+        );
+        master.set_mta(0, 0x20, 0).unwrap();
+        assert_eq!(master.upload(12), Ok((0x20..0x2C).collect::<Vec<u8>>()));
+        master.set_mta(0, 0x40, 0).unwrap();
+        master.download(&[0xAA; 7]).unwrap();
+        master.disconnect().unwrap();
+
+        let memory = slave.join().unwrap();
+        assert_eq!(
+            &memory[0x3F..0x48],
+            &[0x3F, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0x47]
+        );
+    }
+
+    #[test]
+    fn daq_001() {
+        let (mut master, slave) = spawn_slave();
+        master.connect(STATION).unwrap();
+        master.unlock(RESOURCE_CAL, key).unwrap();
+
+        assert_eq!(
+            master.configure_daq(&[DaqList::new(0, 0).with_odt(vec![OdtEntry::new(0, 0, 3)])]),
+            Err(CcpError::InvalidDaqConfig)
+        );
+        let lists = [
+            DaqList::new(0, 0)
+                .with_odt(vec![OdtEntry::new(0x10, 0, 2), OdtEntry::new(0x20, 0, 4)])
+                .with_odt(vec![OdtEntry::new(0x30, 0, 1)]),
+            DaqList::new(1, 2).with_odt(vec![OdtEntry::new(0x40, 0, 4)]),
+        ];
+        master.configure_daq(&lists).unwrap();
+        master.start_daq().unwrap();
+
+        let timeout = Duration::from_millis(500);
+        assert_eq!(
+            master.recv_daq(timeout),
+            Ok(DaqPacket {
+                daq_list: 0,
+                odt: 0,
+                values: vec![vec![0x10, 0x11], vec![0x20, 0x21, 0x22, 0x23]],
+            })
+        );
+        assert_eq!(
+            master.recv_daq(timeout),
+            Ok(DaqPacket {
+                daq_list: 0,
+                odt: 1,
+                values: vec![vec![0x30]],
+            })
+        );
+        assert_eq!(
+            master.recv_daq(timeout),
+            Ok(DaqPacket {
+                daq_list: 1,
+                odt: 0,
+                values: vec![vec![0x40, 0x41, 0x42, 0x43]],
+            })
+        );
+
+        master.stop_daq().unwrap();
+        master.disconnect().unwrap();
+        slave.join().unwrap();
+    }
+}
@@ -0,0 +1,525 @@
+//! Cyclic transmission of periodic frames.
+//!
+//! A [CyclicScheduler] owns a sending socket and transmits the frames of its [Job]s with a fixed
+//! period. Deadlines are absolute, i.e. the n-th transmission of a job is due at
+//! `start + offset + n * period`, so timing errors do not accumulate. Deadlines that could not be
+//! met are skipped and reported in the [JobStatistics] of the job.
+
+use crate::error::PcanError;
+use crate::socket::{CanFdFrame, CanFrame, SendCan, SendCanFd};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, PartialEq)]
+pub enum CyclicError {
+    UnknownJob,
+    InvalidPeriod,
+    FdNotSupported,
+}
+
+/// Identifies a job registered at a [CyclicScheduler].
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Copy, Clone)]
+pub struct JobId(u64);
+
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub struct JobStatistics {
+    pub sent: u64,
+    /// Number of cycles skipped because their deadline had already passed.
+    pub missed_deadlines: u64,
+    pub send_errors: u64,
+    pub last_error: Option<PcanError>,
+    /// Largest delay between a deadline and the actual transmission.
+    pub max_lateness: Duration,
+}
+
+/// Generators are shared with the scheduler thread, which calls them without holding the lock
+/// of the jobs.
+#[derive(Clone)]
+enum Generator {
+    Can(Arc<Mutex<dyn FnMut(u64) -> CanFrame + Send>>),
+    CanFd(Arc<Mutex<dyn FnMut(u64) -> CanFdFrame + Send>>),
+}
+
+enum Payload {
+    Can(CanFrame),
+    CanFd(CanFdFrame),
+}
+
+/// Periodic transmission of frames produced by a generator callback.
+///
+/// The generator receives the number of the cycle, starting at zero, which makes it easy to
+/// compute alive counters and checksums. It may call the methods of the scheduler, e.g. to stop
+/// its own job.
+pub struct Job {
+    period: Duration,
+    offset: Duration,
+    generator: Generator,
+}
+
+impl Job {
+    pub fn new<G>(period: Duration, generator: G) -> Job
+    where
+        G: FnMut(u64) -> CanFrame + Send + 'static,
+    {
+        Job {
+            period,
+            offset: Duration::ZERO,
+            generator: Generator::Can(Arc::new(Mutex::new(generator))),
+        }
+    }
+
+    pub fn new_fd<G>(period: Duration, generator: G) -> Job
+    where
+        G: FnMut(u64) -> CanFdFrame + Send + 'static,
+    {
+        Job {
+            period,
+            offset: Duration::ZERO,
+            generator: Generator::CanFd(Arc::new(Mutex::new(generator))),
+        }
+    }
+
+    /// Creates a job sending the same frame in every cycle.
+    pub fn fixed(period: Duration, frame: CanFrame) -> Job {
+        Job::new(period, move |_| frame)
+    }
+
+    /// Creates a job sending the same CAN FD frame in every cycle.
+    pub fn fixed_fd(period: Duration, frame: CanFdFrame) -> Job {
+        Job::new_fd(period, move |_| frame)
+    }
+
+    /// Delays the first transmission of the job after it was started.
+    pub fn with_offset(mut self, offset: Duration) -> Job {
+        self.offset = offset;
+        self
+    }
+}
+
+struct Entry {
+    job: Job,
+    running: bool,
+    deadline: Instant,
+    cycle: u64,
+    statistics: JobStatistics,
+}
+
+struct State {
+    jobs: BTreeMap<JobId, Entry>,
+    next_id: u64,
+    shutdown: bool,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    condvar: Condvar,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        lock(&self.state)
+    }
+}
+
+fn lock<T: ?Sized>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+type Transmit<S> = fn(&S, Payload) -> Result<(), PcanError>;
+
+fn transmit_can<S: SendCan>(socket: &S, payload: Payload) -> Result<(), PcanError> {
+    match payload {
+        Payload::Can(frame) => socket.send(frame),
+        Payload::CanFd(_) => Err(PcanError::IllOperation),
+    }
+}
+
+fn transmit_can_fd<S: SendCan + SendCanFd>(socket: &S, payload: Payload) -> Result<(), PcanError> {
+    match payload {
+        Payload::Can(frame) => socket.send(frame),
+        Payload::CanFd(frame) => socket.send_fd(frame),
+    }
+}
+
+/// Default time the scheduler busy-waits before a deadline instead of sleeping.
+const DEFAULT_SPIN: Duration = Duration::from_micros(200);
+
+/// Sends the frames of registered jobs from a background thread.
+pub struct CyclicScheduler<S> {
+    shared: Arc<Shared>,
+    supports_fd: bool,
+    handle: Option<JoinHandle<S>>,
+}
+
+impl<S: SendCan + Send + 'static> CyclicScheduler<S> {
+    /// Creates a scheduler for classic frames.
+    pub fn new(socket: S) -> CyclicScheduler<S> {
+        Self::spawn(socket, transmit_can::<S>, false, DEFAULT_SPIN)
+    }
+}
+
+impl<S: SendCan + SendCanFd + Send + 'static> CyclicScheduler<S> {
+    /// Creates a scheduler for classic and CAN FD frames.
+    pub fn new_fd(socket: S) -> CyclicScheduler<S> {
+        Self::spawn(socket, transmit_can_fd::<S>, true, DEFAULT_SPIN)
+    }
+}
+
+impl<S: Send + 'static> CyclicScheduler<S> {
+    fn spawn(socket: S, transmit: Transmit<S>, supports_fd: bool, spin: Duration) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                jobs: BTreeMap::new(),
+                next_id: 0,
+                shutdown: false,
+            }),
+            condvar: Condvar::new(),
+        });
+
+        let thread_shared = shared.clone();
+        let handle = thread::spawn(move || {
+            run(&thread_shared, &socket, transmit, spin);
+            socket
+        });
+
+        CyclicScheduler {
+            shared,
+            supports_fd,
+            handle: Some(handle),
+        }
+    }
+
+    /// Registers a job and starts it.
+    pub fn add(&self, job: Job) -> Result<JobId, CyclicError> {
+        self.check(&job)?;
+
+        let mut state = self.shared.lock();
+        let id = JobId(state.next_id);
+        state.next_id += 1;
+        state.jobs.insert(
+            id,
+            Entry {
+                deadline: Instant::now() + job.offset,
+                job,
+                running: true,
+                cycle: 0,
+                statistics: JobStatistics::default(),
+            },
+        );
+        drop(state);
+
+        self.shared.condvar.notify_all();
+        Ok(id)
+    }
+
+    /// Starts a stopped job. Its first frame is sent after the offset of the job.
+    pub fn start(&self, id: JobId) -> Result<(), CyclicError> {
+        let mut state = self.shared.lock();
+        let entry = state.jobs.get_mut(&id).ok_or(CyclicError::UnknownJob)?;
+        if !entry.running {
+            entry.running = true;
+            entry.deadline = Instant::now() + entry.job.offset;
+        }
+        drop(state);
+
+        self.shared.condvar.notify_all();
+        Ok(())
+    }
+
+    pub fn stop(&self, id: JobId) -> Result<(), CyclicError> {
+        let mut state = self.shared.lock();
+        let entry = state.jobs.get_mut(&id).ok_or(CyclicError::UnknownJob)?;
+        entry.running = false;
+        Ok(())
+    }
+
+    /// Replaces period, offset and generator of a job. A running job keeps its current deadline.
+    pub fn update(&self, id: JobId, job: Job) -> Result<(), CyclicError> {
+        self.check(&job)?;
+
+        let mut state = self.shared.lock();
+        let entry = state.jobs.get_mut(&id).ok_or(CyclicError::UnknownJob)?;
+        entry.job = job;
+        drop(state);
+
+        self.shared.condvar.notify_all();
+        Ok(())
+    }
+
+    /// Changes the period of a job, starting with the next deadline.
+    pub fn set_period(&self, id: JobId, period: Duration) -> Result<(), CyclicError> {
+        if period.is_zero() {
+            return Err(CyclicError::InvalidPeriod);
+        }
+
+        let mut state = self.shared.lock();
+        let entry = state.jobs.get_mut(&id).ok_or(CyclicError::UnknownJob)?;
+        entry.job.period = period;
+        Ok(())
+    }
+
+    pub fn remove(&self, id: JobId) -> Result<(), CyclicError> {
+        let mut state = self.shared.lock();
+        match state.jobs.remove(&id) {
+            Some(_) => Ok(()),
+            None => Err(CyclicError::UnknownJob),
+        }
+    }
+
+    pub fn is_running(&self, id: JobId) -> Result<bool, CyclicError> {
+        let state = self.shared.lock();
+        let entry = state.jobs.get(&id).ok_or(CyclicError::UnknownJob)?;
+        Ok(entry.running)
+    }
+
+    pub fn statistics(&self, id: JobId) -> Result<JobStatistics, CyclicError> {
+        let state = self.shared.lock();
+        let entry = state.jobs.get(&id).ok_or(CyclicError::UnknownJob)?;
+        Ok(entry.statistics)
+    }
+
+    /// Stops all jobs and hands back the socket.
+    pub fn shutdown(mut self) -> Option<S> {
+        self.signal_shutdown();
+        self.handle.take().and_then(|handle| handle.join().ok())
+    }
+
+    fn check(&self, job: &Job) -> Result<(), CyclicError> {
+        if job.period.is_zero() {
+            return Err(CyclicError::InvalidPeriod);
+        }
+
+        match job.generator {
+            Generator::CanFd(_) if !self.supports_fd => Err(CyclicError::FdNotSupported),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<S> CyclicScheduler<S> {
+    fn signal_shutdown(&self) {
+        self.shared.lock().shutdown = true;
+        self.shared.condvar.notify_all();
+    }
+}
+
+impl<S> Drop for CyclicScheduler<S> {
+    fn drop(&mut self) {
+        self.signal_shutdown();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn run<S>(shared: &Shared, socket: &S, transmit: Transmit<S>, spin: Duration) {
+    let mut state = shared.lock();
+
+    loop {
+        if state.shutdown {
+            return;
+        }
+
+        let next_deadline = state
+            .jobs
+            .values()
+            .filter(|entry| entry.running)
+            .map(|entry| entry.deadline)
+            .min();
+
+        let now = Instant::now();
+        match next_deadline {
+            None => {
+                state = match shared.condvar.wait(state) {
+                    Ok(state) => state,
+                    Err(poisoned) => poisoned.into_inner(),
+                };
+            }
+            Some(deadline) if deadline > now + spin => {
+                let timeout = deadline - now - spin;
+                state = match shared.condvar.wait_timeout(state, timeout) {
+                    Ok((state, _)) => state,
+                    Err(poisoned) => poisoned.into_inner().0,
+                };
+            }
+            Some(deadline) if deadline > now => {
+                drop(state);
+                while Instant::now() < deadline {
+                    std::hint::spin_loop();
+                }
+                state = shared.lock();
+            }
+            Some(_) => {
+                let due = state
+                    .jobs
+                    .iter()
+                    .filter(|(_, entry)| entry.running && entry.deadline <= now)
+                    .map(|(id, _)| *id)
+                    .collect::<Vec<_>>();
+                for id in due {
+                    // the job may have been stopped or removed while the lock was released
+                    let (generator, cycle) = match state.jobs.get_mut(&id) {
+                        Some(entry) if entry.running => advance(entry),
+                        _ => continue,
+                    };
+                    drop(state);
+                    let result = transmit(socket, generate(&generator, cycle));
+                    state = shared.lock();
+                    if let Some(entry) = state.jobs.get_mut(&id) {
+                        record(entry, result);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Moves a due job on to its next deadline. Returns its generator and the number of the cycle
+/// due.
+fn advance(entry: &mut Entry) -> (Generator, u64) {
+    let now = Instant::now();
+    let lateness = now.saturating_duration_since(entry.deadline);
+    if lateness > entry.statistics.max_lateness {
+        entry.statistics.max_lateness = lateness;
+    }
+
+    let cycle = entry.cycle;
+    entry.cycle += 1;
+
+    let period = entry.job.period;
+    entry.deadline += period;
+    if entry.deadline <= now {
+        let behind = now - entry.deadline;
+        let missed = (behind.as_nanos() / period.as_nanos()) as u64 + 1;
+        entry.statistics.missed_deadlines += missed;
+        entry.cycle += missed;
+        entry.deadline += period * missed as u32;
+    }
+    (entry.job.generator.clone(), cycle)
+}
+
+fn generate(generator: &Generator, cycle: u64) -> Payload {
+    match generator {
+        Generator::Can(generator) => Payload::Can((*lock(generator))(cycle)),
+        Generator::CanFd(generator) => Payload::CanFd((*lock(generator))(cycle)),
+    }
+}
+
+fn record(entry: &mut Entry, result: Result<(), PcanError>) {
+    match result {
+        Ok(()) => entry.statistics.sent += 1,
+        Err(err) => {
+            entry.statistics.send_errors += 1;
+            entry.statistics.last_error = Some(err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::socket::MessageType;
+    use std::sync::mpsc;
+
+    struct Recorder {
+        frames: Arc<Mutex<Vec<CanFrame>>>,
+    }
+
+    impl SendCan for Recorder {
+        fn send(&self, frame: CanFrame) -> Result<(), PcanError> {
+            self.frames.lock().unwrap().push(frame);
+            Ok(())
+        }
+    }
+
+    /// Polls `condition` until it holds, failing after a generous deadline.
+    fn wait_until<F: FnMut() -> bool>(mut condition: F) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !condition() {
+            assert!(
+                Instant::now() < deadline,
+                "condition not met before the deadline"
+            );
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn cyclic_scheduler_001() {
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let scheduler = CyclicScheduler::new(Recorder {
+            frames: frames.clone(),
+        });
+
+        let job = Job::new(Duration::from_millis(5), |cycle| {
+            CanFrame::new(0x100, MessageType::Standard, &[cycle as u8]).unwrap()
+        });
+        let id = scheduler.add(job).unwrap();
+        wait_until(|| scheduler.statistics(id).unwrap().sent >= 5);
+        scheduler.stop(id).unwrap();
+
+        let statistics = scheduler.statistics(id).unwrap();
+        let frames = frames.lock().unwrap().clone();
+        assert!(frames.len() >= 5);
+        assert_eq!(statistics.sent, frames.len() as u64);
+        // cycles missed under load are skipped, the others are sent in order
+        assert_eq!(frames[0].data(), &[0]);
+        assert!(frames
+            .windows(2)
+            .all(|pair| pair[0].data()[0] < pair[1].data()[0]));
+    }
+
+    #[test]
+    fn cyclic_scheduler_002() {
+        let scheduler = CyclicScheduler::new(Recorder {
+            frames: Arc::new(Mutex::new(Vec::new())),
+        });
+
+        let frame = CanFdFrame::new(0x100, MessageType::Standard, &[0; 12]).unwrap();
+        assert_eq!(
+            scheduler.add(Job::fixed_fd(Duration::from_millis(10), frame)),
+            Err(CyclicError::FdNotSupported)
+        );
+        assert_eq!(
+            scheduler.add(Job::fixed(Duration::ZERO, CanFrame::default())),
+            Err(CyclicError::InvalidPeriod)
+        );
+        assert_eq!(scheduler.stop(JobId(42)), Err(CyclicError::UnknownJob));
+    }
+
+    #[test]
+    fn cyclic_scheduler_003() {
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let scheduler = Arc::new(CyclicScheduler::new(Recorder {
+            frames: frames.clone(),
+        }));
+
+        // the generator stops its own job, learning its id once it was added
+        let weak = Arc::downgrade(&scheduler);
+        let (id_sender, id_receiver) = mpsc::channel();
+        let mut calls = 0;
+        let job = Job::new(Duration::from_millis(5), move |cycle| {
+            calls += 1;
+            if let Some(scheduler) = weak.upgrade() {
+                if calls == 3 {
+                    scheduler.stop(id_receiver.recv().unwrap()).unwrap();
+                }
+            }
+            CanFrame::new(0x100, MessageType::Standard, &[cycle as u8]).unwrap()
+        });
+        let id = scheduler.add(job).unwrap();
+        id_sender.send(id).unwrap();
+        wait_until(|| scheduler.statistics(id).unwrap().sent >= 3);
+
+        // no transmission follows within a few periods
+        thread::sleep(Duration::from_millis(20));
+        assert!(!scheduler.is_running(id).unwrap());
+        assert_eq!(scheduler.statistics(id).unwrap().sent, 3);
+        assert_eq!(frames.lock().unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,1376 @@
+//! DBC databases describing the messages and signals of a network.
+//!
+//! A [Database] is parsed from the text of a DBC file. It decodes the signals of received frames
+//! into physical values and encodes physical values into frames ready to send.
+//!
+//! Signals may be multiplexed: a signal marked `m3` is only present while the multiplexor of its
+//! message (marked `M`) has the raw value 3. With extended multiplexing (`SG_MUL_VAL_`) a signal
+//! names its multiplexor and the ranges of values selecting it, multiplexors may be multiplexed
+//! themselves (marked `m3M`).
+
+use crate::socket::{CanFdFrame, CanFrame, Frame, FrameConstructionError, MessageType};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Flag of extended identifiers in the message identifiers of DBC files.
+const EXTENDED_FLAG: u32 = 0x8000_0000;
+
+/// Nesting of multiplexors followed before a signal counts as inactive.
+const MAX_MULTIPLEXING_DEPTH: usize = 16;
+
+#[derive(Debug, PartialEq)]
+pub enum DbcError {
+    Io(std::io::ErrorKind),
+    /// A statement of the file could not be parsed.
+    Syntax {
+        line: usize,
+    },
+    UnknownMessage,
+    UnknownSignal(String),
+    /// The physical value lies outside the range of the signal or cannot be represented by it.
+    ValueOutOfRange(String),
+    /// The signal is multiplexed and its multiplexor selects other signals.
+    InactiveSignal(String),
+    FrameConstruction(FrameConstructionError),
+}
+
+impl From<std::io::Error> for DbcError {
+    fn from(value: std::io::Error) -> Self {
+        DbcError::Io(value.kind())
+    }
+}
+
+impl From<FrameConstructionError> for DbcError {
+    fn from(value: FrameConstructionError) -> Self {
+        DbcError::FrameConstruction(value)
+    }
+}
+
+/* Database */
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ByteOrder {
+    /// Intel, the start bit is the least significant bit.
+    LittleEndian,
+    /// Motorola, the start bit is the most significant bit.
+    BigEndian,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ValueType {
+    Unsigned,
+    Signed,
+    /// IEEE 754 single precision, set by `SIG_VALTYPE_`.
+    Float32,
+    /// IEEE 754 double precision, set by `SIG_VALTYPE_`.
+    Float64,
+}
+
+/// Condition of a multiplexed signal.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Multiplexed {
+    /// Name of the multiplexor.
+    pub multiplexor: String,
+    /// Ranges of raw multiplexor values selecting the signal, bounds included.
+    pub ranges: Vec<(u64, u64)>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum AttributeValue {
+    Int(i64),
+    Float(f64),
+    /// Strings and the entries of enumerations.
+    String(String),
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum AttributeObject {
+    Network,
+    Node,
+    Message,
+    Signal,
+    EnvironmentVariable,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum AttributeKind {
+    Int { min: i64, max: i64 },
+    Hex { min: i64, max: i64 },
+    Float { min: f64, max: f64 },
+    String,
+    Enum(Vec<String>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct AttributeDefinition {
+    pub object: AttributeObject,
+    pub name: String,
+    pub kind: AttributeKind,
+    pub default: Option<AttributeValue>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Node {
+    pub name: String,
+    pub comment: Option<String>,
+    pub attributes: BTreeMap<String, AttributeValue>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Signal {
+    pub name: String,
+    pub start_bit: u16,
+    pub length: u16,
+    pub byte_order: ByteOrder,
+    pub value_type: ValueType,
+    pub factor: f64,
+    pub offset: f64,
+    pub minimum: f64,
+    pub maximum: f64,
+    pub unit: String,
+    pub receivers: Vec<String>,
+    /// `true` if the signal selects other signals of its message.
+    pub multiplexor: bool,
+    pub multiplexed: Option<Multiplexed>,
+    /// Labels of raw values.
+    pub values: BTreeMap<i64, String>,
+    pub comment: Option<String>,
+    pub attributes: BTreeMap<String, AttributeValue>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Message {
+    pub id: u32,
+    pub extended: bool,
+    pub name: String,
+    /// Length of the data in bytes.
+    pub size: u8,
+    pub transmitter: String,
+    pub signals: Vec<Signal>,
+    pub comment: Option<String>,
+    pub attributes: BTreeMap<String, AttributeValue>,
+}
+
+/// A decoded signal.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SignalValue<'a> {
+    pub signal: &'a Signal,
+    pub raw: u64,
+    /// Physical value, i.e. the raw value scaled by factor and offset.
+    pub value: f64,
+}
+
+impl SignalValue<'_> {
+    pub fn name(&self) -> &str {
+        &self.signal.name
+    }
+
+    pub fn unit(&self) -> &str {
+        &self.signal.unit
+    }
+
+    /// Label of the raw value in the value table of the signal.
+    pub fn label(&self) -> Option<&str> {
+        self.signal.label(self.raw)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Database {
+    pub version: String,
+    pub nodes: Vec<Node>,
+    pub messages: Vec<Message>,
+    pub value_tables: BTreeMap<String, BTreeMap<i64, String>>,
+    pub attribute_definitions: Vec<AttributeDefinition>,
+    /// Attributes of the network.
+    pub attributes: BTreeMap<String, AttributeValue>,
+    pub comment: Option<String>,
+}
+
+impl Signal {
+    /// Bit positions of the signal, least significant bit first. Bit `n` is bit `n % 8` of byte
+    /// `n / 8`.
+    fn bit_positions(&self) -> Vec<usize> {
+        let start = self.start_bit as usize;
+        let length = self.length as usize;
+        match self.byte_order {
+            ByteOrder::LittleEndian => (start..start + length).collect(),
+            ByteOrder::BigEndian => {
+                let mut positions = Vec::with_capacity(length);
+                let mut position = start;
+                for _ in 0..length {
+                    positions.push(position);
+                    position = match position % 8 {
+                        0 => position + 15,
+                        _ => position - 1,
+                    };
+                }
+                positions.reverse();
+                positions
+            }
+        }
+    }
+
+    /// Raw value of the signal in `data`, `None` if `data` is too short.
+    pub fn raw(&self, data: &[u8]) -> Option<u64> {
+        let mut raw = 0u64;
+        for (index, position) in self.bit_positions().into_iter().enumerate().take(64) {
+            let bit = (data.get(position / 8)? >> (position % 8)) & 1;
+            raw |= (bit as u64) << index;
+        }
+        Some(raw)
+    }
+
+    /// Physical value of the signal in `data`, `None` if `data` is too short.
+    pub fn decode(&self, data: &[u8]) -> Option<f64> {
+        self.raw(data).map(|raw| self.to_physical(raw))
+    }
+
+    fn to_signed(&self, raw: u64) -> i64 {
+        match self.length {
+            0 => 0,
+            1..=63 if raw >> (self.length - 1) & 1 == 1 => (raw | !0 << self.length) as i64,
+            _ => raw as i64,
+        }
+    }
+
+    pub fn to_physical(&self, raw: u64) -> f64 {
+        let value = match self.value_type {
+            ValueType::Unsigned => raw as f64,
+            ValueType::Signed => self.to_signed(raw) as f64,
+            ValueType::Float32 => f32::from_bits(raw as u32) as f64,
+            ValueType::Float64 => f64::from_bits(raw),
+        };
+        value * self.factor + self.offset
+    }
+
+    /// Raw value of a physical value, `None` if it does not fit into the signal. Integers are
+    /// rounded to the nearest raw value.
+    pub fn to_raw(&self, value: f64) -> Option<u64> {
+        let scaled = (value - self.offset) / self.factor;
+        if !scaled.is_finite() {
+            return None;
+        }
+        let length = self.length.min(64) as i32;
+        match self.value_type {
+            ValueType::Float32 => Some((scaled as f32).to_bits() as u64),
+            ValueType::Float64 => Some(scaled.to_bits()),
+            ValueType::Unsigned => {
+                let raw = scaled.round();
+                (raw >= 0.0 && raw < 2f64.powi(length)).then_some(raw as u64)
+            }
+            ValueType::Signed => {
+                let raw = scaled.round();
+                let limit = 2f64.powi(length - 1);
+                let mask = match length {
+                    64 => u64::MAX,
+                    _ => (1 << length) - 1,
+                };
+                (raw >= -limit && raw < limit).then_some(raw as i64 as u64 & mask)
+            }
+        }
+    }
+
+    /// Writes a raw value into `data`, `None` if `data` is too short.
+    fn insert(&self, data: &mut [u8], raw: u64) -> Option<()> {
+        for (index, position) in self.bit_positions().into_iter().enumerate().take(64) {
+            let byte = data.get_mut(position / 8)?;
+            let mask = 1 << (position % 8);
+            match raw >> index & 1 {
+                1 => *byte |= mask,
+                _ => *byte &= !mask,
+            }
+        }
+        Some(())
+    }
+
+    /// Label of a raw value in the value table of the signal.
+    pub fn label(&self, raw: u64) -> Option<&str> {
+        let key = match self.value_type {
+            ValueType::Signed => self.to_signed(raw),
+            _ => raw as i64,
+        };
+        self.values.get(&key).map(String::as_str)
+    }
+}
+
+impl Message {
+    pub fn signal(&self, name: &str) -> Option<&Signal> {
+        self.signals.iter().find(|signal| signal.name == name)
+    }
+
+    fn is_active(&self, signal: &Signal, data: &[u8], depth: usize) -> bool {
+        let multiplexed = match &signal.multiplexed {
+            Some(multiplexed) => multiplexed,
+            None => return true,
+        };
+        let multiplexor = match self.signal(&multiplexed.multiplexor) {
+            Some(multiplexor) if depth < MAX_MULTIPLEXING_DEPTH => multiplexor,
+            _ => return false,
+        };
+        match multiplexor.raw(data) {
+            Some(raw) => {
+                multiplexed
+                    .ranges
+                    .iter()
+                    .any(|(min, max)| (*min..=*max).contains(&raw))
+                    && self.is_active(multiplexor, data, depth + 1)
+            }
+            None => false,
+        }
+    }
+
+    /// Decodes the signals present in `data`, skipping signals beyond its end.
+    pub fn decode(&self, data: &[u8]) -> Vec<SignalValue<'_>> {
+        self.signals
+            .iter()
+            .filter(|signal| self.is_active(signal, data, 0))
+            .filter_map(|signal| {
+                signal.raw(data).map(|raw| SignalValue {
+                    signal,
+                    raw,
+                    value: signal.to_physical(raw),
+                })
+            })
+            .collect()
+    }
+
+    /// Encodes physical values into the data of the message. Signals without a value are zero.
+    pub fn encode(&self, values: &[(&str, f64)]) -> Result<Vec<u8>, DbcError> {
+        let mut data = vec![0; self.size as usize];
+        let mut signals = Vec::with_capacity(values.len());
+        for (name, value) in values {
+            let signal = self
+                .signal(name)
+                .ok_or_else(|| DbcError::UnknownSignal(name.to_string()))?;
+            let out_of_range = || DbcError::ValueOutOfRange(name.to_string());
+            if signal.minimum < signal.maximum && !(signal.minimum..=signal.maximum).contains(value)
+            {
+                return Err(out_of_range());
+            }
+            let raw = signal.to_raw(*value).ok_or_else(out_of_range)?;
+            signal.insert(&mut data, raw).ok_or_else(out_of_range)?;
+            signals.push((name, signal));
+        }
+        for (name, signal) in signals {
+            if !self.is_active(signal, &data, 0) {
+                return Err(DbcError::InactiveSignal(name.to_string()));
+            }
+        }
+        Ok(data)
+    }
+
+    fn message_type(&self) -> MessageType {
+        match self.extended {
+            true => MessageType::Extended,
+            false => MessageType::Standard,
+        }
+    }
+}
+
+impl Database {
+    /// Reads a DBC file, which is usually encoded in Windows-1252 rather than UTF-8.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Database, DbcError> {
+        let bytes = std::fs::read(path)?;
+        let text = match String::from_utf8(bytes) {
+            Ok(text) => text,
+            // Latin-1 agrees with Windows-1252 apart from rarely used characters
+            Err(err) => err.into_bytes().into_iter().map(char::from).collect(),
+        };
+        text.parse()
+    }
+
+    pub fn message(&self, id: u32, extended: bool) -> Option<&Message> {
+        self.messages
+            .iter()
+            .find(|message| message.id == id && message.extended == extended)
+    }
+
+    pub fn message_by_name(&self, name: &str) -> Option<&Message> {
+        self.messages.iter().find(|message| message.name == name)
+    }
+
+    pub fn node(&self, name: &str) -> Option<&Node> {
+        self.nodes.iter().find(|node| node.name == name)
+    }
+
+    /// Default value of an attribute as defined by `BA_DEF_DEF_`.
+    pub fn attribute_default(&self, name: &str) -> Option<&AttributeValue> {
+        self.attribute_definitions
+            .iter()
+            .find(|definition| definition.name == name)
+            .and_then(|definition| definition.default.as_ref())
+    }
+
+    /// Attribute of a message, its default if the message does not set it.
+    pub fn message_attribute<'a>(
+        &'a self,
+        message: &'a Message,
+        name: &str,
+    ) -> Option<&'a AttributeValue> {
+        message
+            .attributes
+            .get(name)
+            .or_else(|| self.attribute_default(name))
+    }
+
+    /// Decodes the signals of a received frame.
+    pub fn decode<F: Frame>(&self, frame: &F) -> Result<Vec<SignalValue<'_>>, DbcError> {
+        let message = self
+            .message(frame.can_id(), frame.is_extended_frame())
+            .ok_or(DbcError::UnknownMessage)?;
+        Ok(message.decode(frame.data()))
+    }
+
+    /// Encodes physical values into a classic frame of the message `name`, see [Message::encode].
+    pub fn encode(&self, name: &str, values: &[(&str, f64)]) -> Result<CanFrame, DbcError> {
+        let message = self.message_by_name(name).ok_or(DbcError::UnknownMessage)?;
+        let data = message.encode(values)?;
+        Ok(CanFrame::new(message.id, message.message_type(), &data)?)
+    }
+
+    /// Encodes physical values into a CAN FD frame of the message `name`, see [Message::encode].
+    /// Bit rate switching is enabled if the `VFrameFormat` of the message asks for it.
+    pub fn encode_fd(&self, name: &str, values: &[(&str, f64)]) -> Result<CanFdFrame, DbcError> {
+        let message = self.message_by_name(name).ok_or(DbcError::UnknownMessage)?;
+        let data = message.encode(values)?;
+        let mut frame = CanFdFrame::new(message.id, message.message_type(), &data)?;
+        if let Some(AttributeValue::String(format)) =
+            self.message_attribute(message, "VFrameFormat")
+        {
+            frame.set_brs(format.ends_with("_FD") && !format.contains("NoBRS"));
+        }
+        Ok(frame)
+    }
+}
+
+impl FromStr for Database {
+    type Err = DbcError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            position: 0,
+            database: Database::default(),
+        };
+        parser.parse()?;
+        Ok(parser.database)
+    }
+}
+
+/* Tokens */
+
+#[derive(Debug, PartialEq, Clone)]
+enum Token {
+    Ident(String),
+    Number(String),
+    Text(String),
+    Punct(char),
+}
+
+fn tokenize(text: &str) -> Result<Vec<(Token, usize)>, DbcError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line = 1;
+    while let Some(&c) = chars.peek() {
+        let start_line = line;
+        let token = match c {
+            '\n' => {
+                line += 1;
+                chars.next();
+                continue;
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            }
+            '"' => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c) => value.push(c),
+                            None => return Err(DbcError::Syntax { line }),
+                        },
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            value.push(c)
+                        }
+                        None => return Err(DbcError::Syntax { line: start_line }),
+                    }
+                }
+                Token::Text(value.replace("\r\n", "\n"))
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut value = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_') {
+                    value.push(c);
+                }
+                Token::Ident(value)
+            }
+            c if c.is_ascii_digit() || c == '-' || c == '+' || c == '.' => {
+                let mut value = String::new();
+                value.push(c);
+                chars.next();
+                let is_number = value.starts_with(|c: char| c.is_ascii_digit())
+                    || chars
+                        .peek()
+                        .is_some_and(|c| c.is_ascii_digit() || *c == '.');
+                if !is_number {
+                    Token::Punct(c)
+                } else {
+                    while let Some(c) = chars.next_if(|c| {
+                        c.is_ascii_digit()
+                            || *c == '.'
+                            || *c == 'e'
+                            || *c == 'E'
+                            || ((*c == '-' || *c == '+') && value.ends_with(['e', 'E']))
+                    }) {
+                        value.push(c);
+                    }
+                    Token::Number(value)
+                }
+            }
+            c => {
+                chars.next();
+                Token::Punct(c)
+            }
+        };
+        tokens.push((token, start_line));
+    }
+    Ok(tokens)
+}
+
+/* Parser */
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    database: Database,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(token, _)| token)
+    }
+
+    fn peek_ident(&self, ident: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(value)) if value == ident)
+    }
+
+    fn peek_punct(&self, punct: char) -> bool {
+        self.peek() == Some(&Token::Punct(punct))
+    }
+
+    /// Line of the next token, or of the last one at the end.
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .or(self.tokens.last())
+            .map_or(1, |(_, line)| *line)
+    }
+
+    fn error(&self) -> DbcError {
+        DbcError::Syntax { line: self.line() }
+    }
+
+    fn next(&mut self) -> Result<Token, DbcError> {
+        match self.tokens.get(self.position) {
+            Some((token, _)) => {
+                self.position += 1;
+                Ok(token.clone())
+            }
+            None => Err(self.error()),
+        }
+    }
+
+    fn punct(&mut self, punct: char) -> Result<(), DbcError> {
+        match self.peek_punct(punct) {
+            true => {
+                self.position += 1;
+                Ok(())
+            }
+            false => Err(self.error()),
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, DbcError> {
+        match self.peek() {
+            Some(Token::Ident(value)) => {
+                let value = value.clone();
+                self.position += 1;
+                Ok(value)
+            }
+            _ => Err(self.error()),
+        }
+    }
+
+    fn text(&mut self) -> Result<String, DbcError> {
+        match self.peek() {
+            Some(Token::Text(value)) => {
+                let value = value.clone();
+                self.position += 1;
+                Ok(value)
+            }
+            _ => Err(self.error()),
+        }
+    }
+
+    fn number<T: FromStr>(&mut self) -> Result<T, DbcError> {
+        let value = match self.peek() {
+            Some(Token::Number(value)) => value.parse().map_err(|_| self.error())?,
+            _ => return Err(self.error()),
+        };
+        self.position += 1;
+        Ok(value)
+    }
+
+    /// Skips to the end of the statement, i.e. past the next `;`.
+    fn skip_statement(&mut self) -> Result<(), DbcError> {
+        while self.next()? != Token::Punct(';') {}
+        Ok(())
+    }
+
+    /// Skips the tokens on the line of the previous token.
+    fn skip_line(&mut self) {
+        let line = self.tokens[self.position - 1].1;
+        while self.position < self.tokens.len() && self.tokens[self.position].1 == line {
+            self.position += 1;
+        }
+    }
+
+    fn on_line(&self, line: usize) -> bool {
+        matches!(self.tokens.get(self.position), Some((_, next)) if *next == line)
+    }
+
+    fn message_mut(&mut self, id: u32) -> Result<&mut Message, DbcError> {
+        let error = self.error();
+        let extended = id & EXTENDED_FLAG != 0;
+        let id = id & !EXTENDED_FLAG;
+        self.database
+            .messages
+            .iter_mut()
+            .find(|message| message.id == id && message.extended == extended)
+            .ok_or(error)
+    }
+
+    fn signal_mut(&mut self, id: u32, name: &str) -> Result<&mut Signal, DbcError> {
+        let error = self.error();
+        self.message_mut(id)?
+            .signals
+            .iter_mut()
+            .find(|signal| signal.name == name)
+            .ok_or(error)
+    }
+
+    fn node_mut(&mut self, name: &str) -> Result<&mut Node, DbcError> {
+        let error = self.error();
+        self.database
+            .nodes
+            .iter_mut()
+            .find(|node| node.name == name)
+            .ok_or(error)
+    }
+
+    fn parse(&mut self) -> Result<(), DbcError> {
+        while let Some(token) = self.peek() {
+            let keyword = match token {
+                Token::Ident(keyword) => keyword.clone(),
+                _ => return Err(self.error()),
+            };
+            self.position += 1;
+            match keyword.as_str() {
+                "VERSION" => self.database.version = self.text()?,
+                "NS_" => {
+                    // lists the keywords in use, up to the bit timing
+                    while self.peek().is_some()
+                        && !["BS_", "BU_", "BO_"]
+                            .iter()
+                            .any(|ident| self.peek_ident(ident))
+                    {
+                        self.position += 1;
+                    }
+                }
+                "BS_" => self.skip_line(),
+                "BU_" => self.parse_nodes()?,
+                "VAL_TABLE_" => self.parse_value_table()?,
+                "BO_" => self.parse_message()?,
+                "CM_" => self.parse_comment()?,
+                "BA_DEF_" => self.parse_attribute_definition()?,
+                "BA_DEF_DEF_" => self.parse_attribute_default()?,
+                "BA_" => self.parse_attribute()?,
+                "VAL_" => self.parse_values()?,
+                "SIG_VALTYPE_" => self.parse_value_type()?,
+                "SG_MUL_VAL_" => self.parse_multiplexing()?,
+                _ => self.skip_statement()?,
+            }
+        }
+        self.resolve_multiplexors();
+        Ok(())
+    }
+
+    fn parse_nodes(&mut self) -> Result<(), DbcError> {
+        let line = self.tokens[self.position - 1].1;
+        self.punct(':')?;
+        while self.on_line(line) {
+            let name = self.ident()?;
+            self.database.nodes.push(Node {
+                name,
+                comment: None,
+                attributes: BTreeMap::new(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Parses pairs of raw values and labels up to the end of the statement.
+    fn parse_labels(&mut self) -> Result<BTreeMap<i64, String>, DbcError> {
+        let mut labels = BTreeMap::new();
+        while !self.peek_punct(';') {
+            let value = self.number::<f64>()? as i64;
+            labels.insert(value, self.text()?);
+        }
+        self.punct(';')?;
+        Ok(labels)
+    }
+
+    fn parse_value_table(&mut self) -> Result<(), DbcError> {
+        let name = self.ident()?;
+        let labels = self.parse_labels()?;
+        self.database.value_tables.insert(name, labels);
+        Ok(())
+    }
+
+    fn parse_message(&mut self) -> Result<(), DbcError> {
+        let id = self.number::<u32>()?;
+        let name = self.ident()?;
+        self.punct(':')?;
+        let size = self.number()?;
+        let transmitter = self.ident()?;
+
+        let mut signals = Vec::new();
+        while self.peek_ident("SG_") {
+            self.position += 1;
+            signals.push(self.parse_signal()?);
+        }
+        self.database.messages.push(Message {
+            id: id & !EXTENDED_FLAG,
+            extended: id & EXTENDED_FLAG != 0,
+            name,
+            size,
+            transmitter,
+            signals,
+            comment: None,
+            attributes: BTreeMap::new(),
+        });
+        Ok(())
+    }
+
+    fn parse_signal(&mut self) -> Result<Signal, DbcError> {
+        let line = self.tokens[self.position - 1].1;
+        let error = self.error();
+        let name = self.ident()?;
+
+        let mut multiplexor = false;
+        let mut multiplexed = None;
+        if !self.peek_punct(':') {
+            let indicator = self.ident()?;
+            if let Some(value) = indicator.strip_prefix('m') {
+                let value = value.strip_suffix('M').map_or(value, |value| {
+                    multiplexor = true;
+                    value
+                });
+                let value = value.parse().map_err(|_| error)?;
+                // the multiplexor is resolved once the message is complete
+                multiplexed = Some(Multiplexed {
+                    multiplexor: String::new(),
+                    ranges: vec![(value, value)],
+                });
+            } else if indicator == "M" {
+                multiplexor = true;
+            } else {
+                return Err(self.error());
+            }
+        }
+
+        self.punct(':')?;
+        let start_bit = self.number()?;
+        self.punct('|')?;
+        let length = self.number()?;
+        self.punct('@')?;
+        let byte_order = match self.number::<u8>()? {
+            0 => ByteOrder::BigEndian,
+            1 => ByteOrder::LittleEndian,
+            _ => return Err(self.error()),
+        };
+        let value_type = match self.next()? {
+            Token::Punct('+') => ValueType::Unsigned,
+            Token::Punct('-') => ValueType::Signed,
+            _ => return Err(self.error()),
+        };
+        self.punct('(')?;
+        let factor = self.number()?;
+        self.punct(',')?;
+        let offset = self.number()?;
+        self.punct(')')?;
+        self.punct('[')?;
+        let minimum = self.number()?;
+        self.punct('|')?;
+        let maximum = self.number()?;
+        self.punct(']')?;
+        let unit = self.text()?;
+
+        let mut receivers = Vec::new();
+        while self.on_line(line) {
+            receivers.push(self.ident()?);
+            if self.on_line(line) {
+                self.punct(',')?;
+            }
+        }
+
+        Ok(Signal {
+            name,
+            start_bit,
+            length,
+            byte_order,
+            value_type,
+            factor,
+            offset,
+            minimum,
+            maximum,
+            unit,
+            receivers,
+            multiplexor,
+            multiplexed,
+            values: BTreeMap::new(),
+            comment: None,
+            attributes: BTreeMap::new(),
+        })
+    }
+
+    fn parse_comment(&mut self) -> Result<(), DbcError> {
+        match self.peek() {
+            Some(Token::Text(_)) => self.database.comment = Some(self.text()?),
+            Some(Token::Ident(object)) => match object.as_str() {
+                "BU_" => {
+                    self.position += 1;
+                    let name = self.ident()?;
+                    let comment = self.text()?;
+                    self.node_mut(&name)?.comment = Some(comment);
+                }
+                "BO_" => {
+                    self.position += 1;
+                    let id = self.number()?;
+                    let comment = self.text()?;
+                    self.message_mut(id)?.comment = Some(comment);
+                }
+                "SG_" => {
+                    self.position += 1;
+                    let id = self.number()?;
+                    let name = self.ident()?;
+                    let comment = self.text()?;
+                    self.signal_mut(id, &name)?.comment = Some(comment);
+                }
+                _ => return self.skip_statement(),
+            },
+            _ => return Err(self.error()),
+        }
+        self.punct(';')
+    }
+
+    fn parse_attribute_definition(&mut self) -> Result<(), DbcError> {
+        let object = match self.peek() {
+            Some(Token::Ident(object)) => {
+                let object = match object.as_str() {
+                    "BU_" => AttributeObject::Node,
+                    "BO_" => AttributeObject::Message,
+                    "SG_" => AttributeObject::Signal,
+                    "EV_" => AttributeObject::EnvironmentVariable,
+                    _ => return Err(self.error()),
+                };
+                self.position += 1;
+                object
+            }
+            _ => AttributeObject::Network,
+        };
+        let name = self.text()?;
+        let kind = match self.ident()?.as_str() {
+            "INT" => AttributeKind::Int {
+                min: self.number()?,
+                max: self.number()?,
+            },
+            "HEX" => AttributeKind::Hex {
+                min: self.number()?,
+                max: self.number()?,
+            },
+            "FLOAT" => AttributeKind::Float {
+                min: self.number()?,
+                max: self.number()?,
+            },
+            "STRING" => AttributeKind::String,
+            "ENUM" => {
+                let mut entries = Vec::new();
+                while !self.peek_punct(';') {
+                    entries.push(self.text()?);
+                    if !self.peek_punct(';') {
+                        self.punct(',')?;
+                    }
+                }
+                AttributeKind::Enum(entries)
+            }
+            _ => return Err(self.error()),
+        };
+        self.punct(';')?;
+        self.database
+            .attribute_definitions
+            .push(AttributeDefinition {
+                object,
+                name,
+                kind,
+                default: None,
+            });
+        Ok(())
+    }
+
+    fn attribute_kind(&self, name: &str) -> Option<AttributeKind> {
+        self.database
+            .attribute_definitions
+            .iter()
+            .find(|definition| definition.name == name)
+            .map(|definition| definition.kind.clone())
+    }
+
+    /// Parses a value, enumerations given by index are resolved to their entries.
+    fn parse_attribute_value(&mut self, name: &str) -> Result<AttributeValue, DbcError> {
+        if let Some(Token::Text(_)) = self.peek() {
+            return Ok(AttributeValue::String(self.text()?));
+        }
+        let value = self.number::<f64>()?;
+        Ok(match self.attribute_kind(name) {
+            Some(AttributeKind::Float { .. }) => AttributeValue::Float(value),
+            Some(AttributeKind::Enum(entries)) => match entries.get(value as usize) {
+                Some(entry) => AttributeValue::String(entry.clone()),
+                None => AttributeValue::Int(value as i64),
+            },
+            Some(_) => AttributeValue::Int(value as i64),
+            None if value.fract() == 0.0 => AttributeValue::Int(value as i64),
+            None => AttributeValue::Float(value),
+        })
+    }
+
+    fn parse_attribute_default(&mut self) -> Result<(), DbcError> {
+        let name = self.text()?;
+        let value = self.parse_attribute_value(&name)?;
+        self.punct(';')?;
+        if let Some(definition) = self
+            .database
+            .attribute_definitions
+            .iter_mut()
+            .find(|definition| definition.name == name)
+        {
+            definition.default = Some(value);
+        }
+        Ok(())
+    }
+
+    fn parse_attribute(&mut self) -> Result<(), DbcError> {
+        let name = self.text()?;
+        let object = match self.peek() {
+            Some(Token::Ident(object)) => object.clone(),
+            _ => String::new(),
+        };
+        match object.as_str() {
+            "BU_" => {
+                self.position += 1;
+                let node = self.ident()?;
+                let value = self.parse_attribute_value(&name)?;
+                self.node_mut(&node)?.attributes.insert(name, value);
+            }
+            "BO_" => {
+                self.position += 1;
+                let id = self.number()?;
+                let value = self.parse_attribute_value(&name)?;
+                self.message_mut(id)?.attributes.insert(name, value);
+            }
+            "SG_" => {
+                self.position += 1;
+                let id = self.number()?;
+                let signal = self.ident()?;
+                let value = self.parse_attribute_value(&name)?;
+                self.signal_mut(id, &signal)?.attributes.insert(name, value);
+            }
+            "EV_" => return self.skip_statement(),
+            _ => {
+                let value = self.parse_attribute_value(&name)?;
+                self.database.attributes.insert(name, value);
+            }
+        }
+        self.punct(';')
+    }
+
+    fn parse_values(&mut self) -> Result<(), DbcError> {
+        let id = match self.peek() {
+            Some(Token::Number(_)) => self.number()?,
+            // labels of an environment variable
+            _ => return self.skip_statement(),
+        };
+        let name = self.ident()?;
+        let labels = self.parse_labels()?;
+        self.signal_mut(id, &name)?.values = labels;
+        Ok(())
+    }
+
+    fn parse_value_type(&mut self) -> Result<(), DbcError> {
+        let id = self.number()?;
+        let name = self.ident()?;
+        if self.peek_punct(':') {
+            self.position += 1;
+        }
+        let value_type = match self.number::<u8>()? {
+            1 => ValueType::Float32,
+            2 => ValueType::Float64,
+            _ => return self.skip_statement(),
+        };
+        self.punct(';')?;
+        self.signal_mut(id, &name)?.value_type = value_type;
+        Ok(())
+    }
+
+    fn parse_multiplexing(&mut self) -> Result<(), DbcError> {
+        let id = self.number()?;
+        let name = self.ident()?;
+        let multiplexor = self.ident()?;
+        let mut ranges = Vec::new();
+        while !self.peek_punct(';') {
+            let min = self.number::<u64>()?;
+            // `3-4` is read as the numbers 3 and -4
+            let max = match self.next()? {
+                Token::Punct('-') => self.number::<u64>()?,
+                Token::Number(value) if value.starts_with('-') => {
+                    value[1..].parse().map_err(|_| self.error())?
+                }
+                _ => return Err(self.error()),
+            };
+            ranges.push((min, max));
+            if !self.peek_punct(';') {
+                self.punct(',')?;
+            }
+        }
+        self.punct(';')?;
+        self.signal_mut(id, &name)?.multiplexed = Some(Multiplexed {
+            multiplexor,
+            ranges,
+        });
+        Ok(())
+    }
+
+    /// Assigns the multiplexor of each message to its multiplexed signals not named otherwise.
+    fn resolve_multiplexors(&mut self) {
+        for message in &mut self.database.messages {
+            let multiplexor = message
+                .signals
+                .iter()
+                .find(|signal| signal.multiplexor && signal.multiplexed.is_none())
+                .map(|signal| signal.name.clone())
+                .unwrap_or_default();
+            for signal in &mut message.signals {
+                if let Some(multiplexed) = &mut signal.multiplexed {
+                    if multiplexed.multiplexor.is_empty() {
+                        multiplexed.multiplexor = multiplexor.clone();
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DBC: &str = r#"VERSION "1.0"
+
+NS_ :
+	NS_DESC_
+	CM_
+	BA_DEF_
+	BA_
+	VAL_
+	SIG_VALTYPE_
+	SG_MUL_VAL_
+
+BS_:
+
+BU_: ECU Tester
+
+VAL_TABLE_ Gears 0 "Park" 1 "Reverse" 2 "Neutral" 3 "Drive" ;
+
+BO_ 256 Engine: 8 ECU
+ SG_ Speed : 7|16@0+ (0.1,0) [0|6553.5] "km/h" Tester
+ SG_ Temperature : 16|8@1- (1,-40) [-80|87] "degC" Tester,ECU
+ SG_ Gear : 24|2@1+ (1,0) [0|3] "" Tester
+ SG_ Torque : 32|32@1- (1,0) [0|0] "Nm" Vector__XXX
+
+BO_ 2566844672 Mux: 8 ECU
+ SG_ Selector M : 0|8@1+ (1,0) [0|255] "" Tester
+ SG_ Voltage m0 : 8|16@1+ (0.001,0) [0|65.535] "V" Tester
+ SG_ Current m1 : 8|16@1- (0.01,0) [-327.68|327.67] "A" Tester
+ SG_ Page m2M : 8|8@1+ (1,0) [0|255] "" Tester
+ SG_ Deep m3 : 16|16@1+ (1,0) [0|0] "" Tester
+
+BO_ 1024 Fd: 64 ECU
+ SG_ Ratio : 0|32@1- (1,0) [0|0] "" Tester
+ SG_ Last : 504|8@1+ (1,0) [0|0] "" Tester
+
+BO_TX_BU_ 256 : ECU,Tester;
+
+CM_ "Test network";
+CM_ BU_ ECU "Engine control unit";
+CM_ BO_ 256 "Engine state";
+CM_ SG_ 256 Speed "Vehicle speed
+over ground";
+BA_DEF_ BO_ "GenMsgCycleTime" INT 0 10000;
+BA_DEF_ BO_ "VFrameFormat" ENUM "StandardCAN","ExtendedCAN","reserved","reserved","reserved","reserved","reserved","reserved","reserved","reserved","reserved","reserved","reserved","reserved","StandardCAN_FD","ExtendedCAN_FD";
+BA_DEF_  "BusType" STRING ;
+BA_DEF_ SG_ "GenSigStartValue" FLOAT 0 100000;
+BA_DEF_DEF_ "GenMsgCycleTime" 0;
+BA_DEF_DEF_ "VFrameFormat" "StandardCAN";
+BA_DEF_DEF_ "BusType" "CAN";
+BA_ "BusType" "CAN FD";
+BA_ "GenMsgCycleTime" BO_ 256 100;
+BA_ "VFrameFormat" BO_ 1024 14;
+BA_ "GenSigStartValue" SG_ 256 Speed 12.5;
+VAL_ 256 Gear 0 "Park" 1 "Reverse" 2 "Neutral" 3 "Drive" ;
+SIG_VALTYPE_ 1024 Ratio : 1;
+SG_MUL_VAL_ 2566844672 Deep Page 3-4, 7-7;
+"#;
+
+    fn values(values: &[SignalValue]) -> Vec<(String, f64)> {
+        values
+            .iter()
+            .map(|value| (value.name().to_string(), value.value))
+            .collect()
+    }
+
+    #[test]
+    fn dbc_parse_001() {
+        let database = DBC.parse::<Database>().unwrap();
+        assert_eq!(database.version, "1.0");
+        assert_eq!(database.comment.as_deref(), Some("Test network"));
+        assert_eq!(database.nodes.len(), 2);
+        assert_eq!(
+            database.node("ECU").unwrap().comment.as_deref(),
+            Some("Engine control unit")
+        );
+        assert_eq!(database.value_tables["Gears"][&3], "Drive");
+        assert_eq!(
+            database.attributes["BusType"],
+            AttributeValue::String("CAN FD".to_string())
+        );
+
+        let engine = database.message(0x100, false).unwrap();
+        assert_eq!(engine.name, "Engine");
+        assert_eq!(engine.comment.as_deref(), Some("Engine state"));
+        assert_eq!(
+            engine.attributes["GenMsgCycleTime"],
+            AttributeValue::Int(100)
+        );
+        let speed = engine.signal("Speed").unwrap();
+        assert_eq!(speed.byte_order, ByteOrder::BigEndian);
+        assert_eq!((speed.factor, speed.maximum), (0.1, 6553.5));
+        assert_eq!(speed.unit, "km/h");
+        assert_eq!(speed.comment.as_deref(), Some("Vehicle speed\nover ground"));
+        assert_eq!(
+            speed.attributes["GenSigStartValue"],
+            AttributeValue::Float(12.5)
+        );
+        let temperature = engine.signal("Temperature").unwrap();
+        assert_eq!(temperature.value_type, ValueType::Signed);
+        assert_eq!(temperature.receivers, ["Tester", "ECU"]);
+        assert_eq!(engine.signal("Gear").unwrap().values[&1], "Reverse");
+
+        let mux = database.message_by_name("Mux").unwrap();
+        assert_eq!((mux.id, mux.extended), (0x18FEF100, true));
+        assert!(mux.signal("Selector").unwrap().multiplexor);
+        let page = mux.signal("Page").unwrap();
+        assert!(page.multiplexor);
+        assert_eq!(
+            page.multiplexed,
+            Some(Multiplexed {
+                multiplexor: "Selector".to_string(),
+                ranges: vec![(2, 2)],
+            })
+        );
+        assert_eq!(
+            mux.signal("Deep").unwrap().multiplexed,
+            Some(Multiplexed {
+                multiplexor: "Page".to_string(),
+                ranges: vec![(3, 4), (7, 7)],
+            })
+        );
+
+        let fd = database.message(0x400, false).unwrap();
+        assert_eq!(fd.signal("Ratio").unwrap().value_type, ValueType::Float32);
+        assert_eq!(
+            database.message_attribute(fd, "VFrameFormat"),
+            Some(&AttributeValue::String("StandardCAN_FD".to_string()))
+        );
+        assert_eq!(
+            database.message_attribute(engine, "VFrameFormat"),
+            Some(&AttributeValue::String("StandardCAN".to_string()))
+        );
+    }
+
+    #[test]
+    fn dbc_parse_002() {
+        let dbc =
+            "VERSION \"\"\n\nBO_ 256 Engine: 8 ECU\n SG_ Speed : 7|16@2+ (1,0) [0|0] \"\" X\n";
+        assert_eq!(dbc.parse::<Database>(), Err(DbcError::Syntax { line: 4 }));
+        assert_eq!(
+            "CM_ BO_ 1 \"unknown\";".parse::<Database>(),
+            Err(DbcError::Syntax { line: 1 })
+        );
+    }
+
+    #[test]
+    fn dbc_decode_001() {
+        let database = DBC.parse::<Database>().unwrap();
+        let frame = CanFrame::new(
+            0x100,
+            MessageType::Standard,
+            &[0x01, 0xF4, 0x3C, 0x03, 0x9C, 0xFF, 0xFF, 0xFF],
+        )
+        .unwrap();
+        let decoded = database.decode(&frame).unwrap();
+        assert_eq!(
+            values(&decoded),
+            [
+                ("Speed".to_string(), 50.0),
+                ("Temperature".to_string(), 20.0),
+                ("Gear".to_string(), 3.0),
+                ("Torque".to_string(), -100.0),
+            ]
+        );
+        assert_eq!(decoded[0].unit(), "km/h");
+        assert_eq!(decoded[2].label(), Some("Drive"));
+
+        // signals beyond a short frame are skipped
+        let frame = CanFrame::new(0x100, MessageType::Standard, &[0x01, 0xF4]).unwrap();
+        assert_eq!(database.decode(&frame).unwrap().len(), 1);
+
+        let frame = CanFrame::new(0x100, MessageType::Extended, &[]).unwrap();
+        assert_eq!(database.decode(&frame), Err(DbcError::UnknownMessage));
+    }
+
+    #[test]
+    fn dbc_decode_002() {
+        let database = DBC.parse::<Database>().unwrap();
+        let decode = |data: &[u8]| {
+            let frame = CanFrame::new(0x18FEF100, MessageType::Extended, data).unwrap();
+            let decoded = database.decode(&frame).unwrap();
+            decoded
+                .iter()
+                .map(|value| value.name().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            decode(&[0, 0x10, 0x27, 0, 0, 0, 0, 0]),
+            ["Selector", "Voltage"]
+        );
+        assert_eq!(
+            decode(&[1, 0x10, 0x27, 0, 0, 0, 0, 0]),
+            ["Selector", "Current"]
+        );
+        assert_eq!(
+            decode(&[2, 3, 0, 0, 0, 0, 0, 0]),
+            ["Selector", "Page", "Deep"]
+        );
+        assert_eq!(
+            decode(&[2, 7, 0, 0, 0, 0, 0, 0]),
+            ["Selector", "Page", "Deep"]
+        );
+        assert_eq!(decode(&[2, 5, 0, 0, 0, 0, 0, 0]), ["Selector", "Page"]);
+        // the multiplexor of Deep is inactive
+        assert_eq!(decode(&[3, 3, 0, 0, 0, 0, 0, 0]), ["Selector"]);
+    }
+
+    #[test]
+    fn dbc_encode_001() {
+        let database = DBC.parse::<Database>().unwrap();
+        let frame = database
+            .encode(
+                "Engine",
+                &[
+                    ("Speed", 50.0),
+                    ("Temperature", 20.0),
+                    ("Gear", 3.0),
+                    ("Torque", -100.0),
+                ],
+            )
+            .unwrap();
+        assert_eq!(frame.can_id(), 0x100);
+        assert_eq!(
+            frame.data(),
+            &[0x01, 0xF4, 0x3C, 0x03, 0x9C, 0xFF, 0xFF, 0xFF]
+        );
+
+        let frame = database
+            .encode(
+                "Mux",
+                &[("Selector", 2.0), ("Page", 4.0), ("Deep", 0x1234 as f64)],
+            )
+            .unwrap();
+        assert!(frame.is_extended_frame());
+        assert_eq!(frame.data(), &[2, 4, 0x34, 0x12, 0, 0, 0, 0]);
+        let frame = database
+            .encode("Mux", &[("Selector", 1.0), ("Current", -1.5)])
+            .unwrap();
+        assert_eq!(database.decode(&frame).unwrap()[1].value, -1.5);
+
+        assert_eq!(
+            database.encode("Engine", &[("Speed", -1.0)]),
+            Err(DbcError::ValueOutOfRange("Speed".to_string()))
+        );
+        assert_eq!(
+            database.encode("Engine", &[("Torque", 2f64.powi(31))]),
+            Err(DbcError::ValueOutOfRange("Torque".to_string()))
+        );
+        assert_eq!(
+            database.encode("Engine", &[("Pressure", 1.0)]),
+            Err(DbcError::UnknownSignal("Pressure".to_string()))
+        );
+        assert_eq!(
+            database.encode("Mux", &[("Selector", 1.0), ("Voltage", 1.0)]),
+            Err(DbcError::InactiveSignal("Voltage".to_string()))
+        );
+        assert_eq!(database.encode("Brake", &[]), Err(DbcError::UnknownMessage));
+    }
+
+    #[test]
+    fn dbc_encode_002() {
+        let database = DBC.parse::<Database>().unwrap();
+        let frame = database
+            .encode_fd("Fd", &[("Ratio", 0.5), ("Last", 255.0)])
+            .unwrap();
+        assert!(frame.is_brs_frame());
+        assert_eq!(frame.data().len(), 64);
+        assert_eq!(&frame.data()[..4], &0.5f32.to_le_bytes());
+        assert_eq!(frame.data()[63], 255);
+        assert_eq!(
+            values(&database.decode(&frame).unwrap()),
+            [("Ratio".to_string(), 0.5), ("Last".to_string(), 255.0)]
+        );
+
+        assert_eq!(
+            database.encode("Fd", &[]),
+            Err(DbcError::FrameConstruction(
+                FrameConstructionError::TooMuchData
+            ))
+        );
+    }
+}
@@ -0,0 +1,563 @@
+//! Distribution of received frames to several consumers.
+//!
+//! A frame returned by [RecvCan::recv] can only be consumed once. A [Dispatcher] owns the
+//! receiving side of a socket, receives frames in a background thread and copies every frame
+//! into the queue of each [Subscription] whose [Filter] matches. Subscriptions can be added and
+//! removed at any time without interrupting the reception.
+
+use crate::error::PcanError;
+use crate::socket::{CanFdFrame, CanFrame, Frame, MessageType, RecvCan, RecvCanFd, Timestamp};
+use std::collections::VecDeque;
+use std::ops::RangeInclusive;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, PartialEq)]
+pub enum DispatchError {
+    /// No frame arrived within the given time.
+    Timeout,
+    /// The queue is empty and no frame is going to arrive anymore.
+    Disconnected,
+}
+
+/* Filter */
+
+/// Selects the frames delivered to a subscription.
+///
+/// Standard and extended frames with the same CAN-ID are different frames, the filters on
+/// CAN-IDs only match frames of the given message type.
+pub enum Filter<F> {
+    All,
+    /// Matches a single CAN-ID.
+    Id(u32, MessageType),
+    /// Matches all CAN-IDs within the range.
+    Range(RangeInclusive<u32>, MessageType),
+    /// Matches if `frame_id & mask == can_id & mask`.
+    Mask {
+        can_id: u32,
+        mask: u32,
+        msg_type: MessageType,
+    },
+    Predicate(Box<dyn Fn(&F) -> bool + Send + Sync>),
+}
+
+fn message_type<F: Frame>(frame: &F) -> MessageType {
+    match frame.is_extended_frame() {
+        true => MessageType::Extended,
+        false => MessageType::Standard,
+    }
+}
+
+impl<F: Frame> Filter<F> {
+    pub fn predicate<P>(predicate: P) -> Filter<F>
+    where
+        P: Fn(&F) -> bool + Send + Sync + 'static,
+    {
+        Filter::Predicate(Box::new(predicate))
+    }
+
+    pub fn matches(&self, frame: &F) -> bool {
+        match self {
+            Filter::All => true,
+            Filter::Id(can_id, msg_type) => {
+                message_type(frame) == *msg_type && frame.can_id() == *can_id
+            }
+            Filter::Range(range, msg_type) => {
+                message_type(frame) == *msg_type && range.contains(&frame.can_id())
+            }
+            Filter::Mask {
+                can_id,
+                mask,
+                msg_type,
+            } => message_type(frame) == *msg_type && frame.can_id() & mask == can_id & mask,
+            Filter::Predicate(predicate) => predicate(frame),
+        }
+    }
+}
+
+/* Subscriber queue */
+
+/// Behavior of a subscription whose queue is full.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Overflow {
+    /// Discards the frame that did not fit into the queue.
+    DropNewest,
+    /// Discards the oldest frame in the queue to make room for the new one.
+    DropOldest,
+    /// Waits until the subscriber made room. This stalls the delivery to all subscriptions.
+    Block,
+}
+
+struct Queue<T> {
+    items: Mutex<VecDeque<T>>,
+    not_empty: Condvar,
+    not_full: Condvar,
+    capacity: usize,
+    overflow: Overflow,
+    closed: AtomicBool,
+    dropped: AtomicU64,
+}
+
+impl<T> Queue<T> {
+    fn lock(&self) -> MutexGuard<'_, VecDeque<T>> {
+        match self.items.lock() {
+            Ok(items) => items,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        let _items = self.lock();
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+    }
+
+    fn push(&self, item: T) {
+        let mut items = self.lock();
+        if items.len() >= self.capacity {
+            match self.overflow {
+                Overflow::DropNewest => {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+                Overflow::DropOldest => {
+                    items.pop_front();
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                Overflow::Block => {
+                    while items.len() >= self.capacity && !self.is_closed() {
+                        items = match self.not_full.wait(items) {
+                            Ok(items) => items,
+                            Err(poisoned) => poisoned.into_inner(),
+                        };
+                    }
+                    if self.is_closed() {
+                        return;
+                    }
+                }
+            }
+        }
+
+        items.push_back(item);
+        self.not_empty.notify_one();
+    }
+
+    fn pop(&self, deadline: Option<Instant>) -> Result<T, DispatchError> {
+        let mut items = self.lock();
+        loop {
+            if let Some(item) = items.pop_front() {
+                self.not_full.notify_one();
+                return Ok(item);
+            }
+
+            if self.is_closed() {
+                return Err(DispatchError::Disconnected);
+            }
+
+            items = match deadline {
+                None => match self.not_empty.wait(items) {
+                    Ok(items) => items,
+                    Err(poisoned) => poisoned.into_inner(),
+                },
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(DispatchError::Timeout);
+                    }
+                    match self.not_empty.wait_timeout(items, deadline - now) {
+                        Ok((items, _)) => items,
+                        Err(poisoned) => poisoned.into_inner().0,
+                    }
+                }
+            };
+        }
+    }
+}
+
+/* Subscription */
+
+/// Identifies a subscription registered at a [Dispatcher].
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Copy, Clone)]
+pub struct SubscriptionId(u64);
+
+/// Receiving end of a subscription. Dropping it unsubscribes.
+pub struct Subscription<F, T> {
+    id: SubscriptionId,
+    queue: Arc<Queue<(F, T)>>,
+}
+
+impl<F, T> Subscription<F, T> {
+    pub fn id(&self) -> SubscriptionId {
+        self.id
+    }
+
+    /// Blocks until a frame arrives.
+    pub fn recv(&self) -> Result<(F, T), DispatchError> {
+        self.queue.pop(None)
+    }
+
+    /// Blocks until a frame arrives or `timeout` elapsed.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<(F, T), DispatchError> {
+        self.queue.pop(Some(Instant::now() + timeout))
+    }
+
+    /// Returns a queued frame without blocking.
+    pub fn try_recv(&self) -> Result<(F, T), DispatchError> {
+        self.queue.pop(Some(Instant::now()))
+    }
+
+    /// Number of frames waiting in the queue.
+    pub fn len(&self) -> usize {
+        self.queue.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.lock().is_empty()
+    }
+
+    /// Number of frames discarded because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.queue.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl<F, T> Drop for Subscription<F, T> {
+    fn drop(&mut self) {
+        self.queue.close();
+    }
+}
+
+/* Dispatcher */
+
+struct Subscriber<F, T> {
+    id: SubscriptionId,
+    filter: Filter<F>,
+    queue: Arc<Queue<(F, T)>>,
+}
+
+struct Registry<F, T> {
+    subscribers: Vec<Subscriber<F, T>>,
+    next_id: u64,
+}
+
+struct Shared<F, T> {
+    registry: Mutex<Registry<F, T>>,
+    running: AtomicBool,
+    received: AtomicU64,
+    errors: AtomicU64,
+}
+
+impl<F, T> Shared<F, T> {
+    fn lock(&self) -> MutexGuard<'_, Registry<F, T>> {
+        match self.registry.lock() {
+            Ok(registry) => registry,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+const DEFAULT_CAPACITY: usize = 256;
+const IDLE_SLEEP: Duration = Duration::from_millis(1);
+
+/// Receives frames in a background thread and distributes them to subscriptions.
+///
+/// Frames are delivered together with their timestamp, i.e. as `(CanFrame, Timestamp)` for
+/// [new](Dispatcher::new) and as `(CanFdFrame, Timestamp)` for [new_fd](Dispatcher::new_fd).
+pub struct Dispatcher<S, F, T> {
+    shared: Arc<Shared<F, T>>,
+    handle: Option<JoinHandle<S>>,
+}
+
+impl<S: RecvCan + Send + 'static> Dispatcher<S, CanFrame, Timestamp> {
+    pub fn new(socket: S) -> Self {
+        Self::spawn(socket, |socket| socket.recv())
+    }
+}
+
+impl<S: RecvCanFd + Send + 'static> Dispatcher<S, CanFdFrame, Timestamp> {
+    pub fn new_fd(socket: S) -> Self {
+        Self::spawn(socket, |socket| socket.recv_fd())
+    }
+}
+
+impl<S, F, T> Dispatcher<S, F, T>
+where
+    S: Send + 'static,
+    F: Frame + Copy + Send + 'static,
+    T: Copy + Send + 'static,
+{
+    fn spawn(socket: S, recv: fn(&S) -> Result<(F, T), PcanError>) -> Self {
+        let shared = Arc::new(Shared {
+            registry: Mutex::new(Registry {
+                subscribers: Vec::new(),
+                next_id: 0,
+            }),
+            running: AtomicBool::new(true),
+            received: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+        });
+
+        let thread_shared = shared.clone();
+        let handle = thread::spawn(move || {
+            pump(&thread_shared, &socket, recv);
+            socket
+        });
+
+        Dispatcher {
+            shared,
+            handle: Some(handle),
+        }
+    }
+
+    /// Subscribes to the frames matching `filter` with a queue of default size dropping the
+    /// newest frames on overflow.
+    pub fn subscribe(&self, filter: Filter<F>) -> Subscription<F, T> {
+        self.subscribe_with(filter, DEFAULT_CAPACITY, Overflow::DropNewest)
+    }
+
+    pub fn subscribe_with(
+        &self,
+        filter: Filter<F>,
+        capacity: usize,
+        overflow: Overflow,
+    ) -> Subscription<F, T> {
+        let queue = Arc::new(Queue {
+            items: Mutex::new(VecDeque::with_capacity(capacity)),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+            capacity: capacity.max(1),
+            overflow,
+            closed: AtomicBool::new(!self.shared.running.load(Ordering::Acquire)),
+            dropped: AtomicU64::new(0),
+        });
+
+        let mut registry = self.shared.lock();
+        let id = SubscriptionId(registry.next_id);
+        registry.next_id += 1;
+        registry.subscribers.push(Subscriber {
+            id,
+            filter,
+            queue: queue.clone(),
+        });
+
+        Subscription { id, queue }
+    }
+
+    /// Removes a subscription. Its receiving end reports
+    /// [Disconnected](DispatchError::Disconnected) once its queue is drained.
+    pub fn unsubscribe(&self, id: SubscriptionId) -> bool {
+        let mut registry = self.shared.lock();
+        match registry.subscribers.iter().position(|s| s.id == id) {
+            Some(index) => {
+                registry.subscribers.remove(index).queue.close();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn subscriptions(&self) -> usize {
+        self.shared.lock().subscribers.len()
+    }
+
+    /// Number of frames received from the socket.
+    pub fn received(&self) -> u64 {
+        self.shared.received.load(Ordering::Relaxed)
+    }
+
+    /// Number of failed receive calls, not counting calls finding an empty receive queue.
+    pub fn errors(&self) -> u64 {
+        self.shared.errors.load(Ordering::Relaxed)
+    }
+
+    /// Stops the reception, disconnects all subscriptions and hands back the socket.
+    pub fn shutdown(mut self) -> Option<S> {
+        self.stop();
+        self.handle.take().and_then(|handle| handle.join().ok())
+    }
+}
+
+impl<S, F, T> Dispatcher<S, F, T> {
+    fn stop(&self) {
+        self.shared.running.store(false, Ordering::Release);
+        let registry = self.shared.lock();
+        for subscriber in registry.subscribers.iter() {
+            subscriber.queue.close();
+        }
+    }
+}
+
+impl<S, F, T> Drop for Dispatcher<S, F, T> {
+    fn drop(&mut self) {
+        self.stop();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn pump<S, F: Frame + Copy, T: Copy>(
+    shared: &Shared<F, T>,
+    socket: &S,
+    recv: fn(&S) -> Result<(F, T), PcanError>,
+) {
+    while shared.running.load(Ordering::Acquire) {
+        let (frame, timestamp) = match recv(socket) {
+            Ok(item) => item,
+            Err(err) => {
+                if err != PcanError::QrcvEmpty {
+                    shared.errors.fetch_add(1, Ordering::Relaxed);
+                }
+                thread::sleep(IDLE_SLEEP);
+                continue;
+            }
+        };
+        shared.received.fetch_add(1, Ordering::Relaxed);
+
+        let queues = {
+            let mut registry = shared.lock();
+            registry.subscribers.retain(|s| !s.queue.is_closed());
+            registry
+                .subscribers
+                .iter()
+                .filter(|s| s.filter.matches(&frame))
+                .map(|s| s.queue.clone())
+                .collect::<Vec<_>>()
+        };
+
+        for queue in queues {
+            queue.push((frame, timestamp));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::socket::MessageType;
+
+    struct Source {
+        frames: Arc<Mutex<VecDeque<CanFrame>>>,
+    }
+
+    impl RecvCan for Source {
+        fn recv(&self) -> Result<(CanFrame, Timestamp), PcanError> {
+            self.recv_frame().map(|frame| (frame, Timestamp::default()))
+        }
+
+        fn recv_frame(&self) -> Result<CanFrame, PcanError> {
+            self.frames
+                .lock()
+                .unwrap()
+                .pop_front()
+                .ok_or(PcanError::QrcvEmpty)
+        }
+    }
+
+    fn push(frames: &Mutex<VecDeque<CanFrame>>, ids: &[u32]) {
+        push_with(frames, ids, MessageType::Standard);
+    }
+
+    fn push_with(frames: &Mutex<VecDeque<CanFrame>>, ids: &[u32], msg_type: MessageType) {
+        let mut frames = frames.lock().unwrap();
+        for id in ids {
+            frames.push_back(CanFrame::new(*id, msg_type, &[]).unwrap());
+        }
+    }
+
+    fn collect(subscription: &Subscription<CanFrame, Timestamp>) -> Vec<u32> {
+        let mut ids = Vec::new();
+        while let Ok((frame, _)) = subscription.recv_timeout(Duration::from_millis(50)) {
+            ids.push(frame.can_id());
+        }
+        ids
+    }
+
+    #[test]
+    fn dispatcher_001() {
+        let frames = Arc::new(Mutex::new(VecDeque::new()));
+        let dispatcher = Dispatcher::new(Source {
+            frames: frames.clone(),
+        });
+
+        let all = dispatcher.subscribe(Filter::All);
+        let single = dispatcher.subscribe(Filter::Id(0x7E8, MessageType::Standard));
+        let range = dispatcher.subscribe(Filter::Range(0x100..=0x1FF, MessageType::Standard));
+        let mask = dispatcher.subscribe(Filter::Mask {
+            can_id: 0x700,
+            mask: 0x700,
+            msg_type: MessageType::Standard,
+        });
+        let even = dispatcher.subscribe(Filter::predicate(|frame: &CanFrame| {
+            frame.can_id() & 1 == 0
+        }));
+
+        push(&frames, &[0x100, 0x1FF, 0x7E8, 0x7DF, 0x200]);
+
+        assert_eq!(collect(&all), vec![0x100, 0x1FF, 0x7E8, 0x7DF, 0x200]);
+        assert_eq!(collect(&single), vec![0x7E8]);
+        assert_eq!(collect(&range), vec![0x100, 0x1FF]);
+        assert_eq!(collect(&mask), vec![0x7E8, 0x7DF]);
+        assert_eq!(collect(&even), vec![0x100, 0x7E8, 0x200]);
+        assert_eq!(dispatcher.received(), 5);
+    }
+
+    #[test]
+    fn dispatcher_002() {
+        let frames = Arc::new(Mutex::new(VecDeque::new()));
+        let dispatcher = Dispatcher::new(Source {
+            frames: frames.clone(),
+        });
+
+        let standard = dispatcher.subscribe(Filter::Id(0x7E8, MessageType::Standard));
+        let extended = dispatcher.subscribe(Filter::Id(0x7E8, MessageType::Extended));
+        let range = dispatcher.subscribe(Filter::Range(0x700..=0x7FF, MessageType::Extended));
+        let mask = dispatcher.subscribe(Filter::Mask {
+            can_id: 0x18DA_F100,
+            mask: 0x1FFF_FF00,
+            msg_type: MessageType::Extended,
+        });
+
+        push(&frames, &[0x7E8]);
+        push_with(&frames, &[0x7E8, 0x18DA_F1E8], MessageType::Extended);
+
+        assert_eq!(collect(&standard), vec![0x7E8]);
+        assert_eq!(collect(&extended), vec![0x7E8]);
+        assert_eq!(collect(&range), vec![0x7E8]);
+        assert_eq!(collect(&mask), vec![0x18DA_F1E8]);
+        assert_eq!(dispatcher.received(), 3);
+    }
+
+    #[test]
+    fn dispatcher_003() {
+        let frames = Arc::new(Mutex::new(VecDeque::new()));
+        let dispatcher = Dispatcher::new(Source {
+            frames: frames.clone(),
+        });
+
+        let bounded = dispatcher.subscribe_with(Filter::All, 2, Overflow::DropOldest);
+        let removed = dispatcher.subscribe(Filter::All);
+        assert!(dispatcher.unsubscribe(removed.id()));
+        assert!(!dispatcher.unsubscribe(removed.id()));
+        assert_eq!(removed.try_recv(), Err(DispatchError::Disconnected));
+
+        push(&frames, &[0x1, 0x2, 0x3, 0x4, 0x5]);
+        while dispatcher.received() < 5 {
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        assert_eq!(collect(&bounded), vec![0x4, 0x5]);
+        assert_eq!(bounded.dropped(), 3);
+        assert_eq!(dispatcher.subscriptions(), 1);
+
+        assert!(dispatcher.shutdown().is_some());
+        assert_eq!(bounded.recv(), Err(DispatchError::Disconnected));
+    }
+}
@@ -6,7 +6,7 @@
 use crate::pcan;
 
 ///
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Copy, Clone)]
 pub enum PcanError {
     ///
     XmtFull,
@@ -0,0 +1,1028 @@
+//! ISO-TP (ISO 15765-2) transport protocol.
+//!
+//! An [IsoTpChannel] segments payloads larger than a single frame into first and consecutive
+//! frames, reassembles received messages and handles the flow control in both directions. It
+//! works on classic sockets implementing [SendCan] and [RecvCan] with up to 4095 bytes per message
+//! (or more using the escape sequence of ISO 15765-2:2016) and on CAN FD sockets implementing
+//! [SendCanFd] and [RecvCanFd].
+//!
+//! The reception is implemented by the [Reassembler], which does no I/O on its own and can be used
+//! to follow several transfers at once.
+
+use crate::error::PcanError;
+use crate::socket::{
+    fd_dlc_to_length, fd_length_to_dlc, CanFdFrame, CanFrame, MessageType, RecvCan, RecvCanFd,
+    SendCan, SendCanFd,
+};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, PartialEq)]
+pub enum IsoTpError {
+    Pcan(PcanError),
+    /// A frame could not be handed to the driver within N_As.
+    TimeoutAs,
+    /// No flow control frame arrived within N_Bs.
+    TimeoutBs,
+    /// No consecutive frame arrived within N_Cr.
+    TimeoutCr,
+    /// No message arrived within the time given to [recv_timeout](IsoTpChannel::recv_timeout).
+    Timeout,
+    WrongSequenceNumber {
+        expected: u8,
+        received: u8,
+    },
+    /// The receiver reported that the message exceeds its buffer.
+    ReceiverOverflow,
+    /// A received message exceeds the configured maximum length.
+    BufferOverflow,
+    /// The receiver sent more wait frames than allowed.
+    WaitLimitExceeded,
+    InvalidFrame,
+    InvalidFlowStatus,
+    PayloadTooLarge,
+}
+
+impl From<PcanError> for IsoTpError {
+    fn from(value: PcanError) -> Self {
+        IsoTpError::Pcan(value)
+    }
+}
+
+/* Configuration */
+
+/// Addressing format defining the content of the first data byte of each frame.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Addressing {
+    /// The CAN-ID alone identifies the connection.
+    Normal,
+    /// Frames sent carry `target_address` in their first byte, frames received have to carry
+    /// `source_address` there.
+    Extended {
+        target_address: u8,
+        source_address: u8,
+    },
+    /// Frames in both directions carry the address extension in their first byte.
+    Mixed { address_extension: u8 },
+}
+
+impl Addressing {
+    fn len(&self) -> usize {
+        match self {
+            Addressing::Normal => 0,
+            _ => 1,
+        }
+    }
+
+    fn tx_byte(&self) -> Option<u8> {
+        match self {
+            Addressing::Normal => None,
+            Addressing::Extended { target_address, .. } => Some(*target_address),
+            Addressing::Mixed { address_extension } => Some(*address_extension),
+        }
+    }
+
+    fn rx_byte(&self) -> Option<u8> {
+        match self {
+            Addressing::Normal => None,
+            Addressing::Extended { source_address, .. } => Some(*source_address),
+            Addressing::Mixed { address_extension } => Some(*address_extension),
+        }
+    }
+}
+
+/// Converts a separation time into its STmin byte. Times not representable are rounded up.
+pub fn st_min_to_byte(st_min: Duration) -> u8 {
+    let micros = st_min.as_micros();
+    if micros == 0 {
+        0x00
+    } else if micros < 1000 {
+        let hundreds = micros.div_ceil(100) as u8;
+        0xF0 + hundreds
+    } else {
+        micros.div_ceil(1000).min(0x7F) as u8
+    }
+}
+
+/// Converts an STmin byte into a separation time. Reserved values map to 127 ms.
+pub fn st_min_from_byte(value: u8) -> Duration {
+    match value {
+        0x00..=0x7F => Duration::from_millis(value as u64),
+        0xF1..=0xF9 => Duration::from_micros((value - 0xF0) as u64 * 100),
+        _ => Duration::from_millis(0x7F),
+    }
+}
+
+/// Parameters of an ISO-TP connection.
+#[derive(Debug, PartialEq, Clone)]
+pub struct IsoTpConfig {
+    tx_id: u32,
+    rx_id: u32,
+    msg_type: MessageType,
+    addressing: Addressing,
+    padding: Option<u8>,
+    block_size: u8,
+    st_min: Duration,
+    n_as: Duration,
+    n_bs: Duration,
+    n_cr: Duration,
+    max_wait_frames: u8,
+    tx_dl: usize,
+    brs: bool,
+    max_rx_length: usize,
+}
+
+impl IsoTpConfig {
+    /// Creates a configuration sending with `tx_id` and receiving frames with `rx_id` using
+    /// 11-bit identifiers and normal addressing.
+    pub fn new(tx_id: u32, rx_id: u32) -> IsoTpConfig {
+        IsoTpConfig {
+            tx_id,
+            rx_id,
+            msg_type: MessageType::Standard,
+            addressing: Addressing::Normal,
+            padding: None,
+            block_size: 0,
+            st_min: Duration::ZERO,
+            n_as: Duration::from_millis(1000),
+            n_bs: Duration::from_millis(1000),
+            n_cr: Duration::from_millis(1000),
+            max_wait_frames: 10,
+            tx_dl: 8,
+            brs: false,
+            max_rx_length: 4095,
+        }
+    }
+
+    pub fn with_message_type(mut self, msg_type: MessageType) -> IsoTpConfig {
+        self.msg_type = msg_type;
+        self
+    }
+
+    pub fn with_addressing(mut self, addressing: Addressing) -> IsoTpConfig {
+        self.addressing = addressing;
+        self
+    }
+
+    /// Pads frames shorter than the data length with `padding`.
+    pub fn with_padding(mut self, padding: u8) -> IsoTpConfig {
+        self.padding = Some(padding);
+        self
+    }
+
+    /// Sets the block size sent in flow control frames. Zero lets the sender send all consecutive
+    /// frames without waiting for another flow control frame.
+    pub fn with_block_size(mut self, block_size: u8) -> IsoTpConfig {
+        self.block_size = block_size;
+        self
+    }
+
+    /// Sets the minimum separation time between consecutive frames requested from the sender.
+    pub fn with_st_min(mut self, st_min: Duration) -> IsoTpConfig {
+        self.st_min = st_min;
+        self
+    }
+
+    pub fn with_timeouts(mut self, n_as: Duration, n_bs: Duration, n_cr: Duration) -> IsoTpConfig {
+        self.n_as = n_as;
+        self.n_bs = n_bs;
+        self.n_cr = n_cr;
+        self
+    }
+
+    /// Sets the number of wait frames accepted in a row before the transfer is aborted.
+    pub fn with_max_wait_frames(mut self, max_wait_frames: u8) -> IsoTpConfig {
+        self.max_wait_frames = max_wait_frames;
+        self
+    }
+
+    /// Sets the data length of the frames sent over CAN FD. Ignored on classic sockets.
+    pub fn with_tx_dl(mut self, tx_dl: usize) -> IsoTpConfig {
+        self.tx_dl = fd_dlc_to_length(fd_length_to_dlc(tx_dl.max(8)));
+        self
+    }
+
+    /// Enables the bitrate switch of CAN FD frames.
+    pub fn with_brs(mut self, brs: bool) -> IsoTpConfig {
+        self.brs = brs;
+        self
+    }
+
+    /// Sets the length of the longest message accepted. Longer messages are rejected with a flow
+    /// control frame reporting an overflow.
+    pub fn with_max_rx_length(mut self, max_rx_length: usize) -> IsoTpConfig {
+        self.max_rx_length = max_rx_length;
+        self
+    }
+
+    pub fn tx_id(&self) -> u32 {
+        self.tx_id
+    }
+
+    pub fn rx_id(&self) -> u32 {
+        self.rx_id
+    }
+
+    pub fn msg_type(&self) -> MessageType {
+        self.msg_type
+    }
+
+    pub fn addressing(&self) -> Addressing {
+        self.addressing
+    }
+}
+
+/* Protocol data units */
+
+const PCI_SINGLE_FRAME: u8 = 0x0;
+const PCI_FIRST_FRAME: u8 = 0x1;
+const PCI_CONSECUTIVE_FRAME: u8 = 0x2;
+const PCI_FLOW_CONTROL: u8 = 0x3;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum FlowStatus {
+    ContinueToSend,
+    Wait,
+    Overflow,
+}
+
+/// Content of a flow control frame.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct FlowControl {
+    pub status: FlowStatus,
+    pub block_size: u8,
+    pub st_min: Duration,
+}
+
+impl FlowControl {
+    /// Parses a flow control frame, starting with its protocol control information.
+    pub fn parse(pdu: &[u8]) -> Result<FlowControl, IsoTpError> {
+        if pdu.len() < 3 || pdu[0] >> 4 != PCI_FLOW_CONTROL {
+            return Err(IsoTpError::InvalidFrame);
+        }
+
+        let status = match pdu[0] & 0x0F {
+            0 => FlowStatus::ContinueToSend,
+            1 => FlowStatus::Wait,
+            2 => FlowStatus::Overflow,
+            _ => return Err(IsoTpError::InvalidFlowStatus),
+        };
+
+        Ok(FlowControl {
+            status,
+            block_size: pdu[1],
+            st_min: st_min_from_byte(pdu[2]),
+        })
+    }
+
+    pub fn encode(&self) -> [u8; 3] {
+        let status = match self.status {
+            FlowStatus::ContinueToSend => 0,
+            FlowStatus::Wait => 1,
+            FlowStatus::Overflow => 2,
+        };
+        [
+            (PCI_FLOW_CONTROL << 4) | status,
+            self.block_size,
+            st_min_to_byte(self.st_min),
+        ]
+    }
+}
+
+/* Reassembler */
+
+/// Result of feeding a frame into a [Reassembler].
+#[derive(Debug, PartialEq)]
+pub enum RxEvent {
+    /// The frame was consumed, the message is not complete yet.
+    Pending,
+    /// The frame was consumed and the given flow control frame has to be sent.
+    FlowControl(FlowControl),
+    /// The message is complete.
+    Complete(Vec<u8>),
+}
+
+#[derive(Debug)]
+struct Transfer {
+    data: Vec<u8>,
+    length: usize,
+    sequence_number: u8,
+    block_count: u8,
+}
+
+/// Reassembles messages from single, first and consecutive frames.
+///
+/// The reassembler performs no I/O: frames are passed in by the caller, which also has to send the
+/// flow control frames requested and to watch the N_Cr timeout.
+#[derive(Debug)]
+pub struct Reassembler {
+    block_size: u8,
+    st_min: Duration,
+    max_length: usize,
+    min_first_frame_length: usize,
+    transfer: Option<Transfer>,
+}
+
+impl Reassembler {
+    pub fn new(config: &IsoTpConfig) -> Reassembler {
+        Reassembler {
+            block_size: config.block_size,
+            st_min: config.st_min,
+            max_length: config.max_rx_length,
+            min_first_frame_length: single_frame_length(config.addressing, 8) + 1,
+            transfer: None,
+        }
+    }
+
+    /// Sets the shortest message a first frame may announce, by default one byte more than fits
+    /// into a classic single frame with the addressing of the configuration.
+    pub fn with_min_first_frame_length(mut self, length: usize) -> Reassembler {
+        self.min_first_frame_length = length;
+        self
+    }
+
+    /// Returns `true` while a segmented message is being received.
+    pub fn is_receiving(&self) -> bool {
+        self.transfer.is_some()
+    }
+
+    /// Aborts the reception of the current message.
+    pub fn reset(&mut self) {
+        self.transfer = None;
+    }
+
+    fn continue_to_send(&self) -> FlowControl {
+        FlowControl {
+            status: FlowStatus::ContinueToSend,
+            block_size: self.block_size,
+            st_min: self.st_min,
+        }
+    }
+
+    /// Processes a frame, starting with its protocol control information.
+    ///
+    /// A single or first frame aborts the reception of the current message. First frames
+    /// announcing a message fitting into a single frame, or less than 4096 bytes with the escape
+    /// sequence, are ignored. If
+    /// [BufferOverflow](IsoTpError::BufferOverflow) is returned the caller should answer with a
+    /// flow control frame reporting an [Overflow](FlowStatus::Overflow).
+    pub fn on_frame(&mut self, pdu: &[u8]) -> Result<RxEvent, IsoTpError> {
+        if pdu.is_empty() {
+            return Err(IsoTpError::InvalidFrame);
+        }
+
+        match pdu[0] >> 4 {
+            PCI_SINGLE_FRAME => {
+                self.transfer = None;
+                let (length, offset) = match pdu[0] & 0x0F {
+                    0 if pdu.len() > 8 => (pdu.get(1).copied().unwrap_or(0) as usize, 2),
+                    0 => return Err(IsoTpError::InvalidFrame),
+                    length => (length as usize, 1),
+                };
+
+                if length == 0 || offset + length > pdu.len() {
+                    return Err(IsoTpError::InvalidFrame);
+                }
+                Ok(RxEvent::Complete(pdu[offset..offset + length].to_vec()))
+            }
+            PCI_FIRST_FRAME => {
+                self.transfer = None;
+                if pdu.len() < 2 {
+                    return Err(IsoTpError::InvalidFrame);
+                }
+
+                let (length, offset) = match ((pdu[0] as usize & 0x0F) << 8) | pdu[1] as usize {
+                    0 if pdu.len() >= 6 => {
+                        let length = u32::from_be_bytes([pdu[2], pdu[3], pdu[4], pdu[5]]);
+                        (length as usize, 6)
+                    }
+                    0 => return Err(IsoTpError::InvalidFrame),
+                    length => (length, 2),
+                };
+
+                // too short for the format, ISO 15765-2 has the receiver ignore the frame
+                let min_length = match offset {
+                    6 => 0x1000,
+                    _ => self.min_first_frame_length,
+                };
+                if length < min_length {
+                    return Ok(RxEvent::Pending);
+                }
+
+                if length > self.max_length {
+                    return Err(IsoTpError::BufferOverflow);
+                }
+
+                let mut data = Vec::with_capacity(length);
+                data.extend_from_slice(&pdu[offset..pdu.len().min(offset + length)]);
+                self.transfer = Some(Transfer {
+                    data,
+                    length,
+                    sequence_number: 1,
+                    block_count: 0,
+                });
+                Ok(RxEvent::FlowControl(self.continue_to_send()))
+            }
+            PCI_CONSECUTIVE_FRAME => {
+                let block_size = self.block_size;
+                let transfer = match self.transfer.as_mut() {
+                    Some(transfer) => transfer,
+                    None => return Ok(RxEvent::Pending),
+                };
+
+                let sequence_number = pdu[0] & 0x0F;
+                if sequence_number != transfer.sequence_number {
+                    let expected = transfer.sequence_number;
+                    self.transfer = None;
+                    return Err(IsoTpError::WrongSequenceNumber {
+                        expected,
+                        received: sequence_number,
+                    });
+                }
+
+                let remaining = transfer.length - transfer.data.len();
+                let chunk = &pdu[1..];
+                transfer
+                    .data
+                    .extend_from_slice(&chunk[..chunk.len().min(remaining)]);
+                transfer.sequence_number = (transfer.sequence_number + 1) & 0x0F;
+                transfer.block_count = transfer.block_count.wrapping_add(1);
+
+                if transfer.data.len() >= transfer.length {
+                    let data = std::mem::take(&mut transfer.data);
+                    self.transfer = None;
+                    return Ok(RxEvent::Complete(data));
+                }
+
+                if block_size != 0 && transfer.block_count == block_size {
+                    transfer.block_count = 0;
+                    return Ok(RxEvent::FlowControl(self.continue_to_send()));
+                }
+                Ok(RxEvent::Pending)
+            }
+            _ => Ok(RxEvent::Pending),
+        }
+    }
+}
+
+/* IsoTpChannel */
+
+/// Frame received from the link layer.
+struct LinkFrame {
+    can_id: u32,
+    extended: bool,
+    data: [u8; 64],
+    length: usize,
+}
+
+type SendFn<S> = fn(&S, &IsoTpConfig, &[u8]) -> Result<(), PcanError>;
+type RecvFn<S> = fn(&S) -> Result<LinkFrame, PcanError>;
+
+fn send_can<S: SendCan>(socket: &S, config: &IsoTpConfig, data: &[u8]) -> Result<(), PcanError> {
+    let frame =
+        CanFrame::new(config.tx_id, config.msg_type, data).map_err(|_| PcanError::IllParamVal)?;
+    socket.send(frame)
+}
+
+fn recv_can<S: RecvCan>(socket: &S) -> Result<LinkFrame, PcanError> {
+    let frame = socket.recv_frame()?;
+    let mut data = [0u8; 64];
+    data[..frame.data().len()].copy_from_slice(frame.data());
+    Ok(LinkFrame {
+        can_id: frame.can_id(),
+        extended: frame.is_extended_frame(),
+        data,
+        length: frame.data().len(),
+    })
+}
+
+fn send_can_fd<S: SendCanFd>(
+    socket: &S,
+    config: &IsoTpConfig,
+    data: &[u8],
+) -> Result<(), PcanError> {
+    let mut frame =
+        CanFdFrame::new(config.tx_id, config.msg_type, data).map_err(|_| PcanError::IllParamVal)?;
+    frame.set_brs(config.brs);
+    socket.send_fd(frame)
+}
+
+fn recv_can_fd<S: RecvCanFd>(socket: &S) -> Result<LinkFrame, PcanError> {
+    let frame = socket.recv_fd_frame()?;
+    let mut data = [0u8; 64];
+    data[..frame.data().len()].copy_from_slice(frame.data());
+    Ok(LinkFrame {
+        can_id: frame.can_id(),
+        extended: frame.is_extended_frame(),
+        data,
+        length: frame.data().len(),
+    })
+}
+
+/// Interval between two attempts to read from an empty receive queue.
+const POLL_INTERVAL: Duration = Duration::from_micros(100);
+
+/// Default padding of CAN FD frames longer than 8 bytes if no padding is configured.
+const FD_DEFAULT_PADDING: u8 = 0xCC;
+
+/// A point-to-point ISO-TP connection on top of a socket.
+///
+/// Frames received with an identifier or address other than the configured ones are discarded,
+/// use a [Dispatcher](crate::dispatch::Dispatcher) to share a channel between several consumers.
+pub struct IsoTpChannel<S> {
+    socket: S,
+    config: IsoTpConfig,
+    reassembler: Reassembler,
+    max_dl: usize,
+    send: SendFn<S>,
+    recv: RecvFn<S>,
+}
+
+/// Largest payload fitting into a single frame of `max_dl` bytes.
+fn single_frame_length(addressing: Addressing, max_dl: usize) -> usize {
+    if max_dl > 8 {
+        max_dl - 2 - addressing.len()
+    } else {
+        7 - addressing.len()
+    }
+}
+
+/// Creates a reassembler ignoring first frames of messages a single frame of `max_dl` bytes
+/// carries.
+fn reassembler(config: &IsoTpConfig, max_dl: usize) -> Reassembler {
+    Reassembler::new(config)
+        .with_min_first_frame_length(single_frame_length(config.addressing, max_dl) + 1)
+}
+
+impl<S: SendCan + RecvCan> IsoTpChannel<S> {
+    /// Creates a channel sending classic frames.
+    pub fn new(socket: S, config: IsoTpConfig) -> IsoTpChannel<S> {
+        IsoTpChannel {
+            socket,
+            reassembler: reassembler(&config, 8),
+            config,
+            max_dl: 8,
+            send: send_can::<S>,
+            recv: recv_can::<S>,
+        }
+    }
+}
+
+impl<S: SendCanFd + RecvCanFd> IsoTpChannel<S> {
+    /// Creates a channel sending CAN FD frames with the data length of the configuration.
+    pub fn new_fd(socket: S, config: IsoTpConfig) -> IsoTpChannel<S> {
+        IsoTpChannel {
+            socket,
+            reassembler: reassembler(&config, config.tx_dl),
+            max_dl: config.tx_dl,
+            config,
+            send: send_can_fd::<S>,
+            recv: recv_can_fd::<S>,
+        }
+    }
+}
+
+impl<S> IsoTpChannel<S> {
+    pub fn socket(&self) -> &S {
+        &self.socket
+    }
+
+    pub fn into_socket(self) -> S {
+        self.socket
+    }
+
+    pub fn config(&self) -> &IsoTpConfig {
+        &self.config
+    }
+
+    /// Replaces the configuration, e.g. to talk to another node. Resets the reception.
+    pub fn set_config(&mut self, config: IsoTpConfig) {
+        if self.max_dl > 8 {
+            self.max_dl = config.tx_dl;
+        }
+        self.reassembler = reassembler(&config, self.max_dl);
+        self.config = config;
+    }
+
+    /// Largest payload fitting into a single frame.
+    pub fn max_single_frame_length(&self) -> usize {
+        single_frame_length(self.config.addressing, self.max_dl)
+    }
+
+    /// Adds the addressing byte and padding to a protocol data unit.
+    fn build_frame(&self, pdu: &[u8]) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.max_dl);
+        if let Some(byte) = self.config.addressing.tx_byte() {
+            data.push(byte);
+        }
+        data.extend_from_slice(pdu);
+
+        let padded_length = if data.len() <= 8 {
+            8
+        } else {
+            fd_dlc_to_length(fd_length_to_dlc(data.len()))
+        };
+        match self.config.padding {
+            Some(padding) => data.resize(padded_length, padding),
+            None if data.len() > 8 => data.resize(padded_length, FD_DEFAULT_PADDING),
+            None => {}
+        }
+        data
+    }
+
+    fn transmit(&self, pdu: &[u8]) -> Result<(), IsoTpError> {
+        let data = self.build_frame(pdu);
+        let deadline = Instant::now() + self.config.n_as;
+
+        loop {
+            match (self.send)(&self.socket, &self.config, &data) {
+                Ok(()) => return Ok(()),
+                Err(PcanError::XmtFull) | Err(PcanError::QxmtFull) => {
+                    if Instant::now() >= deadline {
+                        return Err(IsoTpError::TimeoutAs);
+                    }
+                    thread::sleep(POLL_INTERVAL);
+                }
+                Err(err) => return Err(IsoTpError::Pcan(err)),
+            }
+        }
+    }
+
+    /// Waits for a frame of this connection and returns its protocol data unit. Returns `None` if
+    /// the deadline passed.
+    fn receive(&self, deadline: Option<Instant>) -> Result<Option<Vec<u8>>, IsoTpError> {
+        let extended = self.config.msg_type == MessageType::Extended;
+        let addressing = self.config.addressing.len();
+
+        loop {
+            match (self.recv)(&self.socket) {
+                Ok(frame) => {
+                    if frame.can_id != self.config.rx_id
+                        || frame.extended != extended
+                        || frame.length <= addressing
+                    {
+                        continue;
+                    }
+
+                    if let Some(byte) = self.config.addressing.rx_byte() {
+                        if frame.data[0] != byte {
+                            continue;
+                        }
+                    }
+                    return Ok(Some(frame.data[addressing..frame.length].to_vec()));
+                }
+                Err(PcanError::QrcvEmpty) => {
+                    if let Some(deadline) = deadline {
+                        if Instant::now() >= deadline {
+                            return Ok(None);
+                        }
+                    }
+                    thread::sleep(POLL_INTERVAL);
+                }
+                Err(err) => return Err(IsoTpError::Pcan(err)),
+            }
+        }
+    }
+
+    fn wait_flow_control(&self) -> Result<FlowControl, IsoTpError> {
+        let mut wait_frames = 0;
+
+        loop {
+            let deadline = Instant::now() + self.config.n_bs;
+            let flow_control = loop {
+                match self.receive(Some(deadline))? {
+                    Some(pdu) if pdu[0] >> 4 == PCI_FLOW_CONTROL => {
+                        break FlowControl::parse(&pdu)?;
+                    }
+                    Some(_) => continue,
+                    None => return Err(IsoTpError::TimeoutBs),
+                }
+            };
+
+            match flow_control.status {
+                FlowStatus::ContinueToSend => return Ok(flow_control),
+                FlowStatus::Overflow => return Err(IsoTpError::ReceiverOverflow),
+                FlowStatus::Wait => {
+                    wait_frames += 1;
+                    if wait_frames > self.config.max_wait_frames {
+                        return Err(IsoTpError::WaitLimitExceeded);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Sends a message, segmenting it if it does not fit into a single frame.
+    pub fn send(&mut self, payload: &[u8]) -> Result<(), IsoTpError> {
+        let addressing = self.config.addressing.len();
+        let length = payload.len();
+
+        if length == 0 {
+            return Err(IsoTpError::InvalidFrame);
+        }
+
+        if length <= 7 - addressing {
+            let mut pdu = vec![(PCI_SINGLE_FRAME << 4) | length as u8];
+            pdu.extend_from_slice(payload);
+            return self.transmit(&pdu);
+        }
+
+        if length <= self.max_single_frame_length() {
+            let mut pdu = vec![PCI_SINGLE_FRAME << 4, length as u8];
+            pdu.extend_from_slice(payload);
+            return self.transmit(&pdu);
+        }
+
+        let mut pdu = if length <= 0xF_FF {
+            vec![(PCI_FIRST_FRAME << 4) | (length >> 8) as u8, length as u8]
+        } else if length <= u32::MAX as usize {
+            let mut pdu = vec![PCI_FIRST_FRAME << 4, 0x00];
+            pdu.extend_from_slice(&(length as u32).to_be_bytes());
+            pdu
+        } else {
+            return Err(IsoTpError::PayloadTooLarge);
+        };
+
+        let first_length = self.max_dl - addressing - pdu.len();
+        pdu.extend_from_slice(&payload[..first_length]);
+        self.transmit(&pdu)?;
+
+        let consecutive_length = self.max_dl - addressing - 1;
+        let mut offset = first_length;
+        let mut sequence_number = 1u8;
+
+        while offset < length {
+            let flow_control = self.wait_flow_control()?;
+            let mut block_count = 0u8;
+
+            while offset < length {
+                let end = (offset + consecutive_length).min(length);
+                let mut pdu = vec![(PCI_CONSECUTIVE_FRAME << 4) | sequence_number];
+                pdu.extend_from_slice(&payload[offset..end]);
+                self.transmit(&pdu)?;
+
+                offset = end;
+                sequence_number = (sequence_number + 1) & 0x0F;
+                block_count = block_count.wrapping_add(1);
+
+                if flow_control.block_size != 0 && block_count == flow_control.block_size {
+                    break;
+                }
+                if offset < length && !flow_control.st_min.is_zero() {
+                    thread::sleep(flow_control.st_min);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Blocks until a message was received.
+    pub fn recv(&mut self) -> Result<Vec<u8>, IsoTpError> {
+        self.receive_message(None)
+    }
+
+    /// Blocks until a message was received or no message started within `timeout`.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<Vec<u8>, IsoTpError> {
+        self.receive_message(Some(Instant::now() + timeout))
+    }
+
+    fn receive_message(&mut self, deadline: Option<Instant>) -> Result<Vec<u8>, IsoTpError> {
+        self.reassembler.reset();
+
+        loop {
+            let deadline = if self.reassembler.is_receiving() {
+                Some(Instant::now() + self.config.n_cr)
+            } else {
+                deadline
+            };
+
+            let pdu = match self.receive(deadline)? {
+                Some(pdu) => pdu,
+                None if self.reassembler.is_receiving() => {
+                    self.reassembler.reset();
+                    return Err(IsoTpError::TimeoutCr);
+                }
+                None => return Err(IsoTpError::Timeout),
+            };
+
+            match self.reassembler.on_frame(&pdu) {
+                Ok(RxEvent::Pending) => {}
+                Ok(RxEvent::FlowControl(flow_control)) => self.transmit(&flow_control.encode())?,
+                Ok(RxEvent::Complete(data)) => return Ok(data),
+                Err(IsoTpError::BufferOverflow) => {
+                    let flow_control = FlowControl {
+                        status: FlowStatus::Overflow,
+                        block_size: 0,
+                        st_min: Duration::ZERO,
+                    };
+                    self.transmit(&flow_control.encode())?;
+                    return Err(IsoTpError::BufferOverflow);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::socket::pipe::{pipe, PipeSocket};
+
+    fn transfer(
+        tester: IsoTpChannel<PipeSocket>,
+        ecu: IsoTpChannel<PipeSocket>,
+        payload: Vec<u8>,
+    ) -> Vec<u8> {
+        let sender = thread::spawn(move || {
+            let mut tester = tester;
+            tester.send(&payload).map(|_| tester)
+        });
+
+        let mut ecu = ecu;
+        let received = ecu.recv_timeout(Duration::from_secs(2)).unwrap();
+        sender.join().unwrap().unwrap();
+        received
+    }
+
+    fn channels(
+        tester: IsoTpConfig,
+        ecu: IsoTpConfig,
+    ) -> (IsoTpChannel<PipeSocket>, IsoTpChannel<PipeSocket>) {
+        let (a, b) = pipe();
+        (IsoTpChannel::new(a, tester), IsoTpChannel::new(b, ecu))
+    }
+
+    #[test]
+    fn st_min_001() {
+        assert_eq!(st_min_to_byte(Duration::ZERO), 0x00);
+        assert_eq!(st_min_to_byte(Duration::from_micros(300)), 0xF3);
+        assert_eq!(st_min_to_byte(Duration::from_millis(20)), 0x14);
+        assert_eq!(st_min_from_byte(0xF9), Duration::from_micros(900));
+        assert_eq!(st_min_from_byte(0x80), Duration::from_millis(127));
+    }
+
+    #[test]
+    fn single_frame_001() {
+        let (tester, ecu) = channels(
+            IsoTpConfig::new(0x7E0, 0x7E8).with_padding(0xAA),
+            IsoTpConfig::new(0x7E8, 0x7E0),
+        );
+        assert_eq!(
+            transfer(tester, ecu, vec![0x22, 0xF1, 0x90]),
+            vec![0x22, 0xF1, 0x90]
+        );
+    }
+
+    #[test]
+    fn multi_frame_001() {
+        let payload = (0..300u32).map(|i| i as u8).collect::<Vec<_>>();
+        let (tester, ecu) = channels(
+            IsoTpConfig::new(0x7E0, 0x7E8),
+            IsoTpConfig::new(0x7E8, 0x7E0)
+                .with_block_size(4)
+                .with_st_min(Duration::from_micros(100)),
+        );
+        assert_eq!(transfer(tester, ecu, payload.clone()), payload);
+    }
+
+    #[test]
+    fn multi_frame_002() {
+        let payload = (0..100u32).map(|i| i as u8).collect::<Vec<_>>();
+        let (tester, ecu) = channels(
+            IsoTpConfig::new(0x18DA_F110, 0x18DA_10F1)
+                .with_message_type(MessageType::Extended)
+                .with_addressing(Addressing::Extended {
+                    target_address: 0x10,
+                    source_address: 0xF1,
+                })
+                .with_padding(0x55),
+            IsoTpConfig::new(0x18DA_10F1, 0x18DA_F110)
+                .with_message_type(MessageType::Extended)
+                .with_addressing(Addressing::Extended {
+                    target_address: 0xF1,
+                    source_address: 0x10,
+                }),
+        );
+        assert_eq!(transfer(tester, ecu, payload.clone()), payload);
+    }
+
+    #[test]
+    fn multi_frame_003() {
+        let payload = (0..5000u32).map(|i| i as u8).collect::<Vec<_>>();
+        let (a, b) = pipe();
+        let tester = IsoTpChannel::new_fd(
+            a,
+            IsoTpConfig::new(0x7E0, 0x7E8)
+                .with_addressing(Addressing::Mixed {
+                    address_extension: 0x42,
+                })
+                .with_tx_dl(64),
+        );
+        let ecu = IsoTpChannel::new_fd(
+            b,
+            IsoTpConfig::new(0x7E8, 0x7E0)
+                .with_addressing(Addressing::Mixed {
+                    address_extension: 0x42,
+                })
+                .with_tx_dl(64)
+                .with_max_rx_length(8192),
+        );
+        assert_eq!(tester.max_single_frame_length(), 61);
+        assert_eq!(transfer(tester, ecu, payload.clone()), payload);
+    }
+
+    #[test]
+    fn multi_frame_004() {
+        // a single frame with an addressing byte carries 6 bytes, 7 need a first frame
+        let payload = (0..7u8).collect::<Vec<_>>();
+        let (tester, ecu) = channels(
+            IsoTpConfig::new(0x7E0, 0x7E8).with_addressing(Addressing::Extended {
+                target_address: 0x10,
+                source_address: 0xF1,
+            }),
+            IsoTpConfig::new(0x7E8, 0x7E0).with_addressing(Addressing::Extended {
+                target_address: 0xF1,
+                source_address: 0x10,
+            }),
+        );
+        assert_eq!(tester.max_single_frame_length(), 6);
+        assert_eq!(transfer(tester, ecu, payload.clone()), payload);
+
+        let mixed = Addressing::Mixed {
+            address_extension: 0x42,
+        };
+        let (tester, ecu) = channels(
+            IsoTpConfig::new(0x7E0, 0x7E8).with_addressing(mixed),
+            IsoTpConfig::new(0x7E8, 0x7E0).with_addressing(mixed),
+        );
+        assert_eq!(transfer(tester, ecu, payload.clone()), payload);
+    }
+
+    #[test]
+    fn overflow_001() {
+        let payload = vec![0u8; 200];
+        let (mut tester, ecu) = channels(
+            IsoTpConfig::new(0x7E0, 0x7E8),
+            IsoTpConfig::new(0x7E8, 0x7E0).with_max_rx_length(100),
+        );
+
+        let receiver = thread::spawn(move || {
+            let mut ecu = ecu;
+            ecu.recv_timeout(Duration::from_secs(2))
+        });
+        assert_eq!(tester.send(&payload), Err(IsoTpError::ReceiverOverflow));
+        assert_eq!(receiver.join().unwrap(), Err(IsoTpError::BufferOverflow));
+    }
+
+    #[test]
+    fn reassembler_001() {
+        let mut reassembler = Reassembler::new(&IsoTpConfig::new(0x7E8, 0x7E0));
+        assert!(matches!(
+            reassembler.on_frame(&[0x10, 0x0A, 0, 1, 2, 3, 4, 5]),
+            Ok(RxEvent::FlowControl(_))
+        ));
+        assert_eq!(
+            reassembler.on_frame(&[0x22, 6, 7, 8, 9]),
+            Err(IsoTpError::WrongSequenceNumber {
+                expected: 1,
+                received: 2
+            })
+        );
+        assert!(!reassembler.is_receiving());
+    }
+
+    #[test]
+    fn reassembler_002() {
+        let mut reassembler = Reassembler::new(&IsoTpConfig::new(0x7E8, 0x7E0));
+        assert_eq!(
+            reassembler.on_frame(&[0x10, 0x07, 0, 1, 2, 3, 4, 5]),
+            Ok(RxEvent::Pending)
+        );
+        assert_eq!(
+            reassembler.on_frame(&[0x10, 0x00, 0x00, 0x00, 0x0F, 0xFF, 0, 1]),
+            Ok(RxEvent::Pending)
+        );
+        assert!(!reassembler.is_receiving());
+
+        assert!(matches!(
+            reassembler.on_frame(&[0x10, 0x08, 0, 1, 2, 3, 4, 5]),
+            Ok(RxEvent::FlowControl(_))
+        ));
+        assert_eq!(
+            reassembler.on_frame(&[0x21, 6, 7]),
+            Ok(RxEvent::Complete((0..8).collect()))
+        );
+        assert!(!reassembler.is_receiving());
+
+        // the addressing byte leaves 6 bytes for a single frame
+        let config = IsoTpConfig::new(0x7E8, 0x7E0).with_addressing(Addressing::Mixed {
+            address_extension: 0x42,
+        });
+        let mut reassembler = Reassembler::new(&config);
+        assert!(matches!(
+            reassembler.on_frame(&[0x10, 0x07, 0, 1, 2, 3, 4]),
+            Ok(RxEvent::FlowControl(_))
+        ));
+    }
+}
@@ -0,0 +1,891 @@
+//! SAE J1939 network layer.
+//!
+//! [J1939Id] splits 29-bit identifiers into priority, PGN, destination and source address. A
+//! [J1939Node] claims an address on the bus, defends it against other nodes and sends and receives
+//! messages of up to 1785 bytes using the transport protocol (BAM for broadcasts, RTS/CTS for
+//! messages to a single node).
+//!
+//! The node is driven by its caller: incoming frames are processed while waiting in
+//! [recv_timeout](J1939Node::recv_timeout), [send](J1939Node::send) and
+//! [claim_address](J1939Node::claim_address).
+
+use crate::error::PcanError;
+use crate::socket::{CanFrame, MessageType, RecvCan, SendCan};
+use std::collections::{BTreeMap, VecDeque};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, PartialEq)]
+pub enum J1939Error {
+    Pcan(PcanError),
+    /// The node has no address, call [claim_address](J1939Node::claim_address) first.
+    AddressNotClaimed,
+    /// Another node with a higher priority NAME holds the address and no other address could be
+    /// claimed.
+    CannotClaim,
+    /// The peer aborted the transfer.
+    Aborted(AbortReason),
+    Timeout,
+    PayloadTooLarge,
+}
+
+impl From<PcanError> for J1939Error {
+    fn from(value: PcanError) -> Self {
+        J1939Error::Pcan(value)
+    }
+}
+
+/* Parameter groups */
+
+pub const PGN_REQUEST: u32 = 0xEA00;
+pub const PGN_ADDRESS_CLAIMED: u32 = 0xEE00;
+pub const PGN_TP_CM: u32 = 0xEC00;
+pub const PGN_TP_DT: u32 = 0xEB00;
+
+/// Destination address of broadcasts.
+pub const GLOBAL_ADDRESS: u8 = 0xFF;
+/// Source address of nodes without an address.
+pub const NULL_ADDRESS: u8 = 0xFE;
+
+/// Largest message the transport protocol can carry.
+pub const MAX_TP_LENGTH: usize = 255 * 7;
+
+/// A 29-bit J1939 identifier.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub struct J1939Id {
+    pub priority: u8,
+    /// Extended data page.
+    pub edp: bool,
+    /// Data page.
+    pub dp: bool,
+    /// PDU format.
+    pub pf: u8,
+    /// PDU specific, the destination address if `pf` is below 240, else the group extension.
+    pub ps: u8,
+    /// Source address.
+    pub sa: u8,
+}
+
+impl J1939Id {
+    /// Creates an identifier. `destination` is only used for PDU1 parameter groups.
+    pub fn new(priority: u8, pgn: u32, destination: u8, source: u8) -> J1939Id {
+        let pf = (pgn >> 8) as u8;
+        J1939Id {
+            priority: priority & 0x07,
+            edp: pgn & 0x2_0000 != 0,
+            dp: pgn & 0x1_0000 != 0,
+            pf,
+            ps: if pf < 240 { destination } else { pgn as u8 },
+            sa: source,
+        }
+    }
+
+    pub fn from_can_id(can_id: u32) -> J1939Id {
+        J1939Id {
+            priority: ((can_id >> 26) & 0x07) as u8,
+            edp: can_id & (1 << 25) != 0,
+            dp: can_id & (1 << 24) != 0,
+            pf: (can_id >> 16) as u8,
+            ps: (can_id >> 8) as u8,
+            sa: can_id as u8,
+        }
+    }
+
+    pub fn can_id(&self) -> u32 {
+        ((self.priority as u32 & 0x07) << 26)
+            | ((self.edp as u32) << 25)
+            | ((self.dp as u32) << 24)
+            | ((self.pf as u32) << 16)
+            | ((self.ps as u32) << 8)
+            | self.sa as u32
+    }
+
+    /// Returns `true` for PDU1 identifiers, which carry a destination address.
+    pub fn is_pdu1(&self) -> bool {
+        self.pf < 240
+    }
+
+    /// Parameter group number, with the destination address of PDU1 identifiers cleared.
+    pub fn pgn(&self) -> u32 {
+        let ps = if self.is_pdu1() { 0 } else { self.ps as u32 };
+        ((self.edp as u32) << 17) | ((self.dp as u32) << 16) | ((self.pf as u32) << 8) | ps
+    }
+
+    pub fn destination(&self) -> u8 {
+        if self.is_pdu1() {
+            self.ps
+        } else {
+            GLOBAL_ADDRESS
+        }
+    }
+}
+
+/// The 64-bit NAME identifying a node during address claiming. The lower value wins.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Default)]
+pub struct Name {
+    pub identity_number: u32,
+    pub manufacturer_code: u16,
+    pub ecu_instance: u8,
+    pub function_instance: u8,
+    pub function: u8,
+    pub vehicle_system: u8,
+    pub vehicle_system_instance: u8,
+    pub industry_group: u8,
+    pub arbitrary_address_capable: bool,
+}
+
+impl From<Name> for u64 {
+    fn from(value: Name) -> Self {
+        (value.identity_number as u64 & 0x1F_FFFF)
+            | ((value.manufacturer_code as u64 & 0x7FF) << 21)
+            | ((value.ecu_instance as u64 & 0x07) << 32)
+            | ((value.function_instance as u64 & 0x1F) << 35)
+            | ((value.function as u64) << 40)
+            | ((value.vehicle_system as u64 & 0x7F) << 49)
+            | ((value.vehicle_system_instance as u64 & 0x0F) << 56)
+            | ((value.industry_group as u64 & 0x07) << 60)
+            | ((value.arbitrary_address_capable as u64) << 63)
+    }
+}
+
+impl From<u64> for Name {
+    fn from(value: u64) -> Self {
+        Name {
+            identity_number: (value & 0x1F_FFFF) as u32,
+            manufacturer_code: ((value >> 21) & 0x7FF) as u16,
+            ecu_instance: ((value >> 32) & 0x07) as u8,
+            function_instance: ((value >> 35) & 0x1F) as u8,
+            function: (value >> 40) as u8,
+            vehicle_system: ((value >> 49) & 0x7F) as u8,
+            vehicle_system_instance: ((value >> 56) & 0x0F) as u8,
+            industry_group: ((value >> 60) & 0x07) as u8,
+            arbitrary_address_capable: value >> 63 != 0,
+        }
+    }
+}
+
+/// Reason given in a transport protocol abort.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum AbortReason {
+    AlreadyInSession,
+    ResourcesNeeded,
+    Timeout,
+    CtsWhileTransferring,
+    RetransmitLimit,
+    UnexpectedDataTransfer,
+    BadSequenceNumber,
+    DuplicateSequenceNumber,
+    MessageTooLarge,
+    Other(u8),
+}
+
+impl From<u8> for AbortReason {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => AbortReason::AlreadyInSession,
+            2 => AbortReason::ResourcesNeeded,
+            3 => AbortReason::Timeout,
+            4 => AbortReason::CtsWhileTransferring,
+            5 => AbortReason::RetransmitLimit,
+            6 => AbortReason::UnexpectedDataTransfer,
+            7 => AbortReason::BadSequenceNumber,
+            8 => AbortReason::DuplicateSequenceNumber,
+            9 => AbortReason::MessageTooLarge,
+            _ => AbortReason::Other(value),
+        }
+    }
+}
+
+impl From<AbortReason> for u8 {
+    fn from(value: AbortReason) -> Self {
+        match value {
+            AbortReason::AlreadyInSession => 1,
+            AbortReason::ResourcesNeeded => 2,
+            AbortReason::Timeout => 3,
+            AbortReason::CtsWhileTransferring => 4,
+            AbortReason::RetransmitLimit => 5,
+            AbortReason::UnexpectedDataTransfer => 6,
+            AbortReason::BadSequenceNumber => 7,
+            AbortReason::DuplicateSequenceNumber => 8,
+            AbortReason::MessageTooLarge => 9,
+            AbortReason::Other(value) => value,
+        }
+    }
+}
+
+/// A received message, reassembled if it was sent with the transport protocol.
+#[derive(Debug, PartialEq, Clone)]
+pub struct J1939Message {
+    pub pgn: u32,
+    pub priority: u8,
+    pub source: u8,
+    pub destination: u8,
+    pub data: Vec<u8>,
+}
+
+impl J1939Message {
+    /// Returns the parameter group requested if this is a request.
+    pub fn requested_pgn(&self) -> Option<u32> {
+        match self.data.as_slice() {
+            [low, middle, high, ..] if self.pgn == PGN_REQUEST => {
+                Some(u32::from_le_bytes([*low, *middle, *high, 0]))
+            }
+            _ => None,
+        }
+    }
+}
+
+/* Transport protocol */
+
+const TP_RTS: u8 = 16;
+const TP_CTS: u8 = 17;
+const TP_END_OF_MESSAGE_ACK: u8 = 19;
+const TP_BAM: u8 = 32;
+const TP_ABORT: u8 = 255;
+
+const TP_PRIORITY: u8 = 7;
+
+/// Time between data packets of a receive session.
+const T1: Duration = Duration::from_millis(750);
+/// Time until data packets arrive after a CTS.
+const T2: Duration = Duration::from_millis(1250);
+/// Time until a CTS or end of message acknowledge arrives after the last packet.
+const T3: Duration = Duration::from_millis(1250);
+/// Time until the next CTS arrives after a CTS holding the connection open.
+const T4: Duration = Duration::from_millis(1050);
+
+/// Time to wait for contending claims after an address claim.
+const ADDRESS_CLAIM_TIMEOUT: Duration = Duration::from_millis(250);
+
+const POLL_INTERVAL: Duration = Duration::from_micros(100);
+
+struct Session {
+    pgn: u32,
+    priority: u8,
+    size: usize,
+    packets: u8,
+    max_packets: u8,
+    next: u8,
+    window_end: u8,
+    data: Vec<u8>,
+    deadline: Instant,
+}
+
+fn pgn_bytes(pgn: u32) -> [u8; 3] {
+    let [low, middle, high, _] = pgn.to_le_bytes();
+    [low, middle, high]
+}
+
+/* J1939Node */
+
+/// A node (controller application) on a J1939 network.
+pub struct J1939Node<S> {
+    socket: S,
+    name: Name,
+    preferred_address: u8,
+    address: Option<u8>,
+    claimed: BTreeMap<u8, u64>,
+    sessions: BTreeMap<(u8, u8), Session>,
+    control: VecDeque<(u8, [u8; 8])>,
+    inbox: VecDeque<J1939Message>,
+    bam_interval: Duration,
+}
+
+impl<S: SendCan + RecvCan> J1939Node<S> {
+    /// Creates a node which will try to claim `preferred_address`.
+    pub fn new(socket: S, name: Name, preferred_address: u8) -> J1939Node<S> {
+        J1939Node {
+            socket,
+            name,
+            preferred_address,
+            address: None,
+            claimed: BTreeMap::new(),
+            sessions: BTreeMap::new(),
+            control: VecDeque::new(),
+            inbox: VecDeque::new(),
+            bam_interval: Duration::from_millis(50),
+        }
+    }
+
+    /// Sets the time between the data packets of a broadcast, 50 ms by default.
+    pub fn with_bam_interval(mut self, bam_interval: Duration) -> J1939Node<S> {
+        self.bam_interval = bam_interval;
+        self
+    }
+
+    pub fn name(&self) -> Name {
+        self.name
+    }
+
+    /// Returns the claimed address.
+    pub fn address(&self) -> Option<u8> {
+        self.address
+    }
+
+    pub fn socket(&self) -> &S {
+        &self.socket
+    }
+
+    pub fn into_socket(self) -> S {
+        self.socket
+    }
+
+    fn transmit(&self, id: J1939Id, data: &[u8]) -> Result<(), J1939Error> {
+        let frame = CanFrame::new(id.can_id(), MessageType::Extended, data)
+            .map_err(|_| J1939Error::PayloadTooLarge)?;
+        Ok(self.socket.send(frame)?)
+    }
+
+    fn transmit_control(
+        &self,
+        destination: u8,
+        control: [u8; 5],
+        pgn: u32,
+    ) -> Result<(), J1939Error> {
+        let source = self.address.unwrap_or(NULL_ADDRESS);
+        let mut data = [0u8; 8];
+        data[..5].copy_from_slice(&control);
+        data[5..].copy_from_slice(&pgn_bytes(pgn));
+        self.transmit(
+            J1939Id::new(TP_PRIORITY, PGN_TP_CM, destination, source),
+            &data,
+        )
+    }
+
+    fn transmit_abort(
+        &self,
+        destination: u8,
+        reason: AbortReason,
+        pgn: u32,
+    ) -> Result<(), J1939Error> {
+        self.transmit_control(
+            destination,
+            [TP_ABORT, reason.into(), 0xFF, 0xFF, 0xFF],
+            pgn,
+        )
+    }
+
+    fn transmit_claim(&self) -> Result<(), J1939Error> {
+        let source = self.address.unwrap_or(NULL_ADDRESS);
+        let name: u64 = self.name.into();
+        self.transmit(
+            J1939Id::new(6, PGN_ADDRESS_CLAIMED, GLOBAL_ADDRESS, source),
+            &name.to_le_bytes(),
+        )
+    }
+
+    /// Claims the preferred address, or any free address in `128..=247` if the NAME is
+    /// arbitrary address capable and the preferred one is taken by a node with a lower NAME.
+    pub fn claim_address(&mut self) -> Result<u8, J1939Error> {
+        let name: u64 = self.name.into();
+        match self.claimed.get(&self.preferred_address) {
+            Some(other) if *other < name => self.lose_address()?,
+            _ => {
+                self.address = Some(self.preferred_address);
+                self.transmit_claim()?;
+            }
+        }
+
+        loop {
+            let address = self.address;
+            let deadline = Instant::now() + ADDRESS_CLAIM_TIMEOUT;
+            while Instant::now() < deadline {
+                if !self.poll()? {
+                    thread::sleep(POLL_INTERVAL);
+                }
+            }
+
+            if address == self.address {
+                break;
+            }
+        }
+
+        self.address.ok_or(J1939Error::CannotClaim)
+    }
+
+    fn lose_address(&mut self) -> Result<(), J1939Error> {
+        let free = (128..=247).find(|address| !self.claimed.contains_key(address));
+
+        match free {
+            Some(address) if self.name.arbitrary_address_capable => {
+                self.address = Some(address);
+            }
+            _ => self.address = None,
+        }
+        self.transmit_claim()
+    }
+
+    fn on_address_claimed(&mut self, source: u8, name: u64) -> Result<(), J1939Error> {
+        let own: u64 = self.name.into();
+        if name == own {
+            return Ok(());
+        }
+
+        self.claimed.retain(|_, other| *other != name);
+        if source != NULL_ADDRESS {
+            self.claimed.insert(source, name);
+        }
+
+        if Some(source) == self.address {
+            if own < name {
+                self.transmit_claim()?;
+            } else {
+                self.lose_address()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn is_addressed(&self, destination: u8) -> bool {
+        destination == GLOBAL_ADDRESS || Some(destination) == self.address
+    }
+
+    /// Reads a single frame, if any, and processes it. Also expires stalled receive sessions.
+    fn poll(&mut self) -> Result<bool, J1939Error> {
+        self.expire_sessions()?;
+
+        let frame = match self.socket.recv_frame() {
+            Ok(frame) => frame,
+            Err(PcanError::QrcvEmpty) => return Ok(false),
+            Err(err) => return Err(J1939Error::Pcan(err)),
+        };
+
+        if frame.is_extended_frame() {
+            self.handle_frame(J1939Id::from_can_id(frame.can_id()), frame.data())?;
+        }
+        Ok(true)
+    }
+
+    fn handle_frame(&mut self, id: J1939Id, data: &[u8]) -> Result<(), J1939Error> {
+        let destination = id.destination();
+        if !self.is_addressed(destination) && id.pgn() != PGN_ADDRESS_CLAIMED {
+            return Ok(());
+        }
+
+        match id.pgn() {
+            PGN_ADDRESS_CLAIMED if data.len() == 8 => {
+                let mut name = [0u8; 8];
+                name.copy_from_slice(data);
+                self.on_address_claimed(id.sa, u64::from_le_bytes(name))
+            }
+            PGN_REQUEST if data.len() >= 3 && data[..3] == pgn_bytes(PGN_ADDRESS_CLAIMED) => {
+                self.transmit_claim()
+            }
+            PGN_TP_CM if data.len() == 8 => self.on_tp_cm(id, data),
+            PGN_TP_DT if data.len() == 8 => self.on_tp_dt(id, data),
+            pgn => {
+                self.inbox.push_back(J1939Message {
+                    pgn,
+                    priority: id.priority,
+                    source: id.sa,
+                    destination,
+                    data: data.to_vec(),
+                });
+                Ok(())
+            }
+        }
+    }
+
+    fn on_tp_cm(&mut self, id: J1939Id, data: &[u8]) -> Result<(), J1939Error> {
+        let pgn = u32::from_le_bytes([data[5], data[6], data[7], 0]);
+        let destination = id.destination();
+        let size = u16::from_le_bytes([data[1], data[2]]) as usize;
+        let packets = data[3];
+        let valid = size > 8 && size <= MAX_TP_LENGTH && packets as usize == size.div_ceil(7);
+
+        match data[0] {
+            TP_BAM if destination == GLOBAL_ADDRESS && valid => {
+                self.sessions.insert(
+                    (id.sa, GLOBAL_ADDRESS),
+                    Session {
+                        pgn,
+                        priority: id.priority,
+                        size,
+                        packets,
+                        max_packets: packets,
+                        next: 1,
+                        window_end: packets,
+                        data: Vec::with_capacity(size),
+                        deadline: Instant::now() + T1,
+                    },
+                );
+            }
+            TP_RTS if destination != GLOBAL_ADDRESS => {
+                if !valid {
+                    return self.transmit_abort(id.sa, AbortReason::MessageTooLarge, pgn);
+                }
+
+                let max_packets = data[4].max(1);
+                let window = packets.min(max_packets);
+                self.sessions.insert(
+                    (id.sa, destination),
+                    Session {
+                        pgn,
+                        priority: id.priority,
+                        size,
+                        packets,
+                        max_packets,
+                        next: 1,
+                        window_end: window,
+                        data: Vec::with_capacity(size),
+                        deadline: Instant::now() + T2,
+                    },
+                );
+                self.transmit_control(id.sa, [TP_CTS, window, 1, 0xFF, 0xFF], pgn)?;
+            }
+            TP_ABORT => {
+                self.sessions.remove(&(id.sa, destination));
+                self.control
+                    .push_back((id.sa, data.try_into().unwrap_or([0; 8])));
+            }
+            TP_CTS | TP_END_OF_MESSAGE_ACK => {
+                self.control
+                    .push_back((id.sa, data.try_into().unwrap_or([0; 8])));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn on_tp_dt(&mut self, id: J1939Id, data: &[u8]) -> Result<(), J1939Error> {
+        let key = (id.sa, id.destination());
+        let broadcast = key.1 == GLOBAL_ADDRESS;
+        let session = match self.sessions.get_mut(&key) {
+            Some(session) => session,
+            None => return Ok(()),
+        };
+
+        let sequence = data[0];
+        if sequence != session.next {
+            let pgn = session.pgn;
+            self.sessions.remove(&key);
+            if !broadcast {
+                self.transmit_abort(id.sa, AbortReason::BadSequenceNumber, pgn)?;
+            }
+            return Ok(());
+        }
+
+        let remaining = session.size - session.data.len();
+        session
+            .data
+            .extend_from_slice(&data[1..8.min(1 + remaining)]);
+        session.next = session.next.wrapping_add(1);
+        session.deadline = Instant::now() + T1;
+
+        if session.data.len() >= session.size {
+            let message = J1939Message {
+                pgn: session.pgn,
+                priority: session.priority,
+                source: id.sa,
+                destination: key.1,
+                data: std::mem::take(&mut session.data),
+            };
+            let [low, high] = (session.size as u16).to_le_bytes();
+            let packets = session.packets;
+            self.sessions.remove(&key);
+            if !broadcast {
+                self.transmit_control(
+                    id.sa,
+                    [TP_END_OF_MESSAGE_ACK, low, high, packets, 0xFF],
+                    message.pgn,
+                )?;
+            }
+            self.inbox.push_back(message);
+        } else if !broadcast && sequence == session.window_end {
+            let window = (session.packets - sequence).min(session.max_packets);
+            session.window_end = sequence + window;
+            session.deadline = Instant::now() + T2;
+            let (next, pgn) = (session.next, session.pgn);
+            self.transmit_control(id.sa, [TP_CTS, window, next, 0xFF, 0xFF], pgn)?;
+        }
+        Ok(())
+    }
+
+    fn expire_sessions(&mut self) -> Result<(), J1939Error> {
+        let now = Instant::now();
+        let expired = self
+            .sessions
+            .iter()
+            .filter(|(_, session)| session.deadline <= now)
+            .map(|(key, session)| (*key, session.pgn))
+            .collect::<Vec<_>>();
+
+        for ((source, destination), pgn) in expired {
+            self.sessions.remove(&(source, destination));
+            if destination != GLOBAL_ADDRESS {
+                self.transmit_abort(source, AbortReason::Timeout, pgn)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits for a transport protocol control frame of `source` concerning `pgn`.
+    fn wait_control(
+        &mut self,
+        source: u8,
+        pgn: u32,
+        deadline: Instant,
+    ) -> Result<[u8; 8], J1939Error> {
+        let pgn = pgn_bytes(pgn);
+        loop {
+            let position = self
+                .control
+                .iter()
+                .position(|(from, data)| *from == source && data[5..] == pgn);
+            if let Some(control) = position.and_then(|position| self.control.remove(position)) {
+                return Ok(control.1);
+            }
+
+            if !self.poll()? {
+                if Instant::now() >= deadline {
+                    return Err(J1939Error::Timeout);
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+
+    fn transmit_packet(
+        &self,
+        destination: u8,
+        data: &[u8],
+        sequence: u8,
+    ) -> Result<(), J1939Error> {
+        let source = self.address.unwrap_or(NULL_ADDRESS);
+        let start = (sequence as usize - 1) * 7;
+        let end = (start + 7).min(data.len());
+
+        let mut packet = [0xFF; 8];
+        packet[0] = sequence;
+        packet[1..1 + end - start].copy_from_slice(&data[start..end]);
+        self.transmit(
+            J1939Id::new(TP_PRIORITY, PGN_TP_DT, destination, source),
+            &packet,
+        )
+    }
+
+    /// Sends a message to `destination`, which may be [GLOBAL_ADDRESS]. Messages longer than 8
+    /// bytes are sent with the transport protocol.
+    pub fn send(
+        &mut self,
+        pgn: u32,
+        priority: u8,
+        destination: u8,
+        data: &[u8],
+    ) -> Result<(), J1939Error> {
+        let source = self.address.ok_or(J1939Error::AddressNotClaimed)?;
+
+        if data.len() <= 8 {
+            return self.transmit(J1939Id::new(priority, pgn, destination, source), data);
+        }
+        if data.len() > MAX_TP_LENGTH {
+            return Err(J1939Error::PayloadTooLarge);
+        }
+
+        let packets = data.len().div_ceil(7) as u8;
+        let [low, high] = (data.len() as u16).to_le_bytes();
+
+        if destination == GLOBAL_ADDRESS {
+            self.transmit_control(destination, [TP_BAM, low, high, packets, 0xFF], pgn)?;
+            for sequence in 1..=packets {
+                thread::sleep(self.bam_interval);
+                self.transmit_packet(destination, data, sequence)?;
+            }
+            return Ok(());
+        }
+
+        self.transmit_control(destination, [TP_RTS, low, high, packets, 0xFF], pgn)?;
+        let mut deadline = Instant::now() + T3;
+
+        loop {
+            let control = match self.wait_control(destination, pgn, deadline) {
+                Ok(control) => control,
+                Err(J1939Error::Timeout) => {
+                    self.transmit_abort(destination, AbortReason::Timeout, pgn)?;
+                    return Err(J1939Error::Timeout);
+                }
+                Err(err) => return Err(err),
+            };
+
+            match control[0] {
+                TP_CTS if control[1] == 0 => deadline = Instant::now() + T4,
+                TP_CTS => {
+                    let first = control[2].max(1) as u16;
+                    let last = (first + control[1] as u16 - 1).min(packets as u16);
+                    for sequence in first..=last {
+                        self.transmit_packet(destination, data, sequence as u8)?;
+                    }
+                    deadline = Instant::now() + T3;
+                }
+                TP_END_OF_MESSAGE_ACK => return Ok(()),
+                TP_ABORT => return Err(J1939Error::Aborted(AbortReason::from(control[1]))),
+                _ => {}
+            }
+        }
+    }
+
+    /// Sends a request for `pgn` to `destination`.
+    pub fn request(&mut self, pgn: u32, destination: u8) -> Result<(), J1939Error> {
+        self.send(PGN_REQUEST, 6, destination, &pgn_bytes(pgn))
+    }
+
+    /// Sends a request for `pgn` and waits for the response. Other messages received meanwhile
+    /// stay queued.
+    pub fn request_and_wait(
+        &mut self,
+        pgn: u32,
+        destination: u8,
+        timeout: Duration,
+    ) -> Result<J1939Message, J1939Error> {
+        self.request(pgn, destination)?;
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let position = self.inbox.iter().position(|message| {
+                message.pgn == pgn
+                    && (destination == GLOBAL_ADDRESS || message.source == destination)
+            });
+            if let Some(message) = position.and_then(|position| self.inbox.remove(position)) {
+                return Ok(message);
+            }
+
+            if !self.poll()? {
+                if Instant::now() >= deadline {
+                    return Err(J1939Error::Timeout);
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+
+    /// Waits for a message addressed to this node or broadcast.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<J1939Message, J1939Error> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            if let Some(message) = self.inbox.pop_front() {
+                return Ok(message);
+            }
+
+            if !self.poll()? {
+                if Instant::now() >= deadline {
+                    return Err(J1939Error::Timeout);
+                }
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::socket::pipe::{PipeBus, PipeSocket};
+
+    fn name(identity_number: u32, arbitrary_address_capable: bool) -> Name {
+        Name {
+            identity_number,
+            manufacturer_code: 0x123,
+            function: 0x81,
+            industry_group: 2,
+            arbitrary_address_capable,
+            ..Name::default()
+        }
+    }
+
+    fn node(bus: &PipeBus, identity_number: u32, address: u8) -> J1939Node<PipeSocket> {
+        J1939Node::new(bus.connect(), name(identity_number, true), address)
+            .with_bam_interval(Duration::from_millis(1))
+    }
+
+    #[test]
+    fn j1939_id_001() {
+        let id = J1939Id::from_can_id(0x18FE_F100);
+        assert_eq!(id.priority, 6);
+        assert_eq!(id.pgn(), 0xFEF1);
+        assert_eq!(id.destination(), GLOBAL_ADDRESS);
+        assert_eq!(id.can_id(), 0x18FE_F100);
+
+        let id = J1939Id::new(3, PGN_REQUEST, 0x25, 0xF9);
+        assert_eq!(id.can_id(), 0x0CEA_25F9);
+        assert_eq!(id.pgn(), PGN_REQUEST);
+        assert_eq!(id.destination(), 0x25);
+    }
+
+    #[test]
+    fn name_001() {
+        let name = name(0x1_2345, true);
+        let value: u64 = name.into();
+        assert_eq!(value >> 63, 1);
+        assert_eq!(Name::from(value), name);
+    }
+
+    #[test]
+    fn address_claim_001() {
+        let bus = PipeBus::new();
+        let mut first = J1939Node::new(bus.connect(), name(1, false), 0x80);
+        let mut second = node(&bus, 2, 0x80);
+        let mut third = J1939Node::new(bus.connect(), name(3, false), 0x80);
+
+        assert_eq!(first.claim_address(), Ok(0x80));
+        assert_eq!(second.claim_address(), Ok(0x81));
+        assert_eq!(third.claim_address(), Err(J1939Error::CannotClaim));
+        assert_eq!(
+            third.send(0xFEF1, 6, GLOBAL_ADDRESS, &[0]),
+            Err(J1939Error::AddressNotClaimed)
+        );
+    }
+
+    #[test]
+    fn transport_001() {
+        let bus = PipeBus::new();
+        let mut sender = node(&bus, 1, 0x10);
+        let mut receiver = node(&bus, 2, 0x20);
+        sender.claim_address().unwrap();
+        receiver.claim_address().unwrap();
+
+        let payload = (0..100u8).collect::<Vec<_>>();
+        let expected = payload.clone();
+        let handle = thread::spawn(move || {
+            sender.send(0xFECA, 6, GLOBAL_ADDRESS, &payload)?;
+            sender.send(0xEF00, 6, 0x20, &payload)?;
+            Ok::<_, J1939Error>(sender)
+        });
+
+        let broadcast = receiver.recv_timeout(Duration::from_secs(2)).unwrap();
+        assert_eq!(broadcast.pgn, 0xFECA);
+        assert_eq!(broadcast.destination, GLOBAL_ADDRESS);
+        assert_eq!(broadcast.data, expected);
+
+        let directed = receiver.recv_timeout(Duration::from_secs(2)).unwrap();
+        assert_eq!(directed.pgn, 0xEF00);
+        assert_eq!(directed.source, 0x10);
+        assert_eq!(directed.destination, 0x20);
+        assert_eq!(directed.data, expected);
+
+        assert!(handle.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn request_001() {
+        let bus = PipeBus::new();
+        let mut requester = node(&bus, 1, 0x10);
+        let mut responder = node(&bus, 2, 0x20);
+        requester.claim_address().unwrap();
+        responder.claim_address().unwrap();
+
+        let handle = thread::spawn(move || {
+            let request = responder.recv_timeout(Duration::from_secs(2)).unwrap();
+            assert_eq!(request.requested_pgn(), Some(0xFEDA));
+            responder
+                .send(0xFEDA, 6, GLOBAL_ADDRESS, b"1.2.3*")
+                .unwrap();
+        });
+
+        let response = requester
+            .request_and_wait(0xFEDA, 0x20, Duration::from_secs(2))
+            .unwrap();
+        assert_eq!(response.data, b"1.2.3*".to_vec());
+        handle.join().unwrap();
+    }
+}
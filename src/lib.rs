@@ -3,15 +3,29 @@
 
 #[warn(dead_code)]
 pub mod bus;
+pub mod canopen;
+pub mod ccp;
 mod channel;
+pub mod cyclic;
+pub mod dbc;
 pub mod df;
+pub mod dispatch;
 pub mod error;
 pub mod hw;
 pub mod info;
 pub mod io;
+pub mod isotp;
+pub mod j1939;
 pub mod log;
+pub mod nmea2000;
+pub mod obd;
+pub mod queue;
 pub mod socket;
 pub mod special;
+pub mod stats;
 pub mod trace;
+pub mod tracefile;
+pub mod uds;
+pub mod xcp;
 
 use pcan_basic_sys as pcan;
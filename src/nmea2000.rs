@@ -0,0 +1,688 @@
+//! NMEA 2000 network layer.
+//!
+//! An [Nmea2000Node] claims an address like a [J1939Node] (ISO 11783-5) and additionally sends and
+//! reassembles fast-packet parameter groups of up to 223 bytes. A [Decoder] turns the payload of
+//! the parameter groups it is configured for into typed [ParameterGroup]s.
+
+use crate::j1939::{J1939Error, J1939Message, J1939Node, Name};
+use crate::socket::{RecvCan, SendCan};
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, Instant};
+
+/* Parameter groups */
+
+pub const PGN_VESSEL_HEADING: u32 = 127250;
+pub const PGN_ENGINE_RAPID: u32 = 127488;
+pub const PGN_ENGINE_DYNAMIC: u32 = 127489;
+pub const PGN_POSITION_RAPID: u32 = 129025;
+pub const PGN_GNSS_POSITION: u32 = 129029;
+pub const PGN_WIND_DATA: u32 = 130306;
+
+/// Largest payload of a fast-packet: 6 bytes in the first and 7 bytes in each of the 31 following
+/// frames.
+pub const MAX_FAST_PACKET_LENGTH: usize = 223;
+
+/// Parameter groups sent as fast-packets by default.
+pub const DEFAULT_FAST_PACKET_PGNS: [u32; 12] = [
+    126208, // group function
+    126464, // PGN list
+    126996, // product information
+    126998, // configuration information
+    127489, // engine parameters, dynamic
+    127496, // trip parameters, vessel
+    127497, // trip parameters, engine
+    128275, // distance log
+    129029, // GNSS position data
+    129038, // AIS class A position report
+    129039, // AIS class B position report
+    129540, // GNSS satellites in view
+];
+
+/* Fast-packets */
+
+/// Splits `data` into the frames of a fast-packet with the sequence counter `sequence` (0 to 7).
+pub fn fast_packet_frames(sequence: u8, data: &[u8]) -> Result<Vec<[u8; 8]>, J1939Error> {
+    if data.len() > MAX_FAST_PACKET_LENGTH {
+        return Err(J1939Error::PayloadTooLarge);
+    }
+
+    let sequence = (sequence & 0x07) << 5;
+    let mut frames = Vec::new();
+
+    let mut first = [0xFF; 8];
+    first[0] = sequence;
+    first[1] = data.len() as u8;
+    let length = data.len().min(6);
+    first[2..2 + length].copy_from_slice(&data[..length]);
+    frames.push(first);
+
+    for (counter, chunk) in data[length..].chunks(7).enumerate() {
+        let mut frame = [0xFF; 8];
+        frame[0] = sequence | (counter as u8 + 1);
+        frame[1..1 + chunk.len()].copy_from_slice(chunk);
+        frames.push(frame);
+    }
+    Ok(frames)
+}
+
+struct Transfer {
+    sequence: u8,
+    next: u8,
+    length: usize,
+    data: Vec<u8>,
+}
+
+/// Reassembles fast-packets, one transfer per source address and parameter group.
+///
+/// A frame out of order discards the transfer, the first frame of a new transfer replaces it.
+#[derive(Default)]
+pub struct FastPacketAssembler {
+    transfers: BTreeMap<(u8, u32), Transfer>,
+}
+
+impl FastPacketAssembler {
+    pub fn new() -> FastPacketAssembler {
+        FastPacketAssembler::default()
+    }
+
+    /// Processes a frame and returns the payload once the fast-packet is complete.
+    pub fn push(&mut self, source: u8, pgn: u32, frame: &[u8]) -> Option<Vec<u8>> {
+        if frame.len() < 2 {
+            return None;
+        }
+
+        let sequence = frame[0] >> 5;
+        let counter = frame[0] & 0x1F;
+        let key = (source, pgn);
+
+        if counter == 0 {
+            let length = (frame[1] as usize).min(MAX_FAST_PACKET_LENGTH);
+            let mut data = frame[2..].to_vec();
+            if data.len() >= length {
+                data.truncate(length);
+                self.transfers.remove(&key);
+                return Some(data);
+            }
+            self.transfers.insert(
+                key,
+                Transfer {
+                    sequence,
+                    next: 1,
+                    length,
+                    data,
+                },
+            );
+            return None;
+        }
+
+        let transfer = self.transfers.get_mut(&key)?;
+        if transfer.sequence != sequence || transfer.next != counter {
+            self.transfers.remove(&key);
+            return None;
+        }
+
+        transfer.data.extend_from_slice(&frame[1..]);
+        transfer.next += 1;
+        if transfer.data.len() < transfer.length {
+            return None;
+        }
+
+        let mut transfer = self.transfers.remove(&key)?;
+        transfer.data.truncate(transfer.length);
+        Some(transfer.data)
+    }
+}
+
+/* Nmea2000Node */
+
+/// A device on an NMEA 2000 network.
+pub struct Nmea2000Node<S> {
+    node: J1939Node<S>,
+    fast_packet_pgns: BTreeSet<u32>,
+    assembler: FastPacketAssembler,
+    sequences: BTreeMap<u32, u8>,
+}
+
+impl<S: SendCan + RecvCan> Nmea2000Node<S> {
+    /// Creates a node which will try to claim `preferred_address`. The NAME of NMEA 2000 devices
+    /// uses industry group 4.
+    pub fn new(socket: S, name: Name, preferred_address: u8) -> Nmea2000Node<S> {
+        Nmea2000Node {
+            node: J1939Node::new(socket, name, preferred_address),
+            fast_packet_pgns: DEFAULT_FAST_PACKET_PGNS.into_iter().collect(),
+            assembler: FastPacketAssembler::new(),
+            sequences: BTreeMap::new(),
+        }
+    }
+
+    /// Sends and reassembles `pgn` as fast-packet.
+    pub fn with_fast_packet_pgn(mut self, pgn: u32) -> Nmea2000Node<S> {
+        self.fast_packet_pgns.insert(pgn);
+        self
+    }
+
+    pub fn is_fast_packet_pgn(&self, pgn: u32) -> bool {
+        self.fast_packet_pgns.contains(&pgn)
+    }
+
+    pub fn name(&self) -> Name {
+        self.node.name()
+    }
+
+    /// Returns the claimed address.
+    pub fn address(&self) -> Option<u8> {
+        self.node.address()
+    }
+
+    pub fn socket(&self) -> &S {
+        self.node.socket()
+    }
+
+    pub fn into_socket(self) -> S {
+        self.node.into_socket()
+    }
+
+    /// Claims an address, see [J1939Node::claim_address].
+    pub fn claim_address(&mut self) -> Result<u8, J1939Error> {
+        self.node.claim_address()
+    }
+
+    /// Sends a message to `destination`. Fast-packet parameter groups are split into frames with
+    /// a sequence counter incremented per parameter group, others are sent like J1939 messages.
+    pub fn send(
+        &mut self,
+        pgn: u32,
+        priority: u8,
+        destination: u8,
+        data: &[u8],
+    ) -> Result<(), J1939Error> {
+        if !self.is_fast_packet_pgn(pgn) {
+            return self.node.send(pgn, priority, destination, data);
+        }
+
+        let sequence = self.sequences.entry(pgn).or_insert(0);
+        let frames = fast_packet_frames(*sequence, data)?;
+        *sequence = (*sequence + 1) & 0x07;
+
+        for frame in frames {
+            self.node.send(pgn, priority, destination, &frame)?;
+        }
+        Ok(())
+    }
+
+    /// Waits for a message addressed to this node or broadcast, reassembling fast-packets.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<J1939Message, J1939Error> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let mut message = self.node.recv_timeout(remaining)?;
+            if !self.is_fast_packet_pgn(message.pgn) {
+                return Ok(message);
+            }
+
+            if let Some(data) = self
+                .assembler
+                .push(message.source, message.pgn, &message.data)
+            {
+                message.data = data;
+                return Ok(message);
+            }
+        }
+    }
+}
+
+/* Decoding */
+
+/// Position from PGN 129025, in degrees.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Position {
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+}
+
+/// GNSS position data from PGN 129029.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct GnssPosition {
+    pub sid: u8,
+    pub days_since_1970: Option<u16>,
+    /// Seconds since midnight.
+    pub seconds_since_midnight: Option<f64>,
+    /// Latitude in degrees.
+    pub latitude: Option<f64>,
+    /// Longitude in degrees.
+    pub longitude: Option<f64>,
+    /// Altitude in m.
+    pub altitude: Option<f64>,
+    pub satellites: Option<u8>,
+    pub hdop: Option<f64>,
+    pub pdop: Option<f64>,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum HeadingReference {
+    True,
+    Magnetic,
+    Other(u8),
+}
+
+/// Vessel heading from PGN 127250, angles in radians.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Heading {
+    pub sid: u8,
+    pub heading: Option<f64>,
+    pub deviation: Option<f64>,
+    pub variation: Option<f64>,
+    pub reference: HeadingReference,
+}
+
+/// Rapidly updated engine parameters from PGN 127488.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct EngineRapid {
+    pub instance: u8,
+    /// Engine speed in rpm.
+    pub speed: Option<f64>,
+    /// Boost pressure in Pa.
+    pub boost_pressure: Option<f64>,
+    /// Tilt or trim in %.
+    pub tilt_trim: Option<i8>,
+}
+
+/// Dynamic engine parameters from PGN 127489.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct EngineDynamic {
+    pub instance: u8,
+    /// Oil pressure in Pa.
+    pub oil_pressure: Option<f64>,
+    /// Oil temperature in K.
+    pub oil_temperature: Option<f64>,
+    /// Coolant temperature in K.
+    pub temperature: Option<f64>,
+    /// Alternator potential in V.
+    pub alternator_potential: Option<f64>,
+    /// Fuel rate in l/h.
+    pub fuel_rate: Option<f64>,
+    /// Total engine hours in s.
+    pub total_engine_hours: Option<u32>,
+    /// Coolant pressure in Pa.
+    pub coolant_pressure: Option<f64>,
+    /// Fuel pressure in Pa.
+    pub fuel_pressure: Option<f64>,
+    pub discrete_status_1: u16,
+    pub discrete_status_2: u16,
+    /// Engine load in %.
+    pub load: Option<i8>,
+    /// Engine torque in %.
+    pub torque: Option<i8>,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum WindReference {
+    TrueNorth,
+    Magnetic,
+    Apparent,
+    TrueBoat,
+    TrueWater,
+    Other(u8),
+}
+
+/// Wind data from PGN 130306.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Wind {
+    pub sid: u8,
+    /// Wind speed in m/s.
+    pub speed: Option<f64>,
+    /// Wind angle in radians.
+    pub angle: Option<f64>,
+    pub reference: WindReference,
+}
+
+/// A decoded parameter group.
+#[derive(Debug, PartialEq, Clone)]
+pub enum ParameterGroup {
+    Position(Position),
+    GnssPosition(GnssPosition),
+    Heading(Heading),
+    EngineRapid(EngineRapid),
+    EngineDynamic(EngineDynamic),
+    Wind(Wind),
+}
+
+/// Decodes the payload of a parameter group, `None` if it is too short.
+pub type DecodeFn = fn(&[u8]) -> Option<ParameterGroup>;
+
+/// Parameter groups to decode and how.
+///
+/// [Decoder::default] decodes all parameter groups of this module, [Decoder::new] none.
+#[derive(Clone)]
+pub struct Decoder {
+    decoders: BTreeMap<u32, DecodeFn>,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Decoder::new()
+            .with_pgn(PGN_POSITION_RAPID, decode_position)
+            .with_pgn(PGN_GNSS_POSITION, decode_gnss_position)
+            .with_pgn(PGN_VESSEL_HEADING, decode_heading)
+            .with_pgn(PGN_ENGINE_RAPID, decode_engine_rapid)
+            .with_pgn(PGN_ENGINE_DYNAMIC, decode_engine_dynamic)
+            .with_pgn(PGN_WIND_DATA, decode_wind)
+    }
+}
+
+impl Decoder {
+    pub fn new() -> Decoder {
+        Decoder {
+            decoders: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_pgn(mut self, pgn: u32, decode: DecodeFn) -> Decoder {
+        self.decoders.insert(pgn, decode);
+        self
+    }
+
+    pub fn without_pgn(mut self, pgn: u32) -> Decoder {
+        self.decoders.remove(&pgn);
+        self
+    }
+
+    pub fn decode(&self, pgn: u32, data: &[u8]) -> Option<ParameterGroup> {
+        self.decoders.get(&pgn).and_then(|decode| decode(data))
+    }
+
+    pub fn decode_message(&self, message: &J1939Message) -> Option<ParameterGroup> {
+        self.decode(message.pgn, &message.data)
+    }
+}
+
+/* Fields, the largest positive value marks data as not available */
+
+fn field<const N: usize>(data: &[u8], offset: usize) -> Option<[u8; N]> {
+    data.get(offset..offset + N)?.try_into().ok()
+}
+
+fn u16_field(data: &[u8], offset: usize, scale: f64) -> Option<f64> {
+    match u16::from_le_bytes(field(data, offset)?) {
+        u16::MAX => None,
+        value => Some(value as f64 * scale),
+    }
+}
+
+fn i16_field(data: &[u8], offset: usize, scale: f64) -> Option<f64> {
+    match i16::from_le_bytes(field(data, offset)?) {
+        i16::MAX => None,
+        value => Some(value as f64 * scale),
+    }
+}
+
+fn i32_field(data: &[u8], offset: usize, scale: f64) -> Option<f64> {
+    match i32::from_le_bytes(field(data, offset)?) {
+        i32::MAX => None,
+        value => Some(value as f64 * scale),
+    }
+}
+
+fn u32_field(data: &[u8], offset: usize) -> Option<u32> {
+    match u32::from_le_bytes(field(data, offset)?) {
+        u32::MAX => None,
+        value => Some(value),
+    }
+}
+
+fn i64_field(data: &[u8], offset: usize, scale: f64) -> Option<f64> {
+    match i64::from_le_bytes(field(data, offset)?) {
+        i64::MAX => None,
+        value => Some(value as f64 * scale),
+    }
+}
+
+fn i8_field(data: &[u8], offset: usize) -> Option<i8> {
+    match *data.get(offset)? as i8 {
+        i8::MAX => None,
+        value => Some(value),
+    }
+}
+
+pub fn decode_position(data: &[u8]) -> Option<ParameterGroup> {
+    if data.len() < 8 {
+        return None;
+    }
+    Some(ParameterGroup::Position(Position {
+        latitude: i32_field(data, 0, 1e-7),
+        longitude: i32_field(data, 4, 1e-7),
+    }))
+}
+
+pub fn decode_gnss_position(data: &[u8]) -> Option<ParameterGroup> {
+    if data.len() < 43 {
+        return None;
+    }
+    Some(ParameterGroup::GnssPosition(GnssPosition {
+        sid: data[0],
+        days_since_1970: u16_field(data, 1, 1.0).map(|days| days as u16),
+        seconds_since_midnight: u32_field(data, 3).map(|time| time as f64 * 1e-4),
+        latitude: i64_field(data, 7, 1e-16),
+        longitude: i64_field(data, 15, 1e-16),
+        altitude: i64_field(data, 23, 1e-6),
+        satellites: match data[33] {
+            u8::MAX => None,
+            satellites => Some(satellites),
+        },
+        hdop: i16_field(data, 34, 0.01),
+        pdop: i16_field(data, 36, 0.01),
+    }))
+}
+
+pub fn decode_heading(data: &[u8]) -> Option<ParameterGroup> {
+    if data.len() < 8 {
+        return None;
+    }
+    Some(ParameterGroup::Heading(Heading {
+        sid: data[0],
+        heading: u16_field(data, 1, 1e-4),
+        deviation: i16_field(data, 3, 1e-4),
+        variation: i16_field(data, 5, 1e-4),
+        reference: match data[7] & 0x03 {
+            0 => HeadingReference::True,
+            1 => HeadingReference::Magnetic,
+            reference => HeadingReference::Other(reference),
+        },
+    }))
+}
+
+pub fn decode_engine_rapid(data: &[u8]) -> Option<ParameterGroup> {
+    if data.len() < 8 {
+        return None;
+    }
+    Some(ParameterGroup::EngineRapid(EngineRapid {
+        instance: data[0],
+        speed: u16_field(data, 1, 0.25),
+        boost_pressure: u16_field(data, 3, 100.0),
+        tilt_trim: i8_field(data, 5),
+    }))
+}
+
+pub fn decode_engine_dynamic(data: &[u8]) -> Option<ParameterGroup> {
+    if data.len() < 26 {
+        return None;
+    }
+    Some(ParameterGroup::EngineDynamic(EngineDynamic {
+        instance: data[0],
+        oil_pressure: u16_field(data, 1, 100.0),
+        oil_temperature: u16_field(data, 3, 0.1),
+        temperature: u16_field(data, 5, 0.01),
+        alternator_potential: i16_field(data, 7, 0.01),
+        fuel_rate: i16_field(data, 9, 0.1),
+        total_engine_hours: u32_field(data, 11),
+        coolant_pressure: u16_field(data, 15, 100.0),
+        fuel_pressure: u16_field(data, 17, 1000.0),
+        discrete_status_1: u16::from_le_bytes(field(data, 20)?),
+        discrete_status_2: u16::from_le_bytes(field(data, 22)?),
+        load: i8_field(data, 24),
+        torque: i8_field(data, 25),
+    }))
+}
+
+pub fn decode_wind(data: &[u8]) -> Option<ParameterGroup> {
+    if data.len() < 6 {
+        return None;
+    }
+    Some(ParameterGroup::Wind(Wind {
+        sid: data[0],
+        speed: u16_field(data, 1, 0.01),
+        angle: u16_field(data, 3, 1e-4),
+        reference: match data[5] & 0x07 {
+            0 => WindReference::TrueNorth,
+            1 => WindReference::Magnetic,
+            2 => WindReference::Apparent,
+            3 => WindReference::TrueBoat,
+            4 => WindReference::TrueWater,
+            reference => WindReference::Other(reference),
+        },
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::j1939::GLOBAL_ADDRESS;
+    use crate::socket::pipe::PipeBus;
+    use std::thread;
+
+    fn name(identity_number: u32) -> Name {
+        Name {
+            identity_number,
+            manufacturer_code: 0x7FF,
+            function: 140,
+            vehicle_system: 60,
+            industry_group: 4,
+            arbitrary_address_capable: true,
+            ..Name::default()
+        }
+    }
+
+    fn gnss_position() -> Vec<u8> {
+        let mut data = vec![0x07];
+        data.extend_from_slice(&19_650u16.to_le_bytes());
+        data.extend_from_slice(&432_000_000u32.to_le_bytes());
+        data.extend_from_slice(&535_000_000_000_000_000i64.to_le_bytes());
+        data.extend_from_slice(&(-61_000_000_000_000_000i64).to_le_bytes());
+        data.extend_from_slice(&12_500_000i64.to_le_bytes());
+        data.extend_from_slice(&[0x13, 0xFC, 9]);
+        data.extend_from_slice(&85i16.to_le_bytes());
+        data.extend_from_slice(&160i16.to_le_bytes());
+        data.extend_from_slice(&4_700i32.to_le_bytes());
+        data.push(0);
+        data
+    }
+
+    #[test]
+    fn fast_packet_001() {
+        let data = (0..20u8).collect::<Vec<_>>();
+        let frames = fast_packet_frames(3, &data).unwrap();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0], [0x60, 20, 0, 1, 2, 3, 4, 5]);
+        assert_eq!(frames[2], [0x62, 13, 14, 15, 16, 17, 18, 19]);
+
+        // interleaved transfers of two sources
+        let other = fast_packet_frames(0, &[0xAA; 10]).unwrap();
+        let mut assembler = FastPacketAssembler::new();
+        assert_eq!(assembler.push(1, 129029, &frames[0]), None);
+        assert_eq!(assembler.push(2, 129029, &other[0]), None);
+        assert_eq!(assembler.push(1, 129029, &frames[1]), None);
+        assert_eq!(assembler.push(2, 129029, &other[1]), Some(vec![0xAA; 10]));
+        assert_eq!(assembler.push(1, 129029, &frames[2]), Some(data.clone()));
+
+        // a lost frame discards the transfer
+        assert_eq!(assembler.push(1, 129029, &frames[0]), None);
+        assert_eq!(assembler.push(1, 129029, &frames[2]), None);
+        assert_eq!(assembler.push(1, 129029, &frames[1]), None);
+
+        assert_eq!(
+            fast_packet_frames(0, &[0; 224]),
+            Err(J1939Error::PayloadTooLarge)
+        );
+        assert_eq!(fast_packet_frames(0, &[0; 223]).unwrap().len(), 32);
+    }
+
+    #[test]
+    fn decode_001() {
+        let decoder = Decoder::default();
+        let heading = [0x01, 0x10, 0x27, 0xFF, 0x7F, 0xE8, 0x03, 0xFD];
+        assert_eq!(
+            decoder.decode(PGN_VESSEL_HEADING, &heading),
+            Some(ParameterGroup::Heading(Heading {
+                sid: 1,
+                heading: Some(1.0),
+                deviation: None,
+                variation: Some(0.1),
+                reference: HeadingReference::Magnetic,
+            }))
+        );
+
+        let wind = [0x00, 0xF4, 0x01, 0x20, 0x4E, 0xFA, 0xFF, 0xFF];
+        assert_eq!(
+            decoder.decode(PGN_WIND_DATA, &wind),
+            Some(ParameterGroup::Wind(Wind {
+                sid: 0,
+                speed: Some(5.0),
+                angle: Some(2.0),
+                reference: WindReference::Apparent,
+            }))
+        );
+        assert_eq!(decoder.decode(PGN_WIND_DATA, &wind[..4]), None);
+
+        let decoder = decoder.without_pgn(PGN_WIND_DATA);
+        assert_eq!(decoder.decode(PGN_WIND_DATA, &wind), None);
+    }
+
+    #[test]
+    fn nmea2000_node_001() {
+        let bus = PipeBus::new();
+        let mut sender = Nmea2000Node::new(bus.connect(), name(1), 0x10);
+        let mut receiver = Nmea2000Node::new(bus.connect(), name(2), 0x20);
+        sender.claim_address().unwrap();
+        receiver.claim_address().unwrap();
+
+        let handle = thread::spawn(move || {
+            sender.send(PGN_GNSS_POSITION, 3, GLOBAL_ADDRESS, &gnss_position())?;
+            sender.send(
+                PGN_ENGINE_RAPID,
+                2,
+                GLOBAL_ADDRESS,
+                &[0, 0x40, 0x1F, 0xFF, 0xFF, 0x7F, 0xFF, 0xFF],
+            )
+        });
+
+        let message = receiver.recv_timeout(Duration::from_secs(2)).unwrap();
+        assert_eq!(message.pgn, PGN_GNSS_POSITION);
+        assert_eq!(message.source, 0x10);
+        assert_eq!(message.data, gnss_position());
+
+        let position = match Decoder::default().decode_message(&message) {
+            Some(ParameterGroup::GnssPosition(position)) => position,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(position.days_since_1970, Some(19_650));
+        assert_eq!(position.seconds_since_midnight, Some(43_200.0));
+        assert!((position.latitude.unwrap() - 53.5).abs() < 1e-9);
+        assert!((position.longitude.unwrap() + 6.1).abs() < 1e-9);
+        assert!((position.altitude.unwrap() - 12.5).abs() < 1e-9);
+        assert_eq!(position.satellites, Some(9));
+
+        let message = receiver.recv_timeout(Duration::from_secs(2)).unwrap();
+        assert_eq!(
+            Decoder::default().decode_message(&message),
+            Some(ParameterGroup::EngineRapid(EngineRapid {
+                instance: 0,
+                speed: Some(2000.0),
+                boost_pressure: None,
+                tilt_trim: None,
+            }))
+        );
+
+        assert!(handle.join().unwrap().is_ok());
+    }
+}
@@ -0,0 +1,649 @@
+//! On-board diagnostics (SAE J1979 / ISO 15765-4) over CAN.
+//!
+//! An [ObdClient] sends functional requests to all emission related ECUs and collects the
+//! responses of every ECU answering within the timeout. Segmented responses, e.g. the VIN, are
+//! reassembled with the ISO-TP [Reassembler].
+
+use crate::error::PcanError;
+use crate::isotp::{FlowControl, FlowStatus, IsoTpConfig, IsoTpError, Reassembler, RxEvent};
+use crate::socket::{CanFrame, MessageType, RecvCan, SendCan};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, PartialEq)]
+pub enum ObdError {
+    Pcan(PcanError),
+    IsoTp(IsoTpError),
+    /// No ECU answered the request.
+    NoResponse,
+    /// All ECUs answering rejected the request.
+    NegativeResponse {
+        service: u8,
+        code: u8,
+    },
+    /// The request is empty or does not fit into a single frame.
+    InvalidRequest,
+    InvalidResponse,
+    UnknownPid,
+}
+
+impl From<PcanError> for ObdError {
+    fn from(value: PcanError) -> Self {
+        ObdError::Pcan(value)
+    }
+}
+
+impl From<IsoTpError> for ObdError {
+    fn from(value: IsoTpError) -> Self {
+        ObdError::IsoTp(value)
+    }
+}
+
+/* Addressing */
+
+/// Identifier scheme of ISO 15765-4.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ObdAddressing {
+    /// Functional requests on `0x7DF`, responses on `0x7E8..=0x7EF`.
+    Standard,
+    /// Functional requests on `0x18DB33F1`, responses on `0x18DAF1xx`.
+    Extended,
+}
+
+impl ObdAddressing {
+    pub fn functional_id(&self) -> u32 {
+        match self {
+            ObdAddressing::Standard => 0x7DF,
+            ObdAddressing::Extended => 0x18DB_33F1,
+        }
+    }
+
+    pub fn msg_type(&self) -> MessageType {
+        match self {
+            ObdAddressing::Standard => MessageType::Standard,
+            ObdAddressing::Extended => MessageType::Extended,
+        }
+    }
+
+    /// Returns `true` if `can_id` is the identifier of an ECU response.
+    pub fn is_response_id(&self, can_id: u32) -> bool {
+        match self {
+            ObdAddressing::Standard => (0x7E8..=0x7EF).contains(&can_id),
+            ObdAddressing::Extended => can_id & 0xFFFF_FF00 == 0x18DA_F100,
+        }
+    }
+
+    /// Returns the identifier of physical requests to the ECU answering with `response_id`.
+    pub fn physical_id(&self, response_id: u32) -> u32 {
+        match self {
+            ObdAddressing::Standard => response_id - 8,
+            ObdAddressing::Extended => 0x18DA_00F1 | ((response_id & 0xFF) << 8),
+        }
+    }
+}
+
+/* Records */
+
+/// Value reported by a single ECU, identified by the CAN-ID of its response.
+#[derive(Debug, PartialEq, Clone)]
+pub struct EcuResponse<T> {
+    pub ecu: u32,
+    pub value: T,
+}
+
+/// A decoded mode 01 parameter.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct PidValue {
+    pub pid: u8,
+    pub name: &'static str,
+    pub value: f64,
+    pub unit: &'static str,
+}
+
+/// Decodes the data bytes of a mode 01 parameter into engineering units.
+///
+/// Returns `None` for parameters not known or data too short.
+pub fn decode_pid(pid: u8, data: &[u8]) -> Option<PidValue> {
+    let a = *data.first()? as f64;
+    let ab = || -> Option<f64> { Some(a * 256.0 + *data.get(1)? as f64) };
+    let percent = a * 100.0 / 255.0;
+    let temperature = a - 40.0;
+    let fuel_trim = (a - 128.0) * 100.0 / 128.0;
+
+    let (name, value, unit) = match pid {
+        0x04 => ("calculated engine load", percent, "%"),
+        0x05 => ("engine coolant temperature", temperature, "°C"),
+        0x06 => ("short term fuel trim bank 1", fuel_trim, "%"),
+        0x07 => ("long term fuel trim bank 1", fuel_trim, "%"),
+        0x08 => ("short term fuel trim bank 2", fuel_trim, "%"),
+        0x09 => ("long term fuel trim bank 2", fuel_trim, "%"),
+        0x0A => ("fuel pressure", a * 3.0, "kPa"),
+        0x0B => ("intake manifold absolute pressure", a, "kPa"),
+        0x0C => ("engine speed", ab()? / 4.0, "rpm"),
+        0x0D => ("vehicle speed", a, "km/h"),
+        0x0E => ("timing advance", a / 2.0 - 64.0, "°"),
+        0x0F => ("intake air temperature", temperature, "°C"),
+        0x10 => ("mass air flow rate", ab()? / 100.0, "g/s"),
+        0x11 => ("throttle position", percent, "%"),
+        0x1F => ("run time since engine start", ab()?, "s"),
+        0x21 => ("distance traveled with MIL on", ab()?, "km"),
+        0x2C => ("commanded EGR", percent, "%"),
+        0x2F => ("fuel tank level input", percent, "%"),
+        0x30 => ("warm-ups since codes cleared", a, ""),
+        0x31 => ("distance traveled since codes cleared", ab()?, "km"),
+        0x33 => ("absolute barometric pressure", a, "kPa"),
+        0x42 => ("control module voltage", ab()? / 1000.0, "V"),
+        0x43 => ("absolute load value", ab()? * 100.0 / 255.0, "%"),
+        0x44 => (
+            "commanded air-fuel equivalence ratio",
+            ab()? * 2.0 / 65536.0,
+            "",
+        ),
+        0x45 => ("relative throttle position", percent, "%"),
+        0x46 => ("ambient air temperature", temperature, "°C"),
+        0x4D => ("time run with MIL on", ab()?, "min"),
+        0x4E => ("time since codes cleared", ab()?, "min"),
+        0x5C => ("engine oil temperature", temperature, "°C"),
+        0x5E => ("engine fuel rate", ab()? / 20.0, "L/h"),
+        _ => return None,
+    };
+
+    Some(PidValue {
+        pid,
+        name,
+        value,
+        unit,
+    })
+}
+
+/// Emission related diagnostic trouble code, e.g. `P0301`.
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Copy, Clone)]
+pub struct ObdDtc(pub u16);
+
+impl fmt::Display for ObdDtc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let system = match self.0 >> 14 {
+            0 => 'P',
+            1 => 'C',
+            2 => 'B',
+            _ => 'U',
+        };
+        write!(f, "{}{:04X}", system, self.0 & 0x3FFF)
+    }
+}
+
+/// Kind of trouble codes read by [read_dtcs](ObdClient::read_dtcs).
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum DtcKind {
+    /// Confirmed codes, mode 03.
+    Stored,
+    /// Pending codes, mode 07.
+    Pending,
+    /// Permanent codes, mode 0A.
+    Permanent,
+}
+
+impl DtcKind {
+    fn mode(&self) -> u8 {
+        match self {
+            DtcKind::Stored => 0x03,
+            DtcKind::Pending => 0x07,
+            DtcKind::Permanent => 0x0A,
+        }
+    }
+}
+
+/* ObdClient */
+
+const NEGATIVE_RESPONSE: u8 = 0x7F;
+const RESPONSE_PENDING: u8 = 0x78;
+const POLL_INTERVAL: Duration = Duration::from_micros(100);
+const PADDING: u8 = 0x00;
+
+/// Scan tool sending functional requests.
+pub struct ObdClient<S> {
+    socket: S,
+    addressing: ObdAddressing,
+    timeout: Duration,
+    pending_timeout: Duration,
+}
+
+impl<S: SendCan + RecvCan> ObdClient<S> {
+    /// Creates a client using 11-bit identifiers. Use [detect](ObdClient::detect) to find out the
+    /// scheme of the vehicle.
+    pub fn new(socket: S) -> ObdClient<S> {
+        ObdClient {
+            socket,
+            addressing: ObdAddressing::Standard,
+            timeout: Duration::from_millis(100),
+            pending_timeout: Duration::from_millis(5000),
+        }
+    }
+
+    pub fn with_addressing(mut self, addressing: ObdAddressing) -> ObdClient<S> {
+        self.addressing = addressing;
+        self
+    }
+
+    /// Sets the time to wait for responses after a request.
+    pub fn with_timeout(mut self, timeout: Duration) -> ObdClient<S> {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn addressing(&self) -> ObdAddressing {
+        self.addressing
+    }
+
+    pub fn socket(&self) -> &S {
+        &self.socket
+    }
+
+    pub fn into_socket(self) -> S {
+        self.socket
+    }
+
+    /// Detects the addressing scheme by requesting the supported PIDs with 11-bit and then with
+    /// 29-bit identifiers. The scheme of the first answer is kept.
+    pub fn detect(&mut self) -> Result<ObdAddressing, ObdError> {
+        for addressing in [ObdAddressing::Standard, ObdAddressing::Extended] {
+            self.addressing = addressing;
+            match self.request(&[0x01, 0x00]) {
+                Ok(responses) if !responses.is_empty() => return Ok(addressing),
+                Ok(_) => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        self.addressing = ObdAddressing::Standard;
+        Err(ObdError::NoResponse)
+    }
+
+    fn send_frame(&self, can_id: u32, data: &[u8]) -> Result<(), ObdError> {
+        let mut padded = [PADDING; 8];
+        padded[..data.len()].copy_from_slice(data);
+        let frame = CanFrame::new(can_id, self.addressing.msg_type(), &padded)
+            .map_err(|_| ObdError::InvalidRequest)?;
+        Ok(self.socket.send(frame)?)
+    }
+
+    /// Sends a functional request and returns the responses of all ECUs, including negative ones.
+    pub fn request(&mut self, request: &[u8]) -> Result<Vec<EcuResponse<Vec<u8>>>, ObdError> {
+        if request.is_empty() || request.len() > 7 {
+            return Err(ObdError::InvalidRequest);
+        }
+
+        let mut single_frame = vec![request.len() as u8];
+        single_frame.extend_from_slice(request);
+        self.send_frame(self.addressing.functional_id(), &single_frame)?;
+
+        let extended = self.addressing == ObdAddressing::Extended;
+        let mut reassemblers = BTreeMap::new();
+        let mut responses = Vec::new();
+        let mut deadline = Instant::now() + self.timeout;
+
+        loop {
+            let frame = match self.socket.recv_frame() {
+                Ok(frame) => frame,
+                Err(PcanError::QrcvEmpty) => {
+                    if Instant::now() >= deadline {
+                        break;
+                    }
+                    thread::sleep(POLL_INTERVAL);
+                    continue;
+                }
+                Err(err) => return Err(ObdError::Pcan(err)),
+            };
+
+            let ecu = frame.can_id();
+            if frame.is_extended_frame() != extended || !self.addressing.is_response_id(ecu) {
+                continue;
+            }
+
+            let physical_id = self.addressing.physical_id(ecu);
+            let reassembler = reassemblers
+                .entry(ecu)
+                .or_insert_with(|| Reassembler::new(&IsoTpConfig::new(physical_id, ecu)));
+
+            match reassembler.on_frame(frame.data()) {
+                Ok(RxEvent::Pending) => {
+                    deadline = deadline.max(Instant::now() + self.timeout);
+                }
+                Ok(RxEvent::FlowControl(flow_control)) => {
+                    self.send_frame(physical_id, &flow_control.encode())?;
+                    deadline = deadline.max(Instant::now() + self.timeout);
+                }
+                Ok(RxEvent::Complete(data)) => match data.as_slice() {
+                    [NEGATIVE_RESPONSE, _, RESPONSE_PENDING] => {
+                        deadline = deadline.max(Instant::now() + self.pending_timeout);
+                    }
+                    _ => responses.push(EcuResponse { ecu, value: data }),
+                },
+                Err(IsoTpError::BufferOverflow) => {
+                    let flow_control = FlowControl {
+                        status: FlowStatus::Overflow,
+                        block_size: 0,
+                        st_min: Duration::ZERO,
+                    };
+                    self.send_frame(physical_id, &flow_control.encode())?;
+                }
+                Err(_) => reassembler.reset(),
+            }
+        }
+
+        Ok(responses)
+    }
+
+    /// Sends a request and returns the positive responses without their service identifier.
+    ///
+    /// Fails if no ECU answered or if all answers were negative.
+    fn request_positive(&mut self, request: &[u8]) -> Result<Vec<EcuResponse<Vec<u8>>>, ObdError> {
+        let service = request[0];
+        let responses = self.request(request)?;
+
+        let mut negative = None;
+        let mut positive = Vec::new();
+        for response in responses {
+            match response.value.as_slice() {
+                [sid, data @ ..] if *sid == service + 0x40 => positive.push(EcuResponse {
+                    ecu: response.ecu,
+                    value: data.to_vec(),
+                }),
+                [NEGATIVE_RESPONSE, sid, code, ..] if *sid == service => {
+                    negative = Some(ObdError::NegativeResponse {
+                        service,
+                        code: *code,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        match (positive.is_empty(), negative) {
+            (false, _) => Ok(positive),
+            (true, Some(err)) => Err(err),
+            (true, None) => Err(ObdError::NoResponse),
+        }
+    }
+
+    /// Reads the raw data bytes of a mode 01 parameter.
+    pub fn current_data(&mut self, pid: u8) -> Result<Vec<EcuResponse<Vec<u8>>>, ObdError> {
+        let responses = self.request_positive(&[0x01, pid])?;
+        Ok(responses
+            .into_iter()
+            .filter(|response| response.value.first() == Some(&pid))
+            .map(|response| EcuResponse {
+                ecu: response.ecu,
+                value: response.value[1..].to_vec(),
+            })
+            .collect())
+    }
+
+    /// Reads and decodes a mode 01 parameter.
+    pub fn read_pid(&mut self, pid: u8) -> Result<Vec<EcuResponse<PidValue>>, ObdError> {
+        if decode_pid(pid, &[0, 0]).is_none() {
+            return Err(ObdError::UnknownPid);
+        }
+
+        self.current_data(pid)?
+            .into_iter()
+            .map(|response| match decode_pid(pid, &response.value) {
+                Some(value) => Ok(EcuResponse {
+                    ecu: response.ecu,
+                    value,
+                }),
+                None => Err(ObdError::InvalidResponse),
+            })
+            .collect()
+    }
+
+    /// Returns the mode 01 parameters supported by each ECU.
+    pub fn supported_pids(&mut self) -> Result<Vec<EcuResponse<Vec<u8>>>, ObdError> {
+        let mut supported: BTreeMap<u32, Vec<u8>> = BTreeMap::new();
+        let mut range = 0x00u8;
+
+        loop {
+            let responses = match self.current_data(range) {
+                Ok(responses) => responses,
+                Err(_) if range != 0x00 => break,
+                Err(err) => return Err(err),
+            };
+
+            let mut next = false;
+            for response in responses {
+                let pids = supported.entry(response.ecu).or_default();
+                for (byte, bits) in response.value.iter().take(4).enumerate() {
+                    for bit in 0..8 {
+                        if bits & (0x80 >> bit) != 0 {
+                            pids.push(range.wrapping_add((byte * 8 + bit) as u8 + 1));
+                        }
+                    }
+                }
+                next |= pids.contains(&(range.wrapping_add(0x20)));
+            }
+
+            if !next || range == 0xE0 {
+                break;
+            }
+            range += 0x20;
+        }
+
+        Ok(supported
+            .into_iter()
+            .map(|(ecu, pids)| EcuResponse {
+                ecu,
+                value: pids.into_iter().filter(|pid| pid % 0x20 != 0).collect(),
+            })
+            .collect())
+    }
+
+    /// Reads the trouble codes of all ECUs.
+    pub fn read_dtcs(&mut self, kind: DtcKind) -> Result<Vec<EcuResponse<Vec<ObdDtc>>>, ObdError> {
+        self.request_positive(&[kind.mode()])?
+            .into_iter()
+            .map(|response| match parse_dtcs(&response.value) {
+                Some(dtcs) => Ok(EcuResponse {
+                    ecu: response.ecu,
+                    value: dtcs,
+                }),
+                None => Err(ObdError::InvalidResponse),
+            })
+            .collect()
+    }
+
+    /// Clears the trouble codes and freeze frames (mode 04) and returns the ECUs acknowledging.
+    pub fn clear_dtcs(&mut self) -> Result<Vec<u32>, ObdError> {
+        Ok(self
+            .request_positive(&[0x04])?
+            .into_iter()
+            .map(|response| response.ecu)
+            .collect())
+    }
+
+    /// Reads the vehicle identification number (mode 09, info type 02).
+    pub fn read_vin(&mut self) -> Result<Vec<EcuResponse<String>>, ObdError> {
+        self.request_positive(&[0x09, 0x02])?
+            .into_iter()
+            .map(|response| match response.value.as_slice() {
+                [0x02, _count, vin @ ..] if !vin.is_empty() => Ok(EcuResponse {
+                    ecu: response.ecu,
+                    value: String::from_utf8_lossy(vin)
+                        .trim_matches(char::from(0))
+                        .to_string(),
+                }),
+                _ => Err(ObdError::InvalidResponse),
+            })
+            .collect()
+    }
+}
+
+/// Parses the trouble codes of a mode 03, 07 or 0A response, starting with the number of codes.
+fn parse_dtcs(data: &[u8]) -> Option<Vec<ObdDtc>> {
+    let (count, codes) = data.split_first()?;
+    if codes.len() < *count as usize * 2 {
+        return None;
+    }
+
+    Some(
+        codes
+            .chunks_exact(2)
+            .take(*count as usize)
+            .map(|code| ObdDtc(u16::from_be_bytes([code[0], code[1]])))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::isotp::IsoTpChannel;
+    use crate::socket::pipe::PipeBus;
+    use std::thread::JoinHandle;
+
+    /// Emulates an ECU answering functional requests with the response of `handler`.
+    fn ecu<H>(
+        bus: &PipeBus,
+        addressing: ObdAddressing,
+        response_id: u32,
+        handler: H,
+    ) -> JoinHandle<()>
+    where
+        H: Fn(&[u8]) -> Option<Vec<u8>> + Send + 'static,
+    {
+        let functional = IsoTpConfig::new(response_id, addressing.functional_id())
+            .with_message_type(addressing.msg_type());
+        let physical = IsoTpConfig::new(response_id, addressing.physical_id(response_id))
+            .with_message_type(addressing.msg_type())
+            .with_padding(0xAA);
+        let mut channel = IsoTpChannel::new(bus.connect(), functional);
+
+        thread::spawn(move || {
+            let functional = channel.config().clone();
+            while let Ok(request) = channel.recv_timeout(Duration::from_millis(500)) {
+                if let Some(response) = handler(&request) {
+                    channel.set_config(physical.clone());
+                    let _ = channel.send(&response);
+                    channel.set_config(functional.clone());
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn decode_pid_001() {
+        let rpm = decode_pid(0x0C, &[0x1A, 0xF8]).unwrap();
+        assert_eq!(rpm.value, 1726.0);
+        assert_eq!(rpm.unit, "rpm");
+        assert_eq!(decode_pid(0x05, &[0x7B]).unwrap().value, 83.0);
+        assert_eq!(decode_pid(0x0C, &[0x1A]), None);
+        assert_eq!(decode_pid(0x02, &[0x00, 0x00]), None);
+    }
+
+    #[test]
+    fn dtc_001() {
+        assert_eq!(ObdDtc(0x0301).to_string(), "P0301");
+        assert_eq!(ObdDtc(0xC123).to_string(), "U0123");
+        assert_eq!(
+            parse_dtcs(&[0x02, 0x01, 0x43, 0x41, 0x96]),
+            Some(vec![ObdDtc(0x0143), ObdDtc(0x4196)])
+        );
+        assert_eq!(parse_dtcs(&[0x02, 0x01, 0x43]), None);
+    }
+
+    #[test]
+    fn request_001() {
+        let bus = PipeBus::new();
+        let tester = bus.connect();
+
+        let engine = ecu(
+            &bus,
+            ObdAddressing::Standard,
+            0x7E8,
+            |request| match request {
+                [0x01, 0x0C] => Some(vec![0x41, 0x0C, 0x1A, 0xF8]),
+                [0x09, 0x02] => Some([&[0x49, 0x02, 0x01][..], b"1G1JC5444R7252367"].concat()),
+                [0x03] => Some(vec![0x43, 0x01, 0x03, 0x01]),
+                _ => None,
+            },
+        );
+        let transmission = ecu(
+            &bus,
+            ObdAddressing::Standard,
+            0x7E9,
+            |request| match request {
+                [0x01, 0x0C] => Some(vec![0x41, 0x0C, 0x1B, 0x00]),
+                [0x09, 0x02] => Some(vec![0x7F, 0x09, 0x12]),
+                [0x03] => Some(vec![0x43, 0x00]),
+                _ => None,
+            },
+        );
+
+        let mut client = ObdClient::new(tester).with_timeout(Duration::from_millis(50));
+
+        let rpm = client.read_pid(0x0C).unwrap();
+        assert_eq!(rpm.len(), 2);
+        assert!(rpm
+            .iter()
+            .any(|rpm| rpm.ecu == 0x7E8 && rpm.value.value == 1726.0));
+        assert!(rpm
+            .iter()
+            .any(|rpm| rpm.ecu == 0x7E9 && rpm.value.value == 1728.0));
+
+        assert_eq!(
+            client.read_vin(),
+            Ok(vec![EcuResponse {
+                ecu: 0x7E8,
+                value: "1G1JC5444R7252367".to_string()
+            }])
+        );
+
+        let mut dtcs = client.read_dtcs(DtcKind::Stored).unwrap();
+        dtcs.sort_by_key(|response| response.ecu);
+        assert_eq!(dtcs[0].value, vec![ObdDtc(0x0301)]);
+        assert_eq!(dtcs[1].value, vec![]);
+
+        drop(client);
+        engine.join().unwrap();
+        transmission.join().unwrap();
+    }
+
+    #[test]
+    fn detect_001() {
+        let bus = PipeBus::new();
+        let tester = bus.connect();
+
+        let engine = ecu(
+            &bus,
+            ObdAddressing::Extended,
+            0x18DA_F110,
+            |request| match request {
+                [0x01, 0x00] => Some(vec![0x41, 0x00, 0x98, 0x18, 0x00, 0x01]),
+                [0x01, 0x20] => Some(vec![0x41, 0x20, 0x00, 0x00, 0x00, 0x00]),
+                _ => None,
+            },
+        );
+
+        let mut client = ObdClient::new(tester).with_timeout(Duration::from_millis(50));
+        assert_eq!(client.detect(), Ok(ObdAddressing::Extended));
+        assert_eq!(
+            client.supported_pids(),
+            Ok(vec![EcuResponse {
+                ecu: 0x18DA_F110,
+                value: vec![0x01, 0x04, 0x05, 0x0C, 0x0D]
+            }])
+        );
+
+        drop(client);
+        engine.join().unwrap();
+    }
+
+    #[test]
+    fn request_002() {
+        let bus = PipeBus::new();
+        let mut client = ObdClient::new(bus.connect());
+        assert_eq!(client.request(&[]), Err(ObdError::InvalidRequest));
+        assert_eq!(client.request(&[0x01; 8]), Err(ObdError::InvalidRequest));
+    }
+}
@@ -0,0 +1,542 @@
+//! Prioritized transmit queue handling the back-pressure of the driver.
+//!
+//! [SendCan::send] and [SendCanFd::send_fd] fail with [XmtFull](PcanError::XmtFull) or
+//! [QxmtFull](PcanError::QxmtFull) if the transmit queue of the driver is full. A
+//! [TransmitQueue] buffers frames, hands them to the driver in priority order and retries them
+//! once the driver accepts frames again. Frames may carry a deadline after which they are dropped.
+
+use crate::error::PcanError;
+use crate::socket::{CanFdFrame, CanFrame, SendCan, SendCanFd, EXTENDED_MASK, STANDARD_MASK};
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc::{self, Sender};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, PartialEq)]
+pub enum QueueError {
+    /// The queue reached its capacity, the frame was not enqueued.
+    Full,
+    /// The queue could not be flushed within the given time.
+    Timeout,
+    /// The driver rejected a frame for a reason other than a full transmit queue.
+    Pcan(PcanError),
+}
+
+impl From<PcanError> for QueueError {
+    fn from(value: PcanError) -> Self {
+        QueueError::Pcan(value)
+    }
+}
+
+/* Transmit trait */
+
+/// Frame types a [TransmitQueue] can handle.
+pub trait Transmit<F> {
+    fn transmit(&self, frame: F) -> Result<(), PcanError>;
+}
+
+impl<S: SendCan> Transmit<CanFrame> for S {
+    fn transmit(&self, frame: CanFrame) -> Result<(), PcanError> {
+        self.send(frame)
+    }
+}
+
+impl<S: SendCanFd> Transmit<CanFdFrame> for S {
+    fn transmit(&self, frame: CanFdFrame) -> Result<(), PcanError> {
+        self.send_fd(frame)
+    }
+}
+
+/// Computes the priority a frame would have during bus arbitration. Lower values win.
+pub trait ArbitrationPriority {
+    fn arbitration_priority(&self) -> u32;
+}
+
+fn arbitration_priority(can_id: u32, extended: bool) -> u32 {
+    if extended {
+        let can_id = can_id & EXTENDED_MASK;
+        ((can_id >> 18) << 19) | (1 << 18) | (can_id & 0x3_FF_FF)
+    } else {
+        (can_id & STANDARD_MASK) << 19
+    }
+}
+
+impl ArbitrationPriority for CanFrame {
+    fn arbitration_priority(&self) -> u32 {
+        arbitration_priority(self.can_id(), self.is_extended_frame())
+    }
+}
+
+impl ArbitrationPriority for CanFdFrame {
+    fn arbitration_priority(&self) -> u32 {
+        arbitration_priority(self.can_id(), self.is_extended_frame())
+    }
+}
+
+/* QueuedFrame */
+
+/// A frame together with its priority and deadline.
+#[derive(Debug, Copy, Clone)]
+pub struct QueuedFrame<F> {
+    frame: F,
+    priority: u32,
+    deadline: Option<Instant>,
+}
+
+impl<F: ArbitrationPriority> QueuedFrame<F> {
+    /// Queues the frame with the priority derived from its CAN-ID.
+    pub fn new(frame: F) -> QueuedFrame<F> {
+        QueuedFrame {
+            priority: frame.arbitration_priority(),
+            frame,
+            deadline: None,
+        }
+    }
+}
+
+impl<F> QueuedFrame<F> {
+    /// Overrides the priority derived from the CAN-ID. Lower values are sent first.
+    pub fn with_priority(mut self, priority: u32) -> QueuedFrame<F> {
+        self.priority = priority;
+        self
+    }
+
+    /// Drops the frame if it could not be handed to the driver before `deadline`.
+    pub fn with_deadline(mut self, deadline: Instant) -> QueuedFrame<F> {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Drops the frame if it could not be handed to the driver within `timeout`.
+    pub fn with_timeout(self, timeout: Duration) -> QueuedFrame<F> {
+        self.with_deadline(Instant::now() + timeout)
+    }
+
+    pub fn frame(&self) -> &F {
+        &self.frame
+    }
+
+    pub fn priority(&self) -> u32 {
+        self.priority
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+}
+
+impl From<CanFrame> for QueuedFrame<CanFrame> {
+    fn from(value: CanFrame) -> Self {
+        QueuedFrame::new(value)
+    }
+}
+
+impl From<CanFdFrame> for QueuedFrame<CanFdFrame> {
+    fn from(value: CanFdFrame) -> Self {
+        QueuedFrame::new(value)
+    }
+}
+
+struct Entry<F> {
+    queued: QueuedFrame<F>,
+    sequence: u64,
+}
+
+impl<F> Entry<F> {
+    fn key(&self) -> (u32, u64) {
+        (self.queued.priority, self.sequence)
+    }
+
+    fn is_expired(&self, now: Instant) -> bool {
+        matches!(self.queued.deadline, Some(deadline) if deadline <= now)
+    }
+}
+
+impl<F> PartialEq for Entry<F> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl<F> Eq for Entry<F> {}
+
+impl<F> PartialOrd for Entry<F> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<F> Ord for Entry<F> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+/* TransmitQueue */
+
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub struct QueueStatistics {
+    /// Number of frames currently waiting in the queue.
+    pub depth: usize,
+    pub max_depth: usize,
+    pub sent: u64,
+    /// Number of times the driver reported a full transmit queue.
+    pub retries: u64,
+    /// Frames dropped because their deadline passed.
+    pub dropped_expired: u64,
+    /// Frames rejected because the queue reached its capacity.
+    pub dropped_full: u64,
+    /// Frames dropped because the driver rejected them.
+    pub failed: u64,
+}
+
+const DEFAULT_CAPACITY: usize = 1024;
+const DEFAULT_RETRY_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Buffers frames and hands them to the driver in priority order.
+///
+/// Frames with equal priority are sent in the order they were pushed. The queue does not spawn
+/// any thread: [poll](TransmitQueue::poll) sends as many frames as the driver accepts without
+/// blocking, [flush](TransmitQueue::flush) blocks until the queue is empty and
+/// [flush_async](TransmitQueue::flush_async) returns a future doing the same.
+pub struct TransmitQueue<S, F> {
+    socket: S,
+    heap: BinaryHeap<Reverse<Entry<F>>>,
+    capacity: usize,
+    retry_interval: Duration,
+    sequence: u64,
+    statistics: QueueStatistics,
+    timer: Option<Sender<(Instant, Waker)>>,
+}
+
+/// Starts a thread waking tasks at the given instants, in the order they are sent. The thread
+/// ends once the sender is dropped.
+fn spawn_timer() -> Sender<(Instant, Waker)> {
+    let (sender, receiver) = mpsc::channel::<(Instant, Waker)>();
+    thread::spawn(move || {
+        for (instant, waker) in receiver {
+            thread::sleep(instant.saturating_duration_since(Instant::now()));
+            waker.wake();
+        }
+    });
+    sender
+}
+
+impl<S: Transmit<F>, F: Copy> TransmitQueue<S, F> {
+    pub fn new(socket: S) -> TransmitQueue<S, F> {
+        TransmitQueue {
+            socket,
+            heap: BinaryHeap::new(),
+            capacity: DEFAULT_CAPACITY,
+            retry_interval: DEFAULT_RETRY_INTERVAL,
+            sequence: 0,
+            statistics: QueueStatistics::default(),
+            timer: None,
+        }
+    }
+
+    pub fn with_capacity(mut self, capacity: usize) -> TransmitQueue<S, F> {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Sets the time [flush](TransmitQueue::flush) and [poll_flush](TransmitQueue::poll_flush)
+    /// wait before retrying after the driver reported a full transmit queue.
+    pub fn with_retry_interval(mut self, retry_interval: Duration) -> TransmitQueue<S, F> {
+        self.retry_interval = retry_interval;
+        self
+    }
+
+    pub fn socket(&self) -> &S {
+        &self.socket
+    }
+
+    pub fn into_socket(self) -> S {
+        self.socket
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    pub fn statistics(&self) -> QueueStatistics {
+        QueueStatistics {
+            depth: self.heap.len(),
+            ..self.statistics
+        }
+    }
+
+    /// Enqueues a frame without sending it.
+    pub fn push<Q: Into<QueuedFrame<F>>>(&mut self, frame: Q) -> Result<(), QueueError> {
+        if self.heap.len() >= self.capacity {
+            self.statistics.dropped_full += 1;
+            return Err(QueueError::Full);
+        }
+
+        self.heap.push(Reverse(Entry {
+            queued: frame.into(),
+            sequence: self.sequence,
+        }));
+        self.sequence += 1;
+        self.statistics.max_depth = self.statistics.max_depth.max(self.heap.len());
+        Ok(())
+    }
+
+    /// Enqueues a frame and sends as many frames as the driver accepts.
+    pub fn send<Q: Into<QueuedFrame<F>>>(&mut self, frame: Q) -> Result<(), QueueError> {
+        self.push(frame)?;
+        self.poll()?;
+        Ok(())
+    }
+
+    /// Removes all frames from the queue.
+    pub fn clear(&mut self) {
+        self.heap.clear();
+    }
+
+    fn drop_expired(&mut self) {
+        let now = Instant::now();
+        let before = self.heap.len();
+        self.heap.retain(|entry| !entry.0.is_expired(now));
+        self.statistics.dropped_expired += (before - self.heap.len()) as u64;
+    }
+
+    /// Sends frames until the queue is empty or the driver reports a full transmit queue. Returns
+    /// the number of frames sent.
+    ///
+    /// A frame the driver rejects for any other reason is dropped and the error is returned.
+    pub fn poll(&mut self) -> Result<usize, PcanError> {
+        self.drop_expired();
+
+        let mut sent = 0;
+        while let Some(entry) = self.heap.peek() {
+            match self.socket.transmit(entry.0.queued.frame) {
+                Ok(()) => {
+                    self.heap.pop();
+                    self.statistics.sent += 1;
+                    sent += 1;
+                }
+                Err(PcanError::XmtFull) | Err(PcanError::QxmtFull) => {
+                    self.statistics.retries += 1;
+                    break;
+                }
+                Err(err) => {
+                    self.heap.pop();
+                    self.statistics.failed += 1;
+                    return Err(err);
+                }
+            }
+        }
+        Ok(sent)
+    }
+
+    /// Blocks until all frames were handed to the driver or dropped. Gives up after `timeout` if
+    /// it is given.
+    pub fn flush(&mut self, timeout: Option<Duration>) -> Result<(), QueueError> {
+        let start = Instant::now();
+
+        loop {
+            self.poll()?;
+            if self.heap.is_empty() {
+                return Ok(());
+            }
+
+            if let Some(timeout) = timeout {
+                if start.elapsed() >= timeout {
+                    return Err(QueueError::Timeout);
+                }
+            }
+            thread::sleep(self.retry_interval);
+        }
+    }
+
+    /// Polls the queue from an asynchronous context.
+    ///
+    /// If the driver reports a full transmit queue the task is woken again after the retry
+    /// interval. The queue starts a thread for this on first use, as it does not depend on any
+    /// particular runtime.
+    pub fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), PcanError>> {
+        self.poll()?;
+        if self.heap.is_empty() {
+            return Poll::Ready(Ok(()));
+        }
+
+        let retry = (Instant::now() + self.retry_interval, cx.waker().clone());
+        if let Err(mpsc::SendError((_, waker))) =
+            self.timer.get_or_insert_with(spawn_timer).send(retry)
+        {
+            waker.wake();
+        }
+        Poll::Pending
+    }
+
+    /// Returns a future completing once all frames were handed to the driver or dropped.
+    pub fn flush_async(&mut self) -> Flush<'_, S, F> {
+        Flush { queue: self }
+    }
+}
+
+/// Future returned by [TransmitQueue::flush_async].
+pub struct Flush<'a, S, F> {
+    queue: &'a mut TransmitQueue<S, F>,
+}
+
+impl<'a, S: Transmit<F>, F: Copy> Future for Flush<'a, S, F> {
+    type Output = Result<(), PcanError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.get_mut().queue.poll_flush(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::socket::MessageType;
+    use std::cell::{Cell, RefCell};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::task::Wake;
+
+    struct Congested {
+        rejections: Cell<usize>,
+        frames: RefCell<Vec<CanFrame>>,
+    }
+
+    impl SendCan for Congested {
+        fn send(&self, frame: CanFrame) -> Result<(), PcanError> {
+            if self.rejections.get() > 0 {
+                self.rejections.set(self.rejections.get() - 1);
+                return Err(PcanError::QxmtFull);
+            }
+            self.frames.borrow_mut().push(frame);
+            Ok(())
+        }
+    }
+
+    fn frame(can_id: u32, msg_type: MessageType) -> CanFrame {
+        CanFrame::new(can_id, msg_type, &[]).unwrap()
+    }
+
+    #[test]
+    fn transmit_queue_001() {
+        let socket = Congested {
+            rejections: Cell::new(2),
+            frames: RefCell::new(Vec::new()),
+        };
+        let mut queue = TransmitQueue::new(socket);
+
+        queue.push(frame(0x300, MessageType::Standard)).unwrap();
+        queue
+            .push(frame(0x100 << 18, MessageType::Extended))
+            .unwrap();
+        queue.push(frame(0x100, MessageType::Standard)).unwrap();
+        queue
+            .push(QueuedFrame::new(frame(0x7FF, MessageType::Standard)).with_priority(0))
+            .unwrap();
+
+        assert_eq!(queue.poll(), Ok(0));
+        assert_eq!(queue.poll(), Ok(0));
+        assert_eq!(queue.poll(), Ok(4));
+
+        let sent = queue
+            .socket()
+            .frames
+            .borrow()
+            .iter()
+            .map(|frame| (frame.can_id(), frame.is_extended_frame()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sent,
+            vec![
+                (0x7FF, false),
+                (0x100, false),
+                (0x100 << 18, true),
+                (0x300, false)
+            ]
+        );
+
+        let statistics = queue.statistics();
+        assert_eq!(statistics.retries, 2);
+        assert_eq!(statistics.sent, 4);
+        assert_eq!(statistics.max_depth, 4);
+        assert_eq!(statistics.depth, 0);
+    }
+
+    #[test]
+    fn transmit_queue_002() {
+        let socket = Congested {
+            rejections: Cell::new(usize::MAX),
+            frames: RefCell::new(Vec::new()),
+        };
+        let mut queue = TransmitQueue::new(socket).with_capacity(2);
+
+        queue
+            .push(
+                QueuedFrame::new(frame(0x100, MessageType::Standard)).with_timeout(Duration::ZERO),
+            )
+            .unwrap();
+        queue.push(frame(0x200, MessageType::Standard)).unwrap();
+        assert_eq!(
+            queue.push(frame(0x300, MessageType::Standard)),
+            Err(QueueError::Full)
+        );
+
+        assert_eq!(queue.poll(), Ok(0));
+        assert_eq!(
+            queue.flush(Some(Duration::from_millis(5))),
+            Err(QueueError::Timeout)
+        );
+
+        let statistics = queue.statistics();
+        assert_eq!(statistics.dropped_expired, 1);
+        assert_eq!(statistics.dropped_full, 1);
+        assert_eq!(statistics.depth, 1);
+    }
+
+    struct Wakes(AtomicUsize);
+
+    impl Wake for Wakes {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn transmit_queue_003() {
+        let socket = Congested {
+            rejections: Cell::new(1),
+            frames: RefCell::new(Vec::new()),
+        };
+        let retry_interval = Duration::from_millis(50);
+        let mut queue = TransmitQueue::new(socket).with_retry_interval(retry_interval);
+        queue.push(frame(0x100, MessageType::Standard)).unwrap();
+
+        let wakes = Arc::new(Wakes(AtomicUsize::new(0)));
+        let waker = Waker::from(wakes.clone());
+        let mut cx = Context::from_waker(&waker);
+        let mut flush = queue.flush_async();
+
+        // the task is woken once, not before the retry interval passed
+        let start = Instant::now();
+        assert_eq!(Pin::new(&mut flush).poll(&mut cx), Poll::Pending);
+        let deadline = start + Duration::from_secs(5);
+        while wakes.0.load(Ordering::SeqCst) == 0 {
+            assert!(Instant::now() < deadline, "not woken before the deadline");
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert!(start.elapsed() >= retry_interval);
+        assert_eq!(wakes.0.load(Ordering::SeqCst), 1);
+
+        assert_eq!(Pin::new(&mut flush).poll(&mut cx), Poll::Ready(Ok(())));
+        assert_eq!(queue.socket().frames.borrow().len(), 1);
+    }
+}
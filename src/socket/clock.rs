@@ -0,0 +1,240 @@
+//! Mapping of driver timestamps to host time.
+//!
+//! Every socket takes an [Anchor] when it is opened, a pair of wall-clock and monotonic host time.
+//! A [Clock] ties the driver's [Timestamp]s to that anchor by observing when frames are received.
+//! Without drift correction the driver clock is assumed to run at the rate of the host clock,
+//! with drift correction its rate is estimated from the observations.
+//!
+//! ```no_run
+//! use pcan_basic::bus::UsbBus;
+//! use pcan_basic::socket::clock::{Anchored, Clock};
+//! use pcan_basic::socket::usb::UsbCanSocket;
+//! use pcan_basic::socket::{Baudrate, RecvCan};
+//!
+//! let socket = UsbCanSocket::open(UsbBus::USB1, Baudrate::Baud500K).unwrap();
+//! let mut clock = Clock::new(socket.anchor()).with_drift_correction(true);
+//! if let Ok((frame, timestamp)) = socket.recv() {
+//!     clock.observe(&timestamp);
+//!     println!("{:?} {:?}", clock.to_system_time(&timestamp), frame);
+//! }
+//! ```
+
+use crate::socket::Timestamp;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Observations spanning less driver time do not estimate the drift.
+const MIN_DRIFT_SPAN: f64 = 1.0;
+
+/// Wall-clock and monotonic host time taken together.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Anchor {
+    system: SystemTime,
+    instant: Instant,
+}
+
+impl Anchor {
+    pub fn now() -> Anchor {
+        Anchor {
+            system: SystemTime::now(),
+            instant: Instant::now(),
+        }
+    }
+
+    pub fn system_time(&self) -> SystemTime {
+        self.system
+    }
+
+    pub fn instant(&self) -> Instant {
+        self.instant
+    }
+}
+
+/// Sockets knowing the [Anchor] taken when they were opened.
+pub trait Anchored {
+    fn anchor(&self) -> Anchor;
+}
+
+/// Running means and co-moments of the observations, driver time as `x`, host time as `y`, both
+/// in seconds.
+#[derive(Debug, Clone)]
+struct Fit {
+    first: u64,
+    count: f64,
+    mean_x: f64,
+    mean_y: f64,
+    m2_x: f64,
+    c_xy: f64,
+    span: f64,
+}
+
+/// Maps driver timestamps to host time.
+#[derive(Debug, Clone)]
+pub struct Clock {
+    anchor: Anchor,
+    drift_correction: bool,
+    fit: Option<Fit>,
+}
+
+impl Clock {
+    pub fn new(anchor: Anchor) -> Clock {
+        Clock {
+            anchor,
+            drift_correction: false,
+            fit: None,
+        }
+    }
+
+    /// Estimates the rate of the driver clock against the host clock, off by default.
+    pub fn with_drift_correction(mut self, drift_correction: bool) -> Self {
+        self.drift_correction = drift_correction;
+        self
+    }
+
+    pub fn anchor(&self) -> Anchor {
+        self.anchor
+    }
+
+    /// Observes a frame received just now.
+    pub fn observe(&mut self, timestamp: &Timestamp) {
+        self.observe_at(timestamp, Instant::now());
+    }
+
+    /// Observes a frame received at `received`.
+    pub fn observe_at(&mut self, timestamp: &Timestamp, received: Instant) {
+        let micros = timestamp.as_micros();
+        let y = received
+            .saturating_duration_since(self.anchor.instant)
+            .as_secs_f64();
+        let fit = self.fit.get_or_insert(Fit {
+            first: micros,
+            count: 0.0,
+            mean_x: 0.0,
+            mean_y: 0.0,
+            m2_x: 0.0,
+            c_xy: 0.0,
+            span: 0.0,
+        });
+        // timestamps before the first observation count negative
+        let x = (micros as f64 - fit.first as f64) / 1e6;
+
+        fit.count += 1.0;
+        let dx = x - fit.mean_x;
+        fit.mean_x += dx / fit.count;
+        fit.mean_y += (y - fit.mean_y) / fit.count;
+        fit.m2_x += dx * (x - fit.mean_x);
+        fit.c_xy += dx * (y - fit.mean_y);
+        fit.span = fit.span.max(x.abs());
+    }
+
+    /// Rate of the host clock per second of the driver clock.
+    fn rate(&self) -> f64 {
+        match &self.fit {
+            Some(fit) if self.drift_correction && fit.span >= MIN_DRIFT_SPAN && fit.m2_x > 0.0 => {
+                fit.c_xy / fit.m2_x
+            }
+            _ => 1.0,
+        }
+    }
+
+    /// Drift of the driver clock against the host clock in parts per million, zero without drift
+    /// correction or too few observations.
+    pub fn drift(&self) -> f64 {
+        (1.0 / self.rate() - 1.0) * 1e6
+    }
+
+    /// Seconds from the anchor to `timestamp`, `None` before any observation.
+    fn seconds(&self, timestamp: &Timestamp) -> Option<f64> {
+        let fit = self.fit.as_ref()?;
+        let x = (timestamp.as_micros() as f64 - fit.first as f64) / 1e6;
+        Some(fit.mean_y + self.rate() * (x - fit.mean_x))
+    }
+
+    /// Monotonic host time of `timestamp`, `None` before any observation.
+    pub fn to_instant(&self, timestamp: &Timestamp) -> Option<Instant> {
+        let seconds = self.seconds(timestamp)?;
+        match seconds >= 0.0 {
+            true => self
+                .anchor
+                .instant
+                .checked_add(Duration::from_secs_f64(seconds)),
+            false => self
+                .anchor
+                .instant
+                .checked_sub(Duration::from_secs_f64(-seconds)),
+        }
+    }
+
+    /// Wall-clock time of `timestamp`, `None` before any observation.
+    pub fn to_system_time(&self, timestamp: &Timestamp) -> Option<SystemTime> {
+        let seconds = self.seconds(timestamp)?;
+        match seconds >= 0.0 {
+            true => self
+                .anchor
+                .system
+                .checked_add(Duration::from_secs_f64(seconds)),
+            false => self
+                .anchor
+                .system
+                .checked_sub(Duration::from_secs_f64(-seconds)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn micros(duration: Duration) -> u64 {
+        duration.as_micros() as u64
+    }
+
+    #[test]
+    fn clock_001() {
+        let anchor = Anchor::now();
+        let mut clock = Clock::new(anchor);
+        let timestamp = Timestamp::from_micros(5_000_000);
+        assert_eq!(clock.to_system_time(&timestamp), None);
+
+        // received 2.5 s after the anchor on average
+        clock.observe_at(&timestamp, anchor.instant() + Duration::from_millis(2250));
+        clock.observe_at(
+            &Timestamp::from_micros(6_000_000),
+            anchor.instant() + Duration::from_millis(2750),
+        );
+        assert_eq!(
+            clock.to_system_time(&Timestamp::from_micros(7_000_000)),
+            Some(anchor.system_time() + Duration::from_secs(4))
+        );
+        assert_eq!(
+            clock.to_instant(&Timestamp::from_micros(3_000_000)),
+            Some(anchor.instant())
+        );
+        assert_eq!(clock.drift(), 0.0);
+    }
+
+    #[test]
+    fn clock_002() {
+        let anchor = Anchor::now();
+        let mut clock = Clock::new(anchor).with_drift_correction(true);
+
+        // the driver clock runs 100 ppm fast
+        for second in 0..10u64 {
+            let host = Duration::from_secs(second);
+            let driver = host + host / 10_000;
+            clock.observe_at(
+                &Timestamp::from_micros(1_000_000 + micros(driver)),
+                anchor.instant() + host,
+            );
+        }
+        assert!((clock.drift() - 100.0).abs() < 0.01, "{}", clock.drift());
+
+        let driver = Duration::from_secs(100) + Duration::from_millis(10);
+        let time = clock
+            .to_system_time(&Timestamp::from_micros(1_000_000 + micros(driver)))
+            .unwrap();
+        let error = time
+            .duration_since(anchor.system_time() + Duration::from_secs(100))
+            .unwrap_or_else(|err| err.duration());
+        assert!(error < Duration::from_micros(10), "{:?}", error);
+    }
+}
@@ -19,7 +19,10 @@ use crate::info::{
     HasNominalBusSpeed,
 };
 use crate::pcan;
-use crate::socket::{Baudrate, HasRecvCan, HasRecvCanFd, HasSendCan, HasSendCanFd, Socket};
+use crate::socket::clock::{Anchor, Anchored};
+use crate::socket::{
+    Baudrate, HasRecvCan, HasRecvCanFd, HasSendCan, HasSendCanFd, HasSplit, Socket,
+};
 use crate::trace::{
     HasSetTraceConfigure, HasSetTraceLocation, HasSetTraceSize, HasSetTraceStatus,
     HasTraceConfigure, HasTraceLocation, HasTraceSize, HasTraceStatus,
@@ -28,6 +31,7 @@ use crate::trace::{
 #[derive(Debug, PartialEq)]
 pub struct DngCanSocket {
     handle: u16,
+    anchor: Anchor,
 }
 
 impl DngCanSocket {
@@ -36,7 +40,10 @@ impl DngCanSocket {
         let code = unsafe { pcan::CAN_Initialize(handle, baud.into(), 0, 0, 0) };
 
         match PcanOkError::try_from(code) {
-            Ok(PcanOkError::Ok) => Ok(DngCanSocket { handle }),
+            Ok(PcanOkError::Ok) => Ok(DngCanSocket {
+                handle,
+                anchor: Anchor::now(),
+            }),
             Ok(PcanOkError::Err(err)) => Err(err),
             Err(_) => Err(PcanError::Unknown),
         }
@@ -59,6 +66,14 @@ impl Socket for DngCanSocket {
     }
 }
 
+/* Anchored trait implementation */
+
+impl Anchored for DngCanSocket {
+    fn anchor(&self) -> Anchor {
+        self.anchor
+    }
+}
+
 /* Channel trait implementation */
 
 impl Channel for DngCanSocket {
@@ -72,6 +87,8 @@ impl Channel for DngCanSocket {
 impl HasRecvCan for DngCanSocket {}
 impl HasSendCan for DngCanSocket {}
 
+impl HasSplit for DngCanSocket {}
+
 // impl HasRecvCanFd for DngCanSocket {}
 // impl HasSendCanFd for DngCanSocket {}
 
@@ -19,7 +19,10 @@ use crate::info::{
     HasNominalBusSpeed,
 };
 use crate::pcan;
-use crate::socket::{Baudrate, HasRecvCan, HasRecvCanFd, HasSendCan, HasSendCanFd, Socket};
+use crate::socket::clock::{Anchor, Anchored};
+use crate::socket::{
+    Baudrate, HasRecvCan, HasRecvCanFd, HasSendCan, HasSendCanFd, HasSplit, Socket,
+};
 use crate::trace::{
     HasSetTraceConfigure, HasSetTraceLocation, HasSetTraceSize, HasSetTraceStatus,
     HasTraceConfigure, HasTraceLocation, HasTraceSize, HasTraceStatus,
@@ -28,6 +31,7 @@ use crate::trace::{
 #[derive(Debug, PartialEq)]
 pub struct IsaCanSocket {
     handle: u16,
+    anchor: Anchor,
 }
 
 impl IsaCanSocket {
@@ -35,7 +39,10 @@ impl IsaCanSocket {
         let code = unsafe { pcan::CAN_Initialize(bus.into(), baud.into(), 0, 0, 0) };
 
         match PcanOkError::try_from(code) {
-            Ok(PcanOkError::Ok) => Ok(IsaCanSocket { handle: bus.into() }),
+            Ok(PcanOkError::Ok) => Ok(IsaCanSocket {
+                handle: bus.into(),
+                anchor: Anchor::now(),
+            }),
             Ok(PcanOkError::Err(err)) => Err(err),
             Err(_) => Err(PcanError::Unknown),
         }
@@ -58,6 +65,14 @@ impl Socket for IsaCanSocket {
     }
 }
 
+/* Anchored trait implementation */
+
+impl Anchored for IsaCanSocket {
+    fn anchor(&self) -> Anchor {
+        self.anchor
+    }
+}
+
 /* Channel trait implementation */
 
 impl Channel for IsaCanSocket {
@@ -71,6 +86,8 @@ impl Channel for IsaCanSocket {
 impl HasRecvCan for IsaCanSocket {}
 impl HasSendCan for IsaCanSocket {}
 
+impl HasSplit for IsaCanSocket {}
+
 // impl HasRecvCanFd for IsaCanSocket {}
 // impl HasSendCanFd for IsaCanSocket {}
 
@@ -21,7 +21,10 @@ use crate::info::{
     HasNominalBusSpeed,
 };
 use crate::pcan;
-use crate::socket::{Baudrate, HasRecvCan, HasRecvCanFd, HasSendCan, HasSendCanFd, Socket};
+use crate::socket::clock::{Anchor, Anchored};
+use crate::socket::{
+    Baudrate, HasRecvCan, HasRecvCanFd, HasSendCan, HasSendCanFd, HasSplit, Socket,
+};
 use crate::trace::{
     HasSetTraceConfigure, HasSetTraceLocation, HasSetTraceSize, HasSetTraceStatus,
     HasTraceConfigure, HasTraceLocation, HasTraceSize, HasTraceStatus,
@@ -30,6 +33,7 @@ use crate::trace::{
 #[derive(Debug, PartialEq)]
 pub struct LanCanSocket {
     handle: u16,
+    anchor: Anchor,
 }
 
 impl LanCanSocket {
@@ -38,7 +42,10 @@ impl LanCanSocket {
         let code = unsafe { pcan::CAN_Initialize(handle, baud.into(), 0, 0, 0) };
 
         match PcanOkError::try_from(code) {
-            Ok(PcanOkError::Ok) => Ok(LanCanSocket { handle }),
+            Ok(PcanOkError::Ok) => Ok(LanCanSocket {
+                handle,
+                anchor: Anchor::now(),
+            }),
             Ok(PcanOkError::Err(err)) => Err(err),
             Err(_) => Err(PcanError::Unknown),
         }
@@ -61,6 +68,14 @@ impl Socket for LanCanSocket {
     }
 }
 
+/* Anchored trait implementation */
+
+impl Anchored for LanCanSocket {
+    fn anchor(&self) -> Anchor {
+        self.anchor
+    }
+}
+
 /* Channel trait implementation */
 
 impl Channel for LanCanSocket {
@@ -74,6 +89,8 @@ impl Channel for LanCanSocket {
 impl HasRecvCan for LanCanSocket {}
 impl HasSendCan for LanCanSocket {}
 
+impl HasSplit for LanCanSocket {}
+
 // impl HasRecvCanFd for LanCanSocket {}
 // impl HasSendCanFd for LanCanSocket {}
 
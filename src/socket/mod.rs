@@ -2,21 +2,32 @@
 //!
 //!
 
+pub mod clock;
 pub mod dng;
 pub mod isa;
 pub mod lan;
 pub mod pcc;
 pub mod pci;
+pub mod pipe;
 pub mod usb;
 
 use crate::bus::Bus;
 use crate::error::{PcanError, PcanOkError};
 use crate::pcan;
+use crate::socket::clock::{Anchor, Anchored};
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::ops::Sub;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 
 pub const STANDARD_MASK: u32 = 0x07_FF;
 pub const EXTENDED_MASK: u32 = 0x1F_FF_FF_FF;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Copy, Clone)]
 pub enum MessageType {
     Standard,
     Extended,
@@ -70,6 +81,21 @@ impl CanFrame {
         }
     }
 
+    /// Creates a remote transmission request for `dlc` data bytes.
+    pub fn new_rtr(
+        can_id: u32,
+        msg_type: MessageType,
+        dlc: u8,
+    ) -> Result<CanFrame, FrameConstructionError> {
+        if dlc as usize > Self::MAX_DLC {
+            return Err(FrameConstructionError::TooMuchData);
+        }
+
+        let mut frame = CanFrame::new(can_id, msg_type, &[0; 8][..dlc as usize])?;
+        frame.frame.MSGTYPE |= pcan::PCAN_MESSAGE_RTR as u8;
+        Ok(frame)
+    }
+
     pub fn is_standard_frame(&self) -> bool {
         self.frame.MSGTYPE & pcan::PCAN_MESSAGE_STANDARD as u8 != 0
     }
@@ -78,6 +104,18 @@ impl CanFrame {
         self.frame.MSGTYPE & pcan::PCAN_MESSAGE_EXTENDED as u8 != 0
     }
 
+    pub fn is_rtr_frame(&self) -> bool {
+        self.frame.MSGTYPE & pcan::PCAN_MESSAGE_RTR as u8 != 0
+    }
+
+    pub fn is_error_frame(&self) -> bool {
+        self.frame.MSGTYPE & pcan::PCAN_MESSAGE_ERRFRAME as u8 != 0
+    }
+
+    pub fn is_status_frame(&self) -> bool {
+        self.frame.MSGTYPE & pcan::PCAN_MESSAGE_STATUS as u8 != 0
+    }
+
     pub fn can_id(&self) -> u32 {
         if self.is_standard_frame() {
             self.frame.ID & STANDARD_MASK
@@ -133,9 +171,40 @@ pub struct CanFdFrame {
     frame: pcan::TPCANMsgFD,
 }
 
+/// Converts the DLC of a CAN FD frame into the number of data bytes.
+pub fn fd_dlc_to_length(dlc: u8) -> usize {
+    match dlc {
+        0..=8 => dlc as usize,
+        9 => 12,
+        10 => 16,
+        11 => 20,
+        12 => 24,
+        13 => 32,
+        14 => 48,
+        _ => 64,
+    }
+}
+
+/// Converts a number of data bytes into the smallest DLC of a CAN FD frame able to carry them.
+pub fn fd_length_to_dlc(length: usize) -> u8 {
+    match length {
+        0..=8 => length as u8,
+        9..=12 => 9,
+        13..=16 => 10,
+        17..=20 => 11,
+        21..=24 => 12,
+        25..=32 => 13,
+        33..=48 => 14,
+        _ => 15,
+    }
+}
+
 impl CanFdFrame {
     const MAX_DLC: usize = 64;
 
+    /// Creates a CAN FD frame. Data not matching one of the lengths a CAN FD frame can carry is
+    /// padded with zeros up to the next valid length. The frame is marked as FD frame, extended
+    /// identifiers keep all 29 bits.
     pub fn new(
         can_id: u32,
         msg_type: MessageType,
@@ -145,7 +214,7 @@ impl CanFdFrame {
             Err(FrameConstructionError::TooMuchData)
         } else {
             let mut frame_data: [u8; 64] = [0; 64];
-            for (i, v) in data.into_iter().enumerate() {
+            for (i, v) in data.iter().enumerate() {
                 frame_data[i] = *v;
             }
 
@@ -153,16 +222,16 @@ impl CanFdFrame {
                 MessageType::Standard => Ok(CanFdFrame {
                     frame: pcan::TPCANMsgFD {
                         ID: can_id & STANDARD_MASK,
-                        MSGTYPE: pcan::PCAN_MESSAGE_STANDARD as u8,
-                        DLC: data.len() as u8,
+                        MSGTYPE: (pcan::PCAN_MESSAGE_STANDARD | pcan::PCAN_MESSAGE_FD) as u8,
+                        DLC: fd_length_to_dlc(data.len()),
                         DATA: frame_data,
                     },
                 }),
                 MessageType::Extended => Ok(CanFdFrame {
                     frame: pcan::TPCANMsgFD {
-                        ID: can_id & STANDARD_MASK,
-                        MSGTYPE: pcan::PCAN_MESSAGE_STANDARD as u8,
-                        DLC: data.len() as u8,
+                        ID: can_id & EXTENDED_MASK,
+                        MSGTYPE: (pcan::PCAN_MESSAGE_EXTENDED | pcan::PCAN_MESSAGE_FD) as u8,
+                        DLC: fd_length_to_dlc(data.len()),
                         DATA: frame_data,
                     },
                 }),
@@ -170,6 +239,24 @@ impl CanFdFrame {
         }
     }
 
+    /// Enables or disables switching to the data bitrate for the data phase of the frame.
+    pub fn set_brs(&mut self, brs: bool) {
+        if brs {
+            self.frame.MSGTYPE |= pcan::PCAN_MESSAGE_BRS as u8;
+        } else {
+            self.frame.MSGTYPE &= !(pcan::PCAN_MESSAGE_BRS as u8);
+        }
+    }
+
+    /// Sets the error state indicator, e.g. for frames read from a trace.
+    pub fn set_esi(&mut self, esi: bool) {
+        if esi {
+            self.frame.MSGTYPE |= pcan::PCAN_MESSAGE_ESI as u8;
+        } else {
+            self.frame.MSGTYPE &= !(pcan::PCAN_MESSAGE_ESI as u8);
+        }
+    }
+
     pub fn is_standard_frame(&self) -> bool {
         self.frame.MSGTYPE & pcan::PCAN_MESSAGE_STANDARD as u8 != 0
     }
@@ -182,6 +269,22 @@ impl CanFdFrame {
         }
     }
 
+    pub fn is_brs_frame(&self) -> bool {
+        self.frame.MSGTYPE & pcan::PCAN_MESSAGE_BRS as u8 != 0
+    }
+
+    pub fn is_esi_frame(&self) -> bool {
+        self.frame.MSGTYPE & pcan::PCAN_MESSAGE_ESI as u8 != 0
+    }
+
+    pub fn is_error_frame(&self) -> bool {
+        self.frame.MSGTYPE & pcan::PCAN_MESSAGE_ERRFRAME as u8 != 0
+    }
+
+    pub fn is_status_frame(&self) -> bool {
+        self.frame.MSGTYPE & pcan::PCAN_MESSAGE_STATUS as u8 != 0
+    }
+
     pub fn can_id(&self) -> u32 {
         if self.is_standard_frame() {
             self.frame.ID & STANDARD_MASK
@@ -190,17 +293,19 @@ impl CanFdFrame {
         }
     }
 
+    /// The DLC code from 0 to 15, not the number of data bytes beyond 8. See [fd_dlc_to_length].
     pub fn dlc(&self) -> u8 {
         self.frame.DLC
     }
 
+    /// The data bytes selected by the DLC, including padding.
     pub fn data(&self) -> &[u8] {
-        &self.frame.DATA[0..self.dlc() as usize]
+        &self.frame.DATA[0..fd_dlc_to_length(self.dlc())]
     }
 
     pub fn mut_data(&mut self) -> &mut [u8] {
-        let dlc = self.dlc();
-        &mut self.frame.DATA[0..dlc as usize]
+        let length = fd_dlc_to_length(self.dlc());
+        &mut self.frame.DATA[0..length]
     }
 }
 
@@ -232,6 +337,201 @@ impl PartialEq for CanFdFrame {
     }
 }
 
+/* cansend syntax */
+
+/// Error of parsing a frame from the syntax of `cansend`, e.g. `123#DEADBEEF`.
+#[derive(Debug, PartialEq)]
+pub enum ParseFrameError {
+    /// The identifier is not made of 3 or 8 hex digits.
+    InvalidId,
+    /// The separator between identifier and data is missing or of the wrong frame kind.
+    InvalidSeparator,
+    InvalidData,
+    TooMuchData,
+}
+
+impl From<FrameConstructionError> for ParseFrameError {
+    fn from(_: FrameConstructionError) -> Self {
+        ParseFrameError::TooMuchData
+    }
+}
+
+/// Splits `<can_id>#<rest>` into identifier, message type and the rest.
+fn parse_cansend_id(value: &str) -> Result<(u32, MessageType, &str), ParseFrameError> {
+    let (id, rest) = value
+        .split_once('#')
+        .ok_or(ParseFrameError::InvalidSeparator)?;
+    if !id.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err(ParseFrameError::InvalidId);
+    }
+    let can_id = u32::from_str_radix(id, 16).map_err(|_| ParseFrameError::InvalidId)?;
+    match id.len() {
+        3 if can_id <= STANDARD_MASK => Ok((can_id, MessageType::Standard, rest)),
+        8 if can_id <= EXTENDED_MASK => Ok((can_id, MessageType::Extended, rest)),
+        _ => Err(ParseFrameError::InvalidId),
+    }
+}
+
+/// Parses pairs of hex digits, `None` for an odd number of digits or any other character.
+pub(crate) fn parse_hex(digits: &str) -> Option<Vec<u8>> {
+    let digits = digits.as_bytes();
+    if !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let high = (pair[0] as char).to_digit(16)?;
+            let low = (pair[1] as char).to_digit(16)?;
+            Some((high << 4 | low) as u8)
+        })
+        .collect()
+}
+
+/// Parses hex data bytes, optionally separated by dots.
+fn parse_cansend_data(value: &str) -> Result<Vec<u8>, ParseFrameError> {
+    parse_hex(&value.replace('.', "")).ok_or(ParseFrameError::InvalidData)
+}
+
+fn format_cansend_id(f: &mut fmt::Formatter<'_>, can_id: u32, extended: bool) -> fmt::Result {
+    match extended {
+        true => write!(f, "{:08X}", can_id),
+        false => write!(f, "{:03X}", can_id),
+    }
+}
+
+impl FromStr for CanFrame {
+    type Err = ParseFrameError;
+
+    /// Parses data frames like `123#DEADBEEF` and remote frames like `1ABCDEF0#R` or `123#R4`. A
+    /// DLC above 8 appended to 8 data bytes, e.g. `123#0011223344556677_F`, is ignored.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (can_id, msg_type, rest) = parse_cansend_id(value)?;
+        if rest.starts_with('#') {
+            return Err(ParseFrameError::InvalidSeparator);
+        }
+        let rest = rest.split_once('_').map_or(rest, |(rest, _)| rest);
+
+        match rest.strip_prefix(['R', 'r']) {
+            Some("") => Ok(CanFrame::new_rtr(can_id, msg_type, 0)?),
+            Some(dlc) => {
+                let dlc = dlc
+                    .parse::<u8>()
+                    .map_err(|_| ParseFrameError::InvalidData)?;
+                Ok(CanFrame::new_rtr(can_id, msg_type, dlc)?)
+            }
+            None => Ok(CanFrame::new(can_id, msg_type, &parse_cansend_data(rest)?)?),
+        }
+    }
+}
+
+impl fmt::Display for CanFrame {
+    /// Formats the frame like `candump -l`, e.g. `123#DEADBEEF`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        format_cansend_id(f, self.can_id(), self.is_extended_frame())?;
+        write!(f, "#")?;
+        if self.is_rtr_frame() {
+            write!(f, "R")?;
+            if self.dlc() > 0 {
+                write!(f, "{}", self.dlc())?;
+            }
+            return Ok(());
+        }
+        for byte in self.data() {
+            write!(f, "{:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+const CANFD_BRS: u8 = 0x01;
+const CANFD_ESI: u8 = 0x02;
+
+impl FromStr for CanFdFrame {
+    type Err = ParseFrameError;
+
+    /// Parses frames like `123##1AABB`, where the hex digit after `##` holds the flags, 1 for
+    /// the bit rate switch and 2 for the error state indicator.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (can_id, msg_type, rest) = parse_cansend_id(value)?;
+        let rest = rest
+            .strip_prefix('#')
+            .ok_or(ParseFrameError::InvalidSeparator)?;
+        let flags = rest
+            .get(..1)
+            .and_then(|flags| u8::from_str_radix(flags, 16).ok())
+            .ok_or(ParseFrameError::InvalidData)?;
+
+        let mut frame = CanFdFrame::new(can_id, msg_type, &parse_cansend_data(&rest[1..])?)?;
+        frame.set_brs(flags & CANFD_BRS != 0);
+        frame.set_esi(flags & CANFD_ESI != 0);
+        Ok(frame)
+    }
+}
+
+impl fmt::Display for CanFdFrame {
+    /// Formats the frame like `candump -l`, e.g. `123##1AABB`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        format_cansend_id(f, self.can_id(), self.is_extended_frame())?;
+        let flags =
+            (self.is_brs_frame() as u8 * CANFD_BRS) | (self.is_esi_frame() as u8 * CANFD_ESI);
+        write!(f, "##{:X}", flags)?;
+        for byte in self.data() {
+            write!(f, "{:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Accessors shared by [CanFrame] and [CanFdFrame].
+pub trait Frame {
+    fn can_id(&self) -> u32;
+    fn is_extended_frame(&self) -> bool;
+    fn dlc(&self) -> u8;
+    fn data(&self) -> &[u8];
+}
+
+impl Frame for CanFrame {
+    fn can_id(&self) -> u32 {
+        CanFrame::can_id(self)
+    }
+
+    fn is_extended_frame(&self) -> bool {
+        CanFrame::is_extended_frame(self)
+    }
+
+    fn dlc(&self) -> u8 {
+        CanFrame::dlc(self)
+    }
+
+    fn data(&self) -> &[u8] {
+        CanFrame::data(self)
+    }
+}
+
+impl Frame for CanFdFrame {
+    fn can_id(&self) -> u32 {
+        CanFdFrame::can_id(self)
+    }
+
+    fn is_extended_frame(&self) -> bool {
+        CanFdFrame::is_extended_frame(self)
+    }
+
+    fn dlc(&self) -> u8 {
+        CanFdFrame::dlc(self)
+    }
+
+    fn data(&self) -> &[u8] {
+        CanFdFrame::data(self)
+    }
+}
+
+/// Reception time of a frame, counted by the driver since it was started.
+///
+/// The driver counts milliseconds in 32 bits, `millis_overflow` counts how often they wrapped.
+/// Timestamps of CAN FD frames are given in microseconds only and are converted with
+/// [Timestamp::from_micros].
 #[derive(Debug, Copy, Clone)]
 pub struct Timestamp {
     timestamp: pcan::TPCANTimestamp,
@@ -249,21 +549,90 @@ impl Default for Timestamp {
     }
 }
 
+impl Timestamp {
+    pub fn from_micros(micros: u64) -> Timestamp {
+        let millis = micros / 1000;
+        Timestamp {
+            timestamp: pcan::TPCANTimestamp {
+                micros: (micros % 1000) as u16,
+                millis: millis as u32,
+                millis_overflow: (millis >> 32) as u16,
+            },
+        }
+    }
+
+    pub fn millis(&self) -> u32 {
+        self.timestamp.millis
+    }
+
+    pub fn millis_overflow(&self) -> u16 {
+        self.timestamp.millis_overflow
+    }
+
+    pub fn micros(&self) -> u16 {
+        self.timestamp.micros
+    }
+
+    /// Microseconds since the driver was started, including the wrapped milliseconds.
+    pub fn as_micros(&self) -> u64 {
+        let millis = self.timestamp.millis as u64 + ((self.timestamp.millis_overflow as u64) << 32);
+        millis * 1000 + self.timestamp.micros as u64
+    }
+
+    pub fn as_duration(&self) -> Duration {
+        Duration::from_micros(self.as_micros())
+    }
+
+    /// Time elapsed from `earlier` to `self`, zero if `earlier` is later.
+    pub fn duration_since(&self, earlier: Timestamp) -> Duration {
+        self.checked_duration_since(earlier).unwrap_or_default()
+    }
+
+    pub fn checked_duration_since(&self, earlier: Timestamp) -> Option<Duration> {
+        self.as_micros()
+            .checked_sub(earlier.as_micros())
+            .map(Duration::from_micros)
+    }
+}
+
+impl From<Timestamp> for Duration {
+    fn from(value: Timestamp) -> Self {
+        value.as_duration()
+    }
+}
+
 impl PartialEq for Timestamp {
     fn eq(&self, other: &Self) -> bool {
-        if self.timestamp.micros != other.timestamp.micros {
-            return false;
-        }
+        self.as_micros() == other.as_micros()
+    }
+}
 
-        if self.timestamp.millis != other.timestamp.millis {
-            return false;
-        }
+impl Eq for Timestamp {}
 
-        if self.timestamp.millis_overflow != other.timestamp.millis_overflow {
-            return false;
-        }
+impl PartialOrd for Timestamp {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
 
-        true
+impl Ord for Timestamp {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_micros().cmp(&other.as_micros())
+    }
+}
+
+impl Hash for Timestamp {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_micros().hash(state);
+    }
+}
+
+impl Sub for Timestamp {
+    type Output = Duration;
+
+    /// Saturates at zero like [Timestamp::duration_since].
+    fn sub(self, rhs: Timestamp) -> Duration {
+        self.duration_since(rhs)
     }
 }
 
@@ -287,6 +656,12 @@ impl CanSocket {
 
 trait HasRecvCan {}
 
+/// Receiving of classic CAN frames.
+///
+/// The PCAN-Basic API may be called from several threads at once. The sockets of this crate are
+/// `Send` and `Sync`, so receiving on one thread while another thread sends through the same
+/// channel is sound. Other implementors, e.g. test doubles, need not be; bound on `Send + Sync`
+/// where it matters. See [Split] to hand out owned halves instead of references.
 pub trait RecvCan {
     fn recv(&self) -> Result<(CanFrame, Timestamp), PcanError>;
     fn recv_frame(&self) -> Result<CanFrame, PcanError>;
@@ -294,19 +669,25 @@ pub trait RecvCan {
 
 trait HasRecvCanFd {}
 
+/// Receiving of CAN FD frames. Offers the same thread-safety guarantees as [RecvCan].
 pub trait RecvCanFd {
-    fn recv_fd(&self) -> Result<(CanFdFrame, u64), PcanError>;
+    fn recv_fd(&self) -> Result<(CanFdFrame, Timestamp), PcanError>;
     fn recv_fd_frame(&self) -> Result<CanFdFrame, PcanError>;
 }
 
 trait HasSendCan {}
 
+/// Sending of classic CAN frames.
+///
+/// The sockets of this crate are `Send` and `Sync`, see [RecvCan]. Frames sent concurrently from
+/// several threads are queued by the driver in the order the calls reach it.
 pub trait SendCan {
     fn send(&self, frame: CanFrame) -> Result<(), PcanError>;
 }
 
 trait HasSendCanFd {}
 
+/// Sending of CAN FD frames. Offers the same thread-safety guarantees as [SendCan].
 pub trait SendCanFd {
     fn send_fd(&self, frame: CanFdFrame) -> Result<(), PcanError>;
 }
@@ -315,6 +696,109 @@ trait Socket {
     fn handle(&self) -> u16;
 }
 
+/* Split sockets */
+
+/// Owns an initialized channel and uninitializes it once dropped.
+#[derive(Debug)]
+struct SharedHandle {
+    handle: u16,
+    anchor: Anchor,
+}
+
+impl Drop for SharedHandle {
+    fn drop(&mut self) {
+        unsafe { pcan::CAN_Uninitialize(self.handle) };
+    }
+}
+
+/// Receiving half of a socket split by [Split::split].
+///
+/// Implements [RecvCan] and [RecvCanFd] if the socket `S` does. The half is `Send` and `Sync`
+/// independent of `S`.
+#[derive(Debug)]
+pub struct RecvHalf<S> {
+    shared: Arc<SharedHandle>,
+    socket: PhantomData<fn() -> S>,
+}
+
+/// Transmitting half of a socket split by [Split::split].
+///
+/// Implements [SendCan] and [SendCanFd] if the socket `S` does. The half is `Send` and `Sync`
+/// independent of `S`.
+#[derive(Debug)]
+pub struct SendHalf<S> {
+    shared: Arc<SharedHandle>,
+    socket: PhantomData<fn() -> S>,
+}
+
+impl<S> RecvHalf<S> {
+    /// Returns `true` if both halves originate from the same socket.
+    pub fn is_pair_of(&self, other: &SendHalf<S>) -> bool {
+        Arc::ptr_eq(&self.shared, &other.shared)
+    }
+}
+
+impl<S> Socket for RecvHalf<S> {
+    fn handle(&self) -> u16 {
+        self.shared.handle
+    }
+}
+
+impl<S> Anchored for RecvHalf<S> {
+    fn anchor(&self) -> Anchor {
+        self.shared.anchor
+    }
+}
+
+impl<S> Socket for SendHalf<S> {
+    fn handle(&self) -> u16 {
+        self.shared.handle
+    }
+}
+
+impl<S> Anchored for SendHalf<S> {
+    fn anchor(&self) -> Anchor {
+        self.shared.anchor
+    }
+}
+
+impl<S: HasRecvCan> HasRecvCan for RecvHalf<S> {}
+impl<S: HasRecvCanFd> HasRecvCanFd for RecvHalf<S> {}
+
+impl<S: HasSendCan> HasSendCan for SendHalf<S> {}
+impl<S: HasSendCanFd> HasSendCanFd for SendHalf<S> {}
+
+trait HasSplit {}
+
+/// Splits a socket into an owned receiving and an owned transmitting half.
+///
+/// The channel stays initialized until both halves are dropped.
+pub trait Split: Sized {
+    fn split(self) -> (RecvHalf<Self>, SendHalf<Self>);
+}
+
+impl<T: HasSplit + Socket + Anchored> Split for T {
+    fn split(self) -> (RecvHalf<Self>, SendHalf<Self>) {
+        let shared = Arc::new(SharedHandle {
+            handle: self.handle(),
+            anchor: self.anchor(),
+        });
+        // The shared handle takes over uninitializing the channel.
+        std::mem::forget(self);
+
+        (
+            RecvHalf {
+                shared: shared.clone(),
+                socket: PhantomData,
+            },
+            SendHalf {
+                shared,
+                socket: PhantomData,
+            },
+        )
+    }
+}
+
 /* Baudrate */
 
 #[derive(Debug, PartialEq)]
@@ -401,7 +885,7 @@ impl<T: HasRecvCan + Socket> RecvCan for T {
 /* CanRecvFd trait implementation */
 
 impl<T: HasRecvCanFd + Socket> RecvCanFd for T {
-    fn recv_fd(&self) -> Result<(CanFdFrame, u64), PcanError> {
+    fn recv_fd(&self) -> Result<(CanFdFrame, Timestamp), PcanError> {
         let mut frame = CanFdFrame::default();
         let mut timestamp = 0u64;
 
@@ -414,7 +898,7 @@ impl<T: HasRecvCanFd + Socket> RecvCanFd for T {
         };
 
         match PcanOkError::try_from(error_code) {
-            Ok(PcanOkError::Ok) => Ok((frame, timestamp)),
+            Ok(PcanOkError::Ok) => Ok((frame, Timestamp::from_micros(timestamp))),
             Ok(PcanOkError::Err(err)) => Err(err),
             Err(_) => Err(PcanError::Unknown),
         }
@@ -511,6 +995,15 @@ mod tests {
             CanFrame::new(0x20, MessageType::Extended, &[0, 1, 2, 3, 4, 5, 6, 7, 8]).unwrap();
     }
 
+    #[test]
+    fn can_frame_new_005() {
+        let can_frame = CanFrame::new_rtr(0x705, MessageType::Standard, 1).unwrap();
+
+        assert!(can_frame.is_rtr_frame());
+        assert_eq!(can_frame.can_id(), 0x705);
+        assert_eq!(can_frame.dlc(), 1);
+    }
+
     /* CAN FD FRAME */
 
     #[test]
@@ -548,4 +1041,173 @@ mod tests {
         let _can_frame_1 =
             CanFrame::new(0x20, MessageType::Extended, &(0..65u8).collect::<Vec<_>>()).unwrap();
     }
+
+    #[test]
+    fn can_fd_frame_new_005() {
+        let mut frame = CanFdFrame::new(0xFFF, MessageType::Standard, &[0xAA; 9]).unwrap();
+        assert_eq!(frame.can_id(), 0x7FF);
+        assert!(!frame.is_extended_frame());
+        assert_ne!(frame.frame.MSGTYPE & pcan::PCAN_MESSAGE_FD as u8, 0);
+
+        // nine bytes are padded to twelve, the DLC code of which is 9
+        assert_eq!(frame.dlc(), 9);
+        assert_eq!(
+            frame.data(),
+            &[0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0, 0, 0]
+        );
+        assert_eq!(frame.mut_data().len(), 12);
+        frame.mut_data()[11] = 0x55;
+        assert_eq!(frame.data()[11], 0x55);
+    }
+
+    #[test]
+    fn can_fd_frame_new_006() {
+        let frame = CanFdFrame::new(0xFFFF_FFFF, MessageType::Extended, &[1; 30]).unwrap();
+        assert_eq!(frame.can_id(), EXTENDED_MASK);
+        assert!(frame.is_extended_frame());
+        assert_ne!(frame.frame.MSGTYPE & pcan::PCAN_MESSAGE_FD as u8, 0);
+        assert_eq!(frame.dlc(), 13);
+        assert_eq!(frame.data().len(), 32);
+        assert_eq!(&frame.data()[28..], &[1, 1, 0, 0]);
+
+        let frame = CanFdFrame::new(0x1ABC_DEF0, MessageType::Extended, &[]).unwrap();
+        assert_eq!(frame.can_id(), 0x1ABC_DEF0);
+        assert_eq!(frame.dlc(), 0);
+        assert!(frame.data().is_empty());
+    }
+
+    #[test]
+    fn fd_dlc_001() {
+        for dlc in 0..16u8 {
+            assert_eq!(fd_length_to_dlc(fd_dlc_to_length(dlc)), dlc);
+        }
+        assert_eq!(fd_length_to_dlc(49), 15);
+        assert_eq!(fd_dlc_to_length(15), 64);
+    }
+
+    /* TIMESTAMP */
+
+    #[test]
+    fn timestamp_001() {
+        let timestamp = Timestamp {
+            timestamp: pcan::TPCANTimestamp {
+                micros: 250,
+                millis: u32::MAX,
+                millis_overflow: 1,
+            },
+        };
+        let micros = ((2u64 << 32) - 1) * 1000 + 250;
+        assert_eq!(timestamp.as_micros(), micros);
+        assert_eq!(timestamp.as_duration(), Duration::from_micros(micros));
+        assert_eq!(Timestamp::from_micros(micros), timestamp);
+        assert_eq!(Timestamp::from_micros(micros).millis_overflow(), 1);
+    }
+
+    #[test]
+    fn timestamp_002() {
+        // the milliseconds wrapped between both timestamps
+        let earlier = Timestamp::from_micros(u32::MAX as u64 * 1000 + 999);
+        let later = Timestamp::from_micros((1u64 << 32) * 1000 + 1);
+        assert_eq!(later.millis(), 0);
+        assert!(earlier < later);
+        assert_eq!(later - earlier, Duration::from_micros(2));
+        assert_eq!(earlier - later, Duration::ZERO);
+        assert_eq!(earlier.checked_duration_since(later), None);
+        assert_eq!([later, earlier].iter().max(), Some(&later));
+    }
+
+    /* SPLIT */
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn split_001() {
+        assert_send_sync::<CanSocket>();
+        assert_send_sync::<dng::DngCanSocket>();
+        assert_send_sync::<isa::IsaCanSocket>();
+        assert_send_sync::<lan::LanCanSocket>();
+        assert_send_sync::<pcc::PccCanSocket>();
+        assert_send_sync::<pci::PciCanSocket>();
+        assert_send_sync::<usb::UsbCanSocket>();
+        assert_send_sync::<pipe::PipeSocket>();
+        assert_send_sync::<RecvHalf<usb::UsbCanSocket>>();
+        assert_send_sync::<SendHalf<usb::UsbCanSocket>>();
+        assert_send_sync::<RecvHalf<lan::LanCanSocket>>();
+        assert_send_sync::<SendHalf<lan::LanCanSocket>>();
+        // the halves only share the handle, not the socket
+        assert_send_sync::<RecvHalf<std::cell::Cell<u8>>>();
+        assert_send_sync::<SendHalf<std::rc::Rc<u8>>>();
+    }
+
+    #[test]
+    fn cansend_001() {
+        let frame = "123#DEADBEEF".parse::<CanFrame>().unwrap();
+        assert_eq!(
+            frame,
+            CanFrame::new(0x123, MessageType::Standard, &[0xDE, 0xAD, 0xBE, 0xEF]).unwrap()
+        );
+        assert_eq!(frame.to_string(), "123#DEADBEEF");
+
+        let frame = "1ABCDEF0#R".parse::<CanFrame>().unwrap();
+        assert!(frame.is_rtr_frame());
+        assert!(frame.is_extended_frame());
+        assert_eq!(frame.to_string(), "1ABCDEF0#R");
+        assert_eq!("7FF#R3".parse::<CanFrame>().unwrap().dlc(), 3);
+
+        let frame = "00000123#11.22.33".parse::<CanFrame>().unwrap();
+        assert_eq!(frame.to_string(), "00000123#112233");
+        assert_eq!("123#".parse::<CanFrame>().unwrap().to_string(), "123#");
+        assert_eq!(
+            "123#0011223344556677_F".parse::<CanFrame>().unwrap().dlc(),
+            8
+        );
+    }
+
+    #[test]
+    fn cansend_002() {
+        let frame = "123##1AABB".parse::<CanFdFrame>().unwrap();
+        assert!(frame.is_brs_frame());
+        assert!(!frame.is_esi_frame());
+        assert_eq!(frame.data(), &[0xAA, 0xBB]);
+        assert_eq!(frame.to_string(), "123##1AABB");
+
+        // padded to the next valid length
+        let frame = "1ABCDEF0##3".to_string() + &"01".repeat(9);
+        let frame = frame.parse::<CanFdFrame>().unwrap();
+        assert!(frame.is_esi_frame());
+        assert_eq!(frame.data().len(), 12);
+        assert_eq!(
+            frame.to_string(),
+            "1ABCDEF0##3".to_string() + &"01".repeat(9) + "000000"
+        );
+    }
+
+    #[test]
+    fn cansend_003() {
+        assert_eq!("12#00".parse::<CanFrame>(), Err(ParseFrameError::InvalidId));
+        assert_eq!(
+            "800#00".parse::<CanFrame>(),
+            Err(ParseFrameError::InvalidId)
+        );
+        assert_eq!(
+            "123".parse::<CanFrame>(),
+            Err(ParseFrameError::InvalidSeparator)
+        );
+        assert_eq!(
+            "123##100".parse::<CanFrame>(),
+            Err(ParseFrameError::InvalidSeparator)
+        );
+        assert_eq!(
+            "123#0G".parse::<CanFrame>(),
+            Err(ParseFrameError::InvalidData)
+        );
+        assert_eq!(
+            "123#001122334455667788".parse::<CanFrame>(),
+            Err(ParseFrameError::TooMuchData)
+        );
+        assert_eq!(
+            "123#00".parse::<CanFdFrame>(),
+            Err(ParseFrameError::InvalidSeparator)
+        );
+    }
 }
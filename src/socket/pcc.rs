@@ -19,7 +19,10 @@ use crate::info::{
     HasNominalBusSpeed,
 };
 use crate::pcan;
-use crate::socket::{Baudrate, HasRecvCan, HasRecvCanFd, HasSendCan, HasSendCanFd, Socket};
+use crate::socket::clock::{Anchor, Anchored};
+use crate::socket::{
+    Baudrate, HasRecvCan, HasRecvCanFd, HasSendCan, HasSendCanFd, HasSplit, Socket,
+};
 use crate::special::{HasFiveVoltsPower, HasSetFiveVoltsPower};
 use crate::trace::{
     HasSetTraceConfigure, HasSetTraceLocation, HasSetTraceSize, HasSetTraceStatus,
@@ -29,6 +32,7 @@ use crate::trace::{
 #[derive(Debug, PartialEq)]
 pub struct PccCanSocket {
     handle: u16,
+    anchor: Anchor,
 }
 
 impl PccCanSocket {
@@ -37,7 +41,10 @@ impl PccCanSocket {
         let code = unsafe { pcan::CAN_Initialize(handle, baud.into(), 0, 0, 0) };
 
         match PcanOkError::try_from(code) {
-            Ok(PcanOkError::Ok) => Ok(PccCanSocket { handle }),
+            Ok(PcanOkError::Ok) => Ok(PccCanSocket {
+                handle,
+                anchor: Anchor::now(),
+            }),
             Ok(PcanOkError::Err(err)) => Err(err),
             Err(_) => Err(PcanError::Unknown),
         }
@@ -60,6 +67,14 @@ impl Socket for PccCanSocket {
     }
 }
 
+/* Anchored trait implementation */
+
+impl Anchored for PccCanSocket {
+    fn anchor(&self) -> Anchor {
+        self.anchor
+    }
+}
+
 /* Channel trait implementation */
 
 impl Channel for PccCanSocket {
@@ -73,6 +88,8 @@ impl Channel for PccCanSocket {
 impl HasRecvCan for PccCanSocket {}
 impl HasSendCan for PccCanSocket {}
 
+impl HasSplit for PccCanSocket {}
+
 // impl HasRecvCanFd for PccCanSocket {}
 // impl HasSendCanFd for PccCanSocket {}
 
@@ -25,7 +25,10 @@ use crate::io::{
     HasSetDigitalConfiguration, HasSetDigitalSet, HasSetDigitalValue,
 };
 use crate::pcan;
-use crate::socket::{Baudrate, HasRecvCan, HasRecvCanFd, HasSendCan, HasSendCanFd, Socket};
+use crate::socket::clock::{Anchor, Anchored};
+use crate::socket::{
+    Baudrate, HasRecvCan, HasRecvCanFd, HasSendCan, HasSendCanFd, HasSplit, Socket,
+};
 use crate::special::{
     HasBusOffAutoreset, HasFiveVoltsPower, HasInterframeDelay, HasListenOnly,
     HasSetBusOffAutoreset, HasSetFiveVoltsPower, HasSetInterframeDelay, HasSetListenOnly,
@@ -38,6 +41,7 @@ use crate::trace::{
 #[derive(Debug, PartialEq)]
 pub struct UsbCanSocket {
     handle: u16,
+    anchor: Anchor,
 }
 
 impl UsbCanSocket {
@@ -46,7 +50,10 @@ impl UsbCanSocket {
         let code = unsafe { pcan::CAN_Initialize(handle, baud.into(), 0, 0, 0) };
 
         match PcanOkError::try_from(code) {
-            Ok(PcanOkError::Ok) => Ok(UsbCanSocket { handle }),
+            Ok(PcanOkError::Ok) => Ok(UsbCanSocket {
+                handle,
+                anchor: Anchor::now(),
+            }),
             Ok(PcanOkError::Err(err)) => Err(err),
             Err(_) => Err(PcanError::Unknown),
         }
@@ -69,6 +76,14 @@ impl Socket for UsbCanSocket {
     }
 }
 
+/* Anchored trait implementation */
+
+impl Anchored for UsbCanSocket {
+    fn anchor(&self) -> Anchor {
+        self.anchor
+    }
+}
+
 /* Channel trait implementation */
 
 impl Channel for UsbCanSocket {
@@ -82,6 +97,8 @@ impl Channel for UsbCanSocket {
 impl HasRecvCan for UsbCanSocket {}
 impl HasSendCan for UsbCanSocket {}
 
+impl HasSplit for UsbCanSocket {}
+
 // impl HasRecvCanFd for UsbCanSocket {}
 // impl HasSendCanFd for UsbCanSocket {}
 
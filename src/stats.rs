@@ -0,0 +1,614 @@
+//! Bus load and traffic statistics computed from received frames.
+//!
+//! [BusStatistics] is fed with the frames returned by [RecvCan] and [RecvCanFd] and produces
+//! [Snapshot]s containing frame rates, bus load, error-frame rate, per-ID counts, cycle times and
+//! DLC distributions. [StatisticsCollector] runs the reception loop in a background thread and
+//! keeps a periodically updated [Snapshot].
+
+use crate::error::PcanError;
+use crate::info::{DataBusSpeed, NominalBusSpeed};
+use crate::socket::{CanFdFrame, CanFrame, RecvCan, RecvCanFd, Timestamp};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/* Frame length estimation */
+
+/// Strategy used to account for stuff bits when computing the length of a frame on the bus.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Stuffing {
+    /// Ignores dynamic stuff bits. The fixed stuff bits of CAN FD frames are still counted.
+    None,
+    /// Assumes the maximum number of stuff bits possible for the frame.
+    WorstCase,
+    /// Computes the stuff bits of classic frames from their actual bit stream. CAN FD frames fall
+    /// back to [WorstCase](Stuffing::WorstCase).
+    Exact,
+}
+
+/// Bits following the CRC sequence: CRC delimiter, ACK slot, ACK delimiter, EOF and IFS.
+const CLASSIC_TAIL_BITS: u32 = 1 + 1 + 1 + 7 + 3;
+/// Bits following the CRC delimiter of a CAN FD frame: ACK slot, ACK delimiter, EOF and IFS.
+const FD_TAIL_BITS: u32 = 1 + 1 + 7 + 3;
+
+fn worst_case_stuff_bits(stuffable_bits: u32) -> u32 {
+    stuffable_bits.saturating_sub(1) / 4
+}
+
+fn push_bits(bits: &mut Vec<bool>, value: u32, count: u32) {
+    for i in (0..count).rev() {
+        bits.push((value >> i) & 1 == 1);
+    }
+}
+
+fn crc15(bits: &[bool]) -> u32 {
+    let mut crc = 0u32;
+    for bit in bits {
+        let crc_next = *bit ^ ((crc >> 14) & 1 == 1);
+        crc = (crc << 1) & 0x7F_FF;
+        if crc_next {
+            crc ^= 0x45_99;
+        }
+    }
+    crc
+}
+
+fn count_stuff_bits(bits: &[bool]) -> u32 {
+    let mut count = 0;
+    let mut run = 0;
+    let mut previous = None;
+
+    for bit in bits {
+        if previous == Some(*bit) {
+            run += 1;
+        } else {
+            previous = Some(*bit);
+            run = 1;
+        }
+
+        if run == 5 {
+            count += 1;
+            previous = Some(!*bit);
+            run = 1;
+        }
+    }
+    count
+}
+
+/// Builds the stuffable part (SOF up to the end of the CRC sequence) of a classic frame.
+fn classic_bit_stream(can_id: u32, extended: bool, rtr: bool, dlc: u8, data: &[u8]) -> Vec<bool> {
+    let mut bits = Vec::with_capacity(54 + 8 * data.len() + 15);
+    bits.push(false);
+
+    if extended {
+        push_bits(&mut bits, can_id >> 18, 11);
+        bits.push(true);
+        bits.push(true);
+        push_bits(&mut bits, can_id, 18);
+        bits.push(rtr);
+        bits.push(false);
+        bits.push(false);
+    } else {
+        push_bits(&mut bits, can_id, 11);
+        bits.push(rtr);
+        bits.push(false);
+        bits.push(false);
+    }
+
+    push_bits(&mut bits, dlc as u32, 4);
+    if !rtr {
+        for byte in data {
+            push_bits(&mut bits, *byte as u32, 8);
+        }
+    }
+
+    let crc = crc15(&bits);
+    push_bits(&mut bits, crc, 15);
+    bits
+}
+
+/// Returns the number of bits a classic frame occupies on the bus, including the interframe
+/// space.
+pub fn classic_frame_bits(frame: &CanFrame, stuffing: Stuffing) -> u32 {
+    let data_bits = if frame.is_rtr_frame() {
+        0
+    } else {
+        8 * frame.data().len() as u32
+    };
+    let stuffable_bits = if frame.is_extended_frame() { 54 } else { 34 } + data_bits;
+
+    let stuff_bits = match stuffing {
+        Stuffing::None => 0,
+        Stuffing::WorstCase => worst_case_stuff_bits(stuffable_bits),
+        Stuffing::Exact => count_stuff_bits(&classic_bit_stream(
+            frame.can_id(),
+            frame.is_extended_frame(),
+            frame.is_rtr_frame(),
+            frame.dlc(),
+            frame.data(),
+        )),
+    };
+
+    stuffable_bits + stuff_bits + CLASSIC_TAIL_BITS
+}
+
+/// Returns the number of bits a CAN FD frame occupies in its arbitration phase and in its data
+/// phase.
+pub fn fd_frame_bits(frame: &CanFdFrame, stuffing: Stuffing) -> (u32, u32) {
+    let payload = frame.data().len() as u32;
+
+    // SOF, identifier, RRS/SRR, IDE, FDF, res and BRS are sent with the nominal bitrate.
+    let arbitration_bits = if frame.is_extended_frame() { 36 } else { 17 };
+    // ESI, DLC and data are sent with the data bitrate and are dynamically stuffed.
+    let data_bits = 1 + 4 + 8 * payload;
+    // Stuff count, CRC and the fixed stuff bits preceding them, CRC delimiter.
+    let crc_bits = if payload <= 16 {
+        4 + 17 + 6
+    } else {
+        4 + 21 + 7
+    } + 1;
+
+    let (arbitration_stuff, data_stuff) = match stuffing {
+        Stuffing::None => (0, 0),
+        Stuffing::WorstCase | Stuffing::Exact => (
+            worst_case_stuff_bits(arbitration_bits),
+            worst_case_stuff_bits(data_bits + 1),
+        ),
+    };
+
+    (
+        arbitration_bits + arbitration_stuff + FD_TAIL_BITS,
+        data_bits + data_stuff + crc_bits,
+    )
+}
+
+/* Statistics types */
+
+/// Identifies a frame by its CAN-ID and the message type.
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Copy, Clone)]
+pub struct FrameKey {
+    pub can_id: u32,
+    pub extended: bool,
+}
+
+/// Time between two consecutive frames carrying the same identifier.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct CycleTime {
+    pub min: Duration,
+    pub avg: Duration,
+    pub max: Duration,
+    /// Standard deviation of the measured cycle times.
+    pub jitter: Duration,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct IdStatistics {
+    pub count: u64,
+    /// Cycle time measured with the driver timestamps. `None` until two frames were received.
+    pub cycle_time: Option<CycleTime>,
+    pub dlc_distribution: BTreeMap<u8, u64>,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Snapshot {
+    /// Length of the window the rates were computed for.
+    pub window: Duration,
+    pub frames_per_second: f64,
+    pub error_frames_per_second: f64,
+    /// Bus load in percent.
+    pub bus_load: f64,
+    pub total_frames: u64,
+    pub total_error_frames: u64,
+    pub ids: BTreeMap<FrameKey, IdStatistics>,
+    pub dlc_distribution: BTreeMap<u8, u64>,
+}
+
+#[derive(Debug, Default)]
+struct IdTracker {
+    count: u64,
+    last_timestamp: Option<u64>,
+    intervals: u64,
+    min: u64,
+    max: u64,
+    mean: f64,
+    m2: f64,
+    dlc_distribution: BTreeMap<u8, u64>,
+}
+
+impl IdTracker {
+    fn record(&mut self, dlc: u8, timestamp: u64) {
+        self.count += 1;
+        *self.dlc_distribution.entry(dlc).or_insert(0) += 1;
+
+        if let Some(last) = self.last_timestamp {
+            if timestamp >= last {
+                let interval = timestamp - last;
+                if self.intervals == 0 {
+                    self.min = interval;
+                    self.max = interval;
+                } else {
+                    self.min = self.min.min(interval);
+                    self.max = self.max.max(interval);
+                }
+
+                self.intervals += 1;
+                let delta = interval as f64 - self.mean;
+                self.mean += delta / self.intervals as f64;
+                self.m2 += delta * (interval as f64 - self.mean);
+            }
+        }
+        self.last_timestamp = Some(timestamp);
+    }
+
+    fn cycle_time(&self) -> Option<CycleTime> {
+        if self.intervals == 0 {
+            return None;
+        }
+
+        let variance = self.m2 / self.intervals as f64;
+        Some(CycleTime {
+            min: Duration::from_micros(self.min),
+            avg: Duration::from_micros(self.mean.round() as u64),
+            max: Duration::from_micros(self.max),
+            jitter: Duration::from_micros(variance.sqrt().round() as u64),
+        })
+    }
+
+    fn statistics(&self) -> IdStatistics {
+        IdStatistics {
+            count: self.count,
+            cycle_time: self.cycle_time(),
+            dlc_distribution: self.dlc_distribution.clone(),
+        }
+    }
+}
+
+/* BusStatistics */
+
+/// Accumulates statistics of received frames.
+///
+/// Rates are computed per window: every call of [snapshot](BusStatistics::snapshot) closes the
+/// current window. Counters, cycle times and DLC distributions accumulate until
+/// [reset](BusStatistics::reset) is called. Error frames are counted but do not contribute to the
+/// bus load, status frames are ignored.
+#[derive(Debug)]
+pub struct BusStatistics {
+    nominal_bitrate: u32,
+    data_bitrate: u32,
+    stuffing: Stuffing,
+    window_frames: u64,
+    window_error_frames: u64,
+    window_bus_time: f64,
+    total_frames: u64,
+    total_error_frames: u64,
+    ids: HashMap<FrameKey, IdTracker>,
+    dlc_distribution: BTreeMap<u8, u64>,
+}
+
+impl BusStatistics {
+    /// Creates statistics for a bus running at `nominal_bitrate` bit/s. The data bitrate defaults
+    /// to the nominal bitrate.
+    pub fn new(nominal_bitrate: u32) -> BusStatistics {
+        BusStatistics {
+            nominal_bitrate,
+            data_bitrate: nominal_bitrate,
+            stuffing: Stuffing::WorstCase,
+            window_frames: 0,
+            window_error_frames: 0,
+            window_bus_time: 0.0,
+            total_frames: 0,
+            total_error_frames: 0,
+            ids: HashMap::new(),
+            dlc_distribution: BTreeMap::new(),
+        }
+    }
+
+    /// Creates statistics using the nominal bitrate the channel reports.
+    pub fn from_socket<T: NominalBusSpeed>(socket: &T) -> Result<BusStatistics, PcanError> {
+        Ok(BusStatistics::new(socket.nominal_bus_speed()?))
+    }
+
+    /// Creates statistics using the nominal and data bitrates the channel reports.
+    pub fn from_fd_socket<T: NominalBusSpeed + DataBusSpeed>(
+        socket: &T,
+    ) -> Result<BusStatistics, PcanError> {
+        Ok(BusStatistics::new(socket.nominal_bus_speed()?)
+            .with_data_bitrate(socket.data_bus_speed()?))
+    }
+
+    pub fn with_data_bitrate(mut self, data_bitrate: u32) -> BusStatistics {
+        self.data_bitrate = data_bitrate;
+        self
+    }
+
+    pub fn with_stuffing(mut self, stuffing: Stuffing) -> BusStatistics {
+        self.stuffing = stuffing;
+        self
+    }
+
+    pub fn nominal_bitrate(&self) -> u32 {
+        self.nominal_bitrate
+    }
+
+    pub fn data_bitrate(&self) -> u32 {
+        self.data_bitrate
+    }
+
+    /// Records a frame returned by [RecvCan::recv].
+    pub fn record(&mut self, frame: &CanFrame, timestamp: &Timestamp) {
+        if frame.is_status_frame() {
+            return;
+        }
+
+        if frame.is_error_frame() {
+            self.record_error_frame();
+            return;
+        }
+
+        let bits = classic_frame_bits(frame, self.stuffing);
+        let bus_time = bits as f64 / self.nominal_bitrate as f64;
+        let key = FrameKey {
+            can_id: frame.can_id(),
+            extended: frame.is_extended_frame(),
+        };
+        self.record_frame(key, frame.dlc(), bus_time, timestamp.as_micros());
+    }
+
+    /// Records a frame returned by [RecvCanFd::recv_fd].
+    pub fn record_fd(&mut self, frame: &CanFdFrame, timestamp: &Timestamp) {
+        if frame.is_status_frame() {
+            return;
+        }
+
+        if frame.is_error_frame() {
+            self.record_error_frame();
+            return;
+        }
+
+        let (arbitration_bits, data_bits) = fd_frame_bits(frame, self.stuffing);
+        let data_bitrate = if frame.is_brs_frame() {
+            self.data_bitrate
+        } else {
+            self.nominal_bitrate
+        };
+        let bus_time = arbitration_bits as f64 / self.nominal_bitrate as f64
+            + data_bits as f64 / data_bitrate as f64;
+        let key = FrameKey {
+            can_id: frame.can_id(),
+            extended: frame.is_extended_frame(),
+        };
+        self.record_frame(key, frame.dlc(), bus_time, timestamp.as_micros());
+    }
+
+    fn record_frame(&mut self, key: FrameKey, dlc: u8, bus_time: f64, timestamp: u64) {
+        self.window_frames += 1;
+        self.window_bus_time += bus_time;
+        self.total_frames += 1;
+        *self.dlc_distribution.entry(dlc).or_insert(0) += 1;
+        self.ids.entry(key).or_default().record(dlc, timestamp);
+    }
+
+    fn record_error_frame(&mut self) {
+        self.window_error_frames += 1;
+        self.total_error_frames += 1;
+    }
+
+    /// Closes the current window, which lasted `elapsed`, and returns the resulting snapshot.
+    pub fn snapshot(&mut self, elapsed: Duration) -> Snapshot {
+        let seconds = elapsed.as_secs_f64();
+        let (frames_per_second, error_frames_per_second, bus_load) = if seconds > 0.0 {
+            (
+                self.window_frames as f64 / seconds,
+                self.window_error_frames as f64 / seconds,
+                (100.0 * self.window_bus_time / seconds).min(100.0),
+            )
+        } else {
+            (0.0, 0.0, 0.0)
+        };
+
+        self.window_frames = 0;
+        self.window_error_frames = 0;
+        self.window_bus_time = 0.0;
+
+        Snapshot {
+            window: elapsed,
+            frames_per_second,
+            error_frames_per_second,
+            bus_load,
+            total_frames: self.total_frames,
+            total_error_frames: self.total_error_frames,
+            ids: self
+                .ids
+                .iter()
+                .map(|(key, tracker)| (*key, tracker.statistics()))
+                .collect(),
+            dlc_distribution: self.dlc_distribution.clone(),
+        }
+    }
+
+    /// Discards all accumulated statistics.
+    pub fn reset(&mut self) {
+        *self = BusStatistics::new(self.nominal_bitrate)
+            .with_data_bitrate(self.data_bitrate)
+            .with_stuffing(self.stuffing);
+    }
+}
+
+/* StatisticsCollector */
+
+/// Receives frames from a socket in a background thread and updates a [Snapshot] every period.
+pub struct StatisticsCollector<S> {
+    snapshot: Arc<Mutex<Snapshot>>,
+    running: Arc<AtomicBool>,
+    handle: Option<JoinHandle<S>>,
+}
+
+const IDLE_SLEEP: Duration = Duration::from_millis(1);
+
+impl<S: Send + 'static> StatisticsCollector<S> {
+    fn spawn_with<F>(socket: S, statistics: BusStatistics, period: Duration, recv: F) -> Self
+    where
+        F: Fn(&S, &mut BusStatistics) -> Result<(), PcanError> + Send + 'static,
+    {
+        let snapshot = Arc::new(Mutex::new(Snapshot::default()));
+        let running = Arc::new(AtomicBool::new(true));
+
+        let thread_snapshot = snapshot.clone();
+        let thread_running = running.clone();
+        let handle = thread::spawn(move || {
+            let mut statistics = statistics;
+            let mut window_start = Instant::now();
+
+            while thread_running.load(Ordering::Relaxed) {
+                if recv(&socket, &mut statistics).is_err() {
+                    thread::sleep(IDLE_SLEEP);
+                }
+
+                let elapsed = window_start.elapsed();
+                if elapsed >= period {
+                    window_start = Instant::now();
+                    let current = statistics.snapshot(elapsed);
+                    if let Ok(mut snapshot) = thread_snapshot.lock() {
+                        *snapshot = current;
+                    }
+                }
+            }
+            socket
+        });
+
+        StatisticsCollector {
+            snapshot,
+            running,
+            handle: Some(handle),
+        }
+    }
+
+    /// Returns the most recent snapshot.
+    pub fn snapshot(&self) -> Snapshot {
+        match self.snapshot.lock() {
+            Ok(snapshot) => snapshot.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    /// Stops the background thread and hands back the socket.
+    pub fn stop(mut self) -> Option<S> {
+        self.running.store(false, Ordering::Relaxed);
+        self.handle.take().and_then(|handle| handle.join().ok())
+    }
+}
+
+impl<S: RecvCan + Send + 'static> StatisticsCollector<S> {
+    /// Spawns a collector receiving classic frames from `socket`.
+    pub fn spawn(socket: S, statistics: BusStatistics, period: Duration) -> Self {
+        Self::spawn_with(socket, statistics, period, |socket, statistics| {
+            let (frame, timestamp) = socket.recv()?;
+            statistics.record(&frame, &timestamp);
+            Ok(())
+        })
+    }
+}
+
+impl<S: RecvCanFd + Send + 'static> StatisticsCollector<S> {
+    /// Spawns a collector receiving CAN FD frames from `socket`.
+    pub fn spawn_fd(socket: S, statistics: BusStatistics, period: Duration) -> Self {
+        Self::spawn_with(socket, statistics, period, |socket, statistics| {
+            let (frame, timestamp) = socket.recv_fd()?;
+            statistics.record_fd(&frame, &timestamp);
+            Ok(())
+        })
+    }
+}
+
+impl<S> Drop for StatisticsCollector<S> {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::socket::MessageType;
+
+    #[test]
+    fn classic_frame_bits_001() {
+        let frame = CanFrame::new(0x123, MessageType::Standard, &[0; 8]).unwrap();
+        assert_eq!(classic_frame_bits(&frame, Stuffing::None), 111);
+        assert_eq!(classic_frame_bits(&frame, Stuffing::WorstCase), 111 + 24);
+    }
+
+    #[test]
+    fn classic_frame_bits_002() {
+        let frame = CanFrame::new(0x123, MessageType::Extended, &[]).unwrap();
+        assert_eq!(classic_frame_bits(&frame, Stuffing::None), 67);
+    }
+
+    #[test]
+    fn classic_frame_bits_003() {
+        let frame = CanFrame::new(0x0, MessageType::Standard, &[0; 8]).unwrap();
+        let exact = classic_frame_bits(&frame, Stuffing::Exact);
+        assert!(exact > classic_frame_bits(&frame, Stuffing::None));
+        assert!(exact <= classic_frame_bits(&frame, Stuffing::WorstCase));
+    }
+
+    #[test]
+    fn fd_frame_bits_001() {
+        // frames are padded to the next length a CAN FD frame can carry
+        let frame = CanFdFrame::new(0x123, MessageType::Standard, &[0; 9]).unwrap();
+        let padded = CanFdFrame::new(0x123, MessageType::Standard, &[0; 12]).unwrap();
+        assert_eq!(
+            fd_frame_bits(&frame, Stuffing::None),
+            fd_frame_bits(&padded, Stuffing::None)
+        );
+        assert_eq!(
+            fd_frame_bits(&padded, Stuffing::None),
+            (17 + 12, 5 + 96 + 28)
+        );
+
+        let frame = CanFdFrame::new(0x123, MessageType::Extended, &[0; 20]).unwrap();
+        assert_eq!(fd_frame_bits(&frame, Stuffing::None).0, 36 + 12);
+    }
+
+    #[test]
+    fn cycle_time_001() {
+        let mut tracker = IdTracker::default();
+        for timestamp in [0, 10_000, 20_000, 31_000, 40_000] {
+            tracker.record(8, timestamp);
+        }
+
+        let cycle_time = tracker.cycle_time().unwrap();
+        assert_eq!(tracker.count, 5);
+        assert_eq!(cycle_time.min, Duration::from_micros(9_000));
+        assert_eq!(cycle_time.max, Duration::from_micros(11_000));
+        assert_eq!(cycle_time.avg, Duration::from_micros(10_000));
+        assert_eq!(cycle_time.jitter, Duration::from_micros(707));
+    }
+
+    #[test]
+    fn bus_load_001() {
+        let mut statistics = BusStatistics::new(500_000).with_stuffing(Stuffing::None);
+        let frame = CanFrame::new(0x123, MessageType::Standard, &[0; 8]).unwrap();
+        for _ in 0..100 {
+            statistics.record(&frame, &Timestamp::default());
+        }
+
+        let snapshot = statistics.snapshot(Duration::from_millis(100));
+        assert_eq!(snapshot.total_frames, 100);
+        assert!((snapshot.frames_per_second - 1000.0).abs() < 1e-9);
+        assert!((snapshot.bus_load - 22.2).abs() < 1e-9);
+        assert_eq!(
+            snapshot.ids[&FrameKey {
+                can_id: 0x123,
+                extended: false
+            }]
+                .count,
+            100
+        );
+    }
+}
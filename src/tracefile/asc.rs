@@ -0,0 +1,739 @@
+//! Vector ASCII logs (`.asc`).
+//!
+//! [AscReader] streams the classic and CAN FD frames, remote frames and error frames of a log
+//! written by CANalyzer or CANoe, in either number base and with absolute or relative timestamps.
+//! Other events are skipped. [AscWriter] writes logs with absolute timestamps. Channels map to
+//! [Record::bus]. The date in the header is converted as if it were UTC.
+
+use crate::socket::{fd_dlc_to_length, CanFdFrame, CanFrame, MessageType};
+use crate::tracefile::{days_from_civil, from_civil, to_civil, Direction, Event, Record};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, PartialEq)]
+pub enum AscError {
+    Io(std::io::ErrorKind),
+    /// A line of the file could not be parsed.
+    Syntax {
+        line: usize,
+    },
+    /// The event cannot be stored in an ASC log.
+    UnsupportedEvent,
+}
+
+impl From<std::io::Error> for AscError {
+    fn from(value: std::io::Error) -> Self {
+        AscError::Io(value.kind())
+    }
+}
+
+/// Number base of identifiers and data bytes.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Base {
+    Hex,
+    Dec,
+}
+
+impl Base {
+    fn radix(&self) -> u32 {
+        match self {
+            Base::Hex => 16,
+            Base::Dec => 10,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Timestamps {
+    /// Seconds since the start of the measurement.
+    Absolute,
+    /// Seconds since the previous event.
+    Relative,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct AscHeader {
+    pub start_time: Option<SystemTime>,
+    pub base: Base,
+    pub timestamps: Timestamps,
+    pub internal_events: bool,
+}
+
+impl Default for AscHeader {
+    fn default() -> Self {
+        AscHeader {
+            start_time: None,
+            base: Base::Hex,
+            timestamps: Timestamps::Absolute,
+            internal_events: false,
+        }
+    }
+}
+
+/* Date */
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+
+/// Parses dates like `Wed Jan 9 03:25:50.914 pm 2019` or `Wed Jan 09 15:25:50 2019`.
+fn parse_date(value: &str) -> Option<SystemTime> {
+    let mut tokens = value.split_whitespace();
+    tokens.next()?;
+    let month = tokens.next()?;
+    let month = MONTHS.iter().position(|name| *name == month)? as u32 + 1;
+    let day = tokens.next()?.parse::<u32>().ok()?;
+
+    let mut parts = tokens.next()?.split(':');
+    let mut hours = parts.next()?.parse::<u64>().ok()?;
+    let minutes = parts.next()?.parse::<u64>().ok()?;
+    let seconds = parse_seconds(parts.next()?)?;
+
+    let mut token = tokens.next()?;
+    match token.to_lowercase().as_str() {
+        "am" => {
+            hours %= 12;
+            token = tokens.next()?;
+        }
+        "pm" => {
+            hours = hours % 12 + 12;
+            token = tokens.next()?;
+        }
+        _ => {}
+    }
+    let year = token.parse::<i64>().ok()?;
+    if hours >= 24 || minutes >= 60 || seconds.as_secs() >= 60 {
+        return None;
+    }
+
+    from_civil(
+        year,
+        month,
+        day,
+        hours * 3600 + minutes * 60 + seconds.as_secs(),
+        seconds.subsec_micros(),
+    )
+}
+
+fn format_date(time: SystemTime) -> String {
+    let (year, month, day, seconds, micros) = to_civil(time);
+    let weekday = days_from_civil(year, month, day).rem_euclid(7) as usize;
+    let hours = seconds / 3600;
+    format!(
+        "{} {} {} {:02}:{:02}:{:02}.{:03} {} {}",
+        WEEKDAYS[weekday],
+        MONTHS[month as usize - 1],
+        day,
+        match hours % 12 {
+            0 => 12,
+            hours => hours,
+        },
+        seconds / 60 % 60,
+        seconds % 60,
+        micros / 1000,
+        if hours < 12 { "am" } else { "pm" },
+        year
+    )
+}
+
+/// Parses a time in seconds with up to nine decimals, rounded down to µs.
+fn parse_seconds(value: &str) -> Option<Duration> {
+    let (seconds, fraction) = value.split_once('.').unwrap_or((value, ""));
+    let seconds = seconds.parse::<u64>().ok()?;
+    let digits = fraction.get(..fraction.len().min(6))?;
+    let micros = match digits.is_empty() {
+        true => 0,
+        false => digits.parse::<u64>().ok()? * 10u64.pow(6 - digits.len() as u32),
+    };
+    Some(Duration::from_secs(seconds) + Duration::from_micros(micros))
+}
+
+/* Lines */
+
+fn parse_id(token: &str, base: Base) -> Option<(u32, MessageType)> {
+    match token.strip_suffix('x') {
+        Some(id) => Some((
+            u32::from_str_radix(id, base.radix()).ok()?,
+            MessageType::Extended,
+        )),
+        None => Some((
+            u32::from_str_radix(token, base.radix()).ok()?,
+            MessageType::Standard,
+        )),
+    }
+}
+
+fn parse_direction(token: &str) -> Option<Direction> {
+    match token {
+        "Rx" => Some(Direction::Rx),
+        "Tx" | "TxRq" => Some(Direction::Tx),
+        _ => None,
+    }
+}
+
+fn parse_data<'a, I: Iterator<Item = &'a str>>(
+    tokens: &mut I,
+    count: usize,
+    base: Base,
+) -> Option<Vec<u8>> {
+    (0..count)
+        .map(|_| u8::from_str_radix(tokens.next()?, base.radix()).ok())
+        .collect()
+}
+
+/// Parses the part of a classic frame line after the channel. `None` marks a malformed frame,
+/// `Some(None)` a line which is not a frame.
+fn parse_can<'a, I: Iterator<Item = &'a str>>(
+    tokens: &mut I,
+    base: Base,
+) -> Option<Option<(Direction, Event)>> {
+    let token = match tokens.next() {
+        Some(token) => token,
+        None => return Some(None),
+    };
+    if token == "ErrorFrame" {
+        return Some(Some((Direction::Rx, Event::ErrorFrame(Vec::new()))));
+    }
+    let (id, msg_type) = match parse_id(token, base) {
+        Some(id) => id,
+        None => return Some(None),
+    };
+
+    let direction = parse_direction(tokens.next()?)?;
+    let frame = match tokens.next()? {
+        "d" => {
+            let dlc = u8::from_str_radix(tokens.next()?, 16).ok()?;
+            let data = parse_data(tokens, dlc.min(8) as usize, base)?;
+            CanFrame::new(id, msg_type, &data).ok()?
+        }
+        "r" => {
+            let dlc = match tokens.next() {
+                Some(dlc) => u8::from_str_radix(dlc, 16).ok().unwrap_or(0),
+                None => 0,
+            };
+            CanFrame::new_rtr(id, msg_type, dlc.min(8)).ok()?
+        }
+        _ => return None,
+    };
+    Some(Some((direction, Event::Can(frame))))
+}
+
+/// Parses the part of a CAN FD line after `CANFD <channel>`.
+fn parse_can_fd<'a, I: Iterator<Item = &'a str>>(
+    tokens: &mut I,
+    base: Base,
+) -> Option<(Direction, Event)> {
+    let direction = parse_direction(tokens.next()?)?;
+    let token = tokens.next()?;
+    if token == "ErrorFrame" {
+        return Some((direction, Event::ErrorFrame(Vec::new())));
+    }
+    let (id, msg_type) = parse_id(token, base)?;
+
+    // an optional symbolic name precedes the bit rate switch
+    let mut brs = tokens.next()?;
+    if brs != "0" && brs != "1" {
+        brs = tokens.next()?;
+    }
+    let esi = tokens.next()?;
+    let dlc = u8::from_str_radix(tokens.next()?, 16).ok()?;
+    let length = tokens.next()?.parse::<usize>().ok()?;
+    if length != fd_dlc_to_length(dlc) && length != 0 {
+        return None;
+    }
+    let data = parse_data(tokens, length, base)?;
+
+    let mut frame = CanFdFrame::new(id, msg_type, &data).ok()?;
+    frame.set_brs(brs == "1");
+    frame.set_esi(esi == "1");
+    Some((direction, Event::Fd(frame)))
+}
+
+/* AscReader */
+
+/// Streams the frames of an ASC log.
+pub struct AscReader<R> {
+    reader: R,
+    header: AscHeader,
+    start: SystemTime,
+    last: Duration,
+    line: usize,
+    pending: Option<String>,
+}
+
+impl AscReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<AscReader<BufReader<File>>, AscError> {
+        AscReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: BufRead> AscReader<R> {
+    /// Reads the header. Frames of logs without a date are relative to the Unix epoch.
+    pub fn new(reader: R) -> Result<AscReader<R>, AscError> {
+        let mut asc = AscReader {
+            reader,
+            header: AscHeader::default(),
+            start: SystemTime::UNIX_EPOCH,
+            last: Duration::ZERO,
+            line: 0,
+            pending: None,
+        };
+        asc.read_header()?;
+        Ok(asc)
+    }
+
+    pub fn header(&self) -> &AscHeader {
+        &self.header
+    }
+
+    fn read_line(&mut self) -> Result<Option<String>, AscError> {
+        let mut buffer = Vec::new();
+        if self.reader.read_until(b'\n', &mut buffer)? == 0 {
+            return Ok(None);
+        }
+        self.line += 1;
+        Ok(Some(String::from_utf8_lossy(&buffer).trim().to_string()))
+    }
+
+    fn read_header(&mut self) -> Result<(), AscError> {
+        while let Some(line) = self.read_line()? {
+            if let Some(date) = line.strip_prefix("date ") {
+                self.header.start_time = parse_date(date);
+            } else if let Some(settings) = line.strip_prefix("base ") {
+                let mut tokens = settings.split_whitespace();
+                if tokens.next() == Some("dec") {
+                    self.header.base = Base::Dec;
+                }
+                if tokens.next() == Some("timestamps") && tokens.next() == Some("relative") {
+                    self.header.timestamps = Timestamps::Relative;
+                }
+            } else if line == "internal events logged" {
+                self.header.internal_events = true;
+            } else if line.starts_with("Begin Triggerblock") {
+                break;
+            } else if line.starts_with(|c: char| c.is_ascii_digit()) {
+                self.pending = Some(line);
+                break;
+            }
+        }
+
+        self.start = self.header.start_time.unwrap_or(SystemTime::UNIX_EPOCH);
+        Ok(())
+    }
+
+    /// Parses a line, `Some(None)` for lines without a frame.
+    fn parse_line(&mut self, line: &str) -> Option<Option<Record>> {
+        let mut tokens = line.split_whitespace();
+        let time = parse_seconds(tokens.next()?)?;
+        let last = match self.header.timestamps {
+            Timestamps::Absolute => time,
+            Timestamps::Relative => self.last.checked_add(time)?,
+        };
+        let time = self.start.checked_add(last)?;
+        self.last = last;
+
+        let base = self.header.base;
+        let (bus, parsed) = match tokens.next() {
+            Some("CANFD") => {
+                let bus = match tokens.next()?.parse::<u8>() {
+                    Ok(bus) => bus,
+                    Err(_) => return Some(None),
+                };
+                (bus, Some(parse_can_fd(&mut tokens, base)?))
+            }
+            Some(token) => match token.parse::<u8>() {
+                Ok(bus) => (bus, parse_can(&mut tokens, base)?),
+                Err(_) => return Some(None),
+            },
+            None => return Some(None),
+        };
+
+        Some(parsed.map(|(direction, event)| Record {
+            time,
+            bus,
+            direction,
+            event,
+        }))
+    }
+}
+
+impl<R: BufRead> Iterator for AscReader<R> {
+    type Item = Result<Record, AscError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.pending.take() {
+                Some(line) => line,
+                None => match self.read_line() {
+                    Ok(Some(line)) => line,
+                    Ok(None) => return None,
+                    Err(err) => return Some(Err(err)),
+                },
+            };
+
+            if !line.starts_with(|c: char| c.is_ascii_digit()) {
+                continue;
+            }
+            match self.parse_line(&line) {
+                Some(Some(record)) => return Some(Ok(record)),
+                Some(None) => {}
+                None => return Some(Err(AscError::Syntax { line: self.line })),
+            }
+        }
+    }
+}
+
+/* AscWriter */
+
+/// Writes ASC logs with absolute timestamps.
+pub struct AscWriter<W: Write> {
+    writer: W,
+    base: Base,
+    start: SystemTime,
+    finished: bool,
+}
+
+impl AscWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        start: SystemTime,
+        base: Base,
+    ) -> Result<AscWriter<BufWriter<File>>, AscError> {
+        AscWriter::new(BufWriter::new(File::create(path)?), start, base)
+    }
+}
+
+impl<W: Write> AscWriter<W> {
+    /// Writes the header of a log starting at `start`.
+    pub fn new(mut writer: W, start: SystemTime, base: Base) -> Result<AscWriter<W>, AscError> {
+        let date = format_date(start);
+        let base_name = match base {
+            Base::Hex => "hex",
+            Base::Dec => "dec",
+        };
+        write!(
+            writer,
+            "date {}\nbase {}  timestamps absolute\nno internal events logged\n\
+             // version 13.0.0\nBegin Triggerblock {}\n{:>11} Start of measurement\n",
+            date,
+            base_name,
+            date,
+            format_seconds(Duration::ZERO)
+        )?;
+        Ok(AscWriter {
+            writer,
+            base,
+            start,
+            finished: false,
+        })
+    }
+
+    fn format_id(&self, id: u32, extended: bool) -> String {
+        let id = match self.base {
+            Base::Hex => format!("{:X}", id),
+            Base::Dec => format!("{}", id),
+        };
+        match extended {
+            true => id + "x",
+            false => id,
+        }
+    }
+
+    fn format_data(&self, data: &[u8]) -> String {
+        data.iter()
+            .map(|byte| match self.base {
+                Base::Hex => format!("{:02X}", byte),
+                Base::Dec => format!("{}", byte),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Writes a record. Records before the start are written at time 0.
+    pub fn write(&mut self, record: &Record) -> Result<(), AscError> {
+        let time = format_seconds(
+            record
+                .time
+                .duration_since(self.start)
+                .unwrap_or(Duration::ZERO),
+        );
+        let direction = match record.direction {
+            Direction::Rx => "Rx",
+            Direction::Tx => "Tx",
+        };
+
+        let line = match &record.event {
+            Event::Can(frame) if frame.is_rtr_frame() => format!(
+                "{:>11} {:<2} {:<15} {}   r {:X}",
+                time,
+                record.bus,
+                self.format_id(frame.can_id(), frame.is_extended_frame()),
+                direction,
+                frame.dlc()
+            ),
+            Event::Can(frame) => format!(
+                "{:>11} {:<2} {:<15} {}   d {:X} {}",
+                time,
+                record.bus,
+                self.format_id(frame.can_id(), frame.is_extended_frame()),
+                direction,
+                frame.dlc(),
+                self.format_data(frame.data())
+            ),
+            Event::Fd(frame) => {
+                // extended data length, bit rate switch and error state indicator
+                let flags = 0x1000
+                    | (frame.is_brs_frame() as u32) << 13
+                    | (frame.is_esi_frame() as u32) << 14;
+                format!(
+                    "{:>11} CANFD {:>3} {:<4} {:>8} {} {} {:X} {:>2} {} {:>8} {:>4} {:>8X} {:>8} {:>8} {:>8} {:>8} {:>8}",
+                    time,
+                    record.bus,
+                    direction,
+                    self.format_id(frame.can_id(), frame.is_extended_frame()),
+                    frame.is_brs_frame() as u8,
+                    frame.is_esi_frame() as u8,
+                    frame.dlc(),
+                    frame.data().len(),
+                    self.format_data(frame.data()),
+                    0,
+                    0,
+                    flags,
+                    0,
+                    0,
+                    0,
+                    0,
+                    0
+                )
+            }
+            Event::ErrorFrame(_) => format!("{:>11} {:<2} ErrorFrame", time, record.bus),
+            _ => return Err(AscError::UnsupportedEvent),
+        };
+        writeln!(self.writer, "{}", line.trim_end())?;
+        Ok(())
+    }
+
+    /// Ends the trigger block and flushes the log.
+    pub fn finish(&mut self) -> Result<(), AscError> {
+        if !self.finished {
+            self.finished = true;
+            writeln!(self.writer, "End TriggerBlock")?;
+        }
+        Ok(self.writer.flush()?)
+    }
+}
+
+impl<W: Write> Drop for AscWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+fn format_seconds(time: Duration) -> String {
+    format!("{}.{:06}", time.as_secs(), time.subsec_micros())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const ASC_HEX: &str = "date Wed Jan 9 03:25:50.914 pm 2019
+base hex  timestamps absolute
+internal events logged
+// version 13.0.0
+Begin Triggerblock Wed Jan 9 03:25:50.914 pm 2019
+   0.000000 Start of measurement
+   1.059900 1  300             Rx   d 8 00 01 02 03 04 05 06 07  Length = 232000 BitCount = 120 ID = 768
+   1.283200 2  18EFC8FEx       Tx   d 3 01 02 03
+   1.300000 1  100             Rx   r 2
+   1.400500 1  ErrorFrame
+   1.500000 CAN 1 Status:chip status error active
+   2.501000 CANFD   1 Rx        1A1  Speed  1 0 9 12 00 01 02 03 04 05 06 07 08 09 0A 0B   130000  208  3000 0 0 0 0 0
+End TriggerBlock
+";
+
+    const ASC_DEC: &str = "date Wed Jan 09 15:25:50 2019
+base dec  timestamps relative
+no internal events logged
+Begin Triggerblock Wed Jan 09 15:25:50 2019
+   1.000000 1  768             Rx   d 2 18 52
+   0.500000 3  100x            Rx   d 1 255
+End TriggerBlock
+";
+
+    fn start() -> SystemTime {
+        from_civil(2019, 1, 9, 15 * 3600 + 25 * 60 + 50, 914_000).unwrap()
+    }
+
+    #[test]
+    fn asc_reader_001() {
+        let reader = AscReader::new(Cursor::new(ASC_HEX)).unwrap();
+        assert_eq!(reader.header().start_time, Some(start()));
+        assert_eq!(reader.header().base, Base::Hex);
+        assert_eq!(reader.header().timestamps, Timestamps::Absolute);
+        assert!(reader.header().internal_events);
+
+        let records = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(records.len(), 5);
+
+        assert_eq!(records[0].time, start() + Duration::from_micros(1_059_900));
+        assert_eq!(records[0].bus, 1);
+        assert_eq!(
+            records[0].event,
+            Event::Can(
+                CanFrame::new(0x300, MessageType::Standard, &[0, 1, 2, 3, 4, 5, 6, 7]).unwrap()
+            )
+        );
+
+        assert_eq!(records[1].bus, 2);
+        assert_eq!(records[1].direction, Direction::Tx);
+        assert_eq!(
+            records[1].event,
+            Event::Can(CanFrame::new(0x18EFC8FE, MessageType::Extended, &[1, 2, 3]).unwrap())
+        );
+
+        match &records[2].event {
+            Event::Can(frame) => {
+                assert!(frame.is_rtr_frame());
+                assert_eq!(frame.can_id(), 0x100);
+                assert_eq!(frame.dlc(), 2);
+            }
+            event => panic!("{:?}", event),
+        }
+
+        assert_eq!(records[3].event, Event::ErrorFrame(Vec::new()));
+
+        match &records[4].event {
+            Event::Fd(frame) => {
+                assert_eq!(frame.can_id(), 0x1A1);
+                assert_eq!(frame.data().len(), 12);
+                assert_eq!(frame.data()[11], 0x0B);
+                assert!(frame.is_brs_frame());
+                assert!(!frame.is_esi_frame());
+            }
+            event => panic!("{:?}", event),
+        }
+    }
+
+    #[test]
+    fn asc_reader_002() {
+        let reader = AscReader::new(Cursor::new(ASC_DEC)).unwrap();
+        assert_eq!(reader.header().base, Base::Dec);
+        assert_eq!(reader.header().timestamps, Timestamps::Relative);
+        let start = from_civil(2019, 1, 9, 15 * 3600 + 25 * 60 + 50, 0).unwrap();
+
+        let records = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].time, start + Duration::from_secs(1));
+        assert_eq!(
+            records[0].event,
+            Event::Can(CanFrame::new(0x300, MessageType::Standard, &[0x12, 0x34]).unwrap())
+        );
+        assert_eq!(records[1].time, start + Duration::from_millis(1500));
+        assert_eq!(records[1].bus, 3);
+        assert_eq!(
+            records[1].event,
+            Event::Can(CanFrame::new(100, MessageType::Extended, &[0xFF]).unwrap())
+        );
+    }
+
+    #[test]
+    fn asc_reader_003() {
+        let asc = "base hex  timestamps absolute\n   1.000000 1  300 Rx d 4 00 01\n";
+        let mut reader = AscReader::new(Cursor::new(asc)).unwrap();
+        assert_eq!(reader.next(), Some(Err(AscError::Syntax { line: 2 })));
+
+        assert!(parse_date("Wed Jan 9 11:25:50.914 pm 2019").is_some());
+        for date in [
+            "Wed Jan 9 24:25:50 2019",
+            "Wed Jan 9 99999999999999999:25:50 2019",
+            "Wed Jan 9 15:25:99999999999999999 2019",
+        ] {
+            assert_eq!(parse_date(date), None, "{}", date);
+        }
+    }
+
+    #[test]
+    fn asc_reader_004() {
+        let asc = "base hex  timestamps absolute\n9300000000000000000.0 1 123 Rx d 0\n";
+        let mut reader = AscReader::new(Cursor::new(asc)).unwrap();
+        assert_eq!(reader.next(), Some(Err(AscError::Syntax { line: 2 })));
+
+        let asc = "base hex  timestamps relative\n\
+                   4000000000.0 1 123 Rx d 0\n\
+                   18446744073709551615.0 1 123 Rx d 0\n\
+                   1.0 1 123 Rx d 0\n";
+        let mut reader = AscReader::new(Cursor::new(asc)).unwrap();
+        let first = reader.next().unwrap().unwrap().time;
+        assert_eq!(reader.next(), Some(Err(AscError::Syntax { line: 3 })));
+        assert_eq!(
+            reader.next().unwrap().unwrap().time,
+            first + Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn asc_writer_001() {
+        let mut fd = CanFdFrame::new(0x1234, MessageType::Extended, &[0xAA; 20]).unwrap();
+        fd.set_brs(true);
+        let records = vec![
+            Record {
+                time: start() + Duration::from_micros(1_500),
+                bus: 1,
+                direction: Direction::Rx,
+                event: Event::Can(CanFrame::new(0x7FF, MessageType::Standard, &[1, 2]).unwrap()),
+            },
+            Record {
+                time: start() + Duration::from_millis(20),
+                bus: 2,
+                direction: Direction::Tx,
+                event: Event::Can(CanFrame::new_rtr(0x42, MessageType::Standard, 3).unwrap()),
+            },
+            Record {
+                time: start() + Duration::from_millis(30),
+                bus: 1,
+                direction: Direction::Rx,
+                event: Event::Fd(fd),
+            },
+            Record {
+                time: start() + Duration::from_secs(2),
+                bus: 2,
+                direction: Direction::Rx,
+                event: Event::ErrorFrame(Vec::new()),
+            },
+        ];
+
+        for base in [Base::Hex, Base::Dec] {
+            let mut buffer = Vec::new();
+            {
+                let mut writer = AscWriter::new(&mut buffer, start(), base).unwrap();
+                for record in &records {
+                    writer.write(record).unwrap();
+                }
+                assert_eq!(
+                    writer.write(&Record {
+                        time: start(),
+                        bus: 1,
+                        direction: Direction::Rx,
+                        event: Event::Text("comment".to_string()),
+                    }),
+                    Err(AscError::UnsupportedEvent)
+                );
+            }
+
+            let text = String::from_utf8(buffer).unwrap();
+            assert!(text.starts_with("date Wed Jan 9 03:25:50.914 pm 2019\n"));
+            assert!(text.ends_with("End TriggerBlock\n"));
+
+            let reader = AscReader::new(Cursor::new(text)).unwrap();
+            assert_eq!(reader.header().start_time, Some(start()));
+            assert_eq!(reader.header().base, base);
+            let read = reader.collect::<Result<Vec<_>, _>>().unwrap();
+            assert_eq!(read, records);
+        }
+    }
+}
@@ -0,0 +1,943 @@
+//! Vector binary logging format (`.blf`).
+//!
+//! [BlfReader] streams the classic and CAN FD frames and extended error frames of a log, reading
+//! plain and zlib compressed log containers. Objects of other types are skipped. [BlfWriter]
+//! writes zlib compressed logs which can be opened in CANalyzer and CANoe. Channels map to
+//! [Record::bus]. Times are converted as if they were UTC.
+
+use crate::socket::clock::Clock;
+use crate::socket::{CanFdFrame, CanFrame, MessageType, Timestamp};
+use crate::tracefile::{
+    days_from_civil, from_civil, to_civil, Direction, Event, HardwareTime, Record,
+};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+const FILE_SIGNATURE: &[u8; 4] = b"LOGG";
+const OBJECT_SIGNATURE: &[u8; 4] = b"LOBJ";
+const FILE_HEADER_SIZE: usize = 144;
+const OBJECT_HEADER_BASE_SIZE: usize = 16;
+const OBJECT_HEADER_V1_SIZE: usize = 32;
+const CONTAINER_HEADER_SIZE: usize = 16;
+/// Uncompressed size of the containers written.
+const CONTAINER_SIZE: usize = 128 * 1024;
+
+/* Object types */
+
+pub const CAN_MESSAGE: u32 = 1;
+pub const LOG_CONTAINER: u32 = 10;
+pub const CAN_ERROR_EXT: u32 = 73;
+pub const CAN_MESSAGE2: u32 = 86;
+pub const CAN_FD_MESSAGE: u32 = 100;
+pub const CAN_FD_MESSAGE_64: u32 = 101;
+
+/* Flags */
+
+const TIME_TEN_MICS: u32 = 0x01;
+const TIME_ONE_NANS: u32 = 0x02;
+const CAN_MSG_TX: u8 = 0x01;
+const CAN_MSG_RTR: u8 = 0x80;
+const CAN_ID_EXTENDED: u32 = 0x8000_0000;
+const CAN_FD_EDL: u8 = 0x01;
+const CAN_FD_BRS: u8 = 0x02;
+const CAN_FD_ESI: u8 = 0x04;
+const CAN_FD64_REMOTE: u32 = 0x0010;
+const CAN_FD64_EDL: u32 = 0x1000;
+const CAN_FD64_BRS: u32 = 0x2000;
+const CAN_FD64_ESI: u32 = 0x4000;
+
+#[derive(Debug, PartialEq)]
+pub enum BlfError {
+    Io(std::io::ErrorKind),
+    /// The file does not start with a BLF file header.
+    InvalidSignature,
+    /// An object is truncated or malformed.
+    InvalidObject,
+    UnsupportedCompression(u16),
+    /// The event cannot be stored in a BLF log.
+    UnsupportedEvent,
+}
+
+impl From<std::io::Error> for BlfError {
+    fn from(value: std::io::Error) -> Self {
+        BlfError::Io(value.kind())
+    }
+}
+
+/// The file statistics at the start of a BLF log.
+#[derive(Debug, PartialEq, Clone)]
+pub struct BlfHeader {
+    pub application_id: u8,
+    pub application_version: (u8, u8, u8),
+    pub file_size: u64,
+    pub uncompressed_size: u64,
+    pub object_count: u32,
+    pub start_time: SystemTime,
+    pub stop_time: SystemTime,
+}
+
+/* Byte helpers */
+
+fn u16_at(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        bytes.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+/// Reads `length` bytes, allocating as they arrive rather than trusting the length of a
+/// corrupted file.
+fn read_bytes<R: Read>(reader: &mut R, length: usize) -> Result<Vec<u8>, BlfError> {
+    let mut bytes = Vec::new();
+    reader.take(length as u64).read_to_end(&mut bytes)?;
+    match bytes.len() == length {
+        true => Ok(bytes),
+        false => Err(BlfError::Io(std::io::ErrorKind::UnexpectedEof)),
+    }
+}
+
+/// Converts a Windows `SYSTEMTIME`, treating it as UTC. `None` for fields out of range.
+fn parse_system_time(bytes: &[u8]) -> Option<SystemTime> {
+    let mut fields = [0u32; 8];
+    for (index, field) in fields.iter_mut().enumerate() {
+        *field = u16_at(bytes, 2 * index)? as u32;
+    }
+    let [year, month, weekday, day, hour, minute, second, millis] = fields;
+    if year == 0 {
+        return Some(SystemTime::UNIX_EPOCH);
+    }
+    if weekday > 6 || hour >= 24 || minute >= 60 || second >= 60 || millis >= 1000 {
+        return None;
+    }
+    from_civil(
+        year as i64,
+        month,
+        day,
+        (hour * 3600 + minute * 60 + second) as u64,
+        millis * 1000,
+    )
+}
+
+fn system_time_bytes(time: SystemTime) -> [u8; 16] {
+    let (year, month, day, seconds, micros) = to_civil(time);
+    // 1970-01-01 was a Thursday, Sunday counts as 0
+    let weekday = (days_from_civil(year, month, day) + 4).rem_euclid(7);
+    let fields = [
+        year as u16,
+        month as u16,
+        weekday as u16,
+        day as u16,
+        (seconds / 3600) as u16,
+        (seconds / 60 % 60) as u16,
+        (seconds % 60) as u16,
+        (micros / 1000) as u16,
+    ];
+    let mut bytes = [0; 16];
+    for (chunk, field) in bytes.chunks_mut(2).zip(fields) {
+        chunk.copy_from_slice(&field.to_le_bytes());
+    }
+    bytes
+}
+
+/// Truncates a time to the millisecond precision of `SYSTEMTIME`.
+fn truncate_to_millis(time: SystemTime) -> SystemTime {
+    let since_epoch = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO);
+    SystemTime::UNIX_EPOCH + Duration::from_millis(since_epoch.as_millis() as u64)
+}
+
+/* Objects */
+
+fn direction(tx: bool) -> Direction {
+    match tx {
+        true => Direction::Tx,
+        false => Direction::Rx,
+    }
+}
+
+fn message_type(id: u32) -> MessageType {
+    match id & CAN_ID_EXTENDED != 0 {
+        true => MessageType::Extended,
+        false => MessageType::Standard,
+    }
+}
+
+/// Parses the body of an object into a bus, direction and event. `Some(None)` marks objects of
+/// other types.
+fn parse_object(object_type: u32, body: &[u8]) -> Option<Option<(u8, Direction, Event)>> {
+    let parsed = match object_type {
+        CAN_MESSAGE | CAN_MESSAGE2 => {
+            let flags = *body.get(2)?;
+            let dlc = (*body.get(3)?).min(8);
+            let id = u32_at(body, 4)?;
+            let frame = match flags & CAN_MSG_RTR != 0 {
+                true => CanFrame::new_rtr(id & !CAN_ID_EXTENDED, message_type(id), dlc),
+                false => CanFrame::new(
+                    id & !CAN_ID_EXTENDED,
+                    message_type(id),
+                    body.get(8..8 + dlc as usize)?,
+                ),
+            }
+            .ok()?;
+            (
+                u16_at(body, 0)? as u8,
+                direction(flags & CAN_MSG_TX != 0),
+                Event::Can(frame),
+            )
+        }
+        CAN_FD_MESSAGE => {
+            let flags = *body.get(2)?;
+            let dlc = *body.get(3)?;
+            let id = u32_at(body, 4)?;
+            let fd_flags = *body.get(13)?;
+            let length = (*body.get(14)?).min(64) as usize;
+            let data = body.get(20..20 + length)?;
+            let event = match fd_flags & CAN_FD_EDL != 0 {
+                true => {
+                    let mut frame =
+                        CanFdFrame::new(id & !CAN_ID_EXTENDED, message_type(id), data).ok()?;
+                    frame.set_brs(fd_flags & CAN_FD_BRS != 0);
+                    frame.set_esi(fd_flags & CAN_FD_ESI != 0);
+                    Event::Fd(frame)
+                }
+                false if flags & CAN_MSG_RTR != 0 => Event::Can(
+                    CanFrame::new_rtr(id & !CAN_ID_EXTENDED, message_type(id), dlc.min(8)).ok()?,
+                ),
+                false => Event::Can(
+                    CanFrame::new(
+                        id & !CAN_ID_EXTENDED,
+                        message_type(id),
+                        data.get(..8.min(length))?,
+                    )
+                    .ok()?,
+                ),
+            };
+            (
+                u16_at(body, 0)? as u8,
+                direction(flags & CAN_MSG_TX != 0),
+                event,
+            )
+        }
+        CAN_FD_MESSAGE_64 => {
+            let dlc = *body.get(1)?;
+            let length = (*body.get(2)?).min(64) as usize;
+            let id = u32_at(body, 4)?;
+            let flags = u32_at(body, 12)?;
+            let data = body.get(40..40 + length)?;
+            let event = match flags & CAN_FD64_EDL != 0 {
+                true => {
+                    let mut frame =
+                        CanFdFrame::new(id & !CAN_ID_EXTENDED, message_type(id), data).ok()?;
+                    frame.set_brs(flags & CAN_FD64_BRS != 0);
+                    frame.set_esi(flags & CAN_FD64_ESI != 0);
+                    Event::Fd(frame)
+                }
+                false if flags & CAN_FD64_REMOTE != 0 => Event::Can(
+                    CanFrame::new_rtr(id & !CAN_ID_EXTENDED, message_type(id), dlc.min(8)).ok()?,
+                ),
+                false => Event::Can(
+                    CanFrame::new(
+                        id & !CAN_ID_EXTENDED,
+                        message_type(id),
+                        data.get(..8.min(length))?,
+                    )
+                    .ok()?,
+                ),
+            };
+            (*body.first()?, direction(*body.get(34)? == 1), event)
+        }
+        CAN_ERROR_EXT => {
+            let dlc = (*body.get(10)?).min(8) as usize;
+            (
+                u16_at(body, 0)? as u8,
+                Direction::Rx,
+                Event::ErrorFrame(body.get(24..24 + dlc)?.to_vec()),
+            )
+        }
+        _ => return Some(None),
+    };
+    Some(Some(parsed))
+}
+
+/* BlfReader */
+
+/// Streams the frames of a BLF log.
+pub struct BlfReader<R> {
+    reader: R,
+    header: BlfHeader,
+    /// Uncompressed objects not yet parsed.
+    buffer: Vec<u8>,
+    position: usize,
+}
+
+impl BlfReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<BlfReader<BufReader<File>>, BlfError> {
+        BlfReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> BlfReader<R> {
+    /// Reads the file statistics.
+    pub fn new(mut reader: R) -> Result<BlfReader<R>, BlfError> {
+        let mut bytes = [0; 8];
+        reader.read_exact(&mut bytes)?;
+        if &bytes[..4] != FILE_SIGNATURE {
+            return Err(BlfError::InvalidSignature);
+        }
+        let header_size = u32_at(&bytes, 4).unwrap_or(0) as usize;
+        if header_size < 72 {
+            return Err(BlfError::InvalidSignature);
+        }
+        let rest = read_bytes(&mut reader, header_size - 8)?;
+
+        let mut bytes = bytes.to_vec();
+        bytes.extend(rest);
+        let invalid = || BlfError::InvalidObject;
+        let header = BlfHeader {
+            application_id: bytes[8],
+            application_version: (bytes[9], bytes[10], bytes[11]),
+            file_size: u64_at(&bytes, 16).ok_or_else(invalid)?,
+            uncompressed_size: u64_at(&bytes, 24).ok_or_else(invalid)?,
+            object_count: u32_at(&bytes, 32).ok_or_else(invalid)?,
+            start_time: parse_system_time(&bytes[40..56]).ok_or_else(invalid)?,
+            stop_time: parse_system_time(&bytes[56..72]).ok_or_else(invalid)?,
+        };
+
+        Ok(BlfReader {
+            reader,
+            header,
+            buffer: Vec::new(),
+            position: 0,
+        })
+    }
+
+    pub fn header(&self) -> &BlfHeader {
+        &self.header
+    }
+
+    /// Reads the next object of the file into the buffer, unpacking containers. Returns `false`
+    /// at the end of the file.
+    fn fill(&mut self) -> Result<bool, BlfError> {
+        let mut base = [0; OBJECT_HEADER_BASE_SIZE];
+        match self.reader.read_exact(&mut base) {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(false),
+            Err(err) => return Err(err.into()),
+        }
+        let object_size = u32_at(&base, 8).unwrap_or(0) as usize;
+        if &base[..4] != OBJECT_SIGNATURE || object_size < OBJECT_HEADER_BASE_SIZE {
+            return Err(BlfError::InvalidObject);
+        }
+        let object = read_bytes(&mut self.reader, object_size - OBJECT_HEADER_BASE_SIZE)?;
+
+        // the padding is missing after the last object of some files
+        let mut padding = vec![0; object_size % 4];
+        match self.reader.read_exact(&mut padding) {
+            Err(err) if err.kind() != std::io::ErrorKind::UnexpectedEof => return Err(err.into()),
+            _ => {}
+        }
+
+        self.buffer.drain(..self.position);
+        self.position = 0;
+
+        if u32_at(&base, 12) != Some(LOG_CONTAINER) {
+            self.buffer.extend_from_slice(&base);
+            self.buffer.extend(object);
+            return Ok(true);
+        }
+
+        let header_size = u16_at(&base, 4).unwrap_or(0) as usize;
+        let container = object
+            .get(header_size.saturating_sub(OBJECT_HEADER_BASE_SIZE)..)
+            .filter(|container| container.len() >= CONTAINER_HEADER_SIZE)
+            .ok_or(BlfError::InvalidObject)?;
+        let data = &container[CONTAINER_HEADER_SIZE..];
+        match u16_at(container, 0).unwrap_or(0) {
+            0 => self.buffer.extend_from_slice(data),
+            2 => {
+                // the size is claimed by the file, it bounds the output but is not preallocated
+                let uncompressed_size = u32_at(container, 8).unwrap_or(0) as u64;
+                ZlibDecoder::new(data)
+                    .take(uncompressed_size)
+                    .read_to_end(&mut self.buffer)?;
+            }
+            method => return Err(BlfError::UnsupportedCompression(method)),
+        }
+        Ok(true)
+    }
+
+    /// Parses the next complete object of the buffer. `None` if more data is needed.
+    fn next_object(&mut self) -> Option<Result<Option<Record>, BlfError>> {
+        let invalid = BlfError::InvalidObject;
+
+        // skip the padding between objects
+        let remaining = &self.buffer[self.position..];
+        if remaining.len() < OBJECT_HEADER_BASE_SIZE {
+            return None;
+        }
+        if &remaining[..4] != OBJECT_SIGNATURE {
+            match remaining[..8]
+                .windows(4)
+                .position(|window| window == OBJECT_SIGNATURE)
+            {
+                Some(skip) => {
+                    self.position += skip;
+                    return self.next_object();
+                }
+                None => return Some(Err(invalid)),
+            }
+        }
+
+        let header_size = u16_at(remaining, 4).unwrap_or(0) as usize;
+        let object_size = u32_at(remaining, 8).unwrap_or(0) as usize;
+        if object_size < header_size.max(OBJECT_HEADER_V1_SIZE) {
+            return Some(Err(invalid));
+        }
+        if remaining.len() < object_size {
+            return None;
+        }
+        let object = &remaining[..object_size];
+        self.position += object_size;
+
+        let object_type = u32_at(object, 12).unwrap_or(0);
+        let flags = u32_at(object, 16).unwrap_or(0);
+        let timestamp = u64_at(object, 24).unwrap_or(0);
+        let offset = match flags {
+            TIME_TEN_MICS => match timestamp.checked_mul(10) {
+                Some(micros) => Duration::from_micros(micros),
+                None => return Some(Err(invalid)),
+            },
+            _ => Duration::from_nanos(timestamp),
+        };
+        let time = match self.header.start_time.checked_add(offset) {
+            Some(time) => time,
+            None => return Some(Err(invalid)),
+        };
+
+        match parse_object(object_type, &object[header_size..]) {
+            Some(Some((bus, direction, event))) => Some(Ok(Some(Record {
+                time,
+                bus,
+                direction,
+                event,
+            }))),
+            Some(None) => Some(Ok(None)),
+            None => Some(Err(invalid)),
+        }
+    }
+}
+
+impl<R: Read> Iterator for BlfReader<R> {
+    type Item = Result<Record, BlfError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.next_object() {
+                Some(Ok(Some(record))) => return Some(Ok(record)),
+                Some(Ok(None)) => {}
+                Some(Err(err)) => {
+                    // drop the rest, the position of the next object is unknown
+                    self.position = self.buffer.len();
+                    return Some(Err(err));
+                }
+                None => match self.fill() {
+                    Ok(true) => {}
+                    Ok(false) => return None,
+                    Err(err) => return Some(Err(err)),
+                },
+            }
+        }
+    }
+}
+
+/* BlfWriter */
+
+/// Writes zlib compressed BLF logs. The file statistics are completed by
+/// [finish](BlfWriter::finish), which is called on drop.
+pub struct BlfWriter<W: Write + Seek> {
+    writer: W,
+    /// Uncompressed objects of the current container.
+    container: Vec<u8>,
+    start: Option<SystemTime>,
+    stop: SystemTime,
+    file_size: u64,
+    uncompressed_size: u64,
+    object_count: u32,
+    hardware_time: HardwareTime,
+}
+
+impl BlfWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<BlfWriter<BufWriter<File>>, BlfError> {
+        BlfWriter::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write + Seek> BlfWriter<W> {
+    pub fn new(writer: W) -> Result<BlfWriter<W>, BlfError> {
+        let mut blf = BlfWriter {
+            writer,
+            container: Vec::with_capacity(CONTAINER_SIZE),
+            start: None,
+            stop: SystemTime::UNIX_EPOCH,
+            file_size: FILE_HEADER_SIZE as u64,
+            uncompressed_size: FILE_HEADER_SIZE as u64,
+            object_count: 0,
+            hardware_time: HardwareTime::default(),
+        };
+        blf.write_header()?;
+        Ok(blf)
+    }
+
+    fn write_header(&mut self) -> Result<(), BlfError> {
+        let start = self.start.unwrap_or(SystemTime::UNIX_EPOCH);
+        let mut header = Vec::with_capacity(FILE_HEADER_SIZE);
+        header.extend_from_slice(FILE_SIGNATURE);
+        header.extend_from_slice(&(FILE_HEADER_SIZE as u32).to_le_bytes());
+        // application and binary log versions
+        header.extend_from_slice(&[5, 0, 0, 0, 4, 7, 1, 0]);
+        header.extend_from_slice(&self.file_size.to_le_bytes());
+        header.extend_from_slice(&self.uncompressed_size.to_le_bytes());
+        header.extend_from_slice(&self.object_count.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(&system_time_bytes(start));
+        header.extend_from_slice(&system_time_bytes(self.stop.max(start)));
+        header.resize(FILE_HEADER_SIZE, 0);
+        self.writer.write_all(&header)?;
+        Ok(())
+    }
+
+    /// Writes a record. The first record sets the start of the log, earlier records are written
+    /// at its start.
+    pub fn write(&mut self, record: &Record) -> Result<(), BlfError> {
+        let mut body = Vec::new();
+        let object_type = match &record.event {
+            Event::Can(frame) => {
+                let mut flags = 0;
+                if record.direction == Direction::Tx {
+                    flags |= CAN_MSG_TX;
+                }
+                if frame.is_rtr_frame() {
+                    flags |= CAN_MSG_RTR;
+                }
+                let mut data = [0; 8];
+                if !frame.is_rtr_frame() {
+                    data[..frame.data().len()].copy_from_slice(frame.data());
+                }
+                body.extend_from_slice(&(record.bus as u16).to_le_bytes());
+                body.extend_from_slice(&[flags, frame.dlc()]);
+                body.extend_from_slice(&blf_id(frame.can_id(), frame.is_extended_frame()));
+                body.extend_from_slice(&data);
+                CAN_MESSAGE
+            }
+            Event::Fd(frame) => {
+                let mut flags = CAN_FD64_EDL;
+                if frame.is_brs_frame() {
+                    flags |= CAN_FD64_BRS;
+                }
+                if frame.is_esi_frame() {
+                    flags |= CAN_FD64_ESI;
+                }
+                body.extend_from_slice(&[record.bus, frame.dlc(), frame.data().len() as u8, 0]);
+                body.extend_from_slice(&blf_id(frame.can_id(), frame.is_extended_frame()));
+                // frame length, flags, bit timings and time offsets
+                body.extend_from_slice(&0u32.to_le_bytes());
+                body.extend_from_slice(&flags.to_le_bytes());
+                body.extend_from_slice(&[0; 16]);
+                // bit count, direction, extended data offset and CRC
+                body.extend_from_slice(&0u16.to_le_bytes());
+                body.extend_from_slice(&[(record.direction == Direction::Tx) as u8, 0]);
+                body.extend_from_slice(&0u32.to_le_bytes());
+                body.extend_from_slice(frame.data());
+                CAN_FD_MESSAGE_64
+            }
+            Event::ErrorFrame(data) => {
+                let length = data.len().min(8);
+                let mut bytes = [0; 8];
+                bytes[..length].copy_from_slice(&data[..length]);
+                body.extend_from_slice(&(record.bus as u16).to_le_bytes());
+                body.extend_from_slice(&0u16.to_le_bytes());
+                body.extend_from_slice(&0u32.to_le_bytes());
+                body.extend_from_slice(&[0, 0, length as u8, 0]);
+                // frame length, identifier, extended flags and reserved
+                body.extend_from_slice(&[0; 12]);
+                body.extend_from_slice(&bytes);
+                CAN_ERROR_EXT
+            }
+            _ => return Err(BlfError::UnsupportedEvent),
+        };
+
+        let start = *self
+            .start
+            .get_or_insert_with(|| truncate_to_millis(record.time));
+        self.stop = self.stop.max(record.time);
+        let timestamp = record
+            .time
+            .duration_since(start)
+            .unwrap_or(Duration::ZERO)
+            .as_nanos() as u64;
+
+        let object_size = OBJECT_HEADER_V1_SIZE + body.len();
+        self.container.extend_from_slice(OBJECT_SIGNATURE);
+        self.container
+            .extend_from_slice(&(OBJECT_HEADER_V1_SIZE as u16).to_le_bytes());
+        self.container.extend_from_slice(&1u16.to_le_bytes());
+        self.container
+            .extend_from_slice(&(object_size as u32).to_le_bytes());
+        self.container.extend_from_slice(&object_type.to_le_bytes());
+        self.container
+            .extend_from_slice(&TIME_ONE_NANS.to_le_bytes());
+        // client index and object version
+        self.container.extend_from_slice(&[0; 4]);
+        self.container.extend_from_slice(&timestamp.to_le_bytes());
+        self.container.extend(body);
+        self.container
+            .resize(self.container.len() + object_size % 4, 0);
+        self.object_count += 1;
+
+        if self.container.len() >= CONTAINER_SIZE {
+            self.write_container()?;
+        }
+        Ok(())
+    }
+
+    /// Sets the clock giving the [hardware time](crate::tracefile#hardware-time).
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.hardware_time = HardwareTime::new(clock);
+        self
+    }
+
+    /// Writes a classic frame at its [hardware time](crate::tracefile#hardware-time).
+    pub fn write_can(
+        &mut self,
+        frame: &CanFrame,
+        timestamp: &Timestamp,
+        direction: Direction,
+    ) -> Result<(), BlfError> {
+        let record = self
+            .hardware_time
+            .record(Event::Can(*frame), timestamp, direction);
+        self.write(&record)
+    }
+
+    /// Writes a CAN FD frame at its [hardware time](crate::tracefile#hardware-time).
+    pub fn write_can_fd(
+        &mut self,
+        frame: &CanFdFrame,
+        timestamp: &Timestamp,
+        direction: Direction,
+    ) -> Result<(), BlfError> {
+        let record = self
+            .hardware_time
+            .record(Event::Fd(*frame), timestamp, direction);
+        self.write(&record)
+    }
+
+    fn write_container(&mut self) -> Result<(), BlfError> {
+        if self.container.is_empty() {
+            return Ok(());
+        }
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&self.container)?;
+        let compressed = encoder.finish()?;
+
+        let object_size = OBJECT_HEADER_BASE_SIZE + CONTAINER_HEADER_SIZE + compressed.len();
+        let mut object = Vec::with_capacity(object_size + 3);
+        object.extend_from_slice(OBJECT_SIGNATURE);
+        object.extend_from_slice(&(OBJECT_HEADER_BASE_SIZE as u16).to_le_bytes());
+        object.extend_from_slice(&1u16.to_le_bytes());
+        object.extend_from_slice(&(object_size as u32).to_le_bytes());
+        object.extend_from_slice(&LOG_CONTAINER.to_le_bytes());
+        object.extend_from_slice(&2u16.to_le_bytes());
+        object.extend_from_slice(&[0; 6]);
+        object.extend_from_slice(&(self.container.len() as u32).to_le_bytes());
+        object.extend_from_slice(&[0; 4]);
+        object.extend(compressed);
+        object.resize(object_size + object_size % 4, 0);
+        self.writer.write_all(&object)?;
+
+        self.file_size += object.len() as u64;
+        self.uncompressed_size +=
+            (OBJECT_HEADER_BASE_SIZE + CONTAINER_HEADER_SIZE + self.container.len()) as u64;
+        self.container.clear();
+        Ok(())
+    }
+
+    /// Writes the pending objects and updates the file statistics. Records may still be written
+    /// afterwards.
+    pub fn finish(&mut self) -> Result<(), BlfError> {
+        self.write_container()?;
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.write_header()?;
+        self.writer.seek(SeekFrom::End(0))?;
+        Ok(self.writer.flush()?)
+    }
+}
+
+impl<W: Write + Seek> Drop for BlfWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+fn blf_id(id: u32, extended: bool) -> [u8; 4] {
+    match extended {
+        true => (id | CAN_ID_EXTENDED).to_le_bytes(),
+        false => id.to_le_bytes(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn start() -> SystemTime {
+        from_civil(2019, 1, 9, 15 * 3600 + 25 * 60 + 50, 914_000).unwrap()
+    }
+
+    fn object(object_type: u32, timestamp: u64, body: &[u8]) -> Vec<u8> {
+        let object_size = OBJECT_HEADER_V1_SIZE + body.len();
+        let mut object = Vec::new();
+        object.extend_from_slice(OBJECT_SIGNATURE);
+        object.extend_from_slice(&32u16.to_le_bytes());
+        object.extend_from_slice(&1u16.to_le_bytes());
+        object.extend_from_slice(&(object_size as u32).to_le_bytes());
+        object.extend_from_slice(&object_type.to_le_bytes());
+        object.extend_from_slice(&TIME_TEN_MICS.to_le_bytes());
+        object.extend_from_slice(&[0; 4]);
+        object.extend_from_slice(&timestamp.to_le_bytes());
+        object.extend_from_slice(body);
+        object.resize(object_size + object_size % 4, 0);
+        object
+    }
+
+    fn file_header() -> Vec<u8> {
+        let mut file = Vec::new();
+        file.extend_from_slice(FILE_SIGNATURE);
+        file.extend_from_slice(&(FILE_HEADER_SIZE as u32).to_le_bytes());
+        file.resize(40, 0);
+        file.extend_from_slice(&system_time_bytes(start()));
+        file.resize(FILE_HEADER_SIZE, 0);
+        file
+    }
+
+    fn can_message2(timestamp: u64) -> Vec<u8> {
+        let mut body = vec![2, 0, CAN_MSG_TX, 3];
+        body.extend_from_slice(&0x123u32.to_le_bytes());
+        body.extend_from_slice(&[1, 2, 3, 0, 0, 0, 0, 0]);
+        body.extend_from_slice(&[0; 8]);
+        object(CAN_MESSAGE2, timestamp, &body)
+    }
+
+    #[test]
+    fn blf_reader_001() {
+        let mut file = file_header();
+
+        // CAN_MESSAGE2 on channel 2, transmitted
+        let message2 = can_message2(100);
+
+        // CAN_FD_MESSAGE with 12 bytes and bit rate switch
+        let mut body = vec![1, 0, 0, 9];
+        body.extend_from_slice(&(0x1234_5678 | CAN_ID_EXTENDED).to_le_bytes());
+        body.extend_from_slice(&[0, 0, 0, 0, 0, CAN_FD_EDL | CAN_FD_BRS, 12, 0, 0, 0, 0, 0]);
+        body.extend((0..64).map(|byte| byte as u8));
+        let fd = object(CAN_FD_MESSAGE, 200, &body);
+
+        // uncompressed containers, the FD message is split between them
+        let mut objects = message2;
+        objects.extend_from_slice(&fd);
+        for container in objects.chunks(60) {
+            let object_size = OBJECT_HEADER_BASE_SIZE + CONTAINER_HEADER_SIZE + container.len();
+            file.extend_from_slice(OBJECT_SIGNATURE);
+            file.extend_from_slice(&16u16.to_le_bytes());
+            file.extend_from_slice(&1u16.to_le_bytes());
+            file.extend_from_slice(&(object_size as u32).to_le_bytes());
+            file.extend_from_slice(&LOG_CONTAINER.to_le_bytes());
+            file.extend_from_slice(&[0; 8]);
+            file.extend_from_slice(&(container.len() as u32).to_le_bytes());
+            file.extend_from_slice(&[0; 4]);
+            file.extend_from_slice(container);
+            file.resize(file.len() + object_size % 4, 0);
+        }
+
+        // an object outside of any container, of a type which is skipped
+        file.extend(object(65, 300, &[0; 12]));
+
+        let reader = BlfReader::new(Cursor::new(file)).unwrap();
+        assert_eq!(reader.header().start_time, start());
+
+        let records = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(
+            records[0],
+            Record {
+                time: start() + Duration::from_millis(1),
+                bus: 2,
+                direction: Direction::Tx,
+                event: Event::Can(CanFrame::new(0x123, MessageType::Standard, &[1, 2, 3]).unwrap()),
+            }
+        );
+        assert_eq!(records[1].time, start() + Duration::from_millis(2));
+        match &records[1].event {
+            Event::Fd(frame) => {
+                assert_eq!(frame.can_id(), 0x1234_5678);
+                assert!(frame.is_extended_frame());
+                assert!(frame.is_brs_frame());
+                assert_eq!(frame.data(), &(0..12).collect::<Vec<u8>>()[..]);
+            }
+            event => panic!("{:?}", event),
+        }
+    }
+
+    #[test]
+    fn blf_reader_002() {
+        assert_eq!(
+            BlfReader::new(Cursor::new(b"LOBJ\x90\0\0\0".to_vec())).err(),
+            Some(BlfError::InvalidSignature)
+        );
+
+        let header = |field: usize, value: u16| {
+            let mut file = Vec::new();
+            file.extend_from_slice(FILE_SIGNATURE);
+            file.extend_from_slice(&(FILE_HEADER_SIZE as u32).to_le_bytes());
+            file.resize(40, 0);
+            let mut time = system_time_bytes(start());
+            time[2 * field..2 * field + 2].copy_from_slice(&value.to_le_bytes());
+            file.extend_from_slice(&time);
+            file.resize(FILE_HEADER_SIZE, 0);
+            BlfReader::new(Cursor::new(file)).err()
+        };
+        assert_eq!(header(7, 999), None);
+        assert_eq!(
+            BlfReader::new(Cursor::new(b"LOGG\xF0\xFF\xFF\xFF".to_vec())).err(),
+            Some(BlfError::Io(std::io::ErrorKind::UnexpectedEof))
+        );
+        for (field, value) in [
+            (1, 13),
+            (2, 7),
+            (3, 0),
+            (4, 24),
+            (5, 60),
+            (6, 60),
+            (7, 1000),
+        ] {
+            assert_eq!(header(field, value), Some(BlfError::InvalidObject));
+        }
+    }
+
+    #[test]
+    fn blf_reader_003() {
+        // a timestamp overflowing once scaled to microseconds
+        let mut file = file_header();
+        file.extend(can_message2(u64::MAX));
+        let mut reader = BlfReader::new(Cursor::new(file)).unwrap();
+        assert_eq!(reader.next(), Some(Err(BlfError::InvalidObject)));
+
+        // a compressed container holding more than its stated size
+        let objects = [can_message2(100), can_message2(200)].concat();
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&objects).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let object_size = OBJECT_HEADER_BASE_SIZE + CONTAINER_HEADER_SIZE + compressed.len();
+        let mut file = file_header();
+        file.extend_from_slice(OBJECT_SIGNATURE);
+        file.extend_from_slice(&16u16.to_le_bytes());
+        file.extend_from_slice(&1u16.to_le_bytes());
+        file.extend_from_slice(&(object_size as u32).to_le_bytes());
+        file.extend_from_slice(&LOG_CONTAINER.to_le_bytes());
+        file.extend_from_slice(&2u16.to_le_bytes());
+        file.extend_from_slice(&[0; 6]);
+        file.extend_from_slice(&((objects.len() / 2) as u32).to_le_bytes());
+        file.extend_from_slice(&[0; 4]);
+        file.extend_from_slice(&compressed);
+
+        let records = BlfReader::new(Cursor::new(file))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].time, start() + Duration::from_millis(1));
+    }
+
+    #[test]
+    fn blf_writer_001() {
+        let mut fd = CanFdFrame::new(0x7FF, MessageType::Standard, &[0x55; 48]).unwrap();
+        fd.set_esi(true);
+        let events = [
+            Event::Can(CanFrame::new(0x18EFC8FE, MessageType::Extended, &[1, 2, 3]).unwrap()),
+            Event::Can(CanFrame::new_rtr(0x42, MessageType::Standard, 4).unwrap()),
+            Event::Fd(fd),
+            Event::ErrorFrame(vec![4, 0, 8, 0, 0]),
+        ];
+        // enough records to fill several containers
+        let records = (0..10_000)
+            .map(|index| Record {
+                time: start() + Duration::from_nanos(1_234_567 * index as u64 + 321),
+                bus: 1 + (index % 3) as u8,
+                direction: if index % 2 == 0 {
+                    Direction::Rx
+                } else {
+                    Direction::Tx
+                },
+                event: events[index % events.len()].clone(),
+            })
+            .collect::<Vec<_>>();
+
+        let mut file = Cursor::new(Vec::new());
+        {
+            let mut writer = BlfWriter::new(&mut file).unwrap();
+            for record in &records {
+                writer.write(record).unwrap();
+            }
+            assert_eq!(
+                writer.write(&Record {
+                    time: start(),
+                    bus: 1,
+                    direction: Direction::Rx,
+                    event: Event::Status(0x08),
+                }),
+                Err(BlfError::UnsupportedEvent)
+            );
+        }
+        let file = file.into_inner();
+
+        let reader = BlfReader::new(Cursor::new(&file)).unwrap();
+        assert_eq!(reader.header().object_count, 10_000);
+        assert_eq!(reader.header().file_size, file.len() as u64);
+        assert_eq!(reader.header().start_time, start());
+        assert_eq!(
+            reader.header().stop_time,
+            truncate_to_millis(records[9_999].time)
+        );
+
+        // error frames are always received
+        let expected = records
+            .into_iter()
+            .map(|mut record| {
+                if let Event::ErrorFrame(_) = record.event {
+                    record.direction = Direction::Rx;
+                }
+                record
+            })
+            .collect::<Vec<_>>();
+        let read = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(read, expected);
+    }
+}
@@ -0,0 +1,315 @@
+//! Logs of `candump -l` from the Linux can-utils.
+//!
+//! Lines look like `(1700000000.123456) can0 123#DEADBEEF`, the frames use the syntax of
+//! `cansend`. Interfaces are numbered as buses in the order they first appear in a log. Error
+//! frames are recognized by the error flag in their identifier. A trailing `T` marks transmitted
+//! frames, as written by newer versions of `candump`.
+
+use crate::socket::clock::Clock;
+use crate::socket::{CanFdFrame, CanFrame, Timestamp};
+use crate::tracefile::{Direction, Event, HardwareTime, Record};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+/// Error flag of SocketCAN identifiers.
+const CAN_ERR_FLAG: u32 = 0x2000_0000;
+
+#[derive(Debug, PartialEq)]
+pub enum CandumpError {
+    Io(std::io::ErrorKind),
+    /// A line of the file could not be parsed.
+    Syntax {
+        line: usize,
+    },
+    /// The event cannot be stored in a candump log.
+    UnsupportedEvent,
+}
+
+impl From<std::io::Error> for CandumpError {
+    fn from(value: std::io::Error) -> Self {
+        CandumpError::Io(value.kind())
+    }
+}
+
+fn parse_time(value: &str) -> Option<SystemTime> {
+    let value = value.strip_prefix('(')?.strip_suffix(')')?;
+    let (seconds, fraction) = value.split_once('.').unwrap_or((value, ""));
+    let digits = fraction.get(..fraction.len().min(9))?;
+    let nanos = match digits.is_empty() {
+        true => 0,
+        false => digits.parse::<u64>().ok()? * 10u64.pow(9 - digits.len() as u32),
+    };
+    SystemTime::UNIX_EPOCH.checked_add(Duration::new(seconds.parse().ok()?, nanos as u32))
+}
+
+fn parse_event(value: &str) -> Option<Event> {
+    let (id, data) = value.split_once('#')?;
+    if id.len() == 8 && u32::from_str_radix(id, 16).ok()? & CAN_ERR_FLAG != 0 {
+        // the error class in the identifier is dropped, the data holds the details
+        let frame = format!("000#{}", data).parse::<CanFrame>().ok()?;
+        return Some(Event::ErrorFrame(frame.data().to_vec()));
+    }
+    match data.starts_with('#') {
+        true => value.parse::<CanFdFrame>().ok().map(Event::Fd),
+        false => value.parse::<CanFrame>().ok().map(Event::Can),
+    }
+}
+
+/* CandumpReader */
+
+/// Streams the frames of a candump log.
+pub struct CandumpReader<R> {
+    reader: R,
+    interfaces: Vec<String>,
+    line: usize,
+}
+
+impl CandumpReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<CandumpReader<BufReader<File>>, CandumpError> {
+        Ok(CandumpReader::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> CandumpReader<R> {
+    pub fn new(reader: R) -> CandumpReader<R> {
+        CandumpReader {
+            reader,
+            interfaces: Vec::new(),
+            line: 0,
+        }
+    }
+
+    /// Names of the interfaces seen so far, bus 1 first.
+    pub fn interfaces(&self) -> &[String] {
+        &self.interfaces
+    }
+
+    fn bus(&mut self, interface: &str) -> u8 {
+        match self.interfaces.iter().position(|name| name == interface) {
+            Some(index) => index as u8 + 1,
+            None => {
+                self.interfaces.push(interface.to_string());
+                self.interfaces.len() as u8
+            }
+        }
+    }
+
+    fn parse_line(&mut self, line: &str) -> Option<Record> {
+        let mut tokens = line.split_whitespace();
+        let time = parse_time(tokens.next()?)?;
+        let interface = tokens.next()?;
+        let event = parse_event(tokens.next()?)?;
+        let direction = match tokens.next() {
+            Some("T") => Direction::Tx,
+            Some("R") | None => Direction::Rx,
+            Some(_) => return None,
+        };
+        Some(Record {
+            time,
+            bus: self.bus(interface),
+            direction,
+            event,
+        })
+    }
+}
+
+impl<R: BufRead> Iterator for CandumpReader<R> {
+    type Item = Result<Record, CandumpError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => return None,
+                Ok(_) => self.line += 1,
+                Err(err) => return Some(Err(err.into())),
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            return Some(
+                self.parse_line(line.trim())
+                    .ok_or(CandumpError::Syntax { line: self.line }),
+            );
+        }
+    }
+}
+
+/* CandumpWriter */
+
+/// Writes candump logs. Bus `n` is written as interface `can<n - 1>` unless named otherwise.
+pub struct CandumpWriter<W: Write> {
+    writer: W,
+    interfaces: HashMap<u8, String>,
+    hardware_time: HardwareTime,
+}
+
+impl CandumpWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<CandumpWriter<BufWriter<File>>, CandumpError> {
+        Ok(CandumpWriter::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> CandumpWriter<W> {
+    pub fn new(writer: W) -> CandumpWriter<W> {
+        CandumpWriter {
+            writer,
+            interfaces: HashMap::new(),
+            hardware_time: HardwareTime::default(),
+        }
+    }
+
+    /// Names the interface of a bus.
+    pub fn with_interface(mut self, bus: u8, name: &str) -> Self {
+        self.interfaces.insert(bus, name.to_string());
+        self
+    }
+
+    pub fn write(&mut self, record: &Record) -> Result<(), CandumpError> {
+        let frame = match &record.event {
+            Event::Can(frame) => frame.to_string(),
+            Event::Fd(frame) => frame.to_string(),
+            Event::ErrorFrame(data) => {
+                let mut bytes = [0; 8];
+                let length = data.len().min(8);
+                bytes[..length].copy_from_slice(&data[..length]);
+                let data = bytes.iter().map(|byte| format!("{:02X}", byte));
+                format!("{:08X}#{}", CAN_ERR_FLAG, data.collect::<String>())
+            }
+            _ => return Err(CandumpError::UnsupportedEvent),
+        };
+
+        let since_epoch = record
+            .time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO);
+        let interface = match self.interfaces.get(&record.bus) {
+            Some(name) => name.clone(),
+            None => format!("can{}", record.bus.saturating_sub(1)),
+        };
+        write!(
+            self.writer,
+            "({:010}.{:06}) {} {}",
+            since_epoch.as_secs(),
+            since_epoch.subsec_micros(),
+            interface,
+            frame
+        )?;
+        match record.direction {
+            Direction::Tx => writeln!(self.writer, " T")?,
+            Direction::Rx => writeln!(self.writer)?,
+        }
+        Ok(())
+    }
+
+    /// Sets the clock giving the [hardware time](crate::tracefile#hardware-time).
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.hardware_time = HardwareTime::new(clock);
+        self
+    }
+
+    /// Writes a classic frame at its [hardware time](crate::tracefile#hardware-time).
+    pub fn write_can(
+        &mut self,
+        frame: &CanFrame,
+        timestamp: &Timestamp,
+        direction: Direction,
+    ) -> Result<(), CandumpError> {
+        let record = self
+            .hardware_time
+            .record(Event::Can(*frame), timestamp, direction);
+        self.write(&record)
+    }
+
+    /// Writes a CAN FD frame at its [hardware time](crate::tracefile#hardware-time).
+    pub fn write_can_fd(
+        &mut self,
+        frame: &CanFdFrame,
+        timestamp: &Timestamp,
+        direction: Direction,
+    ) -> Result<(), CandumpError> {
+        let record = self
+            .hardware_time
+            .record(Event::Fd(*frame), timestamp, direction);
+        self.write(&record)
+    }
+
+    pub fn flush(&mut self) -> Result<(), CandumpError> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::socket::MessageType;
+    use std::io::Cursor;
+
+    const LOG: &str = "(1700000000.123456) can0 123#DEADBEEF
+(1700000000.200000) vcan1 1ABCDEF0#R
+(1700000000.300000) can0 321##1AABB T
+
+(1700000001.000001) can0 20000080#0000000000000000
+";
+
+    #[test]
+    fn candump_reader_001() {
+        let mut reader = CandumpReader::new(Cursor::new(LOG));
+        let records = reader.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(reader.interfaces(), &["can0", "vcan1"]);
+        assert_eq!(records.len(), 4);
+
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert_eq!(
+            records[0],
+            Record {
+                time: start + Duration::from_micros(123_456),
+                bus: 1,
+                direction: Direction::Rx,
+                event: Event::Can(
+                    CanFrame::new(0x123, MessageType::Standard, &[0xDE, 0xAD, 0xBE, 0xEF]).unwrap()
+                ),
+            }
+        );
+        assert_eq!(records[1].bus, 2);
+        assert_eq!(records[2].direction, Direction::Tx);
+        match &records[2].event {
+            Event::Fd(frame) => assert!(frame.is_brs_frame()),
+            event => panic!("{:?}", event),
+        }
+        assert_eq!(records[3].event, Event::ErrorFrame(vec![0; 8]));
+    }
+
+    #[test]
+    fn candump_reader_002() {
+        let mut reader = CandumpReader::new(Cursor::new("(1700000000.1) can0 123#0\n"));
+        assert_eq!(reader.next(), Some(Err(CandumpError::Syntax { line: 1 })));
+    }
+
+    #[test]
+    fn candump_writer_001() {
+        let mut buffer = Vec::new();
+        {
+            let mut writer = CandumpWriter::new(&mut buffer).with_interface(2, "vcan1");
+            for record in CandumpReader::new(Cursor::new(LOG)) {
+                writer.write(&record.unwrap()).unwrap();
+            }
+            assert_eq!(
+                writer.write(&Record {
+                    time: SystemTime::UNIX_EPOCH,
+                    bus: 1,
+                    direction: Direction::Rx,
+                    event: Event::Status(0),
+                }),
+                Err(CandumpError::UnsupportedEvent)
+            );
+        }
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            LOG.replace("\n\n", "\n").replace("20000080", "20000000")
+        );
+    }
+}
@@ -0,0 +1,440 @@
+//! Columns of the tabular exports, shared by [csv](crate::tracefile::csv) and
+//! [jsonl](crate::tracefile::jsonl).
+
+use crate::socket::{parse_hex, CanFdFrame, CanFrame, MessageType};
+use crate::tracefile::{days_from_civil, from_civil, to_civil, Direction, Event, Record};
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Column {
+    Time,
+    Bus,
+    Direction,
+    Id,
+    Extended,
+    Kind,
+    Brs,
+    Esi,
+    Dlc,
+    Data,
+}
+
+pub const ALL_COLUMNS: [Column; 10] = [
+    Column::Time,
+    Column::Bus,
+    Column::Direction,
+    Column::Id,
+    Column::Extended,
+    Column::Kind,
+    Column::Brs,
+    Column::Esi,
+    Column::Dlc,
+    Column::Data,
+];
+
+impl Column {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Column::Time => "timestamp",
+            Column::Bus => "channel",
+            Column::Direction => "direction",
+            Column::Id => "id",
+            Column::Extended => "extended",
+            Column::Kind => "kind",
+            Column::Brs => "brs",
+            Column::Esi => "esi",
+            Column::Dlc => "dlc",
+            Column::Data => "data",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Column> {
+        ALL_COLUMNS
+            .iter()
+            .find(|column| column.name() == name)
+            .copied()
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum TimeFormat {
+    /// Seconds since the Unix epoch with six decimals.
+    UnixSeconds,
+    /// Microseconds since the Unix epoch.
+    UnixMicros,
+    /// UTC date and time like `2023-11-14T22:13:20.123456Z`.
+    Rfc3339,
+    /// Seconds since the first record with six decimals.
+    Relative,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum FrameKind {
+    Data,
+    Rtr,
+    Error,
+    Status,
+    Fd,
+}
+
+impl FrameKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FrameKind::Data => "data",
+            FrameKind::Rtr => "rtr",
+            FrameKind::Error => "error",
+            FrameKind::Status => "status",
+            FrameKind::Fd => "fd",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<FrameKind> {
+        match name.to_lowercase().as_str() {
+            "data" => Some(FrameKind::Data),
+            "rtr" => Some(FrameKind::Rtr),
+            "error" => Some(FrameKind::Error),
+            "status" => Some(FrameKind::Status),
+            "fd" => Some(FrameKind::Fd),
+            _ => None,
+        }
+    }
+}
+
+/// Columns and time format of an export.
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    pub(crate) columns: Vec<Column>,
+    pub(crate) time_format: TimeFormat,
+}
+
+impl ExportOptions {
+    /// All columns with times in seconds since the Unix epoch.
+    pub fn new() -> Self {
+        ExportOptions {
+            columns: ALL_COLUMNS.to_vec(),
+            time_format: TimeFormat::UnixSeconds,
+        }
+    }
+
+    pub fn with_columns(mut self, columns: &[Column]) -> Self {
+        self.columns = columns.to_vec();
+        self
+    }
+
+    pub fn with_time_format(mut self, time_format: TimeFormat) -> Self {
+        self.time_format = time_format;
+        self
+    }
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        ExportOptions::new()
+    }
+}
+
+/* Rows */
+
+/// A record split into the values of the columns.
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) struct Row {
+    pub time: SystemTime,
+    pub bus: u8,
+    pub direction: Direction,
+    pub id: u32,
+    pub extended: bool,
+    pub kind: FrameKind,
+    pub brs: bool,
+    pub esi: bool,
+    pub dlc: u8,
+    pub data: Vec<u8>,
+}
+
+impl Row {
+    pub fn from_record(record: &Record) -> Option<Row> {
+        let mut row = Row {
+            time: record.time,
+            bus: record.bus,
+            direction: record.direction,
+            id: 0,
+            extended: false,
+            kind: FrameKind::Data,
+            brs: false,
+            esi: false,
+            dlc: 0,
+            data: Vec::new(),
+        };
+        match &record.event {
+            Event::Can(frame) => {
+                row.id = frame.can_id();
+                row.extended = frame.is_extended_frame();
+                row.dlc = frame.dlc();
+                row.kind = match () {
+                    _ if frame.is_status_frame() => FrameKind::Status,
+                    _ if frame.is_error_frame() => FrameKind::Error,
+                    _ if frame.is_rtr_frame() => FrameKind::Rtr,
+                    _ => FrameKind::Data,
+                };
+                if row.kind != FrameKind::Rtr {
+                    row.data = frame.data().to_vec();
+                }
+            }
+            Event::Fd(frame) => {
+                row.id = frame.can_id();
+                row.extended = frame.is_extended_frame();
+                row.kind = FrameKind::Fd;
+                row.brs = frame.is_brs_frame();
+                row.esi = frame.is_esi_frame();
+                row.dlc = frame.dlc();
+                row.data = frame.data().to_vec();
+            }
+            Event::ErrorFrame(data) => {
+                row.kind = FrameKind::Error;
+                row.dlc = data.len().min(u8::MAX as usize) as u8;
+                row.data = data.clone();
+            }
+            Event::Status(status) => {
+                row.kind = FrameKind::Status;
+                row.dlc = 4;
+                row.data = status.to_be_bytes().to_vec();
+            }
+            _ => return None,
+        }
+        Some(row)
+    }
+
+    /// The record of the row. The data of data and FD frames wins over the DLC, so edited data
+    /// needs no matching DLC. Identifiers above `0x7FF` are extended.
+    pub fn into_record(self) -> Option<Record> {
+        let msg_type = match self.extended || self.id > 0x7FF {
+            true => MessageType::Extended,
+            false => MessageType::Standard,
+        };
+        let event = match self.kind {
+            FrameKind::Data => Event::Can(CanFrame::new(self.id, msg_type, &self.data).ok()?),
+            FrameKind::Rtr => Event::Can(CanFrame::new_rtr(self.id, msg_type, self.dlc).ok()?),
+            FrameKind::Fd => {
+                let mut frame = CanFdFrame::new(self.id, msg_type, &self.data).ok()?;
+                frame.set_brs(self.brs);
+                frame.set_esi(self.esi);
+                Event::Fd(frame)
+            }
+            FrameKind::Error => Event::ErrorFrame(self.data),
+            FrameKind::Status => {
+                let mut status = [0; 4];
+                let length = self.data.len().min(4);
+                status[4 - length..].copy_from_slice(&self.data[..length]);
+                Event::Status(u32::from_be_bytes(status))
+            }
+        };
+        Some(Record {
+            time: self.time,
+            bus: self.bus,
+            direction: self.direction,
+            event,
+        })
+    }
+
+    /// Text of a column, times in `time_format` with relative times counted from `start`.
+    pub fn text(&self, column: Column, time_format: TimeFormat, start: SystemTime) -> String {
+        match column {
+            Column::Time => format_time(self.time, time_format, start),
+            Column::Bus => self.bus.to_string(),
+            Column::Direction => match self.direction {
+                Direction::Rx => "rx".to_string(),
+                Direction::Tx => "tx".to_string(),
+            },
+            Column::Id => format!("0x{:X}", self.id),
+            Column::Extended => self.extended.to_string(),
+            Column::Kind => self.kind.name().to_string(),
+            Column::Brs => self.brs.to_string(),
+            Column::Esi => self.esi.to_string(),
+            Column::Dlc => self.dlc.to_string(),
+            Column::Data => format_data(&self.data),
+        }
+    }
+
+    /// Sets a column from its text, the counterpart of [Row::text].
+    pub fn set(
+        &mut self,
+        column: Column,
+        value: &str,
+        time_format: TimeFormat,
+        start: SystemTime,
+    ) -> Option<()> {
+        match column {
+            Column::Time => self.time = parse_time(value, time_format, start)?,
+            Column::Bus => self.bus = value.parse().ok()?,
+            Column::Direction => {
+                self.direction = match value.to_lowercase().as_str() {
+                    "rx" => Direction::Rx,
+                    "tx" => Direction::Tx,
+                    _ => return None,
+                }
+            }
+            Column::Id => self.id = parse_id(value)?,
+            Column::Extended => self.extended = parse_bool(value)?,
+            Column::Kind => self.kind = FrameKind::from_name(value)?,
+            Column::Brs => self.brs = parse_bool(value)?,
+            Column::Esi => self.esi = parse_bool(value)?,
+            Column::Dlc => self.dlc = value.parse().ok()?,
+            Column::Data => self.data = parse_data(value)?,
+        }
+        Some(())
+    }
+}
+
+impl Default for Row {
+    fn default() -> Self {
+        Row {
+            time: SystemTime::UNIX_EPOCH,
+            bus: 1,
+            direction: Direction::Rx,
+            id: 0,
+            extended: false,
+            kind: FrameKind::Data,
+            brs: false,
+            esi: false,
+            dlc: 0,
+            data: Vec::new(),
+        }
+    }
+}
+
+/* Values */
+
+pub(crate) fn format_data(data: &[u8]) -> String {
+    data.iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Parses hex bytes, separated by spaces or not.
+pub(crate) fn parse_data(value: &str) -> Option<Vec<u8>> {
+    parse_hex(&value.split_whitespace().collect::<String>())
+}
+
+pub(crate) fn parse_id(value: &str) -> Option<u32> {
+    match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+pub(crate) fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "1" => Some(true),
+        "false" | "0" | "" => Some(false),
+        _ => None,
+    }
+}
+
+/// Parses a number of seconds with up to nine decimals.
+fn parse_seconds(value: &str) -> Option<Duration> {
+    let (seconds, fraction) = value.split_once('.').unwrap_or((value, ""));
+    let digits = fraction.get(..fraction.len().min(9))?;
+    let nanos = match digits.is_empty() {
+        true => 0,
+        false => digits.parse::<u64>().ok()? * 10u64.pow(9 - digits.len() as u32),
+    };
+    Some(Duration::new(seconds.parse().ok()?, nanos as u32))
+}
+
+pub(crate) fn format_time(time: SystemTime, format: TimeFormat, start: SystemTime) -> String {
+    let since = |origin: SystemTime| time.duration_since(origin).unwrap_or(Duration::ZERO);
+    match format {
+        TimeFormat::UnixSeconds | TimeFormat::Relative => {
+            let elapsed = match format {
+                TimeFormat::Relative => since(start),
+                _ => since(SystemTime::UNIX_EPOCH),
+            };
+            format!("{}.{:06}", elapsed.as_secs(), elapsed.subsec_micros())
+        }
+        TimeFormat::UnixMicros => since(SystemTime::UNIX_EPOCH).as_micros().to_string(),
+        TimeFormat::Rfc3339 => {
+            let (year, month, day, seconds, micros) = to_civil(time);
+            format!(
+                "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+                year,
+                month,
+                day,
+                seconds / 3600,
+                seconds / 60 % 60,
+                seconds % 60,
+                micros
+            )
+        }
+    }
+}
+
+/// Parses a time, relative times are added to `start`.
+pub(crate) fn parse_time(value: &str, format: TimeFormat, start: SystemTime) -> Option<SystemTime> {
+    match format {
+        TimeFormat::UnixSeconds => SystemTime::UNIX_EPOCH.checked_add(parse_seconds(value)?),
+        TimeFormat::Relative => start.checked_add(parse_seconds(value)?),
+        TimeFormat::UnixMicros => {
+            SystemTime::UNIX_EPOCH.checked_add(Duration::from_micros(value.parse().ok()?))
+        }
+        TimeFormat::Rfc3339 => parse_rfc3339(value),
+    }
+}
+
+/// Parses `YYYY-MM-DDTHH:MM:SS[.fraction](Z|±HH:MM)`.
+fn parse_rfc3339(value: &str) -> Option<SystemTime> {
+    let (date, time) = value.split_once(['T', 't', ' '])?;
+    let mut date = date.split('-');
+    let year = date.next()?.parse::<i64>().ok()?;
+    let month = date.next()?.parse::<u32>().ok()?;
+    let day = date.next()?.parse::<u32>().ok()?;
+
+    let (time, offset) = match time.find(['Z', 'z', '+', '-']) {
+        Some(index) => time.split_at(index),
+        None => return None,
+    };
+    let offset_seconds = match offset {
+        "Z" | "z" => 0,
+        offset => {
+            let (hours, minutes) = offset[1..].split_once(':')?;
+            let (hours, minutes) = (hours.parse::<i64>().ok()?, minutes.parse::<i64>().ok()?);
+            if !(0..24).contains(&hours) || !(0..60).contains(&minutes) {
+                return None;
+            }
+            let seconds = hours * 3600 + minutes * 60;
+            match offset.starts_with('-') {
+                true => -seconds,
+                false => seconds,
+            }
+        }
+    };
+
+    let mut parts = time.splitn(3, ':');
+    let hours = parts.next()?.parse::<u64>().ok()?;
+    let minutes = parts.next()?.parse::<u64>().ok()?;
+    let seconds = parse_seconds(parts.next()?)?;
+    // leap seconds are allowed
+    if hours >= 24 || minutes >= 60 || seconds.as_secs() > 60 {
+        return None;
+    }
+
+    let local = from_civil(
+        year,
+        month,
+        day,
+        hours * 3600 + minutes * 60 + seconds.as_secs(),
+        0,
+    )?;
+    // checks the date against the calendar, e.g. rejects February 30
+    let (_, check_month, check_day, _, _) = to_civil(local);
+    if (check_month, check_day) != (month, day) || days_from_civil(year, month, day) < 0 {
+        return None;
+    }
+    let utc = match offset_seconds >= 0 {
+        true => local.checked_sub(Duration::from_secs(offset_seconds as u64))?,
+        false => local.checked_add(Duration::from_secs(offset_seconds.unsigned_abs()))?,
+    };
+    Some(utc + Duration::from_nanos(seconds.subsec_nanos() as u64))
+}
@@ -0,0 +1,357 @@
+//! Comma separated values, one frame per line after a header naming the columns.
+//!
+//! ```text
+//! timestamp,channel,direction,id,extended,kind,brs,esi,dlc,data
+//! 1700000000.123456,1,rx,0x123,false,data,false,false,4,DE AD BE EF
+//! ```
+//!
+//! Identifiers are written in hex with a `0x` prefix and read in hex or decimal. The columns of
+//! a file are taken from its header, columns missing from it are read as bus 1, received, data
+//! frame with standard identifier 0 and no data.
+
+use crate::socket::clock::Clock;
+use crate::socket::{CanFdFrame, CanFrame, Timestamp};
+use crate::tracefile::columns::{Column, ExportOptions, Row, TimeFormat};
+use crate::tracefile::{Direction, Event, HardwareTime, Record};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::SystemTime;
+
+#[derive(Debug, PartialEq)]
+pub enum CsvError {
+    Io(std::io::ErrorKind),
+    /// The file is empty or its header names no known column.
+    InvalidHeader,
+    /// A line of the file could not be parsed.
+    Syntax {
+        line: usize,
+    },
+    /// The event cannot be stored in a CSV file.
+    UnsupportedEvent,
+}
+
+impl From<std::io::Error> for CsvError {
+    fn from(value: std::io::Error) -> Self {
+        CsvError::Io(value.kind())
+    }
+}
+
+/// Splits a line into its fields, quotes around a field are removed.
+fn split_fields(line: &str) -> Vec<&str> {
+    line.split(',')
+        .map(|field| {
+            let field = field.trim();
+            field
+                .strip_prefix('"')
+                .and_then(|field| field.strip_suffix('"'))
+                .unwrap_or(field)
+        })
+        .collect()
+}
+
+/* CsvReader */
+
+/// Streams the frames of a CSV file.
+pub struct CsvReader<R> {
+    reader: R,
+    columns: Vec<Option<Column>>,
+    time_format: TimeFormat,
+    start: SystemTime,
+    line: usize,
+}
+
+impl CsvReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<CsvReader<BufReader<File>>, CsvError> {
+        CsvReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: BufRead> CsvReader<R> {
+    /// Reads the header. Times are read as seconds since the Unix epoch unless set otherwise.
+    pub fn new(mut reader: R) -> Result<CsvReader<R>, CsvError> {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        let header = header.trim_start_matches('\u{feff}');
+        let columns = split_fields(header.trim())
+            .into_iter()
+            .map(Column::from_name)
+            .collect::<Vec<_>>();
+        if columns.iter().all(Option::is_none) {
+            return Err(CsvError::InvalidHeader);
+        }
+        Ok(CsvReader {
+            reader,
+            columns,
+            time_format: TimeFormat::UnixSeconds,
+            start: SystemTime::UNIX_EPOCH,
+            line: 1,
+        })
+    }
+
+    pub fn with_time_format(mut self, time_format: TimeFormat) -> Self {
+        self.time_format = time_format;
+        self
+    }
+
+    /// Sets the time relative times are added to, the Unix epoch by default.
+    pub fn with_start(mut self, start: SystemTime) -> Self {
+        self.start = start;
+        self
+    }
+
+    /// Columns of the file, `None` for columns with unknown names.
+    pub fn columns(&self) -> &[Option<Column>] {
+        &self.columns
+    }
+
+    fn parse_line(&self, line: &str) -> Option<Record> {
+        let fields = split_fields(line);
+        if fields.len() != self.columns.len() {
+            return None;
+        }
+        let mut row = Row::default();
+        for (column, value) in self.columns.iter().zip(fields) {
+            if let Some(column) = column {
+                row.set(*column, value, self.time_format, self.start)?;
+            }
+        }
+        row.into_record()
+    }
+}
+
+impl<R: BufRead> Iterator for CsvReader<R> {
+    type Item = Result<Record, CsvError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => return None,
+                Ok(_) => self.line += 1,
+                Err(err) => return Some(Err(err.into())),
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            return Some(
+                self.parse_line(line.trim())
+                    .ok_or(CsvError::Syntax { line: self.line }),
+            );
+        }
+    }
+}
+
+/* CsvWriter */
+
+/// Writes CSV files with the columns and time format of [ExportOptions].
+pub struct CsvWriter<W: Write> {
+    writer: W,
+    options: ExportOptions,
+    start: Option<SystemTime>,
+    hardware_time: HardwareTime,
+}
+
+impl CsvWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        options: ExportOptions,
+    ) -> Result<CsvWriter<BufWriter<File>>, CsvError> {
+        CsvWriter::new(BufWriter::new(File::create(path)?), options)
+    }
+}
+
+impl<W: Write> CsvWriter<W> {
+    /// Writes the header.
+    pub fn new(mut writer: W, options: ExportOptions) -> Result<CsvWriter<W>, CsvError> {
+        let names = options.columns.iter().map(Column::name).collect::<Vec<_>>();
+        writeln!(writer, "{}", names.join(","))?;
+        Ok(CsvWriter {
+            writer,
+            options,
+            start: None,
+            hardware_time: HardwareTime::default(),
+        })
+    }
+
+    pub fn write(&mut self, record: &Record) -> Result<(), CsvError> {
+        let row = Row::from_record(record).ok_or(CsvError::UnsupportedEvent)?;
+        let start = *self.start.get_or_insert(record.time);
+        let fields = self
+            .options
+            .columns
+            .iter()
+            .map(|column| row.text(*column, self.options.time_format, start))
+            .collect::<Vec<_>>();
+        writeln!(self.writer, "{}", fields.join(","))?;
+        Ok(())
+    }
+
+    /// Sets the clock giving the [hardware time](crate::tracefile#hardware-time).
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.hardware_time = HardwareTime::new(clock);
+        self
+    }
+
+    /// Writes a classic frame at its [hardware time](crate::tracefile#hardware-time).
+    pub fn write_can(
+        &mut self,
+        frame: &CanFrame,
+        timestamp: &Timestamp,
+        direction: Direction,
+    ) -> Result<(), CsvError> {
+        let record = self
+            .hardware_time
+            .record(Event::Can(*frame), timestamp, direction);
+        self.write(&record)
+    }
+
+    /// Writes a CAN FD frame at its [hardware time](crate::tracefile#hardware-time).
+    pub fn write_can_fd(
+        &mut self,
+        frame: &CanFdFrame,
+        timestamp: &Timestamp,
+        direction: Direction,
+    ) -> Result<(), CsvError> {
+        let record = self
+            .hardware_time
+            .record(Event::Fd(*frame), timestamp, direction);
+        self.write(&record)
+    }
+
+    pub fn flush(&mut self) -> Result<(), CsvError> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::socket::MessageType;
+    use std::io::Cursor;
+    use std::time::Duration;
+
+    const CSV: &str = "timestamp,channel,direction,id,extended,kind,brs,esi,dlc,data
+1700000000.123456,1,rx,0x123,false,data,false,false,4,DE AD BE EF
+1700000000.200000,2,tx,0x1ABCDEF0,true,rtr,false,false,2,
+1700000000.300000,1,rx,0x321,false,fd,true,false,9,11 22 33 44 55 66 77 88 99 00 00 00
+1700000001.000000,1,rx,0x0,false,error,false,false,5,01 00 02 60 00
+1700000002.000000,1,rx,0x0,false,status,false,false,4,00 00 00 08
+";
+
+    #[test]
+    fn csv_reader_001() {
+        let records = CsvReader::new(Cursor::new(CSV))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(records.len(), 5);
+
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert_eq!(
+            records[0],
+            Record {
+                time: start + Duration::from_micros(123_456),
+                bus: 1,
+                direction: Direction::Rx,
+                event: Event::Can(
+                    CanFrame::new(0x123, MessageType::Standard, &[0xDE, 0xAD, 0xBE, 0xEF]).unwrap()
+                ),
+            }
+        );
+        match &records[1].event {
+            Event::Can(frame) => {
+                assert!(frame.is_rtr_frame() && frame.is_extended_frame());
+                assert_eq!(frame.dlc(), 2);
+            }
+            event => panic!("{:?}", event),
+        }
+        assert_eq!(records[1].direction, Direction::Tx);
+        match &records[2].event {
+            Event::Fd(frame) => {
+                assert!(frame.is_brs_frame());
+                assert_eq!(frame.dlc(), 9);
+            }
+            event => panic!("{:?}", event),
+        }
+        assert_eq!(
+            records[3].event,
+            Event::ErrorFrame(vec![0x01, 0x00, 0x02, 0x60, 0x00])
+        );
+        assert_eq!(records[4].event, Event::Status(8));
+    }
+
+    #[test]
+    fn csv_reader_002() {
+        let csv = "\u{feff}\"id\",\"data\",comment\n291,\"01 02\",edited\n0x123,0,\n";
+        let mut reader = CsvReader::new(Cursor::new(csv)).unwrap();
+        assert_eq!(
+            reader.columns(),
+            &[Some(Column::Id), Some(Column::Data), None]
+        );
+        assert_eq!(
+            reader.next().unwrap().unwrap().event,
+            Event::Can(CanFrame::new(0x123, MessageType::Standard, &[1, 2]).unwrap())
+        );
+        assert_eq!(reader.next(), Some(Err(CsvError::Syntax { line: 3 })));
+
+        assert_eq!(
+            CsvReader::new(Cursor::new("")).err(),
+            Some(CsvError::InvalidHeader)
+        );
+    }
+
+    #[test]
+    fn csv_writer_001() {
+        let mut buffer = Vec::new();
+        {
+            let mut writer = CsvWriter::new(&mut buffer, ExportOptions::new()).unwrap();
+            for record in CsvReader::new(Cursor::new(CSV)).unwrap() {
+                writer.write(&record.unwrap()).unwrap();
+            }
+            assert_eq!(
+                writer.write(&Record {
+                    time: SystemTime::UNIX_EPOCH,
+                    bus: 1,
+                    direction: Direction::Rx,
+                    event: Event::Text("comment".to_string()),
+                }),
+                Err(CsvError::UnsupportedEvent)
+            );
+        }
+        assert_eq!(String::from_utf8(buffer).unwrap(), CSV);
+    }
+
+    #[test]
+    fn csv_writer_002() {
+        let options = ExportOptions::new()
+            .with_columns(&[Column::Time, Column::Id, Column::Data])
+            .with_time_format(TimeFormat::Relative);
+        let mut buffer = Vec::new();
+        {
+            let mut writer = CsvWriter::new(&mut buffer, options).unwrap();
+            for record in CsvReader::new(Cursor::new(CSV)).unwrap().take(2) {
+                writer.write(&record.unwrap()).unwrap();
+            }
+        }
+        let csv = String::from_utf8(buffer).unwrap();
+        assert_eq!(
+            csv,
+            "timestamp,id,data\n0.000000,0x123,DE AD BE EF\n0.076544,0x1ABCDEF0,\n"
+        );
+
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(10);
+        let records = CsvReader::new(Cursor::new(csv))
+            .unwrap()
+            .with_time_format(TimeFormat::Relative)
+            .with_start(start)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(records[1].time, start + Duration::from_micros(76_544));
+        // the remote flag is not among the columns
+        assert_eq!(
+            records[1].event,
+            Event::Can(CanFrame::new(0x1ABCDEF0, MessageType::Extended, &[]).unwrap())
+        );
+    }
+}
@@ -0,0 +1,386 @@
+//! JSON Lines, one object per frame.
+//!
+//! ```text
+//! {"timestamp":1700000000.123456,"channel":1,"direction":"rx","id":291,"extended":false,"kind":"data","brs":false,"esi":false,"dlc":4,"data":"DE AD BE EF"}
+//! ```
+//!
+//! Identifiers are numbers, data is a string of hex bytes and times are numbers unless written as
+//! [TimeFormat::Rfc3339]. Unknown keys and `null` values are skipped, missing keys are read as
+//! bus 1, received, data frame with standard identifier 0 and no data.
+
+use crate::socket::clock::Clock;
+use crate::socket::{CanFdFrame, CanFrame, Timestamp};
+use crate::tracefile::columns::{Column, ExportOptions, Row, TimeFormat};
+use crate::tracefile::{Direction, Event, HardwareTime, Record};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::iter::Peekable;
+use std::path::Path;
+use std::str::Chars;
+use std::time::SystemTime;
+
+#[derive(Debug, PartialEq)]
+pub enum JsonlError {
+    Io(std::io::ErrorKind),
+    /// A line of the file could not be parsed.
+    Syntax {
+        line: usize,
+    },
+    /// The event cannot be stored in a JSON Lines file.
+    UnsupportedEvent,
+}
+
+impl From<std::io::Error> for JsonlError {
+    fn from(value: std::io::Error) -> Self {
+        JsonlError::Io(value.kind())
+    }
+}
+
+/* Parsing */
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Option<String> {
+    chars.next_if_eq(&'"')?;
+    let mut value = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(value),
+            '\\' => match chars.next()? {
+                'u' => {
+                    let digits = (0..4).map(|_| chars.next()).collect::<Option<String>>()?;
+                    value.push(char::from_u32(u32::from_str_radix(&digits, 16).ok()?)?);
+                }
+                'n' => value.push('\n'),
+                't' => value.push('\t'),
+                'r' => value.push('\r'),
+                'b' => value.push('\u{8}'),
+                'f' => value.push('\u{c}'),
+                c => value.push(c),
+            },
+            c => value.push(c),
+        }
+    }
+}
+
+/// Parses a value as text, `None` inside for `null`.
+fn parse_value(chars: &mut Peekable<Chars>) -> Option<Option<String>> {
+    match chars.peek()? {
+        '"' => parse_string(chars).map(Some),
+        _ => {
+            let mut value = String::new();
+            while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || "+-.".contains(*c)) {
+                value.push(c);
+            }
+            match value.as_str() {
+                "" => None,
+                "null" => Some(None),
+                _ => Some(Some(value)),
+            }
+        }
+    }
+}
+
+/// Parses an object of plain values into its keys and values.
+fn parse_object(line: &str) -> Option<Vec<(String, String)>> {
+    let mut chars = line.chars().peekable();
+    let mut members = Vec::new();
+    skip_whitespace(&mut chars);
+    chars.next_if_eq(&'{')?;
+    skip_whitespace(&mut chars);
+    if chars.next_if_eq(&'}').is_none() {
+        loop {
+            skip_whitespace(&mut chars);
+            let key = parse_string(&mut chars)?;
+            skip_whitespace(&mut chars);
+            chars.next_if_eq(&':')?;
+            skip_whitespace(&mut chars);
+            if let Some(value) = parse_value(&mut chars)? {
+                members.push((key, value));
+            }
+            skip_whitespace(&mut chars);
+            match chars.next()? {
+                ',' => continue,
+                '}' => break,
+                _ => return None,
+            }
+        }
+    }
+    skip_whitespace(&mut chars);
+    chars.next().is_none().then_some(members)
+}
+
+/* JsonlReader */
+
+/// Streams the frames of a JSON Lines file.
+pub struct JsonlReader<R> {
+    reader: R,
+    time_format: TimeFormat,
+    start: SystemTime,
+    line: usize,
+}
+
+impl JsonlReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<JsonlReader<BufReader<File>>, JsonlError> {
+        Ok(JsonlReader::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> JsonlReader<R> {
+    /// Times are read as seconds since the Unix epoch unless set otherwise.
+    pub fn new(reader: R) -> JsonlReader<R> {
+        JsonlReader {
+            reader,
+            time_format: TimeFormat::UnixSeconds,
+            start: SystemTime::UNIX_EPOCH,
+            line: 0,
+        }
+    }
+
+    pub fn with_time_format(mut self, time_format: TimeFormat) -> Self {
+        self.time_format = time_format;
+        self
+    }
+
+    /// Sets the time relative times are added to, the Unix epoch by default.
+    pub fn with_start(mut self, start: SystemTime) -> Self {
+        self.start = start;
+        self
+    }
+
+    fn parse_line(&self, line: &str) -> Option<Record> {
+        let mut row = Row::default();
+        for (key, value) in parse_object(line)? {
+            if let Some(column) = Column::from_name(&key) {
+                row.set(column, &value, self.time_format, self.start)?;
+            }
+        }
+        row.into_record()
+    }
+}
+
+impl<R: BufRead> Iterator for JsonlReader<R> {
+    type Item = Result<Record, JsonlError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => return None,
+                Ok(_) => self.line += 1,
+                Err(err) => return Some(Err(err.into())),
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            return Some(
+                self.parse_line(line.trim())
+                    .ok_or(JsonlError::Syntax { line: self.line }),
+            );
+        }
+    }
+}
+
+/* JsonlWriter */
+
+/// Writes JSON Lines files with the columns and time format of [ExportOptions].
+pub struct JsonlWriter<W: Write> {
+    writer: W,
+    options: ExportOptions,
+    start: Option<SystemTime>,
+    hardware_time: HardwareTime,
+}
+
+impl JsonlWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        options: ExportOptions,
+    ) -> Result<JsonlWriter<BufWriter<File>>, JsonlError> {
+        Ok(JsonlWriter::new(
+            BufWriter::new(File::create(path)?),
+            options,
+        ))
+    }
+}
+
+impl<W: Write> JsonlWriter<W> {
+    pub fn new(writer: W, options: ExportOptions) -> JsonlWriter<W> {
+        JsonlWriter {
+            writer,
+            options,
+            start: None,
+            hardware_time: HardwareTime::default(),
+        }
+    }
+
+    pub fn write(&mut self, record: &Record) -> Result<(), JsonlError> {
+        let row = Row::from_record(record).ok_or(JsonlError::UnsupportedEvent)?;
+        let start = *self.start.get_or_insert(record.time);
+        let time_format = self.options.time_format;
+        let members = self
+            .options
+            .columns
+            .iter()
+            .map(|column| {
+                // the texts of the columns hold no characters to escape
+                let value = match column {
+                    Column::Id => row.id.to_string(),
+                    Column::Direction | Column::Kind | Column::Data => {
+                        format!("\"{}\"", row.text(*column, time_format, start))
+                    }
+                    Column::Time if time_format == TimeFormat::Rfc3339 => {
+                        format!("\"{}\"", row.text(*column, time_format, start))
+                    }
+                    _ => row.text(*column, time_format, start),
+                };
+                format!("\"{}\":{}", column.name(), value)
+            })
+            .collect::<Vec<_>>();
+        writeln!(self.writer, "{{{}}}", members.join(","))?;
+        Ok(())
+    }
+
+    /// Sets the clock giving the [hardware time](crate::tracefile#hardware-time).
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.hardware_time = HardwareTime::new(clock);
+        self
+    }
+
+    /// Writes a classic frame at its [hardware time](crate::tracefile#hardware-time).
+    pub fn write_can(
+        &mut self,
+        frame: &CanFrame,
+        timestamp: &Timestamp,
+        direction: Direction,
+    ) -> Result<(), JsonlError> {
+        let record = self
+            .hardware_time
+            .record(Event::Can(*frame), timestamp, direction);
+        self.write(&record)
+    }
+
+    /// Writes a CAN FD frame at its [hardware time](crate::tracefile#hardware-time).
+    pub fn write_can_fd(
+        &mut self,
+        frame: &CanFdFrame,
+        timestamp: &Timestamp,
+        direction: Direction,
+    ) -> Result<(), JsonlError> {
+        let record = self
+            .hardware_time
+            .record(Event::Fd(*frame), timestamp, direction);
+        self.write(&record)
+    }
+
+    pub fn flush(&mut self) -> Result<(), JsonlError> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::socket::MessageType;
+    use std::io::Cursor;
+    use std::time::Duration;
+
+    const JSONL: &str = r#"{"timestamp":1700000000.123456,"channel":1,"direction":"rx","id":291,"extended":false,"kind":"data","brs":false,"esi":false,"dlc":4,"data":"DE AD BE EF"}
+{"timestamp":1700000000.200000,"channel":2,"direction":"tx","id":448585456,"extended":true,"kind":"rtr","brs":false,"esi":false,"dlc":2,"data":""}
+{"timestamp":1700000000.300000,"channel":1,"direction":"rx","id":801,"extended":false,"kind":"fd","brs":true,"esi":true,"dlc":9,"data":"11 22 33 44 55 66 77 88 99 00 00 00"}
+{"timestamp":1700000001.000000,"channel":1,"direction":"rx","id":0,"extended":false,"kind":"error","brs":false,"esi":false,"dlc":5,"data":"01 00 02 60 00"}
+"#;
+
+    #[test]
+    fn jsonl_reader_001() {
+        let records = JsonlReader::new(Cursor::new(JSONL))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(records.len(), 4);
+
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        assert_eq!(
+            records[0],
+            Record {
+                time: start + Duration::from_micros(123_456),
+                bus: 1,
+                direction: Direction::Rx,
+                event: Event::Can(
+                    CanFrame::new(0x123, MessageType::Standard, &[0xDE, 0xAD, 0xBE, 0xEF]).unwrap()
+                ),
+            }
+        );
+        match &records[1].event {
+            Event::Can(frame) => assert!(frame.is_rtr_frame() && frame.can_id() == 0x1ABCDEF0),
+            event => panic!("{:?}", event),
+        }
+        match &records[2].event {
+            Event::Fd(frame) => assert!(frame.is_brs_frame() && frame.is_esi_frame()),
+            event => panic!("{:?}", event),
+        }
+        assert_eq!(
+            records[3].event,
+            Event::ErrorFrame(vec![0x01, 0x00, 0x02, 0x60, 0x00])
+        );
+    }
+
+    #[test]
+    fn jsonl_reader_002() {
+        let jsonl = concat!(
+            "{ \"timestamp\" : \"2023-11-14T23:13:20.5+01:00\", \"id\": \"0x123\",",
+            " \"data\": \"0102\", \"comment\": \"edited \\\"by hand\\\"\", \"dlc\": null }\n",
+            "{\"id\": 291,}\n",
+            "{\"timestamp\": \"2023-11-14T99999999999999999:13:20Z\"}\n",
+            "{\"timestamp\": \"2023-11-14T23:13:20-99999999999999999:00\"}\n",
+        );
+        let mut reader = JsonlReader::new(Cursor::new(jsonl)).with_time_format(TimeFormat::Rfc3339);
+        assert_eq!(
+            reader.next(),
+            Some(Ok(Record {
+                time: SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_500),
+                bus: 1,
+                direction: Direction::Rx,
+                event: Event::Can(CanFrame::new(0x123, MessageType::Standard, &[1, 2]).unwrap()),
+            }))
+        );
+        assert_eq!(reader.next(), Some(Err(JsonlError::Syntax { line: 2 })));
+        assert_eq!(reader.next(), Some(Err(JsonlError::Syntax { line: 3 })));
+        assert_eq!(reader.next(), Some(Err(JsonlError::Syntax { line: 4 })));
+    }
+
+    #[test]
+    fn jsonl_writer_001() {
+        let mut buffer = Vec::new();
+        {
+            let mut writer = JsonlWriter::new(&mut buffer, ExportOptions::new());
+            for record in JsonlReader::new(Cursor::new(JSONL)) {
+                writer.write(&record.unwrap()).unwrap();
+            }
+        }
+        assert_eq!(String::from_utf8(buffer).unwrap(), JSONL);
+    }
+
+    #[test]
+    fn jsonl_writer_002() {
+        let options = ExportOptions::new()
+            .with_columns(&[Column::Time, Column::Kind])
+            .with_time_format(TimeFormat::Rfc3339);
+        let mut buffer = Vec::new();
+        {
+            let mut writer = JsonlWriter::new(&mut buffer, options);
+            writer
+                .write(&Record {
+                    time: SystemTime::UNIX_EPOCH + Duration::from_micros(1_700_000_000_123_456),
+                    bus: 1,
+                    direction: Direction::Rx,
+                    event: Event::Status(8),
+                })
+                .unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "{\"timestamp\":\"2023-11-14T22:13:20.123456Z\",\"kind\":\"status\"}\n"
+        );
+    }
+}
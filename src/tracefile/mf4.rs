@@ -0,0 +1,1512 @@
+//! ASAM MDF 4 bus logging files (`.mf4`).
+//!
+//! [Mf4Writer] streams frames into the `CAN_DataFrame`, `CAN_RemoteFrame` and `CAN_ErrorFrame`
+//! channel groups of the ASAM MDF bus logging standard, optionally compressing the data blocks.
+//! Until it is finished the file is marked as unfinalized. [Mf4Reader] replays the frames of bus
+//! logging files in the order of their timestamps. Times are converted as if they were UTC.
+
+use crate::socket::clock::Clock;
+use crate::socket::{fd_dlc_to_length, CanFdFrame, CanFrame, MessageType, Timestamp};
+use crate::tracefile::{Direction, Event, HardwareTime, Record};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+const ID_FINALIZED: &[u8; 8] = b"MDF     ";
+const ID_UNFINALIZED: &[u8; 8] = b"UnFinMF ";
+const VERSION: u16 = 411;
+const ID_BLOCK_SIZE: u64 = 64;
+const BLOCK_HEADER_SIZE: usize = 24;
+/// Blocks claiming to be larger are taken as corrupted.
+const MAX_BLOCK_SIZE: u64 = 256 * 1024 * 1024;
+/// Bytes of data read at once from uncompressed data blocks.
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+/* Unfinalized flags */
+
+const UNFIN_CYCLE_COUNTERS: u16 = 0x01;
+const UNFIN_DATA_LIST: u16 = 0x10;
+
+/* Channel and group constants */
+
+const CN_TYPE_FIXED: u8 = 0;
+const CN_TYPE_VLSD: u8 = 1;
+const CN_TYPE_MASTER: u8 = 2;
+const CN_SYNC_TIME: u8 = 1;
+const CN_FLAG_BUS_EVENT: u32 = 0x0400;
+const CG_FLAG_VLSD: u16 = 0x01;
+const CG_FLAG_BUS_EVENT: u16 = 0x02;
+const CG_FLAG_PLAIN_BUS_EVENT: u16 = 0x04;
+
+const DATA_TYPE_UINT_LE: u8 = 0;
+const DATA_TYPE_UINT_BE: u8 = 1;
+const DATA_TYPE_INT_LE: u8 = 2;
+const DATA_TYPE_INT_BE: u8 = 3;
+const DATA_TYPE_FLOAT_LE: u8 = 4;
+const DATA_TYPE_FLOAT_BE: u8 = 5;
+const DATA_TYPE_BYTES: u8 = 10;
+
+#[derive(Debug, PartialEq)]
+pub enum Mf4Error {
+    Io(std::io::ErrorKind),
+    /// The file does not start with an MDF identification block.
+    InvalidIdentifier,
+    UnsupportedVersion(u16),
+    /// A block is truncated or malformed, at the given offset into the file.
+    InvalidBlock {
+        offset: u64,
+    },
+    UnsupportedCompression(u8),
+    /// A data block contains a record of an unknown channel group.
+    InvalidRecord,
+    /// The event cannot be stored in a bus logging file.
+    UnsupportedEvent,
+}
+
+impl From<std::io::Error> for Mf4Error {
+    fn from(value: std::io::Error) -> Self {
+        Mf4Error::Io(value.kind())
+    }
+}
+
+/* Layout of the written records */
+
+/// A channel of the written channel groups, placed after the timestamp.
+struct Field {
+    name: &'static str,
+    byte_offset: u32,
+    bit_offset: u8,
+    bit_count: u32,
+}
+
+const FIELDS: [Field; 9] = [
+    Field {
+        name: "BusChannel",
+        byte_offset: 8,
+        bit_offset: 0,
+        bit_count: 8,
+    },
+    Field {
+        name: "ID",
+        byte_offset: 9,
+        bit_offset: 0,
+        bit_count: 29,
+    },
+    Field {
+        name: "IDE",
+        byte_offset: 12,
+        bit_offset: 7,
+        bit_count: 1,
+    },
+    Field {
+        name: "DLC",
+        byte_offset: 13,
+        bit_offset: 0,
+        bit_count: 4,
+    },
+    Field {
+        name: "DataLength",
+        byte_offset: 14,
+        bit_offset: 0,
+        bit_count: 8,
+    },
+    Field {
+        name: "Dir",
+        byte_offset: 15,
+        bit_offset: 0,
+        bit_count: 1,
+    },
+    Field {
+        name: "EDL",
+        byte_offset: 15,
+        bit_offset: 1,
+        bit_count: 1,
+    },
+    Field {
+        name: "BRS",
+        byte_offset: 15,
+        bit_offset: 2,
+        bit_count: 1,
+    },
+    Field {
+        name: "ESI",
+        byte_offset: 15,
+        bit_offset: 3,
+        bit_count: 1,
+    },
+];
+
+/// Channel groups written, as name, number of fields and number of data bytes.
+const GROUPS: [(&str, usize, u32); 3] = [
+    ("CAN_DataFrame", 9, 64),
+    ("CAN_RemoteFrame", 6, 0),
+    ("CAN_ErrorFrame", 6, 8),
+];
+const DATA_BYTES_OFFSET: usize = 16;
+
+/* Blocks */
+
+fn block(id: &[u8; 4], links: &[u64], data: &[u8]) -> Vec<u8> {
+    let length = BLOCK_HEADER_SIZE + links.len() * 8 + data.len();
+    let mut bytes = Vec::with_capacity(length);
+    bytes.extend_from_slice(id);
+    bytes.extend_from_slice(&[0; 4]);
+    bytes.extend_from_slice(&(length as u64).to_le_bytes());
+    bytes.extend_from_slice(&(links.len() as u64).to_le_bytes());
+    for link in links {
+        bytes.extend_from_slice(&link.to_le_bytes());
+    }
+    bytes.extend_from_slice(data);
+    bytes
+}
+
+/// Zero terminated text padded to 8 bytes, as stored in TX and MD blocks.
+fn text_data(text: &str) -> Vec<u8> {
+    let mut data = text.as_bytes().to_vec();
+    data.push(0);
+    data.resize(data.len().div_ceil(8) * 8, 0);
+    data
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        bytes.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn u64_at(bytes: &[u8], offset: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        bytes.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+fn f64_at(bytes: &[u8], offset: usize) -> Option<f64> {
+    Some(f64::from_le_bytes(
+        bytes.get(offset..offset + 8)?.try_into().ok()?,
+    ))
+}
+
+fn system_time_from_nanos(nanos: u64) -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_nanos(nanos)
+}
+
+fn nanos_since_epoch(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_nanos() as u64
+}
+
+/// Marks a block as read, a link back to it is a cycle.
+fn visit(visited: &mut HashSet<u64>, offset: u64) -> Result<(), Mf4Error> {
+    match visited.insert(offset) {
+        true => Ok(()),
+        false => Err(Mf4Error::InvalidBlock { offset }),
+    }
+}
+
+/// Adds a possibly negative number of seconds to a point in time.
+fn offset_time(start: SystemTime, seconds: f64) -> SystemTime {
+    let offset = Duration::from_nanos((seconds.abs() * 1e9).round() as u64);
+    match seconds < 0.0 {
+        true => start.checked_sub(offset).unwrap_or(SystemTime::UNIX_EPOCH),
+        false => start + offset,
+    }
+}
+
+/* Mf4Options */
+
+#[derive(Debug, Clone)]
+pub struct Mf4Options {
+    compression: bool,
+    block_size: usize,
+}
+
+impl Mf4Options {
+    /// Uncompressed data blocks of 64 KiB.
+    pub fn new() -> Self {
+        Mf4Options {
+            compression: false,
+            block_size: 64 * 1024,
+        }
+    }
+
+    /// Compresses the data blocks with deflate.
+    pub fn with_compression(mut self, compression: bool) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Bytes of records collected before a data block is written.
+    pub fn with_block_size(mut self, block_size: usize) -> Self {
+        self.block_size = block_size.max(1);
+        self
+    }
+}
+
+impl Default for Mf4Options {
+    fn default() -> Self {
+        Mf4Options::new()
+    }
+}
+
+/* Mf4Writer */
+
+/// Streams frames into a bus logging file. The data blocks are linked and the file is finalized
+/// by [finish](Mf4Writer::finish), which is called on drop.
+pub struct Mf4Writer<W: Write + Seek> {
+    writer: W,
+    options: Mf4Options,
+    /// End of the file, where the next block is written.
+    position: u64,
+    buffer: Vec<u8>,
+    /// Data blocks written with the offset of their data in the uncompressed record stream.
+    blocks: Vec<(u64, u64)>,
+    data_length: u64,
+    header: u64,
+    data_group: u64,
+    channel_groups: [u64; 3],
+    cycle_counts: [u64; 3],
+    start: Option<SystemTime>,
+    hardware_time: HardwareTime,
+}
+
+impl Mf4Writer<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        options: Mf4Options,
+    ) -> Result<Mf4Writer<BufWriter<File>>, Mf4Error> {
+        Mf4Writer::new(BufWriter::new(File::create(path)?), options)
+    }
+}
+
+impl<W: Write + Seek> Mf4Writer<W> {
+    /// Writes the structure of the file, the first record sets its start time.
+    pub fn new(mut writer: W, options: Mf4Options) -> Result<Mf4Writer<W>, Mf4Error> {
+        writer.seek(SeekFrom::Start(0))?;
+        let mut mf4 = Mf4Writer {
+            writer,
+            options,
+            position: 0,
+            buffer: Vec::new(),
+            blocks: Vec::new(),
+            data_length: 0,
+            header: ID_BLOCK_SIZE,
+            data_group: 0,
+            channel_groups: [0; 3],
+            cycle_counts: [0; 3],
+            start: None,
+            hardware_time: HardwareTime::default(),
+        };
+
+        let mut id = Vec::with_capacity(ID_BLOCK_SIZE as usize);
+        id.extend_from_slice(ID_UNFINALIZED);
+        id.extend_from_slice(b"4.11    ");
+        id.extend_from_slice(b"pcanbasc");
+        id.extend_from_slice(&[0; 4]);
+        id.extend_from_slice(&VERSION.to_le_bytes());
+        id.resize(60, 0);
+        id.extend_from_slice(&(UNFIN_CYCLE_COUNTERS | UNFIN_DATA_LIST).to_le_bytes());
+        id.extend_from_slice(&0u16.to_le_bytes());
+        mf4.writer.write_all(&id)?;
+        mf4.position = ID_BLOCK_SIZE;
+
+        // the header is completed once its children are written
+        let header = mf4.header_block(0, 0);
+        mf4.write_block(header)?;
+
+        let comment = format!(
+            "<FHcomment>\n<TX>created</TX>\n<tool_id>{}</tool_id>\n\
+             <tool_vendor>{}</tool_vendor>\n<tool_version>{}</tool_version>\n</FHcomment>",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        );
+        let comment = mf4.write_block(block(b"##MD", &[], &text_data(&comment)))?;
+        let mut history = nanos_since_epoch(SystemTime::now()).to_le_bytes().to_vec();
+        history.resize(16, 0);
+        let history = mf4.write_block(block(b"##FH", &[0, comment], &history))?;
+
+        let bus_name = mf4.write_text("CAN")?;
+        // bus source of type CAN
+        let source =
+            mf4.write_block(block(b"##SI", &[bus_name, 0, 0], &[2, 2, 0, 0, 0, 0, 0, 0]))?;
+        let unit = mf4.write_text("s")?;
+
+        let mut next_group = 0;
+        for (index, (name, field_count, data_bytes)) in GROUPS.iter().enumerate().rev() {
+            let mut next = 0;
+            if *data_bytes > 0 {
+                next = mf4.write_channel(
+                    &format!("{}.DataBytes", name),
+                    next,
+                    CN_TYPE_FIXED,
+                    DATA_TYPE_BYTES,
+                    DATA_BYTES_OFFSET as u32,
+                    0,
+                    data_bytes * 8,
+                    0,
+                )?;
+            }
+            for field in FIELDS[..*field_count].iter().rev() {
+                next = mf4.write_channel(
+                    &format!("{}.{}", name, field.name),
+                    next,
+                    CN_TYPE_FIXED,
+                    DATA_TYPE_UINT_LE,
+                    field.byte_offset,
+                    field.bit_offset,
+                    field.bit_count,
+                    0,
+                )?;
+            }
+            let frame = mf4.write_channel_block(
+                name,
+                [0, next, 0],
+                CN_TYPE_FIXED,
+                DATA_TYPE_BYTES,
+                8,
+                0,
+                (8 + data_bytes) * 8,
+                CN_FLAG_BUS_EVENT,
+            )?;
+            let time = mf4.write_channel_block(
+                "Timestamp",
+                [frame, 0, unit],
+                CN_TYPE_MASTER,
+                DATA_TYPE_FLOAT_LE,
+                0,
+                0,
+                64,
+                0,
+            )?;
+
+            let acquisition = mf4.write_text(name)?;
+            let mut data = Vec::with_capacity(32);
+            data.extend_from_slice(&(index as u64 + 1).to_le_bytes());
+            data.extend_from_slice(&0u64.to_le_bytes());
+            data.extend_from_slice(&(CG_FLAG_BUS_EVENT | CG_FLAG_PLAIN_BUS_EVENT).to_le_bytes());
+            data.extend_from_slice(&(b'.' as u16).to_le_bytes());
+            data.extend_from_slice(&[0; 4]);
+            data.extend_from_slice(&(DATA_BYTES_OFFSET as u32 + data_bytes).to_le_bytes());
+            data.extend_from_slice(&0u32.to_le_bytes());
+            next_group = mf4.write_block(block(
+                b"##CG",
+                &[next_group, time, acquisition, source, 0, 0],
+                &data,
+            ))?;
+            mf4.channel_groups[index] = next_group;
+        }
+
+        // records start with a one byte record id
+        mf4.data_group = mf4.write_block(block(
+            b"##DG",
+            &[0, next_group, 0, 0],
+            &[1, 0, 0, 0, 0, 0, 0, 0],
+        ))?;
+        let header = mf4.header_block(mf4.data_group, history);
+        mf4.patch(mf4.header, &header)?;
+        Ok(mf4)
+    }
+
+    fn header_block(&self, data_group: u64, history: u64) -> Vec<u8> {
+        let start = self.start.map_or(0, nanos_since_epoch);
+        let mut data = start.to_le_bytes().to_vec();
+        data.resize(32, 0);
+        block(b"##HD", &[data_group, history, 0, 0, 0, 0], &data)
+    }
+
+    /// Writes a block at the end of the file, aligned to 8 bytes.
+    fn write_block(&mut self, mut bytes: Vec<u8>) -> Result<u64, Mf4Error> {
+        let offset = self.position;
+        bytes.resize(bytes.len().div_ceil(8) * 8, 0);
+        self.writer.write_all(&bytes)?;
+        self.position += bytes.len() as u64;
+        Ok(offset)
+    }
+
+    fn write_text(&mut self, text: &str) -> Result<u64, Mf4Error> {
+        self.write_block(block(b"##TX", &[], &text_data(text)))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn write_channel(
+        &mut self,
+        name: &str,
+        next: u64,
+        channel_type: u8,
+        data_type: u8,
+        byte_offset: u32,
+        bit_offset: u8,
+        bit_count: u32,
+        flags: u32,
+    ) -> Result<u64, Mf4Error> {
+        self.write_channel_block(
+            name,
+            [next, 0, 0],
+            channel_type,
+            data_type,
+            byte_offset,
+            bit_offset,
+            bit_count,
+            flags,
+        )
+    }
+
+    /// Writes a channel linked to its successor, its first child and its unit.
+    #[allow(clippy::too_many_arguments)]
+    fn write_channel_block(
+        &mut self,
+        name: &str,
+        [next, composition, unit]: [u64; 3],
+        channel_type: u8,
+        data_type: u8,
+        byte_offset: u32,
+        bit_offset: u8,
+        bit_count: u32,
+        flags: u32,
+    ) -> Result<u64, Mf4Error> {
+        let name = self.write_text(name)?;
+        let sync_type = match channel_type {
+            CN_TYPE_MASTER => CN_SYNC_TIME,
+            _ => 0,
+        };
+        let mut data = vec![channel_type, sync_type, data_type, bit_offset];
+        data.extend_from_slice(&byte_offset.to_le_bytes());
+        data.extend_from_slice(&bit_count.to_le_bytes());
+        data.extend_from_slice(&flags.to_le_bytes());
+        data.resize(72, 0);
+        self.write_block(block(
+            b"##CN",
+            &[next, composition, name, 0, 0, 0, unit, 0],
+            &data,
+        ))
+    }
+
+    /// Overwrites bytes of an already written block.
+    fn patch(&mut self, offset: u64, bytes: &[u8]) -> Result<(), Mf4Error> {
+        self.writer.seek(SeekFrom::Start(offset))?;
+        self.writer.write_all(bytes)?;
+        self.writer.seek(SeekFrom::Start(self.position))?;
+        Ok(())
+    }
+
+    /// Writes a record. The first record sets the start time of the file.
+    pub fn write(&mut self, record: &Record) -> Result<(), Mf4Error> {
+        let mut fields = [0u8; DATA_BYTES_OFFSET - 8];
+        let tx = (record.direction == Direction::Tx) as u8;
+        let (group, data) = match &record.event {
+            Event::Can(frame) if frame.is_rtr_frame() => {
+                encode_fields(
+                    &mut fields,
+                    record.bus,
+                    frame.can_id(),
+                    frame.is_extended_frame(),
+                );
+                fields[5] = frame.dlc();
+                fields[7] = tx;
+                (1, &[][..])
+            }
+            Event::Can(frame) => {
+                encode_fields(
+                    &mut fields,
+                    record.bus,
+                    frame.can_id(),
+                    frame.is_extended_frame(),
+                );
+                fields[5] = frame.dlc();
+                fields[6] = frame.data().len() as u8;
+                fields[7] = tx;
+                (0, frame.data())
+            }
+            Event::Fd(frame) => {
+                encode_fields(
+                    &mut fields,
+                    record.bus,
+                    frame.can_id(),
+                    frame.is_extended_frame(),
+                );
+                fields[5] = frame.dlc();
+                fields[6] = frame.data().len() as u8;
+                fields[7] = tx
+                    | 0x02
+                    | (frame.is_brs_frame() as u8) << 2
+                    | (frame.is_esi_frame() as u8) << 3;
+                (0, frame.data())
+            }
+            Event::ErrorFrame(data) => {
+                let data = &data[..data.len().min(8)];
+                fields[0] = record.bus;
+                fields[5] = data.len() as u8;
+                fields[6] = data.len() as u8;
+                fields[7] = tx;
+                (2, data)
+            }
+            _ => return Err(Mf4Error::UnsupportedEvent),
+        };
+
+        let start = *self.start.get_or_insert(record.time);
+        let seconds = match record.time.duration_since(start) {
+            Ok(offset) => offset.as_secs_f64(),
+            Err(err) => -err.duration().as_secs_f64(),
+        };
+
+        let data_bytes = GROUPS[group].2 as usize;
+        self.buffer.push(group as u8 + 1);
+        self.buffer.extend_from_slice(&seconds.to_le_bytes());
+        self.buffer.extend_from_slice(&fields);
+        self.buffer.extend_from_slice(data);
+        self.buffer
+            .resize(self.buffer.len() + data_bytes - data.len(), 0);
+        self.cycle_counts[group] += 1;
+
+        if self.buffer.len() >= self.options.block_size {
+            self.write_data()?;
+        }
+        Ok(())
+    }
+
+    /// Sets the clock giving the [hardware time](crate::tracefile#hardware-time).
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.hardware_time = HardwareTime::new(clock);
+        self
+    }
+
+    /// Writes a classic frame at its [hardware time](crate::tracefile#hardware-time).
+    pub fn write_can(
+        &mut self,
+        frame: &CanFrame,
+        timestamp: &Timestamp,
+        direction: Direction,
+    ) -> Result<(), Mf4Error> {
+        let record = self
+            .hardware_time
+            .record(Event::Can(*frame), timestamp, direction);
+        self.write(&record)
+    }
+
+    /// Writes a CAN FD frame at its [hardware time](crate::tracefile#hardware-time).
+    pub fn write_can_fd(
+        &mut self,
+        frame: &CanFdFrame,
+        timestamp: &Timestamp,
+        direction: Direction,
+    ) -> Result<(), Mf4Error> {
+        let record = self
+            .hardware_time
+            .record(Event::Fd(*frame), timestamp, direction);
+        self.write(&record)
+    }
+
+    /// Writes the collected records as DT or DZ block.
+    fn write_data(&mut self) -> Result<(), Mf4Error> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let bytes = match self.options.compression {
+            true => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(&self.buffer)?;
+                let compressed = encoder.finish()?;
+                let mut data = Vec::with_capacity(24 + compressed.len());
+                // deflate without transposition
+                data.extend_from_slice(b"DT");
+                data.extend_from_slice(&[0, 0]);
+                data.extend_from_slice(&0u32.to_le_bytes());
+                data.extend_from_slice(&(self.buffer.len() as u64).to_le_bytes());
+                data.extend_from_slice(&(compressed.len() as u64).to_le_bytes());
+                data.extend(compressed);
+                block(b"##DZ", &[], &data)
+            }
+            false => block(b"##DT", &[], &self.buffer),
+        };
+        let offset = self.write_block(bytes)?;
+        self.blocks.push((offset, self.data_length));
+        self.data_length += self.buffer.len() as u64;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// Writes the collected records, links the data blocks and finalizes the file. Records may
+    /// still be written afterwards.
+    pub fn finish(&mut self) -> Result<(), Mf4Error> {
+        self.write_data()?;
+
+        let data = match self.blocks.len() {
+            0 => 0,
+            1 => self.blocks[0].0,
+            count => {
+                let mut links = vec![0];
+                let mut data = Vec::with_capacity(8 + 8 * count);
+                data.extend_from_slice(&[0; 4]);
+                data.extend_from_slice(&(count as u32).to_le_bytes());
+                for (offset, data_offset) in &self.blocks {
+                    links.push(*offset);
+                    data.extend_from_slice(&data_offset.to_le_bytes());
+                }
+                let list = self.write_block(block(b"##DL", &links, &data))?;
+                match self.options.compression {
+                    true => self.write_block(block(b"##HL", &[list], &[0; 8]))?,
+                    false => list,
+                }
+            }
+        };
+
+        self.patch(self.data_group + 40, &data.to_le_bytes())?;
+        for (group, count) in self.channel_groups.into_iter().zip(self.cycle_counts) {
+            self.patch(group + 80, &count.to_le_bytes())?;
+        }
+        let start = self.start.map_or(0, nanos_since_epoch);
+        self.patch(self.header + 72, &start.to_le_bytes())?;
+        self.patch(0, ID_FINALIZED)?;
+        self.patch(60, &0u16.to_le_bytes())?;
+        Ok(self.writer.flush()?)
+    }
+}
+
+impl<W: Write + Seek> Drop for Mf4Writer<W> {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+/// Stores the bus channel and identifier of a record.
+fn encode_fields(fields: &mut [u8], bus: u8, id: u32, extended: bool) {
+    fields[0] = bus;
+    let id = id | (extended as u32) << 31;
+    fields[1..5].copy_from_slice(&id.to_le_bytes());
+}
+
+/* Reading */
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Mf4Header {
+    pub version: u16,
+    pub program: String,
+    pub start_time: SystemTime,
+    /// Whether the writer of the file finished it.
+    pub finalized: bool,
+}
+
+struct RawBlock {
+    id: [u8; 4],
+    links: Vec<u64>,
+    data: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+struct Channel {
+    name: String,
+    channel_type: u8,
+    data_type: u8,
+    bit_offset: u8,
+    byte_offset: u32,
+    bit_count: u32,
+    /// Offset and factor of a linear conversion.
+    conversion: Option<(f64, f64)>,
+    data: u64,
+}
+
+impl Channel {
+    fn bytes<'a>(&self, record: &'a [u8]) -> Option<&'a [u8]> {
+        let start = self.byte_offset as usize;
+        record.get(start..start + (self.bit_count as usize).div_ceil(8))
+    }
+
+    fn uint(&self, record: &[u8]) -> Option<u64> {
+        let bits = self.bit_offset as u32 + self.bit_count;
+        let start = self.byte_offset as usize;
+        let bytes = record.get(start..start + (bits as usize).div_ceil(8).min(8))?;
+        let raw = match self.data_type {
+            DATA_TYPE_UINT_BE | DATA_TYPE_INT_BE => bytes
+                .iter()
+                .fold(0u64, |value, byte| value << 8 | *byte as u64),
+            _ => bytes
+                .iter()
+                .rev()
+                .fold(0u64, |value, byte| value << 8 | *byte as u64),
+        };
+        let value = raw >> self.bit_offset;
+        match self.bit_count {
+            64.. => Some(value),
+            bits => Some(value & ((1 << bits) - 1)),
+        }
+    }
+
+    fn float(&self, record: &[u8]) -> Option<f64> {
+        let value = match (self.data_type, self.bit_count) {
+            (DATA_TYPE_FLOAT_LE, 64) => f64::from_le_bytes(self.bytes(record)?.try_into().ok()?),
+            (DATA_TYPE_FLOAT_BE, 64) => f64::from_be_bytes(self.bytes(record)?.try_into().ok()?),
+            (DATA_TYPE_FLOAT_LE, 32) => {
+                f32::from_le_bytes(self.bytes(record)?.try_into().ok()?) as f64
+            }
+            (DATA_TYPE_FLOAT_BE, 32) => {
+                f32::from_be_bytes(self.bytes(record)?.try_into().ok()?) as f64
+            }
+            (DATA_TYPE_INT_LE | DATA_TYPE_INT_BE, bits @ 1..=64) => {
+                let shift = 64 - bits;
+                ((self.uint(record)? << shift) as i64 >> shift) as f64
+            }
+            _ => self.uint(record)? as f64,
+        };
+        Some(match self.conversion {
+            Some((offset, factor)) => offset + factor * value,
+            None => value,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+enum FrameType {
+    Data,
+    Remote,
+    Error,
+}
+
+/// The channels of a bus logging channel group.
+#[derive(Default)]
+struct BusGroup {
+    frame_type: Option<FrameType>,
+    time: Option<Channel>,
+    bus: Option<Channel>,
+    id: Option<Channel>,
+    ide: Option<Channel>,
+    dlc: Option<Channel>,
+    data_length: Option<Channel>,
+    dir: Option<Channel>,
+    edl: Option<Channel>,
+    brs: Option<Channel>,
+    esi: Option<Channel>,
+    data_bytes: Option<Channel>,
+    /// Signal data of variable length data bytes.
+    signal_data: Vec<u8>,
+}
+
+impl BusGroup {
+    fn decode(&self, start: SystemTime, record: &[u8]) -> Option<Record> {
+        let value = |channel: &Option<Channel>| match channel {
+            Some(channel) => channel.uint(record),
+            None => Some(0),
+        };
+        let time = match &self.time {
+            Some(channel) => offset_time(start, channel.float(record)?),
+            None => start,
+        };
+        let bus = match &self.bus {
+            Some(channel) => channel.uint(record)? as u8,
+            None => 1,
+        };
+        let direction = match value(&self.dir)? {
+            0 => Direction::Rx,
+            _ => Direction::Tx,
+        };
+
+        // some writers store the IDE flag in the highest bit of the identifier
+        let raw_id = value(&self.id)? as u32;
+        let extended = value(&self.ide)? != 0 || raw_id & 0x8000_0000 != 0;
+        let msg_type = match extended {
+            true => MessageType::Extended,
+            false => MessageType::Standard,
+        };
+        let id = raw_id & 0x1FFF_FFFF;
+        let dlc = value(&self.dlc)? as u8;
+
+        let data = match &self.data_bytes {
+            Some(channel) if channel.channel_type == CN_TYPE_VLSD => {
+                let offset = channel.uint(record)? as usize;
+                let length = u32_at(&self.signal_data, offset)? as usize;
+                self.signal_data.get(offset + 4..offset + 4 + length)?
+            }
+            Some(channel) => channel.bytes(record)?,
+            None => &[],
+        };
+        let length = match &self.data_length {
+            Some(channel) => channel.uint(record)? as usize,
+            None => fd_dlc_to_length(dlc),
+        };
+        let data = &data[..length.min(data.len())];
+
+        let event = match self.frame_type? {
+            FrameType::Data if value(&self.edl)? != 0 => {
+                let mut frame = CanFdFrame::new(id, msg_type, data).ok()?;
+                frame.set_brs(value(&self.brs)? != 0);
+                frame.set_esi(value(&self.esi)? != 0);
+                Event::Fd(frame)
+            }
+            FrameType::Data => {
+                Event::Can(CanFrame::new(id, msg_type, &data[..data.len().min(8)]).ok()?)
+            }
+            FrameType::Remote => Event::Can(CanFrame::new_rtr(id, msg_type, dlc.min(8)).ok()?),
+            FrameType::Error => Event::ErrorFrame(data.to_vec()),
+        };
+        Some(Record {
+            time,
+            bus,
+            direction,
+            event,
+        })
+    }
+}
+
+enum RecordLayout {
+    Fixed(usize),
+    /// Records of variable length signal data, prefixed by their length.
+    Variable,
+}
+
+enum DataBlock {
+    Plain { offset: u64, length: u64 },
+    Compressed(u64),
+}
+
+/// Position of the reader within the records of a data group.
+struct GroupCursor {
+    record_id_size: usize,
+    groups: HashMap<u64, (RecordLayout, Option<BusGroup>)>,
+    blocks: VecDeque<DataBlock>,
+    buffer: Vec<u8>,
+    position: usize,
+    peeked: Option<Record>,
+    done: bool,
+}
+
+/// Replays the frames of a bus logging file.
+pub struct Mf4Reader<R> {
+    reader: R,
+    length: u64,
+    header: Mf4Header,
+    cursors: Vec<GroupCursor>,
+}
+
+impl Mf4Reader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Mf4Reader<BufReader<File>>, Mf4Error> {
+        Mf4Reader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek> Mf4Reader<R> {
+    /// Reads the structure of the file.
+    pub fn new(mut reader: R) -> Result<Mf4Reader<R>, Mf4Error> {
+        let mut id = [0; ID_BLOCK_SIZE as usize];
+        let length = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(0))?;
+        reader.read_exact(&mut id)?;
+        let finalized = match &id[..8] {
+            id if id == ID_FINALIZED => true,
+            id if id == ID_UNFINALIZED => false,
+            _ => return Err(Mf4Error::InvalidIdentifier),
+        };
+        let version = u16_at(&id, 28).unwrap_or(0);
+        if !(400..500).contains(&version) {
+            return Err(Mf4Error::UnsupportedVersion(version));
+        }
+
+        let mut mf4 = Mf4Reader {
+            reader,
+            length,
+            header: Mf4Header {
+                version,
+                program: String::from_utf8_lossy(&id[16..24]).trim().to_string(),
+                start_time: SystemTime::UNIX_EPOCH,
+                finalized,
+            },
+            cursors: Vec::new(),
+        };
+
+        let header = mf4.read_block(ID_BLOCK_SIZE)?;
+        let invalid = Mf4Error::InvalidBlock {
+            offset: ID_BLOCK_SIZE,
+        };
+        mf4.header.start_time = system_time_from_nanos(u64_at(&header.data, 0).ok_or(invalid)?);
+
+        let mut data_group = header.links.first().copied().unwrap_or(0);
+        let mut visited = HashSet::new();
+        while data_group != 0 {
+            visit(&mut visited, data_group)?;
+            let block = mf4.read_block(data_group)?;
+            let cursor = mf4.read_data_group(data_group, &block)?;
+            mf4.cursors.push(cursor);
+            data_group = block.links[0];
+        }
+        Ok(mf4)
+    }
+
+    pub fn header(&self) -> &Mf4Header {
+        &self.header
+    }
+
+    fn read_block(&mut self, offset: u64) -> Result<RawBlock, Mf4Error> {
+        let invalid = || Mf4Error::InvalidBlock { offset };
+        self.reader.seek(SeekFrom::Start(offset))?;
+        let mut header = [0; BLOCK_HEADER_SIZE];
+        self.reader.read_exact(&mut header)?;
+        let length = u64_at(&header, 8).unwrap_or(0);
+        let links_size = u64_at(&header, 16)
+            .unwrap_or(0)
+            .checked_mul(8)
+            .and_then(|size| size.checked_add(BLOCK_HEADER_SIZE as u64))
+            .ok_or_else(invalid)?;
+        let end = offset.checked_add(length).ok_or_else(invalid)?;
+        if &header[..2] != b"##"
+            || length < links_size
+            || length > MAX_BLOCK_SIZE
+            || end > self.length
+        {
+            return Err(invalid());
+        }
+
+        let mut rest = vec![0; length as usize - BLOCK_HEADER_SIZE];
+        self.reader.read_exact(&mut rest)?;
+        let data = rest.split_off(links_size as usize - BLOCK_HEADER_SIZE);
+        Ok(RawBlock {
+            id: [header[0], header[1], header[2], header[3]],
+            links: rest
+                .chunks(8)
+                .map(|link| u64_at(link, 0).unwrap_or(0))
+                .collect(),
+            data,
+        })
+    }
+
+    fn read_text(&mut self, offset: u64) -> Result<String, Mf4Error> {
+        if offset == 0 {
+            return Ok(String::new());
+        }
+        let block = self.read_block(offset)?;
+        let end = block
+            .data
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(block.data.len());
+        Ok(String::from_utf8_lossy(&block.data[..end]).to_string())
+    }
+
+    fn read_data_group(&mut self, offset: u64, block: &RawBlock) -> Result<GroupCursor, Mf4Error> {
+        let invalid = || Mf4Error::InvalidBlock { offset };
+        if &block.id != b"##DG" || block.links.len() < 3 {
+            return Err(invalid());
+        }
+        let record_id_size = *block.data.first().ok_or_else(invalid)? as usize;
+
+        let mut groups = HashMap::new();
+        let mut channel_group = block.links[1];
+        let mut visited = HashSet::new();
+        while channel_group != 0 {
+            visit(&mut visited, channel_group)?;
+            let cg = self.read_block(channel_group)?;
+            let invalid = Mf4Error::InvalidBlock {
+                offset: channel_group,
+            };
+            if &cg.id != b"##CG" || cg.links.len() < 6 {
+                return Err(invalid);
+            }
+            let record_id = u64_at(&cg.data, 0).ok_or(invalid)?;
+            let flags = u16_at(&cg.data, 16).unwrap_or(0);
+            let layout = match flags & CG_FLAG_VLSD != 0 {
+                true => RecordLayout::Variable,
+                false => RecordLayout::Fixed(
+                    u32_at(&cg.data, 24).unwrap_or(0) as usize
+                        + u32_at(&cg.data, 28).unwrap_or(0) as usize,
+                ),
+            };
+            let acquisition_name = self.read_text(cg.links[2])?;
+
+            let mut channels = Vec::new();
+            self.read_channels(cg.links[1], &mut channels, &mut HashSet::new())?;
+            let bus_group = self.bus_group(&acquisition_name, channels)?;
+            groups.insert(record_id, (layout, bus_group));
+            channel_group = cg.links[0];
+        }
+
+        let mut blocks = VecDeque::new();
+        self.data_blocks(block.links[2], &mut blocks, &mut HashSet::new())?;
+        Ok(GroupCursor {
+            record_id_size,
+            groups,
+            blocks,
+            buffer: Vec::new(),
+            position: 0,
+            peeked: None,
+            done: false,
+        })
+    }
+
+    /// Reads a list of channels including their nested channels.
+    fn read_channels(
+        &mut self,
+        mut offset: u64,
+        channels: &mut Vec<Channel>,
+        visited: &mut HashSet<u64>,
+    ) -> Result<(), Mf4Error> {
+        while offset != 0 {
+            visit(visited, offset)?;
+            let block = self.read_block(offset)?;
+            let invalid = Mf4Error::InvalidBlock { offset };
+            if &block.id != b"##CN" || block.links.len() < 8 || block.data.len() < 12 {
+                return Err(invalid);
+            }
+            let conversion = match block.links[4] {
+                0 => None,
+                conversion => {
+                    let cc = self.read_block(conversion)?;
+                    // only linear conversions are applied
+                    match cc.data.first() {
+                        Some(1) => Some((
+                            f64_at(&cc.data, 24).unwrap_or(0.0),
+                            f64_at(&cc.data, 32).unwrap_or(1.0),
+                        )),
+                        _ => None,
+                    }
+                }
+            };
+            channels.push(Channel {
+                name: self.read_text(block.links[2])?,
+                channel_type: block.data[0],
+                data_type: block.data[2],
+                bit_offset: block.data[3],
+                byte_offset: u32_at(&block.data, 4).unwrap_or(0),
+                bit_count: u32_at(&block.data, 8).unwrap_or(0),
+                conversion,
+                data: block.links[5],
+            });
+
+            if block.links[1] != 0 && &self.read_block(block.links[1])?.id == b"##CN" {
+                self.read_channels(block.links[1], channels, visited)?;
+            }
+            offset = block.links[0];
+        }
+        Ok(())
+    }
+
+    /// Sorts the channels of a bus logging group, `None` for other groups.
+    fn bus_group(
+        &mut self,
+        acquisition_name: &str,
+        channels: Vec<Channel>,
+    ) -> Result<Option<BusGroup>, Mf4Error> {
+        let mut group = BusGroup::default();
+        for channel in channels {
+            let (prefix, suffix) = channel.name.rsplit_once('.').unwrap_or(("", &channel.name));
+            let frame_type = match prefix.rsplit('.').next().unwrap_or(prefix) {
+                "CAN_DataFrame" => Some(FrameType::Data),
+                "CAN_RemoteFrame" => Some(FrameType::Remote),
+                "CAN_ErrorFrame" => Some(FrameType::Error),
+                _ => None,
+            };
+            if channel.channel_type == CN_TYPE_MASTER {
+                group.time = Some(channel);
+                continue;
+            }
+            if frame_type.is_none() {
+                continue;
+            }
+            group.frame_type = frame_type;
+            match suffix {
+                "BusChannel" => group.bus = Some(channel),
+                "ID" => group.id = Some(channel),
+                "IDE" => group.ide = Some(channel),
+                "DLC" => group.dlc = Some(channel),
+                "DataLength" => group.data_length = Some(channel),
+                "Dir" => group.dir = Some(channel),
+                "EDL" => group.edl = Some(channel),
+                "BRS" => group.brs = Some(channel),
+                "ESI" => group.esi = Some(channel),
+                "DataBytes" => group.data_bytes = Some(channel),
+                _ => {}
+            }
+        }
+
+        if group.frame_type.is_none() {
+            group.frame_type = match acquisition_name {
+                "CAN_DataFrame" => Some(FrameType::Data),
+                "CAN_RemoteFrame" => Some(FrameType::Remote),
+                "CAN_ErrorFrame" => Some(FrameType::Error),
+                _ => return Ok(None),
+            };
+        }
+        if group.id.is_none() && group.frame_type != Some(FrameType::Error) {
+            return Ok(None);
+        }
+
+        if let Some(channel) = &group.data_bytes {
+            if channel.channel_type == CN_TYPE_VLSD && channel.data != 0 {
+                let mut blocks = VecDeque::new();
+                self.data_blocks(channel.data, &mut blocks, &mut HashSet::new())?;
+                while let Some(block) = blocks.pop_front() {
+                    let data = self.load(block, &mut blocks)?;
+                    group.signal_data.extend(data);
+                }
+            }
+        }
+        Ok(Some(group))
+    }
+
+    /// Collects the data blocks of a data section, following DL and HL blocks.
+    fn data_blocks(
+        &mut self,
+        offset: u64,
+        blocks: &mut VecDeque<DataBlock>,
+        visited: &mut HashSet<u64>,
+    ) -> Result<(), Mf4Error> {
+        if offset == 0 {
+            return Ok(());
+        }
+        visit(visited, offset)?;
+        self.reader.seek(SeekFrom::Start(offset))?;
+        let mut header = [0; BLOCK_HEADER_SIZE];
+        self.reader.read_exact(&mut header)?;
+        let length = u64_at(&header, 8).unwrap_or(0);
+        let link_count = u64_at(&header, 16).unwrap_or(0);
+        match &header[..4] {
+            b"##DT" | b"##SD" | b"##RD" => {
+                let data_offset = link_count
+                    .checked_mul(8)
+                    .and_then(|size| size.checked_add(BLOCK_HEADER_SIZE as u64))
+                    .filter(|size| offset.checked_add(*size).is_some())
+                    .ok_or(Mf4Error::InvalidBlock { offset })?;
+                blocks.push_back(DataBlock::Plain {
+                    offset: offset + data_offset,
+                    length: length.saturating_sub(data_offset),
+                });
+            }
+            b"##DZ" => blocks.push_back(DataBlock::Compressed(offset)),
+            b"##DL" => {
+                let mut list = offset;
+                loop {
+                    let block = self.read_block(list)?;
+                    let count = u32_at(&block.data, 4).unwrap_or(0) as usize;
+                    for link in block.links.iter().skip(1).take(count) {
+                        self.data_blocks(*link, blocks, visited)?;
+                    }
+                    list = block.links.first().copied().unwrap_or(0);
+                    if list == 0 {
+                        break;
+                    }
+                    visit(visited, list)?;
+                }
+            }
+            b"##HL" => {
+                let block = self.read_block(offset)?;
+                self.data_blocks(block.links.first().copied().unwrap_or(0), blocks, visited)?;
+            }
+            _ => return Err(Mf4Error::InvalidBlock { offset }),
+        }
+        Ok(())
+    }
+
+    /// Reads the next part of a data block. Uncompressed blocks are read in chunks, the rest of
+    /// the block is put back.
+    fn load(
+        &mut self,
+        block: DataBlock,
+        blocks: &mut VecDeque<DataBlock>,
+    ) -> Result<Vec<u8>, Mf4Error> {
+        match block {
+            DataBlock::Plain { offset, length } => {
+                let size = length.min(CHUNK_SIZE);
+                let mut data = vec![0; size as usize];
+                self.reader.seek(SeekFrom::Start(offset))?;
+                self.reader.read_exact(&mut data)?;
+                if size < length {
+                    blocks.push_front(DataBlock::Plain {
+                        offset: offset + size,
+                        length: length - size,
+                    });
+                }
+                Ok(data)
+            }
+            DataBlock::Compressed(offset) => {
+                let block = self.read_block(offset)?;
+                let invalid = Mf4Error::InvalidBlock { offset };
+                let zip_type = *block.data.get(2).ok_or(invalid)?;
+                let parameter = u32_at(&block.data, 4).unwrap_or(0) as usize;
+                let length = u64_at(&block.data, 8).unwrap_or(0) as usize;
+
+                let mut data = Vec::with_capacity(length.min(MAX_BLOCK_SIZE as usize));
+                ZlibDecoder::new(block.data.get(24..).unwrap_or(&[])).read_to_end(&mut data)?;
+                match zip_type {
+                    0 => Ok(data),
+                    1 => Ok(untranspose(&data, parameter)),
+                    zip_type => Err(Mf4Error::UnsupportedCompression(zip_type)),
+                }
+            }
+        }
+    }
+
+    /// Reads records of a data group until it yields a frame or its data ends.
+    fn advance(&mut self, index: usize) -> Result<(), Mf4Error> {
+        let start = self.header.start_time;
+        loop {
+            let cursor = &mut self.cursors[index];
+            match cursor.next_record(start) {
+                Some(Ok(Some(record))) => {
+                    cursor.peeked = Some(record);
+                    return Ok(());
+                }
+                Some(Ok(None)) => continue,
+                Some(Err(err)) => return Err(err),
+                None => {}
+            }
+
+            let block = match cursor.blocks.pop_front() {
+                Some(block) => block,
+                None => {
+                    cursor.done = true;
+                    return match cursor.position == cursor.buffer.len() {
+                        true => Ok(()),
+                        false => Err(Mf4Error::InvalidRecord),
+                    };
+                }
+            };
+            cursor.buffer.drain(..cursor.position);
+            cursor.position = 0;
+            let mut blocks = std::mem::take(&mut cursor.blocks);
+            let data = self.load(block, &mut blocks);
+            let cursor = &mut self.cursors[index];
+            cursor.blocks = blocks;
+            cursor.buffer.extend(data?);
+        }
+    }
+}
+
+impl GroupCursor {
+    /// Takes the next record of the buffer, `Some(None)` for records of other channel groups and
+    /// `None` if more data is needed.
+    fn next_record(&mut self, start: SystemTime) -> Option<Result<Option<Record>, Mf4Error>> {
+        let remaining = &self.buffer[self.position..];
+        let id_size = self.record_id_size;
+        if remaining.is_empty() || remaining.len() < id_size {
+            return None;
+        }
+        let record_id = remaining[..id_size]
+            .iter()
+            .rev()
+            .fold(0u64, |value, byte| value << 8 | *byte as u64);
+        let (layout, group) = match self.groups.get(&record_id) {
+            Some(group) => group,
+            None => return Some(Err(Mf4Error::InvalidRecord)),
+        };
+        let length = match layout {
+            RecordLayout::Fixed(length) => *length,
+            RecordLayout::Variable => 4 + u32_at(remaining, id_size)? as usize,
+        };
+        let record = remaining.get(id_size..id_size + length)?;
+
+        let decoded = group
+            .as_ref()
+            .map(|group| group.decode(start, record).ok_or(Mf4Error::InvalidRecord));
+        self.position += id_size + length;
+        Some(decoded.transpose())
+    }
+}
+
+/// Reverses the transposition of records of `columns` bytes.
+fn untranspose(data: &[u8], columns: usize) -> Vec<u8> {
+    if columns == 0 {
+        return data.to_vec();
+    }
+    let rows = data.len() / columns;
+    let mut output = vec![0; data.len()];
+    for column in 0..columns {
+        for row in 0..rows {
+            output[row * columns + column] = data[column * rows + row];
+        }
+    }
+    output[rows * columns..].copy_from_slice(&data[rows * columns..]);
+    output
+}
+
+impl<R: Read + Seek> Iterator for Mf4Reader<R> {
+    type Item = Result<Record, Mf4Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for index in 0..self.cursors.len() {
+            let cursor = &self.cursors[index];
+            if cursor.peeked.is_none() && !cursor.done {
+                if let Err(err) = self.advance(index) {
+                    self.cursors[index].done = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+
+        let next = self
+            .cursors
+            .iter_mut()
+            .filter(|cursor| cursor.peeked.is_some())
+            .min_by_key(|cursor| cursor.peeked.as_ref().map(|record| record.time))?;
+        next.peeked.take().map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn sample_records() -> Vec<Record> {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_nanos(1_547_047_550_914_000_123);
+        let mut fd = CanFdFrame::new(0x1ABCDE, MessageType::Extended, &[0x11; 24]).unwrap();
+        fd.set_brs(true);
+        let events = [
+            Event::Can(CanFrame::new(0x123, MessageType::Standard, &[1, 2, 3]).unwrap()),
+            Event::Fd(fd),
+            Event::Can(CanFrame::new_rtr(0x18EFC8FE, MessageType::Extended, 8).unwrap()),
+            Event::ErrorFrame(vec![4, 0, 8, 0, 0]),
+        ];
+        (0..1000)
+            .map(|index| Record {
+                time: start + Duration::from_micros(1_250 * index as u64),
+                bus: 1 + (index % 2) as u8,
+                direction: if index % 3 == 0 {
+                    Direction::Tx
+                } else {
+                    Direction::Rx
+                },
+                event: events[index % events.len()].clone(),
+            })
+            .collect()
+    }
+
+    fn round_trip(options: Mf4Options) {
+        let records = sample_records();
+        let mut file = Cursor::new(Vec::new());
+        {
+            let mut writer = Mf4Writer::new(&mut file, options).unwrap();
+            for record in &records {
+                writer.write(record).unwrap();
+            }
+            assert_eq!(
+                writer.write(&Record {
+                    time: records[0].time,
+                    bus: 1,
+                    direction: Direction::Rx,
+                    event: Event::Text("marker".to_string()),
+                }),
+                Err(Mf4Error::UnsupportedEvent)
+            );
+        }
+
+        let reader = Mf4Reader::new(Cursor::new(file.into_inner())).unwrap();
+        assert_eq!(reader.header().version, 411);
+        assert_eq!(reader.header().start_time, records[0].time);
+        assert!(reader.header().finalized);
+        let read = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(read, records);
+    }
+
+    #[test]
+    fn mf4_writer_001() {
+        round_trip(Mf4Options::new());
+    }
+
+    #[test]
+    fn mf4_writer_002() {
+        // several compressed blocks linked by a data list
+        round_trip(
+            Mf4Options::new()
+                .with_compression(true)
+                .with_block_size(4096),
+        );
+        round_trip(Mf4Options::new().with_block_size(1000));
+    }
+
+    #[test]
+    fn mf4_reader_001() {
+        assert_eq!(
+            Mf4Reader::new(Cursor::new(vec![0; 64])).err(),
+            Some(Mf4Error::InvalidIdentifier)
+        );
+        let mut id = b"MDF     3.30    ".to_vec();
+        id.resize(64, 0);
+        id[28..30].copy_from_slice(&330u16.to_le_bytes());
+        assert_eq!(
+            Mf4Reader::new(Cursor::new(id)).err(),
+            Some(Mf4Error::UnsupportedVersion(330))
+        );
+    }
+
+    fn link_at(file: &[u8], offset: u64, index: usize) -> u64 {
+        u64_at(file, offset as usize + BLOCK_HEADER_SIZE + index * 8).unwrap()
+    }
+
+    fn set_link(file: &mut [u8], offset: u64, index: usize, link: u64) {
+        let at = offset as usize + BLOCK_HEADER_SIZE + index * 8;
+        file[at..at + 8].copy_from_slice(&link.to_le_bytes());
+    }
+
+    fn sample_file() -> Vec<u8> {
+        let mut file = Cursor::new(Vec::new());
+        {
+            let mut writer = Mf4Writer::new(&mut file, Mf4Options::new()).unwrap();
+            for record in sample_records().iter().take(10) {
+                writer.write(record).unwrap();
+            }
+        }
+        file.into_inner()
+    }
+
+    #[test]
+    fn mf4_reader_002() {
+        // block lengths past the end of the file or of absurd size
+        for length in [u64::MAX, 1 << 40, 1 << 20] {
+            let mut file = sample_file();
+            let at = ID_BLOCK_SIZE as usize + 8;
+            file[at..at + 8].copy_from_slice(&length.to_le_bytes());
+            assert_eq!(
+                Mf4Reader::new(Cursor::new(file)).err(),
+                Some(Mf4Error::InvalidBlock {
+                    offset: ID_BLOCK_SIZE
+                })
+            );
+        }
+        let mut file = sample_file();
+        let at = ID_BLOCK_SIZE as usize + 16;
+        file[at..at + 8].copy_from_slice(&(u64::MAX / 4).to_le_bytes());
+        assert_eq!(
+            Mf4Reader::new(Cursor::new(file)).err(),
+            Some(Mf4Error::InvalidBlock {
+                offset: ID_BLOCK_SIZE
+            })
+        );
+    }
+
+    #[test]
+    fn mf4_reader_003() {
+        // a data group linking to itself
+        let mut file = sample_file();
+        let data_group = link_at(&file, ID_BLOCK_SIZE, 0);
+        set_link(&mut file, data_group, 0, data_group);
+        assert_eq!(
+            Mf4Reader::new(Cursor::new(file)).err(),
+            Some(Mf4Error::InvalidBlock { offset: data_group })
+        );
+
+        // a channel linking to itself
+        let mut file = sample_file();
+        let channel_group = link_at(&file, data_group, 1);
+        let channel = link_at(&file, channel_group, 1);
+        set_link(&mut file, channel, 0, channel);
+        assert_eq!(
+            Mf4Reader::new(Cursor::new(file)).err(),
+            Some(Mf4Error::InvalidBlock { offset: channel })
+        );
+    }
+
+    #[test]
+    fn untranspose_001() {
+        // two records of three bytes transposed, followed by a remainder
+        let transposed = [1, 4, 2, 5, 3, 6, 7];
+        assert_eq!(untranspose(&transposed, 3), vec![1, 2, 3, 4, 5, 6, 7]);
+    }
+}
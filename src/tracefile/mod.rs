@@ -0,0 +1,166 @@
+//! Reading and writing of trace files.
+//!
+//! All formats share [Record], a received or transmitted frame or bus event with its absolute
+//! time.
+//!
+//! # Hardware time
+//!
+//! The writers take frames as returned by [RecvCan::recv](crate::socket::RecvCan::recv) and
+//! [RecvCanFd::recv_fd](crate::socket::RecvCanFd::recv_fd) with `write_can` and `write_can_fd`,
+//! recording them on bus 1. A [Clock] observing each frame as it is written maps the timestamps
+//! to absolute times. It is anchored when the writer is created unless another clock, e.g. one
+//! anchored at the opening of the socket, is passed to `with_clock`.
+
+pub mod asc;
+pub mod blf;
+pub mod candump;
+pub mod columns;
+pub mod csv;
+pub mod jsonl;
+pub mod mf4;
+pub mod pcap;
+pub mod trc;
+
+use crate::socket::clock::{Anchor, Clock};
+use crate::socket::{CanFdFrame, CanFrame, Timestamp};
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Direction {
+    Rx,
+    Tx,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Event {
+    /// A classic data or remote frame.
+    Can(CanFrame),
+    Fd(CanFdFrame),
+    /// An error frame with the error information recorded by the hardware.
+    ErrorFrame(Vec<u8>),
+    /// A change of the error counters.
+    ErrorCounters {
+        rx: u8,
+        tx: u8,
+    },
+    /// A change of the bus status, as PCAN status code.
+    Status(u32),
+    /// A text inserted by the user or the recording software.
+    Text(String),
+}
+
+/// A single entry of a trace.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Record {
+    pub time: SystemTime,
+    /// Bus the entry belongs to, counting from 1.
+    pub bus: u8,
+    pub direction: Direction,
+    pub event: Event,
+}
+
+/* Hardware time */
+
+/// The [hardware time](self#hardware-time) of the frames of a writer.
+#[derive(Debug)]
+pub(crate) struct HardwareTime {
+    clock: Clock,
+}
+
+impl HardwareTime {
+    pub(crate) fn new(clock: Clock) -> HardwareTime {
+        HardwareTime { clock }
+    }
+
+    pub(crate) fn record(
+        &mut self,
+        event: Event,
+        timestamp: &Timestamp,
+        direction: Direction,
+    ) -> Record {
+        self.clock.observe(timestamp);
+        let time = self
+            .clock
+            .to_system_time(timestamp)
+            .unwrap_or_else(|| self.clock.anchor().system_time());
+        Record {
+            time,
+            bus: 1,
+            direction,
+            event,
+        }
+    }
+}
+
+impl Default for HardwareTime {
+    fn default() -> Self {
+        HardwareTime::new(Clock::new(Anchor::now()))
+    }
+}
+
+/* Calendar */
+
+/// Days since 1970-01-01 of a date of the proleptic Gregorian calendar.
+pub(crate) fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Converts a date and a time of day into a point in time, treating it as UTC. Years are
+/// limited to four digits.
+pub(crate) fn from_civil(
+    year: i64,
+    month: u32,
+    day: u32,
+    seconds_of_day: u64,
+    micros: u32,
+) -> Option<SystemTime> {
+    if !(0..=9999).contains(&year)
+        || !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || micros >= 1_000_000
+    {
+        return None;
+    }
+    let seconds = (days_from_civil(year, month, day) * 86_400)
+        .checked_add(i64::try_from(seconds_of_day).ok()?)?;
+    let seconds = u64::try_from(seconds).ok()?;
+    SystemTime::UNIX_EPOCH.checked_add(Duration::new(seconds, micros * 1000))
+}
+
+/// Splits a point in time into year, month, day, seconds of the day and microseconds, as UTC.
+/// Times before the Unix epoch are clamped to it.
+pub(crate) fn to_civil(time: SystemTime) -> (i64, u32, u32, u64, u32) {
+    let since_epoch = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO);
+    let seconds = since_epoch.as_secs();
+
+    let days = (seconds / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    (
+        year,
+        month,
+        day,
+        seconds % 86_400,
+        since_epoch.subsec_micros(),
+    )
+}
@@ -0,0 +1,441 @@
+//! Packet captures (`.pcap`, `.pcapng`) for Wireshark.
+//!
+//! [CaptureWriter] stores frames as `LINKTYPE_CAN_SOCKETCAN` packets, the layout of SocketCAN
+//! frames with the identifier in network byte order. Error frames recorded by PCAN hardware are
+//! translated into SocketCAN error frames. Captures are written as a stream, so they can be fed
+//! into a running Wireshark, e.g. through a named pipe created with `mkfifo` and opened with
+//! [open_pipe](CaptureWriter::open_pipe) while Wireshark listens with `-k -i <pipe>`, or through
+//! standard output with `-k -i -`.
+
+use crate::socket::clock::Clock;
+use crate::socket::{CanFdFrame, CanFrame, Timestamp};
+use crate::tracefile::{Direction, Event, HardwareTime, Record};
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+pub const LINKTYPE_CAN_SOCKETCAN: u16 = 227;
+const SNAPLEN: u32 = 262_144;
+
+/* SocketCAN */
+
+const CAN_EFF_FLAG: u32 = 0x8000_0000;
+const CAN_RTR_FLAG: u32 = 0x4000_0000;
+const CAN_ERR_FLAG: u32 = 0x2000_0000;
+const CAN_ERR_PROT: u32 = 0x0000_0008;
+const CAN_ERR_CNT: u32 = 0x0000_0200;
+const CAN_ERR_PROT_TX: u8 = 0x80;
+const CANFD_BRS: u8 = 0x01;
+const CANFD_ESI: u8 = 0x02;
+const CANFD_FDF: u8 = 0x04;
+/// Direction bit of the error code capture register.
+const ECC_DIR: u8 = 0x20;
+const ECC_SEG: u8 = 0x1F;
+
+/* pcapng */
+
+const SECTION_HEADER_BLOCK: u32 = 0x0A0D_0D0A;
+const INTERFACE_DESCRIPTION_BLOCK: u32 = 0x0000_0001;
+const ENHANCED_PACKET_BLOCK: u32 = 0x0000_0006;
+const OPT_IF_NAME: u16 = 2;
+const OPT_EPB_FLAGS: u16 = 2;
+
+#[derive(Debug, PartialEq)]
+pub enum PcapError {
+    Io(std::io::ErrorKind),
+    /// The event has no SocketCAN representation.
+    UnsupportedEvent,
+}
+
+impl From<std::io::Error> for PcapError {
+    fn from(value: std::io::Error) -> Self {
+        PcapError::Io(value.kind())
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum CaptureFormat {
+    /// Classic pcap with microsecond timestamps, without buses and directions.
+    Pcap,
+    /// pcapng with an interface per bus and the direction of each packet.
+    Pcapng,
+}
+
+/// Converts an event into a SocketCAN frame of 16 bytes, or 72 bytes for CAN FD.
+fn socketcan_frame(event: &Event) -> Result<Vec<u8>, PcapError> {
+    let (can_id, length, flags, data, size) = match event {
+        Event::Can(frame) if frame.is_error_frame() => {
+            return socketcan_frame(&Event::ErrorFrame(frame.data().to_vec()))
+        }
+        Event::Can(frame) => {
+            let mut can_id = frame.can_id();
+            if frame.is_extended_frame() {
+                can_id |= CAN_EFF_FLAG;
+            }
+            let data = match frame.is_rtr_frame() {
+                true => Vec::new(),
+                false => frame.data().to_vec(),
+            };
+            if frame.is_rtr_frame() {
+                can_id |= CAN_RTR_FLAG;
+            }
+            (can_id, frame.dlc(), 0, data, 8)
+        }
+        Event::Fd(frame) => {
+            let mut can_id = frame.can_id();
+            if frame.is_extended_frame() {
+                can_id |= CAN_EFF_FLAG;
+            }
+            let mut flags = CANFD_FDF;
+            if frame.is_brs_frame() {
+                flags |= CANFD_BRS;
+            }
+            if frame.is_esi_frame() {
+                flags |= CANFD_ESI;
+            }
+            (
+                can_id,
+                frame.data().len() as u8,
+                flags,
+                frame.data().to_vec(),
+                64,
+            )
+        }
+        Event::ErrorFrame(info) => {
+            // error type, direction, error code capture, receive and transmit counter
+            let mut info = info.clone();
+            info.resize(5, 0);
+            let mut data = vec![0; 8];
+            data[2] = info[0] & 0x07;
+            if info[2] & ECC_DIR == 0 {
+                data[2] |= CAN_ERR_PROT_TX;
+            }
+            data[3] = info[2] & ECC_SEG;
+            data[6] = info[4];
+            data[7] = info[3];
+            (CAN_ERR_FLAG | CAN_ERR_PROT | CAN_ERR_CNT, 8, 0, data, 8)
+        }
+        _ => return Err(PcapError::UnsupportedEvent),
+    };
+
+    let mut bytes = Vec::with_capacity(8 + size);
+    bytes.extend_from_slice(&can_id.to_be_bytes());
+    bytes.extend_from_slice(&[length, flags, 0, 0]);
+    bytes.extend_from_slice(&data);
+    bytes.resize(8 + size, 0);
+    Ok(bytes)
+}
+
+/// Appends a pcapng option, padded to 4 bytes.
+fn push_option(bytes: &mut Vec<u8>, code: u16, value: &[u8]) {
+    bytes.extend_from_slice(&code.to_le_bytes());
+    bytes.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    bytes.extend_from_slice(value);
+    bytes.resize(bytes.len().div_ceil(4) * 4, 0);
+}
+
+/// Frames the body of a pcapng block with its type and total length.
+fn pcapng_block(block_type: u32, mut body: Vec<u8>) -> Vec<u8> {
+    body.resize(body.len().div_ceil(4) * 4, 0);
+    let length = (body.len() + 12) as u32;
+    let mut bytes = Vec::with_capacity(length as usize);
+    bytes.extend_from_slice(&block_type.to_le_bytes());
+    bytes.extend_from_slice(&length.to_le_bytes());
+    bytes.extend(body);
+    bytes.extend_from_slice(&length.to_le_bytes());
+    bytes
+}
+
+/* CaptureWriter */
+
+/// Writes frames into a pcap or pcapng capture.
+pub struct CaptureWriter<W: Write> {
+    writer: W,
+    format: CaptureFormat,
+    /// Buses described so far, the index is the pcapng interface id.
+    interfaces: Vec<u8>,
+    hardware_time: HardwareTime,
+}
+
+impl CaptureWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        format: CaptureFormat,
+    ) -> Result<CaptureWriter<BufWriter<File>>, PcapError> {
+        CaptureWriter::new(BufWriter::new(File::create(path)?), format)
+    }
+}
+
+impl CaptureWriter<File> {
+    /// Opens an existing named pipe for a live capture. Every packet is passed on as soon as it
+    /// is written. Opening blocks until the reading side of the pipe is opened.
+    pub fn open_pipe<P: AsRef<Path>>(
+        path: P,
+        format: CaptureFormat,
+    ) -> Result<CaptureWriter<File>, PcapError> {
+        CaptureWriter::new(OpenOptions::new().write(true).open(path)?, format)
+    }
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Writes the header of the capture.
+    pub fn new(mut writer: W, format: CaptureFormat) -> Result<CaptureWriter<W>, PcapError> {
+        let mut header = Vec::new();
+        match format {
+            CaptureFormat::Pcap => {
+                header.extend_from_slice(&0xA1B2_C3D4u32.to_le_bytes());
+                header.extend_from_slice(&2u16.to_le_bytes());
+                header.extend_from_slice(&4u16.to_le_bytes());
+                // time zone and accuracy
+                header.extend_from_slice(&[0; 8]);
+                header.extend_from_slice(&SNAPLEN.to_le_bytes());
+                header.extend_from_slice(&(LINKTYPE_CAN_SOCKETCAN as u32).to_le_bytes());
+            }
+            CaptureFormat::Pcapng => {
+                let mut body = Vec::new();
+                body.extend_from_slice(&0x1A2B_3C4Du32.to_le_bytes());
+                body.extend_from_slice(&1u16.to_le_bytes());
+                body.extend_from_slice(&0u16.to_le_bytes());
+                // unknown section length
+                body.extend_from_slice(&(-1i64).to_le_bytes());
+                header = pcapng_block(SECTION_HEADER_BLOCK, body);
+            }
+        }
+        writer.write_all(&header)?;
+        writer.flush()?;
+
+        Ok(CaptureWriter {
+            writer,
+            format,
+            interfaces: Vec::new(),
+            hardware_time: HardwareTime::default(),
+        })
+    }
+
+    /// Interface id of a bus, describing the interface on first use.
+    fn interface(&mut self, bus: u8) -> Result<u32, PcapError> {
+        if let Some(index) = self.interfaces.iter().position(|known| *known == bus) {
+            return Ok(index as u32);
+        }
+
+        let mut body = Vec::new();
+        body.extend_from_slice(&LINKTYPE_CAN_SOCKETCAN.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        body.extend_from_slice(&SNAPLEN.to_le_bytes());
+        let name = format!("can{}", bus.saturating_sub(1));
+        push_option(&mut body, OPT_IF_NAME, name.as_bytes());
+        push_option(&mut body, 0, &[]);
+        self.writer
+            .write_all(&pcapng_block(INTERFACE_DESCRIPTION_BLOCK, body))?;
+
+        self.interfaces.push(bus);
+        Ok(self.interfaces.len() as u32 - 1)
+    }
+
+    pub fn write(&mut self, record: &Record) -> Result<(), PcapError> {
+        let frame = socketcan_frame(&record.event)?;
+        let micros = record
+            .time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO)
+            .as_micros() as u64;
+
+        let packet = match self.format {
+            CaptureFormat::Pcap => {
+                let mut packet = Vec::with_capacity(16 + frame.len());
+                packet.extend_from_slice(&((micros / 1_000_000) as u32).to_le_bytes());
+                packet.extend_from_slice(&((micros % 1_000_000) as u32).to_le_bytes());
+                packet.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+                packet.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+                packet.extend(frame);
+                packet
+            }
+            CaptureFormat::Pcapng => {
+                let interface = self.interface(record.bus)?;
+                let mut body = Vec::with_capacity(32 + frame.len());
+                body.extend_from_slice(&interface.to_le_bytes());
+                body.extend_from_slice(&((micros >> 32) as u32).to_le_bytes());
+                body.extend_from_slice(&(micros as u32).to_le_bytes());
+                body.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+                body.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+                body.extend(frame);
+                body.resize(body.len().div_ceil(4) * 4, 0);
+                let direction: u32 = match record.direction {
+                    Direction::Rx => 1,
+                    Direction::Tx => 2,
+                };
+                push_option(&mut body, OPT_EPB_FLAGS, &direction.to_le_bytes());
+                push_option(&mut body, 0, &[]);
+                pcapng_block(ENHANCED_PACKET_BLOCK, body)
+            }
+        };
+        self.writer.write_all(&packet)?;
+        Ok(())
+    }
+
+    /// Sets the clock giving the [hardware time](crate::tracefile#hardware-time).
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.hardware_time = HardwareTime::new(clock);
+        self
+    }
+
+    /// Writes a classic frame at its [hardware time](crate::tracefile#hardware-time).
+    pub fn write_can(
+        &mut self,
+        frame: &CanFrame,
+        timestamp: &Timestamp,
+        direction: Direction,
+    ) -> Result<(), PcapError> {
+        let record = self
+            .hardware_time
+            .record(Event::Can(*frame), timestamp, direction);
+        self.write(&record)
+    }
+
+    /// Writes a CAN FD frame at its [hardware time](crate::tracefile#hardware-time).
+    pub fn write_can_fd(
+        &mut self,
+        frame: &CanFdFrame,
+        timestamp: &Timestamp,
+        direction: Direction,
+    ) -> Result<(), PcapError> {
+        let record = self
+            .hardware_time
+            .record(Event::Fd(*frame), timestamp, direction);
+        self.write(&record)
+    }
+
+    pub fn flush(&mut self) -> Result<(), PcapError> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::socket::MessageType;
+
+    fn time() -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_micros(1_700_000_000_123_456)
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn socketcan_frame_001() {
+        let frame = CanFrame::new(0x18EFC8FE, MessageType::Extended, &[1, 2, 3]).unwrap();
+        assert_eq!(
+            socketcan_frame(&Event::Can(frame)).unwrap(),
+            vec![0x98, 0xEF, 0xC8, 0xFE, 3, 0, 0, 0, 1, 2, 3, 0, 0, 0, 0, 0]
+        );
+
+        let frame = CanFrame::new_rtr(0x123, MessageType::Standard, 2).unwrap();
+        assert_eq!(
+            socketcan_frame(&Event::Can(frame)).unwrap(),
+            vec![0x40, 0, 0x01, 0x23, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+
+        let mut frame = CanFdFrame::new(0x123, MessageType::Standard, &[0xAA; 12]).unwrap();
+        frame.set_brs(true);
+        let bytes = socketcan_frame(&Event::Fd(frame)).unwrap();
+        assert_eq!(bytes.len(), 72);
+        assert_eq!(
+            &bytes[..8],
+            &[0, 0, 0x01, 0x23, 12, CANFD_FDF | CANFD_BRS, 0, 0]
+        );
+        assert_eq!(&bytes[8..20], &[0xAA; 12]);
+
+        // stuff error while receiving in the data field, 128 receive errors
+        let bytes = socketcan_frame(&Event::ErrorFrame(vec![4, 1, 0x2A, 128, 0])).unwrap();
+        assert_eq!(
+            bytes,
+            vec![0x20, 0, 0x02, 0x08, 8, 0, 0, 0, 0, 0, 0x04, 0x0A, 0, 0, 0, 128]
+        );
+
+        assert_eq!(
+            socketcan_frame(&Event::Status(0)),
+            Err(PcapError::UnsupportedEvent)
+        );
+    }
+
+    #[test]
+    fn pcap_writer_001() {
+        let frame = CanFrame::new(0x123, MessageType::Standard, &[1, 2]).unwrap();
+        let mut buffer = Vec::new();
+        let mut writer = CaptureWriter::new(&mut buffer, CaptureFormat::Pcap).unwrap();
+        writer
+            .write(&Record {
+                time: time(),
+                bus: 1,
+                direction: Direction::Rx,
+                event: Event::Can(frame),
+            })
+            .unwrap();
+
+        assert_eq!(buffer.len(), 24 + 16 + 16);
+        assert_eq!(u32_at(&buffer, 0), 0xA1B2_C3D4);
+        assert_eq!(u32_at(&buffer, 20), LINKTYPE_CAN_SOCKETCAN as u32);
+        assert_eq!(u32_at(&buffer, 24), 1_700_000_000);
+        assert_eq!(u32_at(&buffer, 28), 123_456);
+        assert_eq!(u32_at(&buffer, 32), 16);
+        assert_eq!(&buffer[40..46], &[0, 0, 0x01, 0x23, 2, 0]);
+    }
+
+    #[test]
+    fn pcapng_writer_001() {
+        let frame = CanFrame::new(0x123, MessageType::Standard, &[1, 2]).unwrap();
+        let mut buffer = Vec::new();
+        let mut writer = CaptureWriter::new(&mut buffer, CaptureFormat::Pcapng).unwrap();
+        for (bus, direction) in [(1, Direction::Rx), (2, Direction::Tx), (1, Direction::Tx)] {
+            writer
+                .write(&Record {
+                    time: time(),
+                    bus,
+                    direction,
+                    event: Event::Can(frame),
+                })
+                .unwrap();
+        }
+
+        // walk the blocks, checking both length fields
+        let mut blocks = Vec::new();
+        let mut offset = 0;
+        while offset < buffer.len() {
+            let block_type = u32_at(&buffer, offset);
+            let length = u32_at(&buffer, offset + 4) as usize;
+            assert_eq!(length % 4, 0);
+            assert_eq!(u32_at(&buffer, offset + length - 4) as usize, length);
+            blocks.push((block_type, offset));
+            offset += length;
+        }
+        assert_eq!(offset, buffer.len());
+        let types = blocks.iter().map(|(block_type, _)| *block_type);
+        assert_eq!(
+            types.collect::<Vec<_>>(),
+            vec![
+                SECTION_HEADER_BLOCK,
+                INTERFACE_DESCRIPTION_BLOCK,
+                ENHANCED_PACKET_BLOCK,
+                INTERFACE_DESCRIPTION_BLOCK,
+                ENHANCED_PACKET_BLOCK,
+                ENHANCED_PACKET_BLOCK
+            ]
+        );
+
+        // interface, timestamp, lengths, packet and direction flags
+        let micros = 1_700_000_000_123_456u64;
+        for (index, interface, flags) in [(2, 0, 1), (4, 1, 2), (5, 0, 2)] {
+            let block = blocks[index].1;
+            assert_eq!(u32_at(&buffer, block + 8), interface);
+            assert_eq!(u32_at(&buffer, block + 12), (micros >> 32) as u32);
+            assert_eq!(u32_at(&buffer, block + 16), micros as u32);
+            assert_eq!(u32_at(&buffer, block + 20), 16);
+            assert_eq!(&buffer[block + 28..block + 34], &[0, 0, 0x01, 0x23, 2, 0]);
+            assert_eq!(u32_at(&buffer, block + 44), 0x0004_0002);
+            assert_eq!(u32_at(&buffer, block + 48), flags);
+        }
+    }
+}
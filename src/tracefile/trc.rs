@@ -0,0 +1,1280 @@
+//! PEAK trace files (`.trc`).
+//!
+//! [TrcReader] streams the entries of the formats 1.0 to 2.1 written by PCAN-View and by the
+//! trace of the driver (see [SetTraceStatus](crate::trace::SetTraceStatus)). The files store the
+//! local time of the recording computer, which is converted as if it were UTC. [TrcWriter]
+//! records traffic in format 2.1 or 1.1 without the driver, splitting it into several files like
+//! the driver does for the [TraceFile] modes.
+
+use crate::socket::clock::Clock;
+use crate::socket::{CanFdFrame, CanFrame, MessageType, Timestamp};
+use crate::trace::TraceFile;
+use crate::tracefile::{from_civil, to_civil, Direction, Event, HardwareTime, Record};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, PartialEq)]
+pub enum TrcError {
+    Io(std::io::ErrorKind),
+    /// A line of the file could not be parsed.
+    Syntax {
+        line: usize,
+    },
+    UnsupportedVersion(String),
+    /// The event cannot be stored in the selected format.
+    UnsupportedEvent,
+    /// The single trace file reached its maximum size or duration.
+    Full,
+}
+
+impl From<std::io::Error> for TrcError {
+    fn from(value: std::io::Error) -> Self {
+        TrcError::Io(value.kind())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+pub enum TrcVersion {
+    V1_0,
+    V1_1,
+    V1_2,
+    V1_3,
+    V2_0,
+    V2_1,
+}
+
+impl TrcVersion {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrcVersion::V1_0 => "1.0",
+            TrcVersion::V1_1 => "1.1",
+            TrcVersion::V1_2 => "1.2",
+            TrcVersion::V1_3 => "1.3",
+            TrcVersion::V2_0 => "2.0",
+            TrcVersion::V2_1 => "2.1",
+        }
+    }
+
+    fn parse(value: &str) -> Option<TrcVersion> {
+        match value.trim() {
+            "1.0" => Some(TrcVersion::V1_0),
+            "1.1" => Some(TrcVersion::V1_1),
+            "1.2" => Some(TrcVersion::V1_2),
+            "1.3" => Some(TrcVersion::V1_3),
+            "2.0" => Some(TrcVersion::V2_0),
+            "2.1" => Some(TrcVersion::V2_1),
+            _ => None,
+        }
+    }
+
+    /// Columns of version 2 files without a `$COLUMNS` line.
+    fn default_columns(&self) -> Vec<char> {
+        match self {
+            TrcVersion::V2_1 => vec!['N', 'O', 'T', 'B', 'I', 'd', 'R', 'L', 'D'],
+            _ => vec!['N', 'O', 'T', 'I', 'd', 'l', 'D'],
+        }
+    }
+}
+
+/// A bus of the connection table in the header.
+#[derive(Debug, PartialEq, Clone)]
+pub struct BusInfo {
+    pub bus: u8,
+    pub name: String,
+    pub connection: String,
+    /// Protocol and bit rates as written by the recording software.
+    pub details: String,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct TrcHeader {
+    pub version: TrcVersion,
+    pub start_time: Option<SystemTime>,
+    /// Columns of version 2 files, see the `$COLUMNS` line. Empty for version 1 files.
+    pub columns: Vec<char>,
+    pub buses: Vec<BusInfo>,
+}
+
+/* Time */
+
+/// Days between the epoch of OLE automation dates (1899-12-30) and the Unix epoch.
+const OLE_UNIX_EPOCH_DAYS: f64 = 25_569.0;
+
+pub(crate) fn ole_to_system_time(days: f64) -> Option<SystemTime> {
+    let micros = ((days - OLE_UNIX_EPOCH_DAYS) * 86_400_000_000.0).round();
+    if !micros.is_finite() || micros < 0.0 {
+        return None;
+    }
+    SystemTime::UNIX_EPOCH.checked_add(Duration::from_micros(micros as u64))
+}
+
+/// Parses the start time comment `dd.mm.yyyy hh:mm:ss.mmm[.u]` of version 1 files.
+fn parse_start_time(value: &str) -> Option<SystemTime> {
+    let (date, time) = value.trim().split_once(' ')?;
+    let mut date = date.split('.').map(|part| part.parse::<u32>().ok());
+    let (day, month, year) = (date.next()??, date.next()??, date.next()??);
+
+    let mut time = time.trim().split([':', '.']);
+    let mut next = || time.next().and_then(|part| part.parse::<u64>().ok());
+    let (hours, minutes, seconds) = (next()?, next()?, next()?);
+    let (millis, tenths) = (next().unwrap_or(0), next().unwrap_or(0));
+    if hours >= 24 || minutes >= 60 || seconds >= 60 || millis >= 1000 || tenths >= 10 {
+        return None;
+    }
+
+    from_civil(
+        year as i64,
+        month,
+        day,
+        hours * 3600 + minutes * 60 + seconds,
+        (millis * 1000 + tenths * 100) as u32,
+    )
+}
+
+/// Parses a time offset in ms with up to three decimals.
+fn parse_offset(value: &str) -> Option<Duration> {
+    let (millis, fraction) = value.split_once('.').unwrap_or((value, ""));
+    let millis = millis.parse::<u64>().ok()?;
+    let digits = fraction.get(..fraction.len().min(3))?;
+    let micros = match digits.is_empty() {
+        true => 0,
+        false => digits.parse::<u64>().ok()? * 10u64.pow(3 - digits.len() as u32),
+    };
+    Some(Duration::from_micros(
+        millis.checked_mul(1000)?.checked_add(micros)?,
+    ))
+}
+
+/* Lines */
+
+/// Splits a line into whitespace separated tokens, keeping access to the rest of the line.
+struct Tokens<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Tokens<'a> {
+    fn new(line: &'a str) -> Tokens<'a> {
+        Tokens { rest: line }
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        let rest = self.rest.trim_start();
+        if rest.is_empty() {
+            return None;
+        }
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        self.rest = &rest[end..];
+        Some(&rest[..end])
+    }
+
+    fn remainder(&self) -> &'a str {
+        self.rest.trim()
+    }
+}
+
+fn parse_id(token: &str) -> Option<(u32, MessageType)> {
+    let id = u32::from_str_radix(token, 16).ok()?;
+    match token.len() > 4 || id > 0x7FF {
+        true => Some((id, MessageType::Extended)),
+        false => Some((id, MessageType::Standard)),
+    }
+}
+
+fn parse_direction(token: &str) -> Option<Direction> {
+    match token {
+        "Rx" => Some(Direction::Rx),
+        "Tx" => Some(Direction::Tx),
+        _ => None,
+    }
+}
+
+/// Parses hexadecimal data bytes up to the first token which is not one.
+fn parse_data(tokens: &mut Tokens, count: usize) -> Option<Vec<u8>> {
+    (0..count)
+        .map(|_| {
+            tokens
+                .next()
+                .and_then(|token| u8::from_str_radix(token, 16).ok())
+        })
+        .collect()
+}
+
+fn status(data: &[u8]) -> Option<Event> {
+    let bytes: [u8; 4] = data.get(..4)?.try_into().ok()?;
+    Some(Event::Status(u32::from_be_bytes(bytes)))
+}
+
+/* TrcReader */
+
+/// Streams the entries of a trace file.
+pub struct TrcReader<R> {
+    reader: R,
+    header: TrcHeader,
+    start: SystemTime,
+    line: usize,
+    pending: Option<String>,
+}
+
+impl TrcReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<TrcReader<BufReader<File>>, TrcError> {
+        TrcReader::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: BufRead> TrcReader<R> {
+    /// Reads the header. Entries without a start time in the header are relative to the Unix
+    /// epoch.
+    pub fn new(reader: R) -> Result<TrcReader<R>, TrcError> {
+        let mut trc = TrcReader {
+            reader,
+            header: TrcHeader {
+                version: TrcVersion::V1_0,
+                start_time: None,
+                columns: Vec::new(),
+                buses: Vec::new(),
+            },
+            start: SystemTime::UNIX_EPOCH,
+            line: 0,
+            pending: None,
+        };
+        trc.read_header()?;
+        Ok(trc)
+    }
+
+    pub fn header(&self) -> &TrcHeader {
+        &self.header
+    }
+
+    fn read_line(&mut self) -> Result<Option<String>, TrcError> {
+        let mut buffer = Vec::new();
+        if self.reader.read_until(b'\n', &mut buffer)? == 0 {
+            return Ok(None);
+        }
+        self.line += 1;
+        Ok(Some(
+            String::from_utf8_lossy(&buffer).trim_end().to_string(),
+        ))
+    }
+
+    fn read_header(&mut self) -> Result<(), TrcError> {
+        let mut columns = None;
+        let mut bus_table = false;
+
+        while let Some(line) = self.read_line()? {
+            let comment = match line.strip_prefix(';') {
+                Some(comment) => comment.trim(),
+                None if line.trim().is_empty() => continue,
+                None => {
+                    self.pending = Some(line);
+                    break;
+                }
+            };
+
+            if let Some((key, value)) = comment
+                .strip_prefix('$')
+                .and_then(|setting| setting.split_once('='))
+            {
+                match key {
+                    "FILEVERSION" => {
+                        self.header.version = TrcVersion::parse(value)
+                            .ok_or_else(|| TrcError::UnsupportedVersion(value.to_string()))?;
+                    }
+                    "STARTTIME" => {
+                        self.header.start_time = value
+                            .trim()
+                            .parse::<f64>()
+                            .ok()
+                            .and_then(ole_to_system_time);
+                    }
+                    "COLUMNS" => {
+                        columns = Some(
+                            value
+                                .split(',')
+                                .filter_map(|column| column.trim().chars().next())
+                                .collect::<Vec<char>>(),
+                        );
+                    }
+                    _ => {}
+                }
+            } else if let Some(value) = comment.strip_prefix("Start time:") {
+                if self.header.start_time.is_none() {
+                    self.header.start_time = parse_start_time(value);
+                }
+            } else if comment.starts_with("Bus") && comment.contains("Name") {
+                bus_table = true;
+            } else if comment.starts_with('-') {
+                bus_table = false;
+            } else if bus_table {
+                let mut tokens = Tokens::new(comment);
+                let bus = tokens.next().and_then(|bus| bus.parse::<u8>().ok());
+                if let Some(bus) = bus {
+                    let name = tokens.next().unwrap_or("").to_string();
+                    let connection = tokens.next().unwrap_or("").to_string();
+                    self.header.buses.push(BusInfo {
+                        bus,
+                        name,
+                        connection,
+                        details: tokens.remainder().to_string(),
+                    });
+                }
+            }
+        }
+
+        if self.header.version >= TrcVersion::V2_0 {
+            self.header.columns = columns.unwrap_or_else(|| self.header.version.default_columns());
+        }
+        self.start = self.header.start_time.unwrap_or(SystemTime::UNIX_EPOCH);
+        Ok(())
+    }
+
+    fn parse_line(&self, line: &str) -> Option<Record> {
+        match self.header.version {
+            TrcVersion::V2_0 | TrcVersion::V2_1 => self.parse_v2(line),
+            _ => self.parse_v1(line),
+        }
+    }
+
+    fn parse_v1(&self, line: &str) -> Option<Record> {
+        let version = self.header.version;
+        let mut tokens = Tokens::new(line);
+
+        tokens.next()?.strip_suffix(')')?;
+        let offset = parse_offset(tokens.next()?)?;
+        let bus = match version >= TrcVersion::V1_2 {
+            true => tokens.next()?.parse::<u8>().ok()?,
+            false => 1,
+        };
+        let kind = match version >= TrcVersion::V1_1 {
+            true => tokens.next()?,
+            false => "Rx",
+        };
+        let (id, msg_type) = parse_id(tokens.next()?)?;
+        if version == TrcVersion::V1_3 {
+            tokens.next()?;
+        }
+        let dlc = tokens.next()?.parse::<u8>().ok()?;
+
+        let (direction, event) = match kind {
+            "Warng" => {
+                let data = parse_data(&mut tokens, dlc as usize)?;
+                (Direction::Rx, status(&data)?)
+            }
+            "Error" => {
+                let data = parse_data(&mut tokens, dlc as usize)?;
+                (Direction::Rx, Event::ErrorFrame(data))
+            }
+            kind => {
+                let direction = parse_direction(kind)?;
+                let frame = match tokens.remainder().starts_with("RTR") {
+                    true => CanFrame::new_rtr(id, msg_type, dlc).ok()?,
+                    false => {
+                        CanFrame::new(id, msg_type, &parse_data(&mut tokens, dlc as usize)?).ok()?
+                    }
+                };
+                (direction, Event::Can(frame))
+            }
+        };
+
+        Some(Record {
+            time: self.start + offset,
+            bus,
+            direction,
+            event,
+        })
+    }
+
+    fn parse_v2(&self, line: &str) -> Option<Record> {
+        let mut tokens = Tokens::new(line);
+        let mut offset = None;
+        let mut kind = None;
+        let mut bus = 1;
+        let mut id = None;
+        let mut direction = Direction::Rx;
+        let mut length = None;
+        let mut data = Vec::new();
+
+        for column in self.header.columns.iter() {
+            // events carry a text instead of the remaining columns
+            if kind == Some("EV") && *column != 'B' {
+                break;
+            }
+
+            match column {
+                'O' => offset = Some(parse_offset(tokens.next()?)?),
+                'T' => kind = Some(tokens.next()?),
+                'B' => match tokens.next()? {
+                    "-" => {}
+                    token => bus = token.parse::<u8>().ok()?,
+                },
+                'I' => match tokens.next()? {
+                    "-" => {}
+                    token => id = Some(parse_id(token)?),
+                },
+                'd' => direction = parse_direction(tokens.next()?)?,
+                'L' => {
+                    let dlc = tokens.next()?.parse::<u8>().ok()?;
+                    length = Some(match kind {
+                        Some("DT") | Some("RR") => dlc.min(8) as usize,
+                        _ => crate::socket::fd_dlc_to_length(dlc),
+                    });
+                }
+                'l' => length = Some(tokens.next()?.parse::<usize>().ok()?),
+                'D' if kind != Some("RR") => data = parse_data(&mut tokens, length?)?,
+                _ => {
+                    tokens.next();
+                }
+            }
+        }
+
+        let event = match kind? {
+            "EV" => Event::Text(tokens.remainder().to_string()),
+            "DT" => {
+                let (id, msg_type) = id?;
+                Event::Can(CanFrame::new(id, msg_type, &data).ok()?)
+            }
+            "RR" => {
+                let (id, msg_type) = id?;
+                Event::Can(CanFrame::new_rtr(id, msg_type, length? as u8).ok()?)
+            }
+            kind @ ("FD" | "FB" | "FE" | "BI") => {
+                let (id, msg_type) = id?;
+                let mut frame = CanFdFrame::new(id, msg_type, &data).ok()?;
+                frame.set_brs(kind == "FB" || kind == "BI");
+                frame.set_esi(kind == "FE" || kind == "BI");
+                Event::Fd(frame)
+            }
+            "ST" => status(&data)?,
+            "EC" => Event::ErrorCounters {
+                rx: *data.first()?,
+                tx: *data.get(1)?,
+            },
+            "ER" => Event::ErrorFrame(data),
+            _ => return None,
+        };
+
+        Some(Record {
+            time: self.start + offset?,
+            bus,
+            direction,
+            event,
+        })
+    }
+}
+
+impl<R: BufRead> Iterator for TrcReader<R> {
+    type Item = Result<Record, TrcError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.pending.take() {
+                Some(line) => line,
+                None => match self.read_line() {
+                    Ok(Some(line)) => line,
+                    Ok(None) => return None,
+                    Err(err) => return Some(Err(err)),
+                },
+            };
+
+            if line.trim().is_empty() || line.starts_with(';') {
+                continue;
+            }
+            return Some(
+                self.parse_line(&line)
+                    .ok_or(TrcError::Syntax { line: self.line }),
+            );
+        }
+    }
+}
+
+/* TrcWriter */
+
+/// Settings of a [TrcWriter].
+#[derive(Debug, PartialEq, Clone)]
+pub struct TrcOptions {
+    version: TrcVersion,
+    segmented: bool,
+    date: bool,
+    time: bool,
+    overwrite: bool,
+    max_size: u64,
+    max_duration: Option<Duration>,
+    buses: Vec<BusInfo>,
+}
+
+impl Default for TrcOptions {
+    fn default() -> Self {
+        TrcOptions {
+            version: TrcVersion::V2_1,
+            segmented: false,
+            date: false,
+            time: false,
+            overwrite: false,
+            max_size: 10 * 1024 * 1024,
+            max_duration: None,
+            buses: Vec::new(),
+        }
+    }
+}
+
+impl TrcOptions {
+    /// Creates options for a single file in format 2.1 of at most 10 MB.
+    pub fn new() -> TrcOptions {
+        TrcOptions::default()
+    }
+
+    /// Selects the format, 2.1 or 1.1. Format 1.1 can only hold classic frames, status changes
+    /// and error frames.
+    pub fn with_version(mut self, version: TrcVersion) -> TrcOptions {
+        self.version = version;
+        self
+    }
+
+    /// Enables a mode like the trace of the driver does. [TraceFile::Single] stops writing once
+    /// a file is full, [TraceFile::Segmented] continues in a new file with an incremented number.
+    /// [TraceFile::Date] and [TraceFile::Time] add the start of the file (UTC) to its name.
+    /// [TraceFile::Overwrite] replaces existing files instead of failing.
+    pub fn with_mode(mut self, mode: TraceFile) -> TrcOptions {
+        match mode {
+            TraceFile::Single => self.segmented = false,
+            TraceFile::Segmented => self.segmented = true,
+            TraceFile::Date => self.date = true,
+            TraceFile::Time => self.time = true,
+            TraceFile::Overwrite => self.overwrite = true,
+        }
+        self
+    }
+
+    /// Sets the maximum size of a file in bytes, 10 MB by default.
+    pub fn with_max_size(mut self, max_size: u64) -> TrcOptions {
+        self.max_size = max_size;
+        self
+    }
+
+    /// Limits the time span of a file, like the size.
+    pub fn with_max_duration(mut self, max_duration: Duration) -> TrcOptions {
+        self.max_duration = Some(max_duration);
+        self
+    }
+
+    /// Adds a bus to the connection table of format 2.1 files.
+    pub fn with_bus(mut self, bus: BusInfo) -> TrcOptions {
+        self.buses.push(bus);
+        self
+    }
+}
+
+fn format_start_time(time: SystemTime) -> String {
+    let (year, month, day, seconds, micros) = to_civil(time);
+    format!(
+        "{:02}.{:02}.{:04} {:02}:{:02}:{:02}.{:03}.{}",
+        day,
+        month,
+        year,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+        micros / 1000,
+        micros % 1000 / 100
+    )
+}
+
+fn system_time_to_ole(time: SystemTime) -> f64 {
+    let since_epoch = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO);
+    OLE_UNIX_EPOCH_DAYS + since_epoch.as_micros() as f64 / 86_400_000_000.0
+}
+
+fn format_id(id: u32, extended: bool) -> String {
+    match extended {
+        true => format!("{:08X}", id),
+        false => format!("{:04X}", id),
+    }
+}
+
+fn format_data(data: &[u8]) -> String {
+    data.iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn format_direction(direction: Direction) -> &'static str {
+    match direction {
+        Direction::Rx => "Rx",
+        Direction::Tx => "Tx",
+    }
+}
+
+const HEADER_2_1: &str =
+    ";-------------------------------------------------------------------------------
+;   Message   Time    Type    ID     Rx/Tx
+;   Number    Offset  |  Bus  [hex]  |  Reserved
+;   |         [ms]    |  |    |      |  |  Data Length Code
+;   |         |       |  |    |      |  |  |    Data [hex] ...
+;   |         |       |  |    |      |  |  |    |
+;---+-- ------+------ +- +- --+----- +- +- +--- +- -- -- -- -- -- -- --
+";
+
+const HEADER_1_1: &str = ";
+;   Message Number
+;   |         Time Offset (ms)
+;   |         |        Type
+;   |         |        |        ID (hex)
+;   |         |        |        |     Data Length
+;   |         |        |        |     |   Data Bytes (hex) ...
+;   |         |        |        |     |   |
+;---+--   ----+----  --+--  ----+---  +  -+ -- -- -- -- -- -- --
+";
+
+/// Writes trace files readable by PCAN-View and [TrcReader].
+pub struct TrcWriter {
+    options: TrcOptions,
+    base: PathBuf,
+    path: PathBuf,
+    file: Option<BufWriter<File>>,
+    segment: u32,
+    start: SystemTime,
+    number: u64,
+    written: u64,
+    hardware_time: HardwareTime,
+}
+
+impl TrcWriter {
+    /// Creates the first file at `path`, extended according to the naming modes. The start time
+    /// of the file is the current time.
+    pub fn create<P: AsRef<Path>>(path: P, options: TrcOptions) -> Result<TrcWriter, TrcError> {
+        if options.version != TrcVersion::V1_1 && options.version != TrcVersion::V2_1 {
+            return Err(TrcError::UnsupportedVersion(
+                options.version.as_str().to_string(),
+            ));
+        }
+
+        let mut writer = TrcWriter {
+            options,
+            base: path.as_ref().to_path_buf(),
+            path: PathBuf::new(),
+            file: None,
+            segment: 0,
+            start: SystemTime::now(),
+            number: 0,
+            written: 0,
+            hardware_time: HardwareTime::default(),
+        };
+        writer.open_segment(SystemTime::now())?;
+        Ok(writer)
+    }
+
+    /// Path of the file currently written.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn segment_path(&self) -> PathBuf {
+        let stem = self
+            .base
+            .file_stem()
+            .map_or("trace".into(), |stem| stem.to_string_lossy());
+        let (year, month, day, seconds, _) = to_civil(self.start);
+
+        let mut name = stem.to_string();
+        if self.options.date {
+            name += &format!("_{:04}{:02}{:02}", year, month, day);
+        }
+        if self.options.time {
+            name += &format!(
+                "_{:02}{:02}{:02}",
+                seconds / 3600,
+                seconds / 60 % 60,
+                seconds % 60
+            );
+        }
+        if self.options.segmented {
+            name += &format!("_{}", self.segment);
+        }
+        name += ".trc";
+        self.base.with_file_name(name)
+    }
+
+    fn open_segment(&mut self, start: SystemTime) -> Result<(), TrcError> {
+        if let Some(mut file) = self.file.take() {
+            file.flush()?;
+        }
+
+        self.segment += 1;
+        self.start = start;
+        self.number = 0;
+        self.path = self.segment_path();
+
+        let file = match self.options.overwrite {
+            true => File::create(&self.path)?,
+            false => OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&self.path)?,
+        };
+        let mut file = BufWriter::new(file);
+
+        let header = self.header();
+        file.write_all(header.as_bytes())?;
+        self.written = header.len() as u64;
+        self.file = Some(file);
+        Ok(())
+    }
+
+    fn header(&self) -> String {
+        let mut header = format!(
+            ";$FILEVERSION={}\n;$STARTTIME={:.10}\n",
+            self.options.version.as_str(),
+            system_time_to_ole(self.start)
+        );
+        if self.options.version == TrcVersion::V2_1 {
+            header += ";$COLUMNS=N,O,T,B,I,d,R,L,D\n";
+        }
+        header += &format!(
+            ";\n;   {}\n;   Start time: {}\n;   Generated by pcan-basic\n",
+            self.path.display(),
+            format_start_time(self.start)
+        );
+
+        match self.options.version {
+            TrcVersion::V2_1 => {
+                if !self.options.buses.is_empty() {
+                    header += ";-------------------------------------------------------------------------------\n";
+                    header += ";   Bus  Name            Connection               Protocol\n";
+                    for bus in self.options.buses.iter() {
+                        header += &format!(
+                            ";   {:<4} {:<15} {:<24} {}\n",
+                            bus.bus, bus.name, bus.connection, bus.details
+                        );
+                    }
+                }
+                header += HEADER_2_1;
+            }
+            _ => header += HEADER_1_1,
+        }
+        header
+    }
+
+    fn format_line(&self, number: u64, offset: Duration, record: &Record) -> Option<String> {
+        let micros = offset.as_micros();
+        let direction = format_direction(record.direction);
+
+        if self.options.version == TrcVersion::V1_1 {
+            let prefix = format!(
+                "{:>6}) {:>11}",
+                number,
+                format!("{}.{}", micros / 1000, micros % 1000 / 100)
+            );
+            return match &record.event {
+                Event::Can(frame) if frame.is_rtr_frame() => Some(format!(
+                    "{}  {:<5} {:>8}  {}  RTR",
+                    prefix,
+                    direction,
+                    format_id(frame.can_id(), frame.is_extended_frame()),
+                    frame.dlc()
+                )),
+                Event::Can(frame) => Some(format!(
+                    "{}  {:<5} {:>8}  {}  {}",
+                    prefix,
+                    direction,
+                    format_id(frame.can_id(), frame.is_extended_frame()),
+                    frame.dlc(),
+                    format_data(frame.data())
+                )),
+                Event::Status(status) => Some(format!(
+                    "{}  Warng  FFFFFFFF  4  {}",
+                    prefix,
+                    format_data(&status.to_be_bytes())
+                )),
+                Event::ErrorFrame(data) => Some(format!(
+                    "{}  Error  {:>8}  {}  {}",
+                    prefix,
+                    "0000",
+                    data.len(),
+                    format_data(data)
+                )),
+                _ => None,
+            };
+        }
+
+        let prefix = format!(
+            "{:>7} {:>13}",
+            number,
+            format!("{}.{:03}", micros / 1000, micros % 1000)
+        );
+        let line = match &record.event {
+            Event::Can(frame) => format!(
+                "{} {} {:<2} {:>8} {} -  {:<4} {}",
+                prefix,
+                if frame.is_rtr_frame() { "RR" } else { "DT" },
+                record.bus,
+                format_id(frame.can_id(), frame.is_extended_frame()),
+                direction,
+                frame.dlc(),
+                if frame.is_rtr_frame() {
+                    String::new()
+                } else {
+                    format_data(frame.data())
+                }
+            ),
+            Event::Fd(frame) => format!(
+                "{} {} {:<2} {:>8} {} -  {:<4} {}",
+                prefix,
+                match (frame.is_brs_frame(), frame.is_esi_frame()) {
+                    (false, false) => "FD",
+                    (true, false) => "FB",
+                    (false, true) => "FE",
+                    (true, true) => "BI",
+                },
+                record.bus,
+                format_id(frame.can_id(), frame.is_extended_frame()),
+                direction,
+                frame.dlc(),
+                format_data(frame.data())
+            ),
+            Event::Status(status) => format!(
+                "{} ST {:<2} {:>8} {} -  4    {}",
+                prefix,
+                record.bus,
+                "-",
+                direction,
+                format_data(&status.to_be_bytes())
+            ),
+            Event::ErrorCounters { rx, tx } => format!(
+                "{} EC {:<2} {:>8} {} -  2    {}",
+                prefix,
+                record.bus,
+                "-",
+                direction,
+                format_data(&[*rx, *tx])
+            ),
+            Event::ErrorFrame(data) => format!(
+                "{} ER {:<2} {:>8} {} -  {:<4} {}",
+                prefix,
+                record.bus,
+                "-",
+                direction,
+                data.len(),
+                format_data(data)
+            ),
+            Event::Text(text) => format!("{} EV {:<2} {}", prefix, record.bus, text),
+        };
+        Some(line)
+    }
+
+    /// Writes a record. Records before the start of the current file are written at offset 0.
+    pub fn write(&mut self, record: &Record) -> Result<(), TrcError> {
+        if self.file.is_none() {
+            return Err(TrcError::Full);
+        }
+
+        let elapsed = record
+            .time
+            .duration_since(self.start)
+            .unwrap_or(Duration::ZERO);
+        let expired = self
+            .options
+            .max_duration
+            .is_some_and(|max_duration| elapsed >= max_duration);
+
+        let mut line = self
+            .format_line(self.number + 1, elapsed, record)
+            .ok_or(TrcError::UnsupportedEvent)?;
+        line = line.trim_end().to_string() + "\n";
+
+        if expired || self.written + line.len() as u64 > self.options.max_size {
+            if !self.options.segmented {
+                if let Some(mut file) = self.file.take() {
+                    file.flush()?;
+                }
+                return Err(TrcError::Full);
+            }
+            self.open_segment(record.time.max(self.start))?;
+            return self.write(record);
+        }
+
+        self.number += 1;
+        self.written += line.len() as u64;
+        match self.file.as_mut() {
+            Some(file) => Ok(file.write_all(line.as_bytes())?),
+            None => Err(TrcError::Full),
+        }
+    }
+
+    /// Sets the clock giving the [hardware time](crate::tracefile#hardware-time).
+    pub fn with_clock(mut self, clock: Clock) -> Self {
+        self.hardware_time = HardwareTime::new(clock);
+        self
+    }
+
+    /// Writes a classic frame at its [hardware time](crate::tracefile#hardware-time).
+    pub fn write_can(
+        &mut self,
+        frame: &CanFrame,
+        timestamp: &Timestamp,
+        direction: Direction,
+    ) -> Result<(), TrcError> {
+        let record = self
+            .hardware_time
+            .record(Event::Can(*frame), timestamp, direction);
+        self.write(&record)
+    }
+
+    /// Writes a CAN FD frame at its [hardware time](crate::tracefile#hardware-time).
+    pub fn write_can_fd(
+        &mut self,
+        frame: &CanFdFrame,
+        timestamp: &Timestamp,
+        direction: Direction,
+    ) -> Result<(), TrcError> {
+        let record = self
+            .hardware_time
+            .record(Event::Fd(*frame), timestamp, direction);
+        self.write(&record)
+    }
+
+    pub fn flush(&mut self) -> Result<(), TrcError> {
+        match self.file.as_mut() {
+            Some(file) => Ok(file.flush()?),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for TrcWriter {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const TRC_1_1: &str = ";$FILEVERSION=1.1
+;$STARTTIME=43474.6429503935
+;
+;   Start time: 09.01.2019 15:25:50.914.0
+;
+;   Message Number
+;   |         Time Offset (ms)
+;   |         |        Type
+;   |         |        |        ID (hex)
+;   |         |        |        |     Data Length
+;   |         |        |        |     |   Data Bytes (hex) ...
+;   |         |        |        |     |   |
+;---+--   ----+----  --+--  ----+---  +  -+ -- -- -- -- -- -- --
+     1)      1059.9  Rx         0300  8  00 00 00 00 04 00 00 00
+     2)      1283.2  Tx     18EFC8FE  3  01 02 03
+     3)      1300.0  Rx         0100  2  RTR
+     4)      1400.5  Warng  FFFFFFFF  4  00 00 00 08  BUSHEAVY
+";
+
+    const TRC_1_3: &str = ";$FILEVERSION=1.3
+;$STARTTIME=43474.6429503935
+;-------------------------------------------------------------------------------
+;   Bus  Name            Connection               Protocol
+;   1    Connection1     TestNet@pcan_usb         CAN
+;   2    Connection2     TestNet@pcan_usb2        CAN
+;-------------------------------------------------------------------------------
+     1)      1059.9 2  Rx        0300 -  2    12 34
+";
+
+    const TRC_2_1: &str = ";$FILEVERSION=2.1
+;$STARTTIME=43474.6429503935
+;$COLUMNS=N,O,T,B,I,d,R,L,D
+;
+;   Start time: 09.01.2019 15:25:50.914.0
+;   Generated by PCAN-View v4.2.1.533
+;-------------------------------------------------------------------------------
+;   Bus  Name            Connection               Protocol  Bit rate
+;   1    Connection1     TestNet@pcan_usb         CAN FD    500 kbit/s, 2 MBit/s
+;-------------------------------------------------------------------------------
+;---+-- ------+------ +- +- --+----- +- +- +--- +- -- -- -- -- -- -- --
+      1      1059.900 DT 1      0300 Rx -  8    00 01 02 03 04 05 06 07
+      2      1060.125 FB 1  18EFC8FE Tx -  9    00 01 02 03 04 05 06 07 08 09 0A 0B
+      3      1061.000 RR 1      0123 Rx -  4
+      4      1062.000 ST 1         - Rx -  4    00 00 00 08
+      5      1063.000 EC 1         - Rx -  2    60 00
+      6      1064.000 ER 1         - Rx -  5    04 00 08 00 00
+      7      1065.000 EV 1  Recording paused
+";
+
+    fn start() -> SystemTime {
+        from_civil(2019, 1, 9, 15 * 3600 + 25 * 60 + 50, 914_000).unwrap()
+    }
+
+    fn assert_time(record: &Record, offset_micros: u64) {
+        let time = record.time.duration_since(start()).unwrap();
+        let expected = Duration::from_micros(offset_micros);
+        assert!(
+            time.abs_diff(expected) < Duration::from_micros(10),
+            "{:?}",
+            time
+        );
+    }
+
+    #[test]
+    fn trc_reader_001() {
+        let reader = TrcReader::new(Cursor::new(TRC_1_1)).unwrap();
+        assert_eq!(reader.header().version, TrcVersion::V1_1);
+        let records = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(records.len(), 4);
+
+        assert_time(&records[0], 1_059_900);
+        assert_eq!(
+            records[1],
+            Record {
+                time: records[1].time,
+                bus: 1,
+                direction: Direction::Tx,
+                event: Event::Can(
+                    CanFrame::new(0x18EF_C8FE, MessageType::Extended, &[1, 2, 3]).unwrap()
+                ),
+            }
+        );
+        assert_eq!(
+            records[2].event,
+            Event::Can(CanFrame::new_rtr(0x100, MessageType::Standard, 2).unwrap())
+        );
+        assert_eq!(records[3].event, Event::Status(0x08));
+
+        let mut reader = TrcReader::new(Cursor::new(TRC_1_3)).unwrap();
+        assert_eq!(reader.header().buses.len(), 2);
+        assert_eq!(reader.header().buses[1].connection, "TestNet@pcan_usb2");
+        let record = reader.next().unwrap().unwrap();
+        assert_eq!(record.bus, 2);
+        assert_eq!(
+            record.event,
+            Event::Can(CanFrame::new(0x300, MessageType::Standard, &[0x12, 0x34]).unwrap())
+        );
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn trc_reader_002() {
+        let reader = TrcReader::new(Cursor::new(TRC_2_1)).unwrap();
+        let header = reader.header().clone();
+        assert_eq!(header.version, TrcVersion::V2_1);
+        assert_eq!(header.columns.len(), 9);
+        assert_eq!(header.buses[0].details, "CAN FD    500 kbit/s, 2 MBit/s");
+
+        let records = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(records.len(), 7);
+        assert_time(&records[1], 1_060_125);
+
+        let mut fd = CanFdFrame::new(
+            0x18EF_C8FE,
+            MessageType::Extended,
+            &(0..12).collect::<Vec<u8>>(),
+        )
+        .unwrap();
+        fd.set_brs(true);
+        assert_eq!(records[1].event, Event::Fd(fd));
+        assert_eq!(records[1].direction, Direction::Tx);
+        assert_eq!(
+            records[2].event,
+            Event::Can(CanFrame::new_rtr(0x123, MessageType::Standard, 4).unwrap())
+        );
+        assert_eq!(records[3].event, Event::Status(0x08));
+        assert_eq!(records[4].event, Event::ErrorCounters { rx: 0x60, tx: 0 });
+        assert_eq!(records[5].event, Event::ErrorFrame(vec![4, 0, 8, 0, 0]));
+        assert_eq!(
+            records[6].event,
+            Event::Text("Recording paused".to_string())
+        );
+    }
+
+    #[test]
+    fn trc_reader_003() {
+        let trc = ";$FILEVERSION=2.0\n;$STARTTIME=25569.5\n      1        21.5 DT     0300 Rx 2  AA BB\n      2        xx DT     0300 Rx 2  AA BB\n";
+        let mut reader = TrcReader::new(Cursor::new(trc)).unwrap();
+        let record = reader.next().unwrap().unwrap();
+        assert_eq!(
+            record.time,
+            SystemTime::UNIX_EPOCH + Duration::from_micros(43_200_021_500)
+        );
+        assert_eq!(reader.next(), Some(Err(TrcError::Syntax { line: 4 })));
+        assert_eq!(reader.next(), None);
+
+        let trc = ";   Start time: 09.01.2019 15:25:50.914\n     1)      1841  0300  1  FF\n";
+        let record = TrcReader::new(Cursor::new(trc))
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_time(&record, 1_841_000);
+        assert_eq!(
+            record.event,
+            Event::Can(CanFrame::new(0x300, MessageType::Standard, &[0xFF]).unwrap())
+        );
+
+        assert_eq!(
+            TrcReader::new(Cursor::new(";$FILEVERSION=3.0\n")).err(),
+            Some(TrcError::UnsupportedVersion("3.0".to_string()))
+        );
+    }
+
+    #[test]
+    fn start_time_001() {
+        assert_eq!(
+            parse_start_time("09.01.2019 15:25:50.914.5"),
+            from_civil(2019, 1, 9, 15 * 3600 + 25 * 60 + 50, 914_500)
+        );
+        for value in [
+            "09.01.2019 15:25:50.1000",
+            "09.01.2019 24:00:00.0",
+            "09.01.2019 99999999999999999:00:00.0",
+            "09.01.2019 15:25:50.914.10",
+            "09.01.99999 15:25:50.914",
+        ] {
+            assert_eq!(parse_start_time(value), None, "{}", value);
+        }
+        assert_eq!(parse_offset("18446744073709552.5"), None);
+        assert_eq!(from_civil(2019, 1, 9, 0, 1_000_000), None);
+        assert_eq!(from_civil(i64::MAX, 1, 9, 0, 0), None);
+        assert_eq!(from_civil(2019, 1, 9, u64::MAX, 0), None);
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pcan_basic_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn sample_records(start: SystemTime) -> Vec<Record> {
+        let mut fd = CanFdFrame::new(0x1234_5678, MessageType::Extended, &[0x55; 20]).unwrap();
+        fd.set_esi(true);
+        let events = vec![
+            Event::Can(CanFrame::new(0x123, MessageType::Standard, &[1, 2, 3]).unwrap()),
+            Event::Can(CanFrame::new_rtr(0x7FF, MessageType::Standard, 8).unwrap()),
+            Event::Fd(fd),
+            Event::Status(0x0000_0008),
+            Event::ErrorCounters { rx: 128, tx: 3 },
+            Event::ErrorFrame(vec![4, 0, 8, 0, 0]),
+            Event::Text("Marker 1".to_string()),
+        ];
+        events
+            .into_iter()
+            .enumerate()
+            .map(|(index, event)| Record {
+                time: start + Duration::from_micros(1_500_250 * index as u64),
+                bus: 1 + (index % 2) as u8,
+                direction: if index == 0 {
+                    Direction::Tx
+                } else {
+                    Direction::Rx
+                },
+                event,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn trc_writer_001() {
+        let dir = temp_dir("trc_writer_001");
+        let path = dir.join("trace.trc");
+        let options = TrcOptions::new().with_bus(BusInfo {
+            bus: 1,
+            name: "Connection1".to_string(),
+            connection: "PCAN_USBBUS1".to_string(),
+            details: "CAN FD".to_string(),
+        });
+
+        let mut writer = TrcWriter::create(&path, options).unwrap();
+        let start = writer.start;
+        let expected = sample_records(start);
+        for record in expected.iter() {
+            writer.write(record).unwrap();
+        }
+        drop(writer);
+
+        let reader = TrcReader::open(&path).unwrap();
+        assert_eq!(reader.header().version, TrcVersion::V2_1);
+        assert_eq!(reader.header().buses[0].connection, "PCAN_USBBUS1");
+        let header_start = reader.header().start_time.unwrap();
+        let records = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(records.len(), expected.len());
+        for (record, expected) in records.iter().zip(expected.iter()) {
+            assert_eq!(record.event, expected.event);
+            assert_eq!(record.bus, expected.bus);
+            assert_eq!(record.direction, expected.direction);
+            let offset = record.time.duration_since(header_start).unwrap();
+            let expected_offset = expected.time.duration_since(start).unwrap();
+            assert_eq!(offset, expected_offset);
+        }
+
+        // format 1.1 only stores classic frames, status changes and error frames
+        let path = dir.join("trace_1_1.trc");
+        let options = TrcOptions::new().with_version(TrcVersion::V1_1);
+        let mut writer = TrcWriter::create(&path, options).unwrap();
+        let expected = sample_records(writer.start);
+        assert_eq!(writer.write(&expected[2]), Err(TrcError::UnsupportedEvent));
+        for index in [0, 1, 3, 5] {
+            writer.write(&expected[index]).unwrap();
+        }
+        drop(writer);
+
+        let reader = TrcReader::open(&path).unwrap();
+        assert_eq!(reader.header().version, TrcVersion::V1_1);
+        let events = reader
+            .map(|record| record.unwrap().event)
+            .collect::<Vec<_>>();
+        let expected = [0, 1, 3, 5].map(|index| expected[index].event.clone());
+        assert_eq!(events, expected.to_vec());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn trc_writer_002() {
+        let dir = temp_dir("trc_writer_002");
+        let path = dir.join("trace.trc");
+        let frame = CanFrame::new(0x100, MessageType::Standard, &[0; 8]).unwrap();
+
+        let options = TrcOptions::new()
+            .with_mode(TraceFile::Segmented)
+            .with_max_size(2048);
+        let mut writer = TrcWriter::create(&path, options).unwrap();
+        for micros in 0..60u64 {
+            let timestamp = Timestamp::from_micros(1_000_000 + micros * 1000);
+            writer.write_can(&frame, &timestamp, Direction::Rx).unwrap();
+        }
+        let segments = writer.segment;
+        assert!(segments > 1);
+        assert_eq!(writer.path(), dir.join(format!("trace_{}.trc", segments)));
+        drop(writer);
+
+        let mut total = 0;
+        for segment in 1..=segments {
+            let path = dir.join(format!("trace_{}.trc", segment));
+            assert!(std::fs::metadata(&path).unwrap().len() <= 2048);
+            total += TrcReader::open(&path).unwrap().count();
+        }
+        assert_eq!(total, 60);
+
+        // a single file stops when full and is not overwritten
+        let options = TrcOptions::new().with_max_size(2048);
+        let mut writer = TrcWriter::create(&path, options.clone()).unwrap();
+        let mut result = Ok(());
+        for micros in 0..60u64 {
+            result = writer.write_can(&frame, &Timestamp::from_micros(micros), Direction::Rx);
+            if result.is_err() {
+                break;
+            }
+        }
+        assert_eq!(result, Err(TrcError::Full));
+        assert_eq!(
+            TrcWriter::create(&path, options.clone()).err(),
+            Some(TrcError::Io(std::io::ErrorKind::AlreadyExists))
+        );
+        assert!(TrcWriter::create(&path, options.with_mode(TraceFile::Overwrite)).is_ok());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
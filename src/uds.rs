@@ -0,0 +1,786 @@
+//! Unified diagnostic services (ISO 14229) client.
+//!
+//! A [UdsClient] sends requests over an [IsoTpChannel] and waits for the matching response. A
+//! negative response with the code [ResponsePending](NegativeResponseCode::ResponsePending)
+//! extends the wait from P2 to P2*. While a non-default session is active the client keeps it alive
+//! by sending TesterPresent whenever no other request was sent within the keep-alive interval.
+
+use crate::isotp::{IsoTpChannel, IsoTpError};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, PartialEq)]
+pub enum UdsError {
+    IsoTp(IsoTpError),
+    /// The server rejected the request.
+    NegativeResponse {
+        service: u8,
+        code: NegativeResponseCode,
+    },
+    /// No response arrived within P2, or P2* after a pending response.
+    Timeout,
+    /// The response is too short or does not echo the parameters of the request.
+    InvalidResponse,
+    InvalidRequest,
+}
+
+impl From<IsoTpError> for UdsError {
+    fn from(value: IsoTpError) -> Self {
+        UdsError::IsoTp(value)
+    }
+}
+
+/* Identifiers */
+
+pub const DIAGNOSTIC_SESSION_CONTROL: u8 = 0x10;
+pub const ECU_RESET: u8 = 0x11;
+pub const READ_DTC_INFORMATION: u8 = 0x19;
+pub const READ_DATA_BY_IDENTIFIER: u8 = 0x22;
+pub const SECURITY_ACCESS: u8 = 0x27;
+pub const WRITE_DATA_BY_IDENTIFIER: u8 = 0x2E;
+pub const ROUTINE_CONTROL: u8 = 0x31;
+pub const REQUEST_DOWNLOAD: u8 = 0x34;
+pub const TRANSFER_DATA: u8 = 0x36;
+pub const REQUEST_TRANSFER_EXIT: u8 = 0x37;
+pub const TESTER_PRESENT: u8 = 0x3E;
+
+const NEGATIVE_RESPONSE: u8 = 0x7F;
+const POSITIVE_RESPONSE_OFFSET: u8 = 0x40;
+const SUPPRESS_POSITIVE_RESPONSE: u8 = 0x80;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum NegativeResponseCode {
+    GeneralReject,
+    ServiceNotSupported,
+    SubFunctionNotSupported,
+    IncorrectMessageLengthOrInvalidFormat,
+    ResponseTooLong,
+    BusyRepeatRequest,
+    ConditionsNotCorrect,
+    RequestSequenceError,
+    NoResponseFromSubnetComponent,
+    FailurePreventsExecutionOfRequestedAction,
+    RequestOutOfRange,
+    SecurityAccessDenied,
+    InvalidKey,
+    ExceededNumberOfAttempts,
+    RequiredTimeDelayNotExpired,
+    UploadDownloadNotAccepted,
+    TransferDataSuspended,
+    GeneralProgrammingFailure,
+    WrongBlockSequenceCounter,
+    ResponsePending,
+    SubFunctionNotSupportedInActiveSession,
+    ServiceNotSupportedInActiveSession,
+    Other(u8),
+}
+
+impl From<u8> for NegativeResponseCode {
+    fn from(value: u8) -> Self {
+        match value {
+            0x10 => NegativeResponseCode::GeneralReject,
+            0x11 => NegativeResponseCode::ServiceNotSupported,
+            0x12 => NegativeResponseCode::SubFunctionNotSupported,
+            0x13 => NegativeResponseCode::IncorrectMessageLengthOrInvalidFormat,
+            0x14 => NegativeResponseCode::ResponseTooLong,
+            0x21 => NegativeResponseCode::BusyRepeatRequest,
+            0x22 => NegativeResponseCode::ConditionsNotCorrect,
+            0x24 => NegativeResponseCode::RequestSequenceError,
+            0x25 => NegativeResponseCode::NoResponseFromSubnetComponent,
+            0x26 => NegativeResponseCode::FailurePreventsExecutionOfRequestedAction,
+            0x31 => NegativeResponseCode::RequestOutOfRange,
+            0x33 => NegativeResponseCode::SecurityAccessDenied,
+            0x35 => NegativeResponseCode::InvalidKey,
+            0x36 => NegativeResponseCode::ExceededNumberOfAttempts,
+            0x37 => NegativeResponseCode::RequiredTimeDelayNotExpired,
+            0x70 => NegativeResponseCode::UploadDownloadNotAccepted,
+            0x71 => NegativeResponseCode::TransferDataSuspended,
+            0x72 => NegativeResponseCode::GeneralProgrammingFailure,
+            0x73 => NegativeResponseCode::WrongBlockSequenceCounter,
+            0x78 => NegativeResponseCode::ResponsePending,
+            0x7E => NegativeResponseCode::SubFunctionNotSupportedInActiveSession,
+            0x7F => NegativeResponseCode::ServiceNotSupportedInActiveSession,
+            _ => NegativeResponseCode::Other(value),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum SessionType {
+    Default,
+    Programming,
+    ExtendedDiagnostic,
+    SafetySystemDiagnostic,
+    Other(u8),
+}
+
+impl From<SessionType> for u8 {
+    fn from(value: SessionType) -> Self {
+        match value {
+            SessionType::Default => 0x01,
+            SessionType::Programming => 0x02,
+            SessionType::ExtendedDiagnostic => 0x03,
+            SessionType::SafetySystemDiagnostic => 0x04,
+            SessionType::Other(value) => value,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ResetType {
+    Hard,
+    KeyOffOn,
+    Soft,
+    EnableRapidPowerShutDown,
+    DisableRapidPowerShutDown,
+    Other(u8),
+}
+
+impl From<ResetType> for u8 {
+    fn from(value: ResetType) -> Self {
+        match value {
+            ResetType::Hard => 0x01,
+            ResetType::KeyOffOn => 0x02,
+            ResetType::Soft => 0x03,
+            ResetType::EnableRapidPowerShutDown => 0x04,
+            ResetType::DisableRapidPowerShutDown => 0x05,
+            ResetType::Other(value) => value,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum RoutineControlType {
+    Start,
+    Stop,
+    RequestResults,
+}
+
+impl From<RoutineControlType> for u8 {
+    fn from(value: RoutineControlType) -> Self {
+        match value {
+            RoutineControlType::Start => 0x01,
+            RoutineControlType::Stop => 0x02,
+            RoutineControlType::RequestResults => 0x03,
+        }
+    }
+}
+
+/* Timing */
+
+/// Response timing of the server.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Timing {
+    /// Time until the server starts its response.
+    pub p2: Duration,
+    /// Time until the server starts its response after it sent a pending response.
+    pub p2_star: Duration,
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Timing {
+            p2: Duration::from_millis(50),
+            p2_star: Duration::from_millis(5000),
+        }
+    }
+}
+
+/// Added to the timing of the server to account for the latency of the bus and the driver.
+const TIMING_MARGIN: Duration = Duration::from_millis(50);
+
+/* Records */
+
+/// Memory area addressed by RequestDownload.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct MemoryRange {
+    pub address: u64,
+    pub size: u64,
+    /// Number of bytes used to encode the address.
+    pub address_length: u8,
+    /// Number of bytes used to encode the size.
+    pub size_length: u8,
+}
+
+impl MemoryRange {
+    /// Creates a range encoded with four bytes for the address and the size.
+    pub fn new(address: u32, size: u32) -> MemoryRange {
+        MemoryRange {
+            address: address as u64,
+            size: size as u64,
+            address_length: 4,
+            size_length: 4,
+        }
+    }
+
+    pub fn with_lengths(mut self, address_length: u8, size_length: u8) -> MemoryRange {
+        self.address_length = address_length;
+        self.size_length = size_length;
+        self
+    }
+
+    fn encode(&self, request: &mut Vec<u8>) -> Result<(), UdsError> {
+        if !(1..=8).contains(&self.address_length) || !(1..=8).contains(&self.size_length) {
+            return Err(UdsError::InvalidRequest);
+        }
+
+        request.push((self.size_length << 4) | self.address_length);
+        request.extend_from_slice(&self.address.to_be_bytes()[8 - self.address_length as usize..]);
+        request.extend_from_slice(&self.size.to_be_bytes()[8 - self.size_length as usize..]);
+        Ok(())
+    }
+}
+
+/// Diagnostic trouble code together with its status byte.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Dtc {
+    /// The three bytes of the code, e.g. `0x012345`.
+    pub code: u32,
+    pub status: u8,
+}
+
+/// Result of a ReportNumberOfDTCByStatusMask request.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct DtcCount {
+    pub availability_mask: u8,
+    pub format: u8,
+    pub count: u16,
+}
+
+/// Computes the key of a SecurityAccess level from the seed of the server.
+pub trait SeedKey {
+    fn key(&mut self, level: u8, seed: &[u8]) -> Vec<u8>;
+}
+
+impl<F: FnMut(u8, &[u8]) -> Vec<u8>> SeedKey for F {
+    fn key(&mut self, level: u8, seed: &[u8]) -> Vec<u8> {
+        self(level, seed)
+    }
+}
+
+/* UdsClient */
+
+struct State {
+    timing: Timing,
+    keep_alive: bool,
+    last_activity: Instant,
+    shutdown: bool,
+}
+
+struct Shared<S> {
+    channel: Mutex<IsoTpChannel<S>>,
+    state: Mutex<State>,
+    condvar: Condvar,
+}
+
+impl<S> Shared<S> {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        match self.state.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    fn lock_channel(&self) -> MutexGuard<'_, IsoTpChannel<S>> {
+        match self.channel.lock() {
+            Ok(channel) => channel,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+
+    fn touch(&self) {
+        self.lock().last_activity = Instant::now();
+    }
+}
+
+/// Diagnostic client talking to a single server.
+pub struct UdsClient<S> {
+    shared: Arc<Shared<S>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl<S: Send + 'static> UdsClient<S> {
+    /// Creates a client sending TesterPresent every two seconds while a non-default session is
+    /// active.
+    pub fn new(channel: IsoTpChannel<S>) -> UdsClient<S> {
+        UdsClient::with_keep_alive(channel, Some(Duration::from_secs(2)))
+    }
+
+    /// Creates a client with the given keep-alive interval. With `None` the session is not kept
+    /// alive automatically.
+    pub fn with_keep_alive(channel: IsoTpChannel<S>, interval: Option<Duration>) -> UdsClient<S> {
+        let shared = Arc::new(Shared {
+            channel: Mutex::new(channel),
+            state: Mutex::new(State {
+                timing: Timing::default(),
+                keep_alive: false,
+                last_activity: Instant::now(),
+                shutdown: false,
+            }),
+            condvar: Condvar::new(),
+        });
+
+        let handle = interval.map(|interval| {
+            let shared = shared.clone();
+            thread::spawn(move || keep_alive(&shared, interval))
+        });
+
+        UdsClient { shared, handle }
+    }
+}
+
+impl<S> UdsClient<S> {
+    pub fn timing(&self) -> Timing {
+        self.shared.lock().timing
+    }
+
+    /// Overrides the timing used to wait for responses.
+    pub fn set_timing(&self, timing: Timing) {
+        self.shared.lock().timing = timing;
+    }
+
+    fn set_keep_alive(&self, keep_alive: bool) {
+        let mut state = self.shared.lock();
+        state.keep_alive = keep_alive;
+        state.last_activity = Instant::now();
+        self.shared.condvar.notify_all();
+    }
+
+    /// Sends a raw request and returns the positive response, including its service identifier.
+    pub fn request(&self, request: &[u8]) -> Result<Vec<u8>, UdsError> {
+        let service = *request.first().ok_or(UdsError::InvalidRequest)?;
+        let timing = self.timing();
+        let mut channel = self.shared.lock_channel();
+
+        channel.send(request)?;
+        let mut deadline = Instant::now() + timing.p2;
+
+        let result = loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let response = match channel.recv_timeout(timeout) {
+                Ok(response) => response,
+                Err(IsoTpError::Timeout) => break Err(UdsError::Timeout),
+                Err(err) => break Err(UdsError::IsoTp(err)),
+            };
+
+            match response.as_slice() {
+                [NEGATIVE_RESPONSE, rejected, code, ..] if *rejected == service => {
+                    match NegativeResponseCode::from(*code) {
+                        NegativeResponseCode::ResponsePending => {
+                            deadline = Instant::now() + timing.p2_star;
+                        }
+                        code => break Err(UdsError::NegativeResponse { service, code }),
+                    }
+                }
+                [sid, ..] if *sid == service.wrapping_add(POSITIVE_RESPONSE_OFFSET) => {
+                    break Ok(response);
+                }
+                // responses to other requests, e.g. late ones after a timeout
+                _ => {}
+            }
+        };
+
+        drop(channel);
+        self.shared.touch();
+        result
+    }
+
+    /// Sends a request without waiting for a response.
+    pub fn request_no_response(&self, request: &[u8]) -> Result<(), UdsError> {
+        self.shared.lock_channel().send(request)?;
+        self.shared.touch();
+        Ok(())
+    }
+
+    fn request_checked(&self, request: &[u8], echo: usize) -> Result<Vec<u8>, UdsError> {
+        let response = self.request(request)?;
+        if response.len() < echo || response[1..echo] != request[1..echo] {
+            return Err(UdsError::InvalidResponse);
+        }
+        Ok(response)
+    }
+
+    /// Switches to another session and adopts the timing reported by the server. Keep-alive is
+    /// active in all sessions but the default session.
+    pub fn diagnostic_session_control(&self, session: SessionType) -> Result<Timing, UdsError> {
+        let response = self.request_checked(&[DIAGNOSTIC_SESSION_CONTROL, session.into()], 2)?;
+
+        let timing = match response[2..] {
+            [p2_high, p2_low, p2_star_high, p2_star_low, ..] => {
+                let p2 = u16::from_be_bytes([p2_high, p2_low]) as u64;
+                let p2_star = u16::from_be_bytes([p2_star_high, p2_star_low]) as u64;
+                Timing {
+                    p2: Duration::from_millis(p2),
+                    p2_star: Duration::from_millis(p2_star * 10),
+                }
+            }
+            _ => Timing::default(),
+        };
+
+        self.set_timing(Timing {
+            p2: timing.p2 + TIMING_MARGIN,
+            p2_star: timing.p2_star + TIMING_MARGIN,
+        });
+        self.set_keep_alive(session != SessionType::Default);
+        Ok(timing)
+    }
+
+    /// Resets the server, which returns to the default session.
+    pub fn ecu_reset(&self, reset: ResetType) -> Result<(), UdsError> {
+        self.request_checked(&[ECU_RESET, reset.into()], 2)?;
+        self.set_keep_alive(false);
+        Ok(())
+    }
+
+    /// Requests the seed of an odd security level. A seed of zeros means that the level is already
+    /// unlocked.
+    pub fn request_seed(&self, level: u8) -> Result<Vec<u8>, UdsError> {
+        if level & 1 == 0 {
+            return Err(UdsError::InvalidRequest);
+        }
+        let response = self.request_checked(&[SECURITY_ACCESS, level], 2)?;
+        Ok(response[2..].to_vec())
+    }
+
+    /// Sends the key for an odd security level.
+    pub fn send_key(&self, level: u8, key: &[u8]) -> Result<(), UdsError> {
+        let mut request = vec![SECURITY_ACCESS, level.wrapping_add(1)];
+        request.extend_from_slice(key);
+        self.request_checked(&request, 2)?;
+        Ok(())
+    }
+
+    /// Unlocks an odd security level, computing the key with `seed_key`.
+    pub fn security_access<K: SeedKey>(&self, level: u8, mut seed_key: K) -> Result<(), UdsError> {
+        let seed = self.request_seed(level)?;
+        if seed.iter().all(|byte| *byte == 0) {
+            return Ok(());
+        }
+        let key = seed_key.key(level, &seed);
+        self.send_key(level, &key)
+    }
+
+    pub fn read_data_by_identifier(&self, identifier: u16) -> Result<Vec<u8>, UdsError> {
+        let [high, low] = identifier.to_be_bytes();
+        let response = self.request_checked(&[READ_DATA_BY_IDENTIFIER, high, low], 3)?;
+        Ok(response[3..].to_vec())
+    }
+
+    pub fn write_data_by_identifier(&self, identifier: u16, data: &[u8]) -> Result<(), UdsError> {
+        let [high, low] = identifier.to_be_bytes();
+        let mut request = vec![WRITE_DATA_BY_IDENTIFIER, high, low];
+        request.extend_from_slice(data);
+        self.request_checked(&request, 3)?;
+        Ok(())
+    }
+
+    /// Controls a routine and returns the routine status record.
+    pub fn routine_control(
+        &self,
+        control: RoutineControlType,
+        identifier: u16,
+        options: &[u8],
+    ) -> Result<Vec<u8>, UdsError> {
+        let [high, low] = identifier.to_be_bytes();
+        let mut request = vec![ROUTINE_CONTROL, control.into(), high, low];
+        request.extend_from_slice(options);
+        let response = self.request_checked(&request, 4)?;
+        Ok(response[4..].to_vec())
+    }
+
+    /// Requests a download into `range` and returns the maximum length of a TransferData request,
+    /// including its service identifier and block sequence counter.
+    pub fn request_download(&self, range: MemoryRange, data_format: u8) -> Result<usize, UdsError> {
+        let mut request = vec![REQUEST_DOWNLOAD, data_format];
+        range.encode(&mut request)?;
+        let response = self.request(&request)?;
+
+        let length = match response.get(1) {
+            Some(format) => (format >> 4) as usize,
+            None => return Err(UdsError::InvalidResponse),
+        };
+        if length == 0 || length > 8 || response.len() < 2 + length {
+            return Err(UdsError::InvalidResponse);
+        }
+
+        let max_block_length = response[2..2 + length]
+            .iter()
+            .fold(0u64, |value, byte| (value << 8) | *byte as u64);
+        Ok(max_block_length as usize)
+    }
+
+    /// Transfers a block and returns the transfer response parameters.
+    pub fn transfer_data(&self, sequence_counter: u8, data: &[u8]) -> Result<Vec<u8>, UdsError> {
+        let mut request = vec![TRANSFER_DATA, sequence_counter];
+        request.extend_from_slice(data);
+        let response = self.request_checked(&request, 2)?;
+        Ok(response[2..].to_vec())
+    }
+
+    pub fn request_transfer_exit(&self, parameters: &[u8]) -> Result<Vec<u8>, UdsError> {
+        let mut request = vec![REQUEST_TRANSFER_EXIT];
+        request.extend_from_slice(parameters);
+        let response = self.request(&request)?;
+        Ok(response[1..].to_vec())
+    }
+
+    /// Downloads `data` into `range` using RequestDownload, TransferData and RequestTransferExit.
+    pub fn download(
+        &self,
+        range: MemoryRange,
+        data_format: u8,
+        data: &[u8],
+    ) -> Result<(), UdsError> {
+        let max_block_length = self.request_download(range, data_format)?;
+        if max_block_length <= 2 {
+            return Err(UdsError::InvalidResponse);
+        }
+
+        let mut sequence_counter = 1u8;
+        for block in data.chunks(max_block_length - 2) {
+            self.transfer_data(sequence_counter, block)?;
+            sequence_counter = sequence_counter.wrapping_add(1);
+        }
+
+        self.request_transfer_exit(&[])?;
+        Ok(())
+    }
+
+    /// Returns the number of trouble codes matching the status mask.
+    pub fn read_dtc_count(&self, status_mask: u8) -> Result<DtcCount, UdsError> {
+        let response = self.request_checked(&[READ_DTC_INFORMATION, 0x01, status_mask], 2)?;
+        match response[2..] {
+            [availability_mask, format, high, low, ..] => Ok(DtcCount {
+                availability_mask,
+                format,
+                count: u16::from_be_bytes([high, low]),
+            }),
+            _ => Err(UdsError::InvalidResponse),
+        }
+    }
+
+    /// Returns the status availability mask and the trouble codes matching the status mask.
+    pub fn read_dtcs_by_status_mask(&self, status_mask: u8) -> Result<(u8, Vec<Dtc>), UdsError> {
+        let response = self.request_checked(&[READ_DTC_INFORMATION, 0x02, status_mask], 2)?;
+        parse_dtcs(&response[2..])
+    }
+
+    /// Returns the status availability mask and all trouble codes supported by the server.
+    pub fn read_supported_dtcs(&self) -> Result<(u8, Vec<Dtc>), UdsError> {
+        let response = self.request_checked(&[READ_DTC_INFORMATION, 0x0A], 2)?;
+        parse_dtcs(&response[2..])
+    }
+
+    pub fn tester_present(&self) -> Result<(), UdsError> {
+        self.request_checked(&[TESTER_PRESENT, 0x00], 2)?;
+        Ok(())
+    }
+
+    /// Stops the keep-alive and returns the channel.
+    pub fn shutdown(self) -> Option<IsoTpChannel<S>> {
+        let shared = self.shared.clone();
+        drop(self);
+
+        match Arc::try_unwrap(shared) {
+            Ok(shared) => match shared.channel.into_inner() {
+                Ok(channel) => Some(channel),
+                Err(poisoned) => Some(poisoned.into_inner()),
+            },
+            Err(_) => None,
+        }
+    }
+}
+
+impl<S> Drop for UdsClient<S> {
+    fn drop(&mut self) {
+        self.shared.lock().shutdown = true;
+        self.shared.condvar.notify_all();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn parse_dtcs(record: &[u8]) -> Result<(u8, Vec<Dtc>), UdsError> {
+    let (availability_mask, records) = match record.split_first() {
+        Some((availability_mask, records)) if records.len() % 4 == 0 => {
+            (*availability_mask, records)
+        }
+        _ => return Err(UdsError::InvalidResponse),
+    };
+
+    let dtcs = records
+        .chunks(4)
+        .map(|record| Dtc {
+            code: u32::from_be_bytes([0, record[0], record[1], record[2]]),
+            status: record[3],
+        })
+        .collect();
+    Ok((availability_mask, dtcs))
+}
+
+fn keep_alive<S>(shared: &Shared<S>, interval: Duration) {
+    let mut state = shared.lock();
+
+    loop {
+        if state.shutdown {
+            return;
+        }
+
+        if !state.keep_alive {
+            state = match shared.condvar.wait(state) {
+                Ok(state) => state,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            continue;
+        }
+
+        let due = state.last_activity + interval;
+        let now = Instant::now();
+        if now < due {
+            state = match shared.condvar.wait_timeout(state, due - now) {
+                Ok((state, _)) => state,
+                Err(poisoned) => poisoned.into_inner().0,
+            };
+            continue;
+        }
+
+        drop(state);
+        let _ = shared
+            .lock_channel()
+            .send(&[TESTER_PRESENT, SUPPRESS_POSITIVE_RESPONSE]);
+        state = shared.lock();
+        state.last_activity = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::isotp::IsoTpConfig;
+    use crate::socket::pipe::{pipe, PipeSocket};
+
+    /// Answers each request with the responses returned by `handler`.
+    fn server<H>(handler: H) -> (UdsClient<PipeSocket>, JoinHandle<Vec<Vec<u8>>>)
+    where
+        H: Fn(&[u8]) -> Vec<Vec<u8>> + Send + 'static,
+    {
+        let (tester, ecu) = pipe();
+        let client = UdsClient::with_keep_alive(
+            IsoTpChannel::new(tester, IsoTpConfig::new(0x7E0, 0x7E8)),
+            Some(Duration::from_millis(20)),
+        );
+        client.set_timing(Timing {
+            p2: Duration::from_millis(200),
+            p2_star: Duration::from_millis(500),
+        });
+
+        let handle = thread::spawn(move || {
+            let mut channel = IsoTpChannel::new(ecu, IsoTpConfig::new(0x7E8, 0x7E0));
+            let mut requests = Vec::new();
+
+            while let Ok(request) = channel.recv_timeout(Duration::from_millis(300)) {
+                for response in handler(&request) {
+                    channel.send(&response).unwrap();
+                }
+                requests.push(request);
+            }
+            requests
+        });
+        (client, handle)
+    }
+
+    #[test]
+    fn request_001() {
+        let (client, server) = server(|request| match request {
+            [0x22, 0xF1, 0x90] => vec![
+                vec![0x7F, 0x22, 0x78],
+                [&[0x62, 0xF1, 0x90][..], b"WVWZZZ1JZXW000001"].concat(),
+            ],
+            [0x2E, ..] => vec![vec![0x7F, 0x2E, 0x33]],
+            _ => vec![vec![0x7F, request[0], 0x11]],
+        });
+
+        assert_eq!(
+            client.read_data_by_identifier(0xF190),
+            Ok(b"WVWZZZ1JZXW000001".to_vec())
+        );
+        assert_eq!(
+            client.write_data_by_identifier(0xF190, &[0x00]),
+            Err(UdsError::NegativeResponse {
+                service: 0x2E,
+                code: NegativeResponseCode::SecurityAccessDenied
+            })
+        );
+
+        drop(client);
+        assert_eq!(server.join().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn security_access_001() {
+        let (client, server) = server(|request| match request {
+            [0x10, 0x03] => vec![vec![0x50, 0x03, 0x00, 0x32, 0x01, 0xF4]],
+            [0x27, 0x01] => vec![vec![0x67, 0x01, 0x12, 0x34]],
+            [0x27, 0x02, 0xED, 0xCB] => vec![vec![0x67, 0x02]],
+            [0x27, 0x02, ..] => vec![vec![0x7F, 0x27, 0x35]],
+            _ => vec![],
+        });
+
+        let timing = client
+            .diagnostic_session_control(SessionType::ExtendedDiagnostic)
+            .unwrap();
+        assert_eq!(timing.p2, Duration::from_millis(50));
+        assert_eq!(timing.p2_star, Duration::from_millis(5000));
+
+        let result = client.security_access(0x01, |_: u8, seed: &[u8]| {
+            seed.iter().map(|byte| !byte).collect::<Vec<u8>>()
+        });
+        assert_eq!(result, Ok(()));
+
+        // idle, keep-alive has to kick in
+        thread::sleep(Duration::from_millis(100));
+        drop(client);
+
+        let requests = server.join().unwrap();
+        assert!(requests.iter().any(|request| request == &[0x3E, 0x80]));
+    }
+
+    #[test]
+    fn download_001() {
+        let (client, server) = server(|request| match request {
+            [0x34, 0x00, 0x44, ..] => vec![vec![0x74, 0x20, 0x00, 0x12]],
+            [0x36, counter, ..] => vec![vec![0x76, *counter]],
+            [0x37] => vec![vec![0x77]],
+            _ => vec![],
+        });
+
+        let data = vec![0xA5; 40];
+        assert_eq!(
+            client.download(MemoryRange::new(0x0800_0000, 40), 0x00, &data),
+            Ok(())
+        );
+        drop(client);
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests.len(), 5);
+        assert_eq!(requests[1].len(), 18);
+        assert_eq!(requests[3][1], 3);
+    }
+
+    #[test]
+    fn parse_dtcs_001() {
+        assert_eq!(
+            parse_dtcs(&[0xFF, 0x01, 0x23, 0x45, 0x09]),
+            Ok((
+                0xFF,
+                vec![Dtc {
+                    code: 0x012345,
+                    status: 0x09
+                }]
+            ))
+        );
+        assert_eq!(parse_dtcs(&[0xFF, 0x01]), Err(UdsError::InvalidResponse));
+    }
+}
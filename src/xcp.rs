@@ -0,0 +1,1291 @@
+//! Universal measurement and calibration protocol (ASAM MCD-1 XCP) on CAN.
+//!
+//! An [XcpMaster] sends command transfer objects (CTO) to a single slave and waits for the
+//! response. Multi-byte parameters are encoded in the byte order reported by the slave on
+//! [connect](XcpMaster::connect), unless the configuration overrides it. Measurements are
+//! configured as dynamic [DaqList]s; the data transfer objects (DTO) the slave sends for them are
+//! received with [recv_daq](XcpMaster::recv_daq).
+
+use crate::error::PcanError;
+use crate::socket::{CanFdFrame, CanFrame, MessageType, RecvCan, RecvCanFd, SendCan, SendCanFd};
+use crate::uds::SeedKey;
+use std::collections::VecDeque;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, PartialEq)]
+pub enum XcpError {
+    Pcan(PcanError),
+    /// The slave did not answer within the timeout.
+    Timeout,
+    /// The slave answered with an error packet.
+    CommandError {
+        command: u8,
+        code: ErrorCode,
+    },
+    /// The response is too short or malformed.
+    InvalidResponse,
+    /// The command requires a connection established with [connect](XcpMaster::connect).
+    NotConnected,
+    /// The data does not fit into the packets negotiated with the slave.
+    PayloadTooLarge,
+    /// The DAQ lists do not describe a valid configuration.
+    InvalidDaqConfig,
+}
+
+impl From<PcanError> for XcpError {
+    fn from(value: PcanError) -> Self {
+        XcpError::Pcan(value)
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ErrorCode {
+    CmdSynch,
+    CmdBusy,
+    DaqActive,
+    PgmActive,
+    CmdUnknown,
+    CmdSyntax,
+    OutOfRange,
+    WriteProtected,
+    AccessDenied,
+    AccessLocked,
+    PageNotValid,
+    ModeNotValid,
+    SegmentNotValid,
+    Sequence,
+    DaqConfig,
+    MemoryOverflow,
+    Generic,
+    Verify,
+    ResourceTemporaryNotAccessible,
+    SubcmdUnknown,
+    Other(u8),
+}
+
+impl From<u8> for ErrorCode {
+    fn from(value: u8) -> Self {
+        match value {
+            0x00 => ErrorCode::CmdSynch,
+            0x10 => ErrorCode::CmdBusy,
+            0x11 => ErrorCode::DaqActive,
+            0x12 => ErrorCode::PgmActive,
+            0x20 => ErrorCode::CmdUnknown,
+            0x21 => ErrorCode::CmdSyntax,
+            0x22 => ErrorCode::OutOfRange,
+            0x23 => ErrorCode::WriteProtected,
+            0x24 => ErrorCode::AccessDenied,
+            0x25 => ErrorCode::AccessLocked,
+            0x26 => ErrorCode::PageNotValid,
+            0x27 => ErrorCode::ModeNotValid,
+            0x28 => ErrorCode::SegmentNotValid,
+            0x29 => ErrorCode::Sequence,
+            0x2A => ErrorCode::DaqConfig,
+            0x30 => ErrorCode::MemoryOverflow,
+            0x31 => ErrorCode::Generic,
+            0x32 => ErrorCode::Verify,
+            0x33 => ErrorCode::ResourceTemporaryNotAccessible,
+            0x34 => ErrorCode::SubcmdUnknown,
+            _ => ErrorCode::Other(value),
+        }
+    }
+}
+
+impl From<ErrorCode> for u8 {
+    fn from(value: ErrorCode) -> Self {
+        match value {
+            ErrorCode::CmdSynch => 0x00,
+            ErrorCode::CmdBusy => 0x10,
+            ErrorCode::DaqActive => 0x11,
+            ErrorCode::PgmActive => 0x12,
+            ErrorCode::CmdUnknown => 0x20,
+            ErrorCode::CmdSyntax => 0x21,
+            ErrorCode::OutOfRange => 0x22,
+            ErrorCode::WriteProtected => 0x23,
+            ErrorCode::AccessDenied => 0x24,
+            ErrorCode::AccessLocked => 0x25,
+            ErrorCode::PageNotValid => 0x26,
+            ErrorCode::ModeNotValid => 0x27,
+            ErrorCode::SegmentNotValid => 0x28,
+            ErrorCode::Sequence => 0x29,
+            ErrorCode::DaqConfig => 0x2A,
+            ErrorCode::MemoryOverflow => 0x30,
+            ErrorCode::Generic => 0x31,
+            ErrorCode::Verify => 0x32,
+            ErrorCode::ResourceTemporaryNotAccessible => 0x33,
+            ErrorCode::SubcmdUnknown => 0x34,
+            ErrorCode::Other(value) => value,
+        }
+    }
+}
+
+/* Commands */
+
+pub const CONNECT: u8 = 0xFF;
+pub const DISCONNECT: u8 = 0xFE;
+pub const GET_STATUS: u8 = 0xFD;
+pub const SYNCH: u8 = 0xFC;
+pub const GET_SEED: u8 = 0xF8;
+pub const UNLOCK: u8 = 0xF7;
+pub const SET_MTA: u8 = 0xF6;
+pub const UPLOAD: u8 = 0xF5;
+pub const SHORT_UPLOAD: u8 = 0xF4;
+pub const DOWNLOAD: u8 = 0xF0;
+pub const SET_DAQ_PTR: u8 = 0xE2;
+pub const WRITE_DAQ: u8 = 0xE1;
+pub const SET_DAQ_LIST_MODE: u8 = 0xE0;
+pub const START_STOP_DAQ_LIST: u8 = 0xDE;
+pub const START_STOP_SYNCH: u8 = 0xDD;
+pub const GET_DAQ_PROCESSOR_INFO: u8 = 0xDA;
+pub const GET_DAQ_RESOLUTION_INFO: u8 = 0xD9;
+pub const FREE_DAQ: u8 = 0xD6;
+pub const ALLOC_DAQ: u8 = 0xD5;
+pub const ALLOC_ODT: u8 = 0xD4;
+pub const ALLOC_ODT_ENTRY: u8 = 0xD3;
+
+/* Packet identifiers of the slave */
+
+const PID_RESPONSE: u8 = 0xFF;
+const PID_ERROR: u8 = 0xFE;
+const PID_EVENT: u8 = 0xFD;
+const PID_SERVICE: u8 = 0xFC;
+
+/* Resources */
+
+pub const RESOURCE_CAL_PAG: u8 = 0x01;
+pub const RESOURCE_DAQ: u8 = 0x04;
+pub const RESOURCE_STIM: u8 = 0x08;
+pub const RESOURCE_PGM: u8 = 0x10;
+
+/// Byte order of multi-byte parameters and values.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ByteOrder {
+    /// Little endian.
+    Intel,
+    /// Big endian.
+    Motorola,
+}
+
+impl ByteOrder {
+    pub fn encode_u16(&self, value: u16) -> [u8; 2] {
+        match self {
+            ByteOrder::Intel => value.to_le_bytes(),
+            ByteOrder::Motorola => value.to_be_bytes(),
+        }
+    }
+
+    pub fn encode_u32(&self, value: u32) -> [u8; 4] {
+        match self {
+            ByteOrder::Intel => value.to_le_bytes(),
+            ByteOrder::Motorola => value.to_be_bytes(),
+        }
+    }
+
+    pub fn decode_u16(&self, bytes: [u8; 2]) -> u16 {
+        match self {
+            ByteOrder::Intel => u16::from_le_bytes(bytes),
+            ByteOrder::Motorola => u16::from_be_bytes(bytes),
+        }
+    }
+
+    pub fn decode_u32(&self, bytes: [u8; 4]) -> u32 {
+        match self {
+            ByteOrder::Intel => u32::from_le_bytes(bytes),
+            ByteOrder::Motorola => u32::from_be_bytes(bytes),
+        }
+    }
+}
+
+/// Parameters of the connection reported by the slave.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct ConnectInfo {
+    /// Resources available in the slave, see `RESOURCE_*`.
+    pub resource: u8,
+    pub comm_mode_basic: u8,
+    pub byte_order: ByteOrder,
+    /// Size of an element in bytes: 1, 2 or 4.
+    pub address_granularity: u8,
+    /// Maximum length of command and response packets.
+    pub max_cto: u8,
+    /// Maximum length of DAQ packets.
+    pub max_dto: u16,
+    pub protocol_version: u8,
+    pub transport_version: u8,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Status {
+    pub session_status: u8,
+    /// Resources protected by seed and key, see `RESOURCE_*`.
+    pub protection_status: u8,
+    pub session_configuration_id: u16,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct DaqProcessorInfo {
+    pub properties: u8,
+    pub max_daq: u16,
+    pub max_event_channel: u16,
+    /// Number of predefined DAQ lists, which are not touched by dynamic configuration.
+    pub min_daq: u8,
+    pub key_byte: u8,
+}
+
+/* DAQ lists */
+
+/// An element measured by a DAQ list.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct OdtEntry {
+    pub address: u32,
+    pub extension: u8,
+    /// Size of the element in bytes.
+    pub size: u8,
+}
+
+impl OdtEntry {
+    pub fn new(address: u32, extension: u8, size: u8) -> OdtEntry {
+        OdtEntry {
+            address,
+            extension,
+            size,
+        }
+    }
+}
+
+/// A DAQ list sampled on an event channel of the slave.
+///
+/// Each object descriptor table (ODT) becomes one DAQ packet and must fit into MAX_DTO together
+/// with the packet identifier and, for the first ODT of a timestamped list, the timestamp.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DaqList {
+    event_channel: u16,
+    prescaler: u8,
+    priority: u8,
+    timestamp: bool,
+    odts: Vec<Vec<OdtEntry>>,
+}
+
+impl DaqList {
+    pub fn new(event_channel: u16) -> DaqList {
+        DaqList {
+            event_channel,
+            prescaler: 1,
+            priority: 0,
+            timestamp: false,
+            odts: Vec::new(),
+        }
+    }
+
+    /// Transmits only every n-th sample of the event channel.
+    pub fn with_prescaler(mut self, prescaler: u8) -> DaqList {
+        self.prescaler = prescaler.max(1);
+        self
+    }
+
+    pub fn with_priority(mut self, priority: u8) -> DaqList {
+        self.priority = priority;
+        self
+    }
+
+    /// Requests a timestamp in the first packet of every sample.
+    pub fn with_timestamp(mut self, timestamp: bool) -> DaqList {
+        self.timestamp = timestamp;
+        self
+    }
+
+    pub fn with_odt(mut self, entries: Vec<OdtEntry>) -> DaqList {
+        self.odts.push(entries);
+        self
+    }
+
+    pub fn odts(&self) -> &[Vec<OdtEntry>] {
+        &self.odts
+    }
+}
+
+/// A decoded DAQ packet.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DaqPacket {
+    /// Index of the list in the configuration passed to [configure_daq](XcpMaster::configure_daq).
+    pub daq_list: u16,
+    pub odt: u8,
+    /// Time of the sample on the clock of the slave, if requested for the list.
+    pub timestamp: Option<Duration>,
+    /// Raw values of the ODT entries, in the byte order of the slave.
+    pub values: Vec<Vec<u8>>,
+}
+
+/// Settings of an [XcpMaster].
+#[derive(Debug, PartialEq, Clone)]
+pub struct XcpConfig {
+    master_id: u32,
+    slave_id: u32,
+    msg_type: MessageType,
+    timeout: Duration,
+    padding: Option<u8>,
+    brs: bool,
+    byte_order: Option<ByteOrder>,
+}
+
+impl XcpConfig {
+    /// Creates a configuration sending commands with `master_id` and receiving responses with
+    /// `slave_id` using 11-bit identifiers.
+    pub fn new(master_id: u32, slave_id: u32) -> XcpConfig {
+        XcpConfig {
+            master_id,
+            slave_id,
+            msg_type: MessageType::Standard,
+            timeout: Duration::from_millis(1000),
+            padding: None,
+            brs: false,
+            byte_order: None,
+        }
+    }
+
+    pub fn with_message_type(mut self, msg_type: MessageType) -> XcpConfig {
+        self.msg_type = msg_type;
+        self
+    }
+
+    /// Sets how long to wait for a response (timeout t1).
+    pub fn with_timeout(mut self, timeout: Duration) -> XcpConfig {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Pads commands to the maximum data length with `padding`, as required by slaves with
+    /// MAX_DLC_REQUIRED.
+    pub fn with_padding(mut self, padding: u8) -> XcpConfig {
+        self.padding = Some(padding);
+        self
+    }
+
+    /// Enables the bit rate switch of CAN FD frames. Ignored on classic sockets.
+    pub fn with_brs(mut self, brs: bool) -> XcpConfig {
+        self.brs = brs;
+        self
+    }
+
+    /// Overrides the byte order reported by the slave on connect.
+    pub fn with_byte_order(mut self, byte_order: ByteOrder) -> XcpConfig {
+        self.byte_order = Some(byte_order);
+        self
+    }
+
+    pub fn master_id(&self) -> u32 {
+        self.master_id
+    }
+
+    pub fn slave_id(&self) -> u32 {
+        self.slave_id
+    }
+}
+
+/* XcpMaster */
+
+type SendFn<S> = fn(&S, &XcpConfig, &[u8]) -> Result<(), PcanError>;
+type RecvFn<S> = fn(&S) -> Result<(u32, bool, Vec<u8>), PcanError>;
+
+fn send_can<S: SendCan>(socket: &S, config: &XcpConfig, data: &[u8]) -> Result<(), PcanError> {
+    let frame = CanFrame::new(config.master_id, config.msg_type, data)
+        .map_err(|_| PcanError::IllParamVal)?;
+    socket.send(frame)
+}
+
+fn recv_can<S: RecvCan>(socket: &S) -> Result<(u32, bool, Vec<u8>), PcanError> {
+    let frame = socket.recv_frame()?;
+    Ok((
+        frame.can_id(),
+        frame.is_extended_frame(),
+        frame.data().to_vec(),
+    ))
+}
+
+fn send_can_fd<S: SendCanFd>(socket: &S, config: &XcpConfig, data: &[u8]) -> Result<(), PcanError> {
+    let mut frame = CanFdFrame::new(config.master_id, config.msg_type, data)
+        .map_err(|_| PcanError::IllParamVal)?;
+    frame.set_brs(config.brs);
+    socket.send_fd(frame)
+}
+
+fn recv_can_fd<S: RecvCanFd>(socket: &S) -> Result<(u32, bool, Vec<u8>), PcanError> {
+    let frame = socket.recv_fd_frame()?;
+    Ok((
+        frame.can_id(),
+        frame.is_extended_frame(),
+        frame.data().to_vec(),
+    ))
+}
+
+const POLL_INTERVAL: Duration = Duration::from_micros(100);
+
+/// Layout of a configured DAQ list.
+struct DaqLayout {
+    first_pid: u8,
+    timestamp: bool,
+    odts: Vec<Vec<u8>>,
+}
+
+/// An XCP master talking to a single slave.
+///
+/// DAQ packets received while waiting for a response are queued for
+/// [recv_daq](XcpMaster::recv_daq); events and service requests of the slave are discarded.
+pub struct XcpMaster<S> {
+    socket: S,
+    config: XcpConfig,
+    max_dl: usize,
+    send: SendFn<S>,
+    recv: RecvFn<S>,
+    connection: Option<ConnectInfo>,
+    daq: Vec<DaqLayout>,
+    timestamp_size: usize,
+    timestamp_tick: Duration,
+    packets: VecDeque<DaqPacket>,
+}
+
+impl<S: SendCan + RecvCan> XcpMaster<S> {
+    /// Creates a master sending classic frames.
+    pub fn new(socket: S, config: XcpConfig) -> XcpMaster<S> {
+        XcpMaster::with_link(socket, config, 8, send_can::<S>, recv_can::<S>)
+    }
+}
+
+impl<S: SendCanFd + RecvCanFd> XcpMaster<S> {
+    /// Creates a master sending CAN FD frames. MAX_CTO and MAX_DTO may be up to 64 bytes.
+    pub fn new_fd(socket: S, config: XcpConfig) -> XcpMaster<S> {
+        XcpMaster::with_link(socket, config, 64, send_can_fd::<S>, recv_can_fd::<S>)
+    }
+}
+
+impl<S> XcpMaster<S> {
+    fn with_link(
+        socket: S,
+        config: XcpConfig,
+        max_dl: usize,
+        send: SendFn<S>,
+        recv: RecvFn<S>,
+    ) -> XcpMaster<S> {
+        XcpMaster {
+            socket,
+            config,
+            max_dl,
+            send,
+            recv,
+            connection: None,
+            daq: Vec::new(),
+            timestamp_size: 0,
+            timestamp_tick: Duration::ZERO,
+            packets: VecDeque::new(),
+        }
+    }
+
+    pub fn socket(&self) -> &S {
+        &self.socket
+    }
+
+    pub fn into_socket(self) -> S {
+        self.socket
+    }
+
+    pub fn config(&self) -> &XcpConfig {
+        &self.config
+    }
+
+    /// Returns the parameters of the current connection.
+    pub fn connection(&self) -> Option<&ConnectInfo> {
+        self.connection.as_ref()
+    }
+
+    /// Byte order used for multi-byte parameters.
+    pub fn byte_order(&self) -> ByteOrder {
+        match (self.config.byte_order, self.connection) {
+            (Some(byte_order), _) => byte_order,
+            (None, Some(connection)) => connection.byte_order,
+            (None, None) => ByteOrder::Intel,
+        }
+    }
+
+    /// Maximum length of command packets, 8 bytes before connecting.
+    fn max_cto(&self) -> usize {
+        self.connection
+            .map_or(8, |connection| connection.max_cto as usize)
+            .min(self.max_dl)
+    }
+
+    fn connected(&self) -> Result<ConnectInfo, XcpError> {
+        self.connection.ok_or(XcpError::NotConnected)
+    }
+
+    /* Transport */
+
+    fn send_packet(&self, packet: &[u8]) -> Result<(), XcpError> {
+        if packet.len() > self.max_cto() {
+            return Err(XcpError::PayloadTooLarge);
+        }
+
+        match self.config.padding {
+            Some(padding) if packet.len() < self.max_cto() => {
+                let mut padded = packet.to_vec();
+                padded.resize(self.max_cto(), padding);
+                Ok((self.send)(&self.socket, &self.config, &padded)?)
+            }
+            _ => Ok((self.send)(&self.socket, &self.config, packet)?),
+        }
+    }
+
+    /// Sends a command and waits for the positive response, which is returned with its PID.
+    fn command(&mut self, packet: &[u8]) -> Result<Vec<u8>, XcpError> {
+        self.send_packet(packet)?;
+        let deadline = Instant::now() + self.config.timeout;
+
+        loop {
+            let (can_id, extended, data) = match (self.recv)(&self.socket) {
+                Ok(frame) => frame,
+                Err(PcanError::QrcvEmpty) => {
+                    if Instant::now() >= deadline {
+                        return Err(XcpError::Timeout);
+                    }
+                    thread::sleep(POLL_INTERVAL);
+                    continue;
+                }
+                Err(err) => return Err(XcpError::Pcan(err)),
+            };
+
+            if !self.is_from_slave(can_id, extended) || data.is_empty() {
+                continue;
+            }
+
+            match data[0] {
+                PID_RESPONSE => return Ok(data),
+                PID_ERROR => {
+                    return Err(XcpError::CommandError {
+                        command: packet[0],
+                        code: ErrorCode::from(*data.get(1).unwrap_or(&0x31)),
+                    })
+                }
+                PID_EVENT | PID_SERVICE => {}
+                _ => self.queue_daq(&data),
+            }
+        }
+    }
+
+    /// Sends a command and checks that the response has at least `length` bytes.
+    fn command_checked(&mut self, packet: &[u8], length: usize) -> Result<Vec<u8>, XcpError> {
+        let response = self.command(packet)?;
+        if response.len() < length {
+            return Err(XcpError::InvalidResponse);
+        }
+        Ok(response)
+    }
+
+    fn is_from_slave(&self, can_id: u32, extended: bool) -> bool {
+        can_id == self.config.slave_id
+            && extended == (self.config.msg_type == MessageType::Extended)
+    }
+
+    /* Session */
+
+    /// Connects to the slave in normal mode and negotiates the packet sizes.
+    pub fn connect(&mut self) -> Result<ConnectInfo, XcpError> {
+        self.connection = None;
+        let response = self.command_checked(&[CONNECT, 0x00], 8)?;
+
+        let comm_mode_basic = response[2];
+        let byte_order = match comm_mode_basic & 0x01 {
+            0 => ByteOrder::Intel,
+            _ => ByteOrder::Motorola,
+        };
+        let max_dto = [response[4], response[5]];
+        let connection = ConnectInfo {
+            resource: response[1],
+            comm_mode_basic,
+            byte_order,
+            address_granularity: 1 << ((comm_mode_basic >> 1) & 0x03),
+            max_cto: response[3],
+            max_dto: self
+                .config
+                .byte_order
+                .unwrap_or(byte_order)
+                .decode_u16(max_dto),
+            protocol_version: response[6],
+            transport_version: response[7],
+        };
+
+        if (connection.max_cto as usize) < 8 {
+            return Err(XcpError::InvalidResponse);
+        }
+        self.connection = Some(connection);
+        Ok(connection)
+    }
+
+    pub fn disconnect(&mut self) -> Result<(), XcpError> {
+        self.connected()?;
+        self.command(&[DISCONNECT])?;
+        self.connection = None;
+        Ok(())
+    }
+
+    pub fn get_status(&mut self) -> Result<Status, XcpError> {
+        self.connected()?;
+        let response = self.command_checked(&[GET_STATUS], 6)?;
+        Ok(Status {
+            session_status: response[1],
+            protection_status: response[2],
+            session_configuration_id: self.byte_order().decode_u16([response[4], response[5]]),
+        })
+    }
+
+    /// Resynchronizes the command processor of the slave. A positive slave answers with
+    /// [ErrorCode::CmdSynch], which is reported as success.
+    pub fn synch(&mut self) -> Result<(), XcpError> {
+        match self.command(&[SYNCH]) {
+            Ok(_)
+            | Err(XcpError::CommandError {
+                code: ErrorCode::CmdSynch,
+                ..
+            }) => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /* Seed and key */
+
+    /// Unlocks `resource` (one of `RESOURCE_*`) with the key computed by `seed_key` from the
+    /// seed of the slave. The level passed to `seed_key` is the resource. Returns the resources
+    /// still protected afterwards.
+    pub fn unlock<K: SeedKey>(&mut self, resource: u8, mut seed_key: K) -> Result<u8, XcpError> {
+        self.connected()?;
+
+        let mut seed = Vec::new();
+        let mut mode = 0u8;
+        loop {
+            let response = self.command_checked(&[GET_SEED, mode, resource], 2)?;
+            let remaining = response[1] as usize;
+            // a zero length seed means the resource is not protected
+            if remaining == 0 && mode == 0 {
+                return Ok(self.get_status()?.protection_status);
+            }
+
+            let available = (response.len() - 2).min(remaining);
+            seed.extend_from_slice(&response[2..2 + available]);
+            if available == remaining {
+                break;
+            }
+            mode = 1;
+        }
+
+        let key = seed_key.key(resource, &seed);
+        if key.len() > u8::MAX as usize {
+            return Err(XcpError::PayloadTooLarge);
+        }
+
+        let chunk_size = self.max_cto() - 2;
+        let mut remaining = key.as_slice();
+        loop {
+            let chunk = &remaining[..remaining.len().min(chunk_size)];
+            let mut packet = vec![UNLOCK, remaining.len() as u8];
+            packet.extend_from_slice(chunk);
+            let response = self.command(&packet)?;
+
+            remaining = &remaining[chunk.len()..];
+            if remaining.is_empty() {
+                return response.get(1).copied().ok_or(XcpError::InvalidResponse);
+            }
+        }
+    }
+
+    /* Memory */
+
+    /// Sets the memory transfer address used by [upload](XcpMaster::upload) and
+    /// [download](XcpMaster::download).
+    pub fn set_mta(&mut self, address: u32, extension: u8) -> Result<(), XcpError> {
+        self.connected()?;
+        let mut packet = vec![SET_MTA, 0, 0, extension];
+        packet.extend_from_slice(&self.byte_order().encode_u32(address));
+        self.command(&packet).map(|_| ())
+    }
+
+    /// Reads `size` bytes from the memory transfer address, which is advanced accordingly.
+    ///
+    /// Sizes count bytes, so the slave must use an address granularity of one byte.
+    pub fn upload(&mut self, size: usize) -> Result<Vec<u8>, XcpError> {
+        let chunk_size = self.max_cto() - 1;
+        self.connected()?;
+
+        let mut data = Vec::with_capacity(size);
+        while data.len() < size {
+            let count = (size - data.len()).min(chunk_size);
+            let response = self.command_checked(&[UPLOAD, count as u8], 1 + count)?;
+            data.extend_from_slice(&response[1..1 + count]);
+        }
+        Ok(data)
+    }
+
+    /// Reads up to MAX_CTO - 1 bytes from `address` with a single command.
+    pub fn short_upload(
+        &mut self,
+        address: u32,
+        extension: u8,
+        size: u8,
+    ) -> Result<Vec<u8>, XcpError> {
+        self.connected()?;
+        if size as usize > self.max_cto() - 1 {
+            return Err(XcpError::PayloadTooLarge);
+        }
+
+        let mut packet = vec![SHORT_UPLOAD, size, 0, extension];
+        packet.extend_from_slice(&self.byte_order().encode_u32(address));
+        let response = self.command_checked(&packet, 1 + size as usize)?;
+        Ok(response[1..1 + size as usize].to_vec())
+    }
+
+    /// Writes `data` to the memory transfer address, which is advanced accordingly.
+    pub fn download(&mut self, data: &[u8]) -> Result<(), XcpError> {
+        self.connected()?;
+        for chunk in data.chunks(self.max_cto() - 2) {
+            let mut packet = vec![DOWNLOAD, chunk.len() as u8];
+            packet.extend_from_slice(chunk);
+            self.command(&packet)?;
+        }
+        Ok(())
+    }
+
+    /// Reads `size` bytes at `address`, with a single short upload if possible.
+    pub fn read_memory(
+        &mut self,
+        address: u32,
+        extension: u8,
+        size: usize,
+    ) -> Result<Vec<u8>, XcpError> {
+        if size < self.max_cto() {
+            return self.short_upload(address, extension, size as u8);
+        }
+        self.set_mta(address, extension)?;
+        self.upload(size)
+    }
+
+    pub fn write_memory(
+        &mut self,
+        address: u32,
+        extension: u8,
+        data: &[u8],
+    ) -> Result<(), XcpError> {
+        self.set_mta(address, extension)?;
+        self.download(data)
+    }
+
+    /* DAQ */
+
+    pub fn daq_processor_info(&mut self) -> Result<DaqProcessorInfo, XcpError> {
+        self.connected()?;
+        let response = self.command_checked(&[GET_DAQ_PROCESSOR_INFO], 8)?;
+        let byte_order = self.byte_order();
+        Ok(DaqProcessorInfo {
+            properties: response[1],
+            max_daq: byte_order.decode_u16([response[2], response[3]]),
+            max_event_channel: byte_order.decode_u16([response[4], response[5]]),
+            min_daq: response[6],
+            key_byte: response[7],
+        })
+    }
+
+    /// Reads the size and the resolution of DAQ timestamps.
+    fn timestamp_resolution(&mut self) -> Result<(usize, Duration), XcpError> {
+        let response = self.command_checked(&[GET_DAQ_RESOLUTION_INFO], 8)?;
+        let mode = response[5];
+        let ticks = self.byte_order().decode_u16([response[6], response[7]]) as u64;
+
+        let size = match mode & 0x07 {
+            size @ (1 | 2 | 4) => size as usize,
+            _ => return Err(XcpError::InvalidDaqConfig),
+        };
+        // the unit is 10^n ns for n up to 9, i.e. one second
+        let unit = match mode >> 4 {
+            exponent @ 0..=9 => Duration::from_nanos(10u64.pow(exponent as u32)),
+            _ => return Err(XcpError::InvalidDaqConfig),
+        };
+        Ok((size, unit * ticks as u32))
+    }
+
+    /// Replaces the dynamic DAQ configuration of the slave with `lists`.
+    ///
+    /// The lists are numbered from zero, so the slave must not have predefined lists (MIN_DAQ 0).
+    pub fn configure_daq(&mut self, lists: &[DaqList]) -> Result<(), XcpError> {
+        let connection = self.connected()?;
+        let byte_order = self.byte_order();
+        let max_dto = (connection.max_dto as usize).min(self.max_dl);
+
+        let timestamped = lists.iter().any(|list| list.timestamp);
+        let (timestamp_size, timestamp_tick) = match timestamped {
+            true => self.timestamp_resolution()?,
+            false => (0, Duration::ZERO),
+        };
+
+        for list in lists {
+            if list.odts.is_empty() || list.odts.len() > 252 {
+                return Err(XcpError::InvalidDaqConfig);
+            }
+            for (odt, entries) in list.odts.iter().enumerate() {
+                let timestamp = match list.timestamp && odt == 0 {
+                    true => timestamp_size,
+                    false => 0,
+                };
+                let length = entries
+                    .iter()
+                    .map(|entry| entry.size as usize)
+                    .sum::<usize>();
+                if entries.is_empty() || entries.len() > 255 || 1 + timestamp + length > max_dto {
+                    return Err(XcpError::InvalidDaqConfig);
+                }
+            }
+        }
+
+        self.daq.clear();
+        self.command(&[FREE_DAQ])?;
+
+        let count = byte_order.encode_u16(lists.len() as u16);
+        self.command(&[ALLOC_DAQ, 0, count[0], count[1]])?;
+        for (number, list) in lists.iter().enumerate() {
+            let [low, high] = byte_order.encode_u16(number as u16);
+            self.command(&[ALLOC_ODT, 0, low, high, list.odts.len() as u8])?;
+        }
+        for (number, list) in lists.iter().enumerate() {
+            let [low, high] = byte_order.encode_u16(number as u16);
+            for (odt, entries) in list.odts.iter().enumerate() {
+                self.command(&[
+                    ALLOC_ODT_ENTRY,
+                    0,
+                    low,
+                    high,
+                    odt as u8,
+                    entries.len() as u8,
+                ])?;
+            }
+        }
+
+        for (number, list) in lists.iter().enumerate() {
+            let [low, high] = byte_order.encode_u16(number as u16);
+            for (odt, entries) in list.odts.iter().enumerate() {
+                self.command(&[SET_DAQ_PTR, 0, low, high, odt as u8, 0])?;
+                for entry in entries {
+                    let mut packet = vec![WRITE_DAQ, 0xFF, entry.size, entry.extension];
+                    packet.extend_from_slice(&byte_order.encode_u32(entry.address));
+                    self.command(&packet)?;
+                }
+            }
+
+            let mode = match list.timestamp {
+                true => 0x10,
+                false => 0x00,
+            };
+            let [event_low, event_high] = byte_order.encode_u16(list.event_channel);
+            self.command(&[
+                SET_DAQ_LIST_MODE,
+                mode,
+                low,
+                high,
+                event_low,
+                event_high,
+                list.prescaler,
+                list.priority,
+            ])?;
+        }
+
+        self.timestamp_size = timestamp_size;
+        self.timestamp_tick = timestamp_tick;
+        self.daq = lists
+            .iter()
+            .map(|list| DaqLayout {
+                first_pid: 0,
+                timestamp: list.timestamp,
+                odts: list
+                    .odts
+                    .iter()
+                    .map(|entries| entries.iter().map(|entry| entry.size).collect())
+                    .collect(),
+            })
+            .collect();
+        Ok(())
+    }
+
+    /// Starts all configured DAQ lists synchronously.
+    pub fn start_daq(&mut self) -> Result<(), XcpError> {
+        self.connected()?;
+        if self.daq.is_empty() {
+            return Err(XcpError::InvalidDaqConfig);
+        }
+
+        let byte_order = self.byte_order();
+        for number in 0..self.daq.len() {
+            let [low, high] = byte_order.encode_u16(number as u16);
+            // select, the response carries the PID of the first ODT
+            let response = self.command_checked(&[START_STOP_DAQ_LIST, 0x02, low, high], 2)?;
+            self.daq[number].first_pid = response[1];
+        }
+        self.packets.clear();
+        self.command(&[START_STOP_SYNCH, 0x01]).map(|_| ())
+    }
+
+    /// Stops all DAQ lists.
+    pub fn stop_daq(&mut self) -> Result<(), XcpError> {
+        self.connected()?;
+        self.command(&[START_STOP_SYNCH, 0x00]).map(|_| ())
+    }
+
+    /// Decodes a DAQ packet, dropping packets of unknown ODTs.
+    fn queue_daq(&mut self, data: &[u8]) {
+        let pid = data[0];
+        let found = self.daq.iter().enumerate().find_map(|(number, layout)| {
+            let odt = pid.checked_sub(layout.first_pid)? as usize;
+            (odt < layout.odts.len()).then_some((number, odt))
+        });
+        let (number, odt) = match found {
+            Some(found) => found,
+            None => return,
+        };
+
+        let layout = &self.daq[number];
+        let mut offset = 1;
+        let timestamp = match layout.timestamp && odt == 0 {
+            true => {
+                let size = self.timestamp_size;
+                if data.len() < offset + size {
+                    return;
+                }
+                let mut bytes = [0u8; 4];
+                match self.byte_order() {
+                    ByteOrder::Intel => bytes[..size].copy_from_slice(&data[1..1 + size]),
+                    ByteOrder::Motorola => bytes[4 - size..].copy_from_slice(&data[1..1 + size]),
+                }
+                offset += size;
+                let ticks = self.byte_order().decode_u32(bytes);
+                Some(self.timestamp_tick * ticks)
+            }
+            false => None,
+        };
+
+        let mut values = Vec::with_capacity(layout.odts[odt].len());
+        for size in layout.odts[odt].iter() {
+            let size = *size as usize;
+            match data.get(offset..offset + size) {
+                Some(value) => values.push(value.to_vec()),
+                None => return,
+            }
+            offset += size;
+        }
+
+        self.packets.push_back(DaqPacket {
+            daq_list: number as u16,
+            odt: odt as u8,
+            timestamp,
+            values,
+        });
+    }
+
+    /// Waits for the next DAQ packet.
+    pub fn recv_daq(&mut self, timeout: Duration) -> Result<DaqPacket, XcpError> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            if let Some(packet) = self.packets.pop_front() {
+                return Ok(packet);
+            }
+
+            match (self.recv)(&self.socket) {
+                Ok((can_id, extended, data)) => {
+                    if self.is_from_slave(can_id, extended)
+                        && !data.is_empty()
+                        && data[0] < PID_SERVICE
+                    {
+                        self.queue_daq(&data);
+                    }
+                }
+                Err(PcanError::QrcvEmpty) => {
+                    if Instant::now() >= deadline {
+                        return Err(XcpError::Timeout);
+                    }
+                    thread::sleep(POLL_INTERVAL);
+                }
+                Err(err) => return Err(XcpError::Pcan(err)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::socket::pipe::{pipe, PipeSocket};
+    use std::thread::JoinHandle;
+
+    const MASTER_ID: u32 = 0x1800_0001;
+    const SLAVE_ID: u32 = 0x1800_0002;
+
+    /// Big endian slave with 256 bytes of memory, protecting calibration with a seed whose key
+    /// is the inverted seed.
+    struct Slave {
+        socket: PipeSocket,
+        memory: Vec<u8>,
+        mta: usize,
+        locked: bool,
+        daq: Vec<Vec<Vec<(u32, u8)>>>,
+        pointer: (usize, usize),
+        running: bool,
+    }
+
+    impl Slave {
+        fn respond(&self, data: &[u8]) {
+            self.socket
+                .send(CanFrame::new(SLAVE_ID, MessageType::Extended, data).unwrap())
+                .unwrap();
+        }
+
+        fn handle(&mut self, request: &[u8]) -> bool {
+            let address = u32::from_be_bytes([request[4], request[5], request[6], request[7]]);
+            let seed = [0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC, 0xDE];
+            match request[0] {
+                CONNECT => self.respond(&[0xFF, 0x05, 0x01, 8, 0x00, 0x08, 1, 1]),
+                GET_STATUS => {
+                    self.respond(&[0xFF, self.running as u8, self.locked as u8, 0, 0x12, 0x34])
+                }
+                GET_SEED if request[1] == 0 => {
+                    self.respond(&[0xFF, 7, 0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC])
+                }
+                GET_SEED => self.respond(&[0xFF, 1, 0xDE]),
+                UNLOCK => {
+                    let offset = 7 - request[1] as usize;
+                    let length = (request[1] as usize).min(6);
+                    let valid = request[2..2 + length]
+                        .iter()
+                        .zip(&seed[offset..offset + length])
+                        .all(|(key, seed)| *key == !*seed);
+                    if !valid {
+                        self.respond(&[0xFE, 0x29]);
+                    } else {
+                        self.locked = request[1] as usize > length;
+                        self.respond(&[0xFF, self.locked as u8]);
+                    }
+                }
+                _ if self.locked => self.respond(&[0xFE, 0x25]),
+                SET_MTA => {
+                    self.mta = address as usize;
+                    self.respond(&[0xFF]);
+                }
+                UPLOAD => {
+                    let size = request[1] as usize;
+                    let mut response = vec![0xFF];
+                    response.extend_from_slice(&self.memory[self.mta..self.mta + size]);
+                    self.mta += size;
+                    self.respond(&response);
+                }
+                SHORT_UPLOAD => {
+                    let (start, size) = (address as usize, request[1] as usize);
+                    let mut response = vec![0xFF];
+                    response.extend_from_slice(&self.memory[start..start + size]);
+                    self.respond(&response);
+                }
+                DOWNLOAD => {
+                    let size = request[1] as usize;
+                    self.memory[self.mta..self.mta + size].copy_from_slice(&request[2..2 + size]);
+                    self.mta += size;
+                    self.respond(&[0xFF]);
+                }
+                FREE_DAQ => {
+                    self.daq.clear();
+                    self.respond(&[0xFF]);
+                }
+                ALLOC_DAQ => {
+                    self.daq = vec![Vec::new(); request[3] as usize];
+                    self.respond(&[0xFF]);
+                }
+                ALLOC_ODT => {
+                    self.daq[request[3] as usize] = vec![Vec::new(); request[4] as usize];
+                    self.respond(&[0xFF]);
+                }
+                ALLOC_ODT_ENTRY => self.respond(&[0xFF]),
+                SET_DAQ_PTR => {
+                    self.pointer = (request[3] as usize, request[4] as usize);
+                    self.respond(&[0xFF]);
+                }
+                WRITE_DAQ => {
+                    let (list, odt) = self.pointer;
+                    self.daq[list][odt].push((address, request[2]));
+                    self.respond(&[0xFF]);
+                }
+                SET_DAQ_LIST_MODE => self.respond(&[0xFF]),
+                GET_DAQ_RESOLUTION_INFO => {
+                    // 2 byte timestamps with 10 ticks of 1 us
+                    self.respond(&[0xFF, 1, 6, 1, 6, 0x32, 0x00, 10])
+                }
+                START_STOP_DAQ_LIST => self.respond(&[0xFF, 4 * request[3]]),
+                START_STOP_SYNCH => {
+                    self.respond(&[0xFF]);
+                    self.running = request[1] == 1;
+                    if self.running {
+                        for (list, odts) in self.daq.iter().enumerate() {
+                            for (odt, entries) in odts.iter().enumerate() {
+                                let mut packet = vec![(4 * list + odt) as u8];
+                                if list == 0 && odt == 0 {
+                                    packet.extend_from_slice(&[0x01, 0x00]);
+                                }
+                                for (address, size) in entries {
+                                    let start = *address as usize;
+                                    packet.extend_from_slice(
+                                        &self.memory[start..start + *size as usize],
+                                    );
+                                }
+                                self.respond(&packet);
+                            }
+                        }
+                    }
+                }
+                DISCONNECT => {
+                    self.respond(&[0xFF]);
+                    return false;
+                }
+                _ => self.respond(&[0xFE, 0x20]),
+            }
+            true
+        }
+    }
+
+    fn spawn_slave() -> (XcpMaster<PipeSocket>, JoinHandle<Vec<u8>>) {
+        let (master, slave) = pipe();
+        let config = XcpConfig::new(MASTER_ID, SLAVE_ID)
+            .with_message_type(MessageType::Extended)
+            .with_timeout(Duration::from_millis(500));
+
+        let handle = thread::spawn(move || {
+            let mut slave = Slave {
+                socket: slave,
+                memory: (0..=255u8).collect(),
+                mta: 0,
+                locked: true,
+                daq: Vec::new(),
+                pointer: (0, 0),
+                running: false,
+            };
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while Instant::now() < deadline {
+                match slave.socket.recv_frame() {
+                    Ok(frame) if frame.can_id() == MASTER_ID && frame.is_extended_frame() => {
+                        let mut request = frame.data().to_vec();
+                        request.resize(8, 0);
+                        if !slave.handle(&request) {
+                            break;
+                        }
+                    }
+                    _ => thread::sleep(Duration::from_micros(50)),
+                }
+            }
+            slave.memory
+        });
+
+        (XcpMaster::new(master, config), handle)
+    }
+
+    #[test]
+    fn xcp_001() {
+        let (mut master, slave) = spawn_slave();
+        assert_eq!(master.get_status(), Err(XcpError::NotConnected));
+
+        let connection = master.connect().unwrap();
+        assert_eq!(connection.byte_order, ByteOrder::Motorola);
+        assert_eq!(connection.max_dto, 8);
+        assert_eq!(master.byte_order(), ByteOrder::Motorola);
+
+        assert_eq!(
+            master.short_upload(0x10, 0, 4),
+            Err(XcpError::CommandError {
+                command: SHORT_UPLOAD,
+                code: ErrorCode::AccessLocked
+            })
+        );
+        let key = |resource: u8, seed: &[u8]| {
+            assert_eq!(resource, RESOURCE_CAL_PAG);
+            seed.iter().map(|byte| !byte).collect::<Vec<_>>()
+        };
+        assert_eq!(master.unlock(RESOURCE_CAL_PAG, key), Ok(0));
+        assert_eq!(
+            master.get_status(),
+            Ok(Status {
+                session_status: 0,
+                protection_status: 0,
+                session_configuration_id: 0x1234
+            })
+        );
+
+        assert_eq!(
+            master.short_upload(0x10, 0, 4),
+            Ok(vec![0x10, 0x11, 0x12, 0x13])
+        );
+        assert_eq!(
+            master.read_memory(0x20, 0, 20),
+            Ok((0x20..0x34).collect::<Vec<u8>>())
+        );
+        assert_eq!(master.write_memory(0x40, 0, &[0xAA; 10]), Ok(()));
+        assert_eq!(master.disconnect(), Ok(()));
+
+        let memory = slave.join().unwrap();
+        assert_eq!(
+            &memory[0x3F..0x4B],
+            &[0x3F, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA, 0x4A]
+        );
+    }
+
+    #[test]
+    fn daq_001() {
+        let (mut master, slave) = spawn_slave();
+        master.connect().unwrap();
+        master
+            .unlock(RESOURCE_CAL_PAG, |_: u8, seed: &[u8]| {
+                seed.iter().map(|byte| !byte).collect::<Vec<_>>()
+            })
+            .unwrap();
+
+        let lists = [
+            DaqList::new(0)
+                .with_timestamp(true)
+                .with_odt(vec![OdtEntry::new(0x10, 0, 2), OdtEntry::new(0x20, 0, 1)])
+                .with_odt(vec![OdtEntry::new(0x30, 0, 4)]),
+            DaqList::new(1).with_odt(vec![OdtEntry::new(0x40, 0, 7)]),
+        ];
+        assert_eq!(
+            master.configure_daq(&[DaqList::new(0).with_odt(vec![OdtEntry::new(0, 0, 8)])]),
+            Err(XcpError::InvalidDaqConfig)
+        );
+        master.configure_daq(&lists).unwrap();
+        master.start_daq().unwrap();
+
+        let timeout = Duration::from_millis(500);
+        assert_eq!(
+            master.recv_daq(timeout),
+            Ok(DaqPacket {
+                daq_list: 0,
+                odt: 0,
+                timestamp: Some(Duration::from_micros(2560)),
+                values: vec![vec![0x10, 0x11], vec![0x20]],
+            })
+        );
+        assert_eq!(
+            master.recv_daq(timeout),
+            Ok(DaqPacket {
+                daq_list: 0,
+                odt: 1,
+                timestamp: None,
+                values: vec![vec![0x30, 0x31, 0x32, 0x33]],
+            })
+        );
+        assert_eq!(
+            master
+                .recv_daq(timeout)
+                .map(|packet| (packet.daq_list, packet.values)),
+            Ok((1, vec![(0x40..0x47).collect::<Vec<u8>>()]))
+        );
+
+        master.stop_daq().unwrap();
+        master.disconnect().unwrap();
+        slave.join().unwrap();
+    }
+}